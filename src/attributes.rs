@@ -0,0 +1,256 @@
+//! Attribute checking.
+//!
+//! An item or an impl method may be preceded by attributes such as
+//! `@inline` or `@deprecated("use g")`. Each must be one of the attributes
+//! below, given the arguments it takes, put on a kind of item it applies to
+//! and given at most once. Methods only take the inlining hints. Later passes read them with `Item::attribute`: the type checker
+//! warns about uses of deprecated items and the backends act on the rest.
+
+use crate::diagnostic::Diagnostic;
+use crate::parser::{Attribute, Item, ItemKind, Method, Program};
+use std::collections::HashMap;
+
+/// How many string arguments an attribute takes.
+#[derive(Clone, Copy)]
+enum Args {
+    None,
+    /// An optional message.
+    Optional,
+    /// Exactly one.
+    One,
+}
+
+/// The items an attribute can be put on, besides impls and imports, which
+/// take none.
+#[derive(Clone, Copy)]
+enum Target {
+    /// Functions with a body.
+    Functions,
+    Any,
+}
+
+struct Known {
+    name: &'static str,
+    args: Args,
+    target: Target,
+    /// Whether the attribute can also be put on a method of an impl.
+    methods: bool,
+}
+
+const KNOWN: &[Known] = &[
+    // Ask the backend to always, or never, inline calls to the function.
+    Known { name: "inline", args: Args::None, target: Target::Functions, methods: true },
+    Known { name: "noinline", args: Args::None, target: Target::Functions, methods: true },
+    // A function run by the test harness; it takes nothing and returns nothing.
+    Known { name: "test", args: Args::None, target: Target::Functions, methods: false },
+    // Uses of the item get a warning, with the message if there is one.
+    Known { name: "deprecated", args: Args::Optional, target: Target::Any, methods: false },
+    // The function is visible to the linker under the given symbol name.
+    Known { name: "export", args: Args::One, target: Target::Functions, methods: false },
+];
+
+pub fn check_program(program: &Program) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut exported: HashMap<&str, &Attribute> = HashMap::new();
+    for item in &program.items {
+        check_item(item, &mut diagnostics);
+        if let ItemKind::Impl(definition) = &item.kind {
+            for method in &definition.methods {
+                check_method(method, &mut diagnostics);
+            }
+        }
+        if let (ItemKind::Function(_), Some(export)) = (&item.kind, item.attribute("export")) {
+            let Some(symbol) = export.args.first() else { continue };
+            if let Some(previous) = exported.insert(symbol, export) {
+                diagnostics.push(
+                    Diagnostic::error(export.span, format!("symbol `{}` is exported more than once", symbol))
+                        .with_note(previous.span, "first exported here"),
+                );
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Looks up `attributes[index]`, reporting it if it is unknown, repeated or
+/// given the wrong number of arguments.
+fn well_formed(attributes: &[Attribute], index: usize, diagnostics: &mut Vec<Diagnostic>) -> Option<&'static Known> {
+    let attribute = &attributes[index];
+    let Some(known) = KNOWN.iter().find(|known| known.name == attribute.name) else {
+        diagnostics.push(Diagnostic::error(attribute.span, format!("unknown attribute `@{}`", attribute.name)));
+        return None;
+    };
+    if let Some(first) = attributes[..index].iter().find(|other| other.name == attribute.name) {
+        diagnostics.push(
+            Diagnostic::error(attribute.span, format!("attribute `@{}` is given more than once", attribute.name))
+                .with_note(first.span, "first given here"),
+        );
+        return None;
+    }
+    let message = match (known.args, attribute.args.len()) {
+        (Args::None, 0) | (Args::Optional, 0 | 1) | (Args::One, 1) => return Some(known),
+        (Args::None, _) => format!("`@{}` takes no arguments", attribute.name),
+        (Args::Optional, _) => format!("`@{}` takes at most one argument", attribute.name),
+        (Args::One, _) => format!("`@{}` takes exactly one argument", attribute.name),
+    };
+    diagnostics.push(Diagnostic::error(attribute.span, message));
+    None
+}
+
+fn check_item(item: &Item, diagnostics: &mut Vec<Diagnostic>) {
+    for (index, attribute) in item.attributes.iter().enumerate() {
+        let Some(known) = well_formed(&item.attributes, index, diagnostics) else { continue };
+        let message = match (&item.kind, known.target) {
+            (ItemKind::Impl(_) | ItemKind::Import(_), _) => Some(format!("`@{}` cannot be put on {}", attribute.name, kind_text(&item.kind))),
+            (ItemKind::Function(_), Target::Functions) | (_, Target::Any) => None,
+            (_, Target::Functions) => Some(format!("`@{}` can only be put on functions with a body", attribute.name)),
+        };
+        if let Some(message) = message {
+            diagnostics.push(Diagnostic::error(attribute.span, message));
+            continue;
+        }
+        if let Some(message) = misuse(item, attribute) {
+            diagnostics.push(Diagnostic::error(attribute.span, message));
+        }
+    }
+}
+
+fn check_method(method: &Method, diagnostics: &mut Vec<Diagnostic>) {
+    for (index, attribute) in method.attributes.iter().enumerate() {
+        let Some(known) = well_formed(&method.attributes, index, diagnostics) else { continue };
+        let message = if !known.methods {
+            format!("`@{}` cannot be put on a method", attribute.name)
+        } else if attribute.name == "inline" && method.attribute("noinline").is_some() {
+            format!("`@inline` and `@noinline` cannot both be put on `{}`", method.function.name)
+        } else {
+            continue;
+        };
+        diagnostics.push(Diagnostic::error(attribute.span, message));
+    }
+}
+
+/// What is wrong with putting the known, well-formed `attribute` on `item`.
+fn misuse(item: &Item, attribute: &Attribute) -> Option<String> {
+    let ItemKind::Function(function) = &item.kind else { return None };
+    match attribute.name.as_str() {
+        "inline" if item.attribute("noinline").is_some() => {
+            Some(format!("`@inline` and `@noinline` cannot both be put on `{}`", function.name))
+        }
+        "test" if !function.params.is_empty() || function.return_type.is_some() || !function.type_params.is_empty() => {
+            Some(format!("test function `{}` must take no parameters and return nothing", function.name))
+        }
+        "export" if !function.type_params.is_empty() => Some(format!("generic function `{}` cannot be exported", function.name)),
+        "export" if !is_symbol(&attribute.args[0]) => Some(format!("`{}` is not a valid symbol name", attribute.args[0])),
+        _ => None,
+    }
+}
+
+/// Whether `name` can be used as a C symbol.
+fn is_symbol(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_') && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn kind_text(kind: &ItemKind) -> &'static str {
+    match kind {
+        ItemKind::Function(_) => "a function",
+        ItemKind::Global(_) => "a global",
+        ItemKind::Const(_) => "a constant",
+        ItemKind::Struct(_) => "a struct",
+        ItemKind::Enum(_) => "an enum",
+        ItemKind::Interface(_) => "an interface",
+        ItemKind::Impl(_) => "an impl",
+        ItemKind::Import(_) => "an import",
+        ItemKind::Extern(_) => "an extern function",
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn errors(source: &str) -> Vec<String> {
+        let mut lexer = Lexer::new(source.to_string());
+        check_program(&Parser::new(&mut lexer).parse_program()).into_iter().map(|diagnostic| diagnostic.message).collect()
+    }
+
+    #[test]
+    fn accepts_known_attributes() {
+        assert_eq!(
+            errors(
+                "@inline function f() {} @noinline @deprecated function g() {} @test function t() {} \
+                 @deprecated(\"use Q\") pub struct P {} @export(\"c_name\") function e(x: int) -> int { return x; }"
+            ),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_repeated_and_malformed_attributes() {
+        assert_eq!(
+            errors("@inlined function f() {} @inline @inline function g() {} @inline(\"x\") function h() {} @export function i() {} @deprecated(\"a\", \"b\") let x = 1;"),
+            [
+                "unknown attribute `@inlined`",
+                "attribute `@inline` is given more than once",
+                "`@inline` takes no arguments",
+                "`@export` takes exactly one argument",
+                "`@deprecated` takes at most one argument"
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_attributes_on_impl_methods() {
+        assert_eq!(
+            errors(
+                "impl Show for P { @inline function show(self) -> int { return 1; } @noinline function pad(self) {} } \
+                 impl Eq for P { @test function eq(self) {} @export(\"eq\") @deprecated function ne(self) {} @inline @noinline @inline function hash(self) {} @cold function f(self) {} }"
+            ),
+            [
+                "`@test` cannot be put on a method",
+                "`@export` cannot be put on a method",
+                "`@deprecated` cannot be put on a method",
+                "`@inline` and `@noinline` cannot both be put on `hash`",
+                "attribute `@inline` is given more than once",
+                "unknown attribute `@cold`"
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_attributes_on_the_wrong_items() {
+        assert_eq!(
+            errors("@inline struct S {} @deprecated impl I for int {} @test let x = 1; @deprecated import m;"),
+            [
+                "`@inline` can only be put on functions with a body",
+                "`@deprecated` cannot be put on an impl",
+                "`@test` can only be put on functions with a body",
+                "`@deprecated` cannot be put on an import"
+            ]
+        );
+        assert_eq!(
+            errors("@inline extern \"C\" function g();"),
+            ["`@inline` can only be put on functions with a body"]
+        );
+    }
+
+    #[test]
+    fn checks_uses_of_function_attributes() {
+        assert_eq!(
+            errors(
+                "@inline @noinline function f() {} @test function t(n: int) {} @export(\"id\") function id<T>(x: T) -> T { return x; } \
+                 @export(\"not a symbol\") function g() {} @export(\"e\") function e1() {} @export(\"e\") function e2() {}"
+            ),
+            [
+                "`@inline` and `@noinline` cannot both be put on `f`",
+                "test function `t` must take no parameters and return nothing",
+                "generic function `id` cannot be exported",
+                "`not a symbol` is not a valid symbol name",
+                "symbol `e` is exported more than once"
+            ]
+        );
+    }
+}
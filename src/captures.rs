@@ -0,0 +1,298 @@
+//! Capture analysis for closures.
+//!
+//! A closure captures every local variable of an enclosing function or
+//! closure that its body refers to; a closure nested in another captures
+//! through it, so the outer closure captures the variable as well. Globals
+//! and named functions are never captured, because they can be reached
+//! directly.
+//!
+//! A variable that is never assigned after its declaration is captured by
+//! value: the closure's copy cannot be told apart from the original. A
+//! variable that is assigned anywhere, inside or outside a closure, is
+//! captured by reference so that the function and all its closures share one
+//! storage location. The lowering keeps such variables in a heap cell, which
+//! stays valid when a closure outlives the function that created it.
+
+use crate::parser::{
+    ArmBody, Block, ClosureParam, Expr, ExprKind, Function, Let, MatchArm, MethodSig, NodeId, Param, Pattern,
+    PatternKind, Program, Stmt, StmtKind,
+};
+use crate::visit::{self, Visitor};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureMode {
+    ByValue,
+    ByReference,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capture {
+    pub name: String,
+    /// The binding pattern that declared the variable.
+    pub binding: NodeId,
+    pub mode: CaptureMode,
+}
+
+#[derive(Debug, Default)]
+pub struct CaptureTable {
+    closures: HashMap<NodeId, Vec<Capture>>,
+    by_reference: HashSet<NodeId>,
+}
+
+impl CaptureTable {
+    /// The variables captured by the closure expression `closure`, in order
+    /// of first use.
+    pub fn captures(&self, closure: NodeId) -> &[Capture] {
+        self.closures.get(&closure).map_or(&[], Vec::as_slice)
+    }
+
+    /// Whether the variable declared by `binding` is captured by reference
+    /// and so has to live in a heap cell.
+    pub fn is_boxed(&self, binding: NodeId) -> bool {
+        self.by_reference.contains(&binding)
+    }
+}
+
+pub fn analyze(program: &Program) -> CaptureTable {
+    let mut analysis = Analysis::default();
+    analysis.visit_program(program);
+
+    let mut table = CaptureTable::default();
+    for (closure, captured) in analysis.captures {
+        let captures = captured
+            .into_iter()
+            .map(|(name, binding)| {
+                let mode = if analysis.assigned.contains(&binding) {
+                    table.by_reference.insert(binding);
+                    CaptureMode::ByReference
+                } else {
+                    CaptureMode::ByValue
+                };
+                Capture { name, binding, mode }
+            })
+            .collect();
+        table.closures.insert(closure, captures);
+    }
+    table
+}
+
+#[derive(Default)]
+struct Analysis {
+    /// Local variables in scope, innermost last, with the binding that
+    /// declared each. Globals are not tracked.
+    scopes: Vec<Vec<(String, NodeId)>>,
+    /// The closures being walked, innermost last, with the number of scopes
+    /// that were open when each began.
+    closures: Vec<(NodeId, usize)>,
+    captures: HashMap<NodeId, Vec<(String, NodeId)>>,
+    /// Bindings that are the target of an assignment somewhere.
+    assigned: HashSet<NodeId>,
+}
+
+impl Analysis {
+    fn bind(&mut self, pattern: &Pattern) {
+        // Globals are not tracked, so there is no scope to bind them in.
+        if self.scopes.is_empty() {
+            return;
+        }
+        let mut bindings = Vec::new();
+        collect_bindings(pattern, &mut bindings);
+        self.scopes.last_mut().expect("checked above").extend(bindings);
+    }
+
+    fn in_scope(&mut self, walk: impl FnOnce(&mut Self)) {
+        self.scopes.push(Vec::new());
+        walk(self);
+        self.scopes.pop();
+    }
+
+    /// The scope depth and binding of the local variable `name`.
+    fn resolve(&self, name: &str) -> Option<(usize, NodeId)> {
+        self.scopes.iter().enumerate().rev().find_map(|(depth, scope)| {
+            scope.iter().rev().find(|(bound, _)| bound == name).map(|(_, binding)| (depth, *binding))
+        })
+    }
+
+    fn use_variable(&mut self, name: &str) {
+        let Some((depth, binding)) = self.resolve(name) else {
+            return;
+        };
+        for (closure, base) in &self.closures {
+            if depth < *base {
+                let captured = self.captures.entry(*closure).or_default();
+                if !captured.iter().any(|(_, existing)| *existing == binding) {
+                    captured.push((name.to_string(), binding));
+                }
+            }
+        }
+    }
+}
+
+impl Visitor for Analysis {
+    fn visit_function(&mut self, function: &Function) {
+        self.in_scope(|analysis| visit::walk_function(analysis, function));
+    }
+
+    fn visit_param(&mut self, param: &Param) {
+        self.bind(&param.pattern);
+    }
+
+    /// An interface's method signatures have no body to bind their
+    /// parameters in.
+    fn visit_method_sig(&mut self, _: &MethodSig) {}
+
+    fn visit_closure_param(&mut self, param: &ClosureParam) {
+        self.bind(&param.pattern);
+    }
+
+    /// The value is walked before the pattern binds, so `let x = x;` refers
+    /// to the outer `x`.
+    fn visit_let(&mut self, binding: &Let) {
+        self.visit_expr(&binding.value);
+        self.bind(&binding.pattern);
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        self.in_scope(|analysis| visit::walk_block(analysis, block));
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::For { .. } => self.in_scope(|analysis| visit::walk_stmt(analysis, stmt)),
+            _ => visit::walk_stmt(self, stmt),
+        }
+    }
+
+    fn visit_match_arm(&mut self, arm: &MatchArm) {
+        self.in_scope(|analysis| {
+            analysis.bind(&arm.pattern);
+            if let Some(guard) = &arm.guard {
+                analysis.visit_expr(guard);
+            }
+            match &arm.body {
+                ArmBody::Expr(body) => analysis.visit_expr(body),
+                ArmBody::Block(block) => analysis.visit_block(block),
+            }
+        });
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Identifier(name) => self.use_variable(name),
+            ExprKind::Assign { target, .. } => {
+                if let Some((_, binding)) = assigned_variable(target).and_then(|name| self.resolve(name)) {
+                    self.assigned.insert(binding);
+                }
+                visit::walk_expr(self, expr);
+            }
+            ExprKind::Closure { .. } => {
+                self.closures.push((expr.id, self.scopes.len()));
+                self.captures.entry(expr.id).or_default();
+                self.in_scope(|analysis| visit::walk_expr(analysis, expr));
+                self.closures.pop();
+            }
+            _ => visit::walk_expr(self, expr),
+        }
+    }
+}
+
+/// The variable whose storage an assignment to `target` changes, if any.
+pub fn assigned_variable(target: &Expr) -> Option<&str> {
+    match &target.kind {
+        ExprKind::Identifier(name) => Some(name),
+        ExprKind::Index { base, .. } | ExprKind::Field { base, .. } | ExprKind::TupleIndex { base, .. } => {
+            assigned_variable(base)
+        }
+        _ => None,
+    }
+}
+
+fn collect_bindings(pattern: &Pattern, bindings: &mut Vec<(String, NodeId)>) {
+    match &pattern.kind {
+        PatternKind::Binding { name, .. } => bindings.push((name.clone(), pattern.id)),
+        PatternKind::Wildcard | PatternKind::IntLiteral(_) | PatternKind::BoolLiteral(_) => {}
+        PatternKind::Variant { fields: elements, .. } | PatternKind::Tuple(elements) => {
+            for element in elements {
+                collect_bindings(element, bindings);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::{ItemKind, Parser};
+
+    /// The captures of every closure in `source`, in source order, as
+    /// `name` or `&name` for a capture by reference.
+    fn captures(source: &str) -> Vec<Vec<String>> {
+        struct Closures(Vec<NodeId>);
+
+        impl Visitor for Closures {
+            fn visit_expr(&mut self, expr: &Expr) {
+                if let ExprKind::Closure { .. } = expr.kind {
+                    self.0.push(expr.id);
+                }
+                visit::walk_expr(self, expr);
+            }
+        }
+
+        let mut lexer = Lexer::new(source.to_string());
+        let program = Parser::new(&mut lexer).parse_program();
+        let table = analyze(&program);
+        let mut closures = Closures(Vec::new());
+        closures.visit_program(&program);
+        closures
+            .0
+            .iter()
+            .map(|closure| {
+                table
+                    .captures(*closure)
+                    .iter()
+                    .map(|capture| match capture.mode {
+                        CaptureMode::ByValue => capture.name.clone(),
+                        CaptureMode::ByReference => format!("&{}", capture.name),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn captures_enclosing_locals_but_not_globals_or_functions() {
+        assert_eq!(
+            captures("let g = 1; function f(n: int, (a, b): (int, int)) { let m = n; let c = |x: int| x + m + g + a + f(x, (b, m)); }"),
+            [["m", "a", "b"]]
+        );
+    }
+
+    #[test]
+    fn respects_shadowing_and_closure_scopes() {
+        assert_eq!(
+            captures("function f(n: int) { let x = n; let c = |n: int| { let x = n; return x; }; let d = |y| match y { x => x + n }; }"),
+            [vec![], vec!["n"]]
+        );
+    }
+
+    #[test]
+    fn captures_assigned_variables_by_reference() {
+        assert_eq!(
+            captures("function f(n: int) { let count = 0; let t = (0, 1); let inc = || { count = count + n; }; t.1 = 2; let get = || t.0 + count; }"),
+            [["&count", "n"], ["&t", "&count"]]
+        );
+    }
+
+    #[test]
+    fn nested_closures_capture_through_their_parents() {
+        let source = "function f(n: int) { let outer = |x: int| |y: int| x + y + n; }";
+        assert_eq!(captures(source), [vec!["n"], vec!["x", "n"]]);
+        let mut lexer = Lexer::new(source.to_string());
+        let program = Parser::new(&mut lexer).parse_program();
+        let ItemKind::Function(function) = &program.items[0].kind else { panic!("expected function") };
+        let table = analyze(&program);
+        assert!(!table.is_boxed(function.params[0].pattern.id));
+    }
+}
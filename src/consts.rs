@@ -0,0 +1,680 @@
+//! Compile-time evaluation of `const` declarations.
+//!
+//! A constant's initializer may use literals, arithmetic, comparisons, array
+//! literals and indexing, other constants and calls to `const function`s,
+//! whose bodies may do the same with `let`, `if`, loops, assignments to their
+//! own variables and the elements of them, and `return`. The body of every
+//! `const function` is checked for anything else, such as strings, closures
+//! or calls to functions that are not `const`, whether or not a constant
+//! calls it; reading a global `let` is reported when it is evaluated.
+//!
+//! Integer arithmetic is exact rather than done in the width of its operands.
+//! A value that does not fit the integer type declared for it, as a constant,
+//! parameter, return value or `let` with a type, is reported as an overflow;
+//! only `as` truncates.
+//!
+//! Every loop iteration and function call uses up a step of fuel, so a loop
+//! that never ends is reported once the fuel runs out rather than hanging
+//! the compiler.
+//!
+//! Constants are evaluated on demand, so they may refer to constants declared
+//! after them; a constant that depends on itself is reported as a cycle.
+//! Operands of the wrong type are left for the type checker to report, and
+//! the constant is simply left without a value.
+
+use crate::diagnostic::Diagnostic;
+use crate::lexer::Span;
+use crate::parser::{
+    BinaryOp, Block, Const, Expr, ExprKind, Function, ItemKind, Let, Param, PatternKind, Program, Stmt, StmtKind, Type, TypeKind, UnaryOp,
+};
+use crate::printer::print_type;
+use crate::visit::{self, Visitor};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// The deepest nesting of function calls while evaluating one constant.
+/// Recursion that never ends, as in `const function f() { return f(); }`,
+/// reaches it long before the fuel runs out.
+const CALL_DEPTH_LIMIT: usize = 64;
+
+/// The loop iterations and function calls that evaluating one constant may
+/// take.
+const FUEL: u64 = 1_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConstValue {
+    /// Wide enough for every value of every integer type.
+    Int(i128),
+    Bool(bool),
+    /// The elements of an array, such as a lookup table.
+    Array(Vec<ConstValue>),
+}
+
+impl fmt::Display for ConstValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConstValue::Int(value) => write!(f, "{}", value),
+            ConstValue::Bool(value) => write!(f, "{}", value),
+            ConstValue::Array(elements) => {
+                let elements: Vec<String> = elements.iter().map(ConstValue::to_string).collect();
+                write!(f, "[{}]", elements.join(", "))
+            }
+        }
+    }
+}
+
+/// Evaluates every constant of `program`. Constants that fail to evaluate
+/// are left out of the result.
+pub fn evaluate(program: &Program) -> (HashMap<String, ConstValue>, Vec<Diagnostic>) {
+    let mut evaluator = Evaluator {
+        consts: HashMap::new(),
+        functions: HashMap::new(),
+        rejected: HashSet::new(),
+        values: HashMap::new(),
+        evaluating: Vec::new(),
+        locals: Vec::new(),
+        deferred: Vec::new(),
+        depth: 0,
+        fuel: FUEL,
+        diagnostics: Vec::new(),
+    };
+    for item in &program.items {
+        match &item.kind {
+            ItemKind::Const(constant) => {
+                evaluator.consts.insert(&constant.name, constant);
+            }
+            ItemKind::Function(function) => {
+                evaluator.functions.insert(&function.name, function);
+            }
+            _ => {}
+        }
+    }
+    for item in &program.items {
+        if let ItemKind::Function(function) = &item.kind {
+            if function.constant {
+                let diagnostics = check_const_function(function, &evaluator.functions);
+                if !diagnostics.is_empty() {
+                    evaluator.rejected.insert(&function.name);
+                }
+                evaluator.diagnostics.extend(diagnostics);
+            }
+        }
+    }
+    for item in &program.items {
+        if let ItemKind::Const(constant) = &item.kind {
+            // Failures have been recorded as diagnostics already.
+            let _ = evaluator.constant(&constant.name, item.span);
+        }
+    }
+    let values = evaluator.values.into_iter().filter_map(|(name, value)| Some((name, value?))).collect();
+    (values, evaluator.diagnostics)
+}
+
+/// Why evaluation stopped before producing a value.
+enum Stop {
+    /// A `return` out of the function being called.
+    Return(Option<ConstValue>),
+    Error(Diagnostic),
+    /// A failure that is reported elsewhere: a type error, or a constant
+    /// that failed to evaluate.
+    Reported,
+}
+
+type Eval<T> = Result<T, Stop>;
+
+fn error<T>(span: Span, message: impl Into<String>) -> Eval<T> {
+    Err(Stop::Error(Diagnostic::error(span, message)))
+}
+
+struct Evaluator<'a> {
+    consts: HashMap<&'a str, &'a Const>,
+    functions: HashMap<&'a str, &'a Function>,
+    /// The `const function`s whose bodies were reported by
+    /// `check_const_function`; calls to them are not evaluated.
+    rejected: HashSet<&'a str>,
+    /// The value of each constant evaluated so far; `None` if it failed.
+    values: HashMap<String, Option<ConstValue>>,
+    /// The constants being evaluated, outermost first.
+    evaluating: Vec<&'a str>,
+    /// The variables of the function call being evaluated, innermost last.
+    locals: Vec<(&'a str, ConstValue)>,
+    /// The statements deferred by the blocks being evaluated, innermost
+    /// last, with the number of locals in scope at each `defer`.
+    deferred: Vec<(&'a Stmt, usize)>,
+    /// The number of function calls being evaluated.
+    depth: usize,
+    /// The steps left for the constant being evaluated.
+    fuel: u64,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Evaluator<'a> {
+    /// The value of the constant `name`, used at `span`.
+    fn constant(&mut self, name: &'a str, span: Span) -> Eval<ConstValue> {
+        if let Some(value) = self.values.get(name) {
+            return value.clone().ok_or(Stop::Reported);
+        }
+        if let Some(start) = self.evaluating.iter().position(|evaluating| *evaluating == name) {
+            let cycle: Vec<&str> = self.evaluating[start..].iter().copied().chain([name]).collect();
+            return error(span, format!("cycle between constants: {}", cycle.join(" -> ")));
+        }
+        let constant = self.consts[name];
+        // The initializer sees no variables of the call it is used from.
+        let locals = std::mem::take(&mut self.locals);
+        let depth = std::mem::take(&mut self.depth);
+        let fuel = std::mem::replace(&mut self.fuel, FUEL);
+        self.evaluating.push(name);
+        let result = self.expr(&constant.value).and_then(|value| fit(value, &constant.ty, constant.value.span));
+        self.evaluating.pop();
+        self.locals = locals;
+        self.depth = depth;
+        self.fuel = fuel;
+        let value = match result {
+            Ok(value) => Some(value),
+            Err(Stop::Error(diagnostic)) => {
+                self.diagnostics.push(diagnostic);
+                None
+            }
+            Err(Stop::Return(_) | Stop::Reported) => None,
+        };
+        self.values.insert(name.to_string(), value.clone());
+        value.ok_or(Stop::Reported)
+    }
+
+    /// Uses up a step of fuel for a loop iteration or call at `span`.
+    fn burn(&mut self, span: Span) -> Eval<()> {
+        if self.fuel == 0 {
+            return error(span, format!("constant evaluation ran out of fuel after {} steps; it may never finish", FUEL));
+        }
+        self.fuel -= 1;
+        Ok(())
+    }
+
+    fn int(&mut self, expr: &'a Expr) -> Eval<i128> {
+        match self.expr(expr)? {
+            ConstValue::Int(value) => Ok(value),
+            _ => Err(Stop::Reported),
+        }
+    }
+
+    fn bool(&mut self, expr: &'a Expr) -> Eval<bool> {
+        match self.expr(expr)? {
+            ConstValue::Bool(value) => Ok(value),
+            _ => Err(Stop::Reported),
+        }
+    }
+
+    /// Evaluates `index` into an array of `length` elements.
+    fn index(&mut self, index: &'a Expr, length: usize) -> Eval<usize> {
+        let value = self.int(index)?;
+        match usize::try_from(value) {
+            Ok(position) if position < length => Ok(position),
+            _ => out_of_bounds(index.span, value, length),
+        }
+    }
+
+    /// The local variable that the assignment target `target` names, and the
+    /// indices of the element of it that is assigned, outermost first.
+    fn place(&mut self, target: &'a Expr) -> Eval<(&'a str, Vec<(i128, Span)>)> {
+        match &target.kind {
+            ExprKind::Identifier(name) => Ok((name, Vec::new())),
+            ExprKind::Index { base, index } => {
+                let (name, mut indices) = self.place(base)?;
+                indices.push((self.int(index)?, index.span));
+                Ok((name, indices))
+            }
+            _ => error(target.span, "only local variables and their elements can be assigned in a constant expression"),
+        }
+    }
+
+    fn expr(&mut self, expr: &'a Expr) -> Eval<ConstValue> {
+        match &expr.kind {
+            ExprKind::IntLiteral(value) => Ok(ConstValue::Int(i128::from(*value))),
+            ExprKind::BoolLiteral(value) => Ok(ConstValue::Bool(*value)),
+            ExprKind::Identifier(name) => {
+                if let Some((_, value)) = self.locals.iter().rev().find(|(local, _)| local == name) {
+                    Ok(value.clone())
+                } else if self.consts.contains_key(name.as_str()) {
+                    self.constant(name, expr.span)
+                } else {
+                    error(expr.span, format!("`{}` is not a constant", name))
+                }
+            }
+            ExprKind::Unary { op: UnaryOp::Neg, operand } => match self.int(operand)?.checked_neg() {
+                Some(value) => Ok(ConstValue::Int(value)),
+                None => error(expr.span, "overflow in constant evaluation"),
+            },
+            ExprKind::Unary { op: UnaryOp::Not, operand } => Ok(ConstValue::Bool(!self.bool(operand)?)),
+            ExprKind::Cast { operand, ty } => match (self.expr(operand)?, &ty.kind) {
+                (ConstValue::Int(value), TypeKind::Int(ty)) => Ok(ConstValue::Int(ty.wrap(value))),
+                (ConstValue::Bool(value), TypeKind::Int(_)) => Ok(ConstValue::Int(i128::from(value))),
+                _ => Err(Stop::Reported),
+            },
+            ExprKind::Binary { op, left, right } => {
+                let (left, right) = (self.expr(left)?, self.expr(right)?);
+                binary(*op, left, right, expr.span)
+            }
+            ExprKind::Call { callee, args } => {
+                let ExprKind::Identifier(name) = &callee.kind else {
+                    return error(callee.span, "only functions can be called in a constant expression");
+                };
+                let Some(function) = self.functions.get(name.as_str()).copied() else {
+                    return error(callee.span, format!("`{}` is not a function that can be called in a constant expression", name));
+                };
+                if !function.constant {
+                    return error(callee.span, not_const(name));
+                }
+                if self.rejected.contains(name.as_str()) {
+                    return Err(Stop::Reported);
+                }
+                let args = args.iter().map(|arg| self.expr(arg)).collect::<Eval<Vec<_>>>()?;
+                self.call(function, args, expr.span)
+            }
+            ExprKind::ArrayLiteral(elements) => {
+                let elements = elements.iter().map(|element| self.expr(element)).collect::<Eval<_>>()?;
+                Ok(ConstValue::Array(elements))
+            }
+            ExprKind::Index { base, index } => {
+                let ConstValue::Array(mut elements) = self.expr(base)? else {
+                    return Err(Stop::Reported);
+                };
+                let index = self.index(index, elements.len())?;
+                Ok(elements.swap_remove(index))
+            }
+            ExprKind::Assign { target, value } => {
+                let value = self.expr(value)?;
+                let (name, indices) = self.place(target)?;
+                let Some(local) = self.locals.iter_mut().rev().find(|(local, _)| *local == name) else {
+                    return error(target.span, format!("`{}` is not a local variable", name));
+                };
+                let mut slot = &mut local.1;
+                for (index, span) in indices {
+                    let ConstValue::Array(elements) = slot else {
+                        return Err(Stop::Reported);
+                    };
+                    let length = elements.len();
+                    match usize::try_from(index).ok().and_then(|index| elements.get_mut(index)) {
+                        Some(element) => slot = element,
+                        None => return out_of_bounds(span, index, length),
+                    }
+                }
+                *slot = value.clone();
+                Ok(value)
+            }
+            kind => error(expr.span, format!("{} cannot be evaluated at compile time", unsupported(kind).unwrap_or("this expression"))),
+        }
+    }
+
+    fn call(&mut self, function: &'a Function, args: Vec<ConstValue>, span: Span) -> Eval<ConstValue> {
+        if args.len() != function.params.len() {
+            return Err(Stop::Reported);
+        }
+        if self.depth == CALL_DEPTH_LIMIT {
+            return error(span, format!("constant evaluation exceeded the call depth limit of {}", CALL_DEPTH_LIMIT));
+        }
+        self.burn(span)?;
+        let mut locals = Vec::new();
+        for (param, arg) in function.params.iter().zip(args) {
+            let PatternKind::Binding { name, .. } = &param.pattern.kind else {
+                return error(param.span, "destructuring parameters cannot be evaluated at compile time");
+            };
+            locals.push((name.as_str(), fit(arg, &param.ty, span)?));
+        }
+        let caller = std::mem::replace(&mut self.locals, locals);
+        self.depth += 1;
+        let result = self.block(&function.body);
+        self.depth -= 1;
+        self.locals = caller;
+        match result {
+            Ok(()) | Err(Stop::Return(None)) => error(span, format!("`{}` does not return a value", function.name)),
+            Err(Stop::Return(Some(value))) => match &function.return_type {
+                Some(ty) => fit(value, ty, span),
+                None => Ok(value),
+            },
+            Err(stop) => Err(stop),
+        }
+    }
+
+    fn block(&mut self, block: &'a Block) -> Eval<()> {
+        let scope = self.locals.len();
+        let deferred = self.deferred.len();
+        let mut result = block.stmts.iter().try_for_each(|stmt| self.stmt(stmt));
+        // The block's deferred statements run, last first, however it is
+        // left, unless evaluating it failed. Each sees only the variables in
+        // scope at its `defer`, not those declared after it.
+        for (stmt, scope) in self.deferred.split_off(deferred).into_iter().rev() {
+            self.locals.truncate(scope);
+            if matches!(result, Ok(()) | Err(Stop::Return(_))) {
+                if let Err(stop) = self.stmt(stmt) {
+                    result = Err(stop);
+                }
+            }
+        }
+        self.locals.truncate(scope);
+        result
+    }
+
+    fn stmt(&mut self, stmt: &'a Stmt) -> Eval<()> {
+        match &stmt.kind {
+            StmtKind::Let(binding) => {
+                let PatternKind::Binding { name, .. } = &binding.pattern.kind else {
+                    return error(binding.pattern.span, "destructuring cannot be evaluated at compile time");
+                };
+                let mut value = self.expr(&binding.value)?;
+                if let Some(ty) = &binding.ty {
+                    value = fit(value, ty, binding.value.span)?;
+                }
+                self.locals.push((name, value));
+                Ok(())
+            }
+            StmtKind::Expr(expr) => self.expr(expr).map(|_| ()),
+            StmtKind::Defer(deferred) => {
+                self.deferred.push((deferred, self.locals.len()));
+                Ok(())
+            }
+            StmtKind::Return(value) => {
+                let value = value.as_ref().map(|value| self.expr(value)).transpose()?;
+                Err(Stop::Return(value))
+            }
+            StmtKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                if self.bool(condition)? {
+                    self.block(then_branch)
+                } else if let Some(else_branch) = else_branch {
+                    self.block(else_branch)
+                } else {
+                    Ok(())
+                }
+            }
+            StmtKind::While { condition, body } => {
+                while self.bool(condition)? {
+                    self.burn(stmt.span)?;
+                    self.block(body)?;
+                }
+                Ok(())
+            }
+            StmtKind::For {
+                init,
+                condition,
+                increment,
+                body,
+            } => {
+                // The variable declared by `init` goes out of scope after the loop.
+                let scope = self.locals.len();
+                if let Some(init) = init {
+                    self.stmt(init)?;
+                }
+                while condition.as_ref().map_or(Ok(true), |condition| self.bool(condition))? {
+                    self.burn(stmt.span)?;
+                    self.block(body)?;
+                    if let Some(increment) = increment {
+                        self.expr(increment)?;
+                    }
+                }
+                self.locals.truncate(scope);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Reports whatever the body of the `const function` `function` does that
+/// cannot be evaluated at compile time, and parameter and result types that
+/// constants cannot have.
+fn check_const_function(function: &Function, functions: &HashMap<&str, &Function>) -> Vec<Diagnostic> {
+    let mut checker = ConstFunctionChecker {
+        functions,
+        diagnostics: Vec::new(),
+    };
+    for ty in function.params.iter().map(|param| &param.ty).chain(&function.return_type) {
+        if !is_constant_type(ty) {
+            let message = format!("a `const function` can only take and return integers, `bool` and arrays of them, found `{}`", print_type(ty));
+            checker.diagnostics.push(Diagnostic::error(ty.span, message));
+        }
+    }
+    checker.visit_function(function);
+    checker.diagnostics
+}
+
+struct ConstFunctionChecker<'f, 'a> {
+    functions: &'f HashMap<&'a str, &'a Function>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Visitor for ConstFunctionChecker<'_, '_> {
+    fn visit_param(&mut self, param: &Param) {
+        if !matches!(param.pattern.kind, PatternKind::Binding { .. }) {
+            self.diagnostics.push(Diagnostic::error(param.span, "destructuring parameters cannot be evaluated at compile time"));
+        }
+    }
+
+    fn visit_let(&mut self, binding: &Let) {
+        if !matches!(binding.pattern.kind, PatternKind::Binding { .. }) {
+            self.diagnostics.push(Diagnostic::error(binding.pattern.span, "destructuring cannot be evaluated at compile time"));
+        }
+        visit::walk_let(self, binding);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        if let Some(what) = unsupported(&expr.kind) {
+            self.diagnostics.push(Diagnostic::error(expr.span, format!("{} cannot be evaluated at compile time", what)));
+            return;
+        }
+        if let ExprKind::Call { callee, .. } = &expr.kind {
+            if let ExprKind::Identifier(name) = &callee.kind {
+                if self.functions.get(name.as_str()).is_some_and(|function| !function.constant) {
+                    self.diagnostics.push(Diagnostic::error(callee.span, not_const(name)));
+                }
+            }
+        }
+        visit::walk_expr(self, expr);
+    }
+}
+
+/// What `kind` is, if it is an expression that can never be evaluated at
+/// compile time.
+fn unsupported(kind: &ExprKind) -> Option<&'static str> {
+    match kind {
+        ExprKind::StringLiteral(_) | ExprKind::Interpolation(_) => Some("strings"),
+        ExprKind::Tuple(_) | ExprKind::TupleIndex { .. } => Some("tuples"),
+        ExprKind::StructLiteral { .. } => Some("struct literals"),
+        ExprKind::Field { .. } => Some("fields and methods"),
+        ExprKind::Path(_) => Some("enum variants"),
+        ExprKind::Match { .. } => Some("`match` expressions"),
+        ExprKind::Closure { .. } => Some("closures"),
+        ExprKind::Try(_) => Some("`?` operators"),
+        _ => None,
+    }
+}
+
+fn not_const(name: &str) -> String {
+    format!("`{}` is not a `const function`, so it cannot be called at compile time", name)
+}
+
+fn out_of_bounds<T>(span: Span, index: i128, length: usize) -> Eval<T> {
+    error(span, format!("index {} is out of bounds for an array of length {} in constant evaluation", index, length))
+}
+
+/// Whether constants can have the type `ty`: integers, `bool` and arrays of
+/// them.
+fn is_constant_type(ty: &Type) -> bool {
+    match &ty.kind {
+        TypeKind::Int(_) | TypeKind::Bool => true,
+        TypeKind::Array { element, .. } => is_constant_type(element),
+        _ => false,
+    }
+}
+
+/// `value`, unless it is an integer that `ty` cannot hold or an array with
+/// such an element.
+fn fit(value: ConstValue, ty: &Type, span: Span) -> Eval<ConstValue> {
+    match (value, &ty.kind) {
+        (ConstValue::Int(int), TypeKind::Int(ty)) if !ty.contains(int) => {
+            error(span, format!("overflow in constant evaluation: {} does not fit in `{}`", int, ty.name()))
+        }
+        (ConstValue::Array(elements), TypeKind::Array { element, .. }) => {
+            let elements = elements.into_iter().map(|value| fit(value, element, span)).collect::<Eval<_>>()?;
+            Ok(ConstValue::Array(elements))
+        }
+        (value, _) => Ok(value),
+    }
+}
+
+fn binary(op: BinaryOp, left: ConstValue, right: ConstValue, span: Span) -> Eval<ConstValue> {
+    use ConstValue::{Bool, Int};
+    let value = match (op, left, right) {
+        (BinaryOp::Add, Int(a), Int(b)) => a.checked_add(b).map(Int),
+        (BinaryOp::Sub, Int(a), Int(b)) => a.checked_sub(b).map(Int),
+        (BinaryOp::Mul, Int(a), Int(b)) => a.checked_mul(b).map(Int),
+        (BinaryOp::Div, Int(_), Int(0)) => return error(span, "division by zero in constant evaluation"),
+        (BinaryOp::Div, Int(a), Int(b)) => a.checked_div(b).map(Int),
+        (BinaryOp::Shl | BinaryOp::Shr, Int(_), Int(b)) if !(0..64).contains(&b) => {
+            return error(span, format!("shift amount {} is out of range in constant evaluation", b));
+        }
+        (BinaryOp::Shl, Int(a), Int(b)) => a.checked_shl(b as u32).filter(|value| value >> b == a).map(Int),
+        (BinaryOp::Shr, Int(a), Int(b)) => Some(Int(a >> b)),
+        (BinaryOp::Lt, Int(a), Int(b)) => Some(Bool(a < b)),
+        (BinaryOp::Le, Int(a), Int(b)) => Some(Bool(a <= b)),
+        (BinaryOp::Gt, Int(a), Int(b)) => Some(Bool(a > b)),
+        (BinaryOp::Ge, Int(a), Int(b)) => Some(Bool(a >= b)),
+        (BinaryOp::Eq, Int(a), Int(b)) => Some(Bool(a == b)),
+        (BinaryOp::Ne, Int(a), Int(b)) => Some(Bool(a != b)),
+        (BinaryOp::Eq, Bool(a), Bool(b)) => Some(Bool(a == b)),
+        (BinaryOp::Ne, Bool(a), Bool(b)) => Some(Bool(a != b)),
+        _ => return Err(Stop::Reported),
+    };
+    value.map_or_else(|| error(span, "overflow in constant evaluation"), Ok)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn evaluate_source(source: &str) -> (HashMap<String, ConstValue>, Vec<String>) {
+        let mut lexer = Lexer::new(source.to_string());
+        let program = Parser::new(&mut lexer).parse_program();
+        let (values, diagnostics) = evaluate(&program);
+        (values, diagnostics.into_iter().map(|diagnostic| diagnostic.message).collect())
+    }
+
+    #[test]
+    fn evaluates_constants_and_const_functions() {
+        let (values, errors) = evaluate_source(
+            "const AREA: int = square(SIDE) + 1; const SIDE: int = 2 * 3; const BIG: bool = AREA > 30 == !false; \
+             const function square(n: int) -> int { let result = 0; if n < 0 { result = -n * -n; } else { result = n * n; } return result; } \
+             const function fact(n: int) -> int { if n <= 1 { return 1; } return n * fact(n - 1); } const F: int = fact(5);",
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(values["SIDE"], ConstValue::Int(6));
+        assert_eq!(values["AREA"], ConstValue::Int(37));
+        assert_eq!(values["BIG"], ConstValue::Bool(true));
+        assert_eq!(values["F"], ConstValue::Int(120));
+    }
+
+    #[test]
+    fn reports_non_constant_initializers() {
+        let (values, errors) = evaluate_source(
+            "let g = 1; const A: int = g + 1; const B: int = [1][2]; const C: int = 1 / 0; const D: int = spin(); \
+             const E: int = forever(); const F: bool = 1 + true; const G: int = runtime(); \
+             const function spin() -> int { while (true) {} return 0; } const function forever() -> int { return forever(); } \
+             function runtime() -> int { return 1; }",
+        );
+        assert!(values.is_empty());
+        assert_eq!(
+            errors,
+            [
+                "`g` is not a constant",
+                "index 2 is out of bounds for an array of length 1 in constant evaluation",
+                "division by zero in constant evaluation",
+                "constant evaluation ran out of fuel after 1000000 steps; it may never finish",
+                "constant evaluation exceeded the call depth limit of 64",
+                "`runtime` is not a `const function`, so it cannot be called at compile time"
+            ]
+        );
+    }
+
+    #[test]
+    fn evaluates_sized_integers_exactly_and_casts_by_truncating() {
+        let (values, errors) = evaluate_source(
+            "const A: u8 = 300 as u8; const B: i8 = 255 as i8; const C: u64 = -1 as u64; const D: i64 = 1 << 40 >> 38; \
+             const E: u8 = 200 + 100; const F: u8 = double(200); const G: int = 1 << 64; const H: u8 = double(100); \
+             const function double(x: u8) -> u8 { let y: u16 = x * 2; return y; }",
+        );
+        assert_eq!(values["A"], ConstValue::Int(44));
+        assert_eq!(values["B"], ConstValue::Int(-1));
+        assert_eq!(values["C"], ConstValue::Int(i128::from(u64::MAX)));
+        assert_eq!(values["D"], ConstValue::Int(4));
+        assert_eq!(values["H"], ConstValue::Int(200));
+        assert_eq!(
+            errors,
+            [
+                "overflow in constant evaluation: 300 does not fit in `u8`",
+                "overflow in constant evaluation: 400 does not fit in `u8`",
+                "shift amount 64 is out of range in constant evaluation"
+            ]
+        );
+    }
+
+    #[test]
+    fn builds_lookup_tables_with_loops() {
+        let (values, errors) = evaluate_source(
+            "const SQUARES: int[4] = squares(); const NINE: int = SQUARES[3]; const GRID: u8[2][2] = [[1, 2], [3, 4]]; \
+             const function squares() -> int[4] { let table = [0, 0, 0, 0]; for (let i = 0; i < 4; i = i + 1) { table[i] = i * i; } return table; } \
+             const function sum(table: int[4]) -> int { let total = 0; let i = 0; while i < 4 { total = total + table[i]; i = i + 1; } return total; } \
+             const TOTAL: int = sum(SQUARES); const BAD: u8[2] = [1, 256];",
+        );
+        assert_eq!(values["SQUARES"].to_string(), "[0, 1, 4, 9]");
+        assert_eq!(values["NINE"], ConstValue::Int(9));
+        assert_eq!(values["GRID"].to_string(), "[[1, 2], [3, 4]]");
+        assert_eq!(values["TOTAL"], ConstValue::Int(14));
+        assert_eq!(errors, ["overflow in constant evaluation: 256 does not fit in `u8`"]);
+    }
+
+    #[test]
+    fn runs_deferred_statements_last_first_on_every_exit() {
+        let (values, errors) = evaluate_source(
+            "const function order() -> int { let log = 0; if true { defer log = log * 10 + 1; defer log = log * 10 + 2; log = 3; } return log; } \
+             const function loops() -> int { let log = 0; for (let i = 1; i < 4; i = i + 1) { defer log = log * 10 + i; log = log * 10; } return log; } \
+             const function leave() -> int { defer 1 / 0; return 1; } \
+             const function shadowed() -> int { let mut log = 0; if true { defer log = log + 1; let log = 50; } return log; } \
+             const ORDER: int = order(); const LOOPS: int = loops(); const LEAVE: int = leave(); const SHADOWED: int = shadowed();",
+        );
+        assert_eq!(values["ORDER"], ConstValue::Int(321));
+        assert_eq!(values["SHADOWED"], ConstValue::Int(1));
+        assert_eq!(values["LOOPS"], ConstValue::Int(10203));
+        assert_eq!(errors, ["division by zero in constant evaluation"]);
+    }
+
+    #[test]
+    fn checks_const_function_bodies_up_front() {
+        let (values, errors) = evaluate_source(
+            "const function f(s: string) -> int { let (a, b) = (1, 2); print(\"x\"); return |x| x; } \
+             const function g(n: int) -> int { return helper(n); } function helper(n: int) -> int { return n; } \
+             const function h(n: int) -> int { return n + 1; } const H: int = h(1); const G: int = g(1);",
+        );
+        assert_eq!(values.into_iter().collect::<Vec<_>>(), [("H".to_string(), ConstValue::Int(2))]);
+        assert_eq!(
+            errors,
+            [
+                "a `const function` can only take and return integers, `bool` and arrays of them, found `string`",
+                "destructuring cannot be evaluated at compile time",
+                "tuples cannot be evaluated at compile time",
+                "strings cannot be evaluated at compile time",
+                "closures cannot be evaluated at compile time",
+                "`helper` is not a `const function`, so it cannot be called at compile time"
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_cycles_between_constants() {
+        let (values, errors) = evaluate_source("const A: int = B + 1; const B: int = twice(A); const C: int = C; const D: int = 1; const function twice(n: int) -> int { return 2 * n; }");
+        assert_eq!(values.into_iter().collect::<Vec<_>>(), [("D".to_string(), ConstValue::Int(1))]);
+        assert_eq!(errors, ["cycle between constants: A -> B -> A", "cycle between constants: C -> C"]);
+    }
+}
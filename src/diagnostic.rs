@@ -0,0 +1,144 @@
+//! Errors and warnings reported by the checking passes, with the source span
+//! they refer to.
+
+use crate::lexer::Span;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub span: Span,
+    pub message: String,
+    /// Further locations that explain the diagnostic, such as a definition.
+    pub notes: Vec<Note>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn error(span: Span, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            span,
+            message: message.into(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn warning(span: Span, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            span,
+            message: message.into(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn with_note(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.notes.push(Note {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Renders the diagnostic as `path:line:column: severity: message`
+    /// followed by the offending source line with the span underlined, and
+    /// each note the same way.
+    pub fn render(&self, path: &str, source: &str) -> String {
+        let mut out = render_span(path, source, self.span, &self.severity.to_string(), &self.message);
+        for note in &self.notes {
+            out.push_str(&render_span(path, source, note.span, "note", &note.message));
+        }
+        out
+    }
+}
+
+/// Renders `path:line:column: label: message` followed by the source line
+/// containing `span` with the span underlined.
+pub fn render_span(path: &str, source: &str, span: Span, label: &str, message: &str) -> String {
+    let (line, column, text) = locate(source, span.start);
+    // Underline at least one column and stop at the end of the line.
+    let rest_of_line = text.chars().count().saturating_sub(column - 1).max(1);
+    let width = span.end.saturating_sub(span.start).clamp(1, rest_of_line);
+    format!(
+        "{}:{}:{}: {}: {}\n    {}\n    {}{}\n",
+        path,
+        line,
+        column,
+        label,
+        message,
+        text,
+        " ".repeat(column - 1),
+        "^".repeat(width)
+    )
+}
+
+/// The 1-based line and column of a character offset, and the text of that
+/// line.
+fn locate(source: &str, offset: usize) -> (usize, usize, &str) {
+    let mut line_start = 0;
+    let mut line = 1;
+    for (index, (byte, c)) in source.char_indices().enumerate() {
+        if index == offset {
+            break;
+        }
+        if c == '\n' {
+            line += 1;
+            line_start = byte + 1;
+        }
+    }
+    let text = source[line_start..].split('\n').next().unwrap_or("");
+    let column = source[..line_start].chars().count();
+    (line, offset - column + 1, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_location_and_underline() {
+        let source = "let a = 1;\nlet b = oops + 1;\n";
+        let diagnostic = Diagnostic::error(Span::new(19, 23), "unknown name `oops`");
+        assert_eq!(
+            diagnostic.render("main.cl", source),
+            "main.cl:2:9: error: unknown name `oops`\n    let b = oops + 1;\n            ^^^^\n"
+        );
+        let at_end = Diagnostic::warning(Span::new(29, 29), "at end of input");
+        assert!(at_end.render("main.cl", source).starts_with("main.cl:3:1: warning: at end of input\n    \n    ^\n"));
+    }
+
+    #[test]
+    fn renders_notes_after_the_diagnostic() {
+        let source = "let a = 1;\nlet b = a;\n";
+        let diagnostic = Diagnostic::error(Span::new(19, 20), "bad use").with_note(Span::new(4, 5), "defined here");
+        assert_eq!(
+            diagnostic.render("main.cl", source),
+            "main.cl:2:9: error: bad use\n    let b = a;\n            ^\nmain.cl:1:5: note: defined here\n    let a = 1;\n        ^\n"
+        );
+    }
+}
@@ -0,0 +1,1628 @@
+//! Machine-readable dumps of token streams and syntax trees.
+//!
+//! Two formats are supported, both including node ids and spans.
+//!
+//! **JSON.** A token is `{"kind": "Identifier", "span": [0, 3], "value": "foo"}`.
+//! `value` is only present for `Identifier`, `IntLiteral`, `StringLiteral`
+//! (the text between the quotes as written), `TypeInt` (the type's name) and
+//! `Error`. Every AST node is an object with `id` (number), `span`
+//! (`[start, end]`, character offsets, end exclusive) and, for items,
+//! statements, expressions and types, a `kind` string naming the variant.
+//! The remaining fields mirror the Rust structs in `parser` using the same
+//! field names. Optional children are `null` when absent and operators use
+//! their variant names (`"Add"`, `"Neg"`). Beyond those, node kinds carry:
+//!
+//! - Items: a boolean `public` and an `attributes` array of nodes with a
+//!   `name` and string `args`. Impl methods have an `attributes` array too.
+//! - `Extern` items: the `abi` string and a boolean `variadic`.
+//! - Functions and methods: a boolean `constant`.
+//! - Bindings: a boolean `mutable`.
+//! - `Defer` statements: the deferred `stmt`.
+//! - `Int` types: the `name` they are written with (`"int"`, `"u8"`, ...).
+//! - Array types: a size that is a number, or the name of a constant.
+//! - `StringLiteral` expressions: the decoded `value`.
+//! - `Interpolation` expressions: `parts` that are strings for literal text
+//!   and expressions for holes.
+//!
+//! `program_from_json` reads this form back into a `Program`.
+//!
+//! **S-expressions.** Every node is `(kind #id start..end field...)`, where
+//! names are quoted strings, absent children are `nil` and operators are
+//! their source symbols, e.g.
+//! `(binary #2 4..9 + (identifier #0 4..5 "a") (int #1 8..9 1))`. Tokens are
+//! `(Kind start..end)` or `(Kind start..end value)`. Node kinds are laid out
+//! as follows:
+//!
+//! - Types are bare words: `int`, `u8` and the other integer types are their
+//!   names, as are `bool`, `string`, `ptr` and `void`.
+//! - `array` is followed by the element type and the size, quoted if it is a
+//!   constant.
+//! - `tuple` is followed by the element types.
+//! - `fn` is followed by a `(params ...)` list and the return type or `nil`.
+//! - A named type is its quoted name followed by any type arguments.
+//! - `dyn` is followed by the quoted interface name.
+//! - A generic function, struct or enum has its type parameters after its
+//!   name as a `(type-params ...)` list, left out when there are none. Each
+//!   `(type-param ...)` lists its bounds after its name.
+//! - An interface holds `(method-sig ...)` nodes.
+//! - An impl names its interface and type before its `(method ...)` nodes,
+//!   which are laid out like functions, with any `(attribute ...)` nodes
+//!   after the method's span.
+//! - An import is `(import #id start..end "path")` with the path as written.
+//! - An extern function is `(extern ...)` with its quoted calling convention
+//!   and name, a `(params ...)` list ending in the bare word `...` if it is
+//!   variadic, and the return type or `nil`.
+//! - A `pub` item has the bare word `pub` after its span. Its
+//!   `(attribute ...)` nodes come next, each the quoted name followed by the
+//!   quoted arguments.
+//! - A `const function` has the bare word `const` before its name.
+//! - A `mut` binding has `mut` before its name.
+//!
+//! Field names and kind strings are part of the format; change them only
+//! together with the tools that read them.
+
+use crate::json::{self, JsonError, Value};
+use crate::lexer::{IntType, Lexer, Span, Token};
+use crate::parser::{
+    ArmBody, ArraySize, Attribute, BinaryOp, Block, ClosureParam, Const, Enum, Expr, ExprKind, Extern, Field, FieldInit, Function, Impl, Import, Interface, Item,
+    ItemKind, Let, MatchArm, Method, MethodSig, NodeId, Param, Pattern, PatternKind, Program, Stmt, StmtKind, StringPart, Struct, Type,
+    TypeKind, TypeParam, UnaryOp, Variant,
+};
+
+pub fn token_name(token: &Token) -> &'static str {
+    match token {
+        Token::Let => "Let",
+        Token::Mut => "Mut",
+        Token::Const => "Const",
+        Token::Function => "Function",
+        Token::Fn => "Fn",
+        Token::Struct => "Struct",
+        Token::Enum => "Enum",
+        Token::Interface => "Interface",
+        Token::Impl => "Impl",
+        Token::Dyn => "Dyn",
+        Token::Import => "Import",
+        Token::Extern => "Extern",
+        Token::Pub => "Pub",
+        Token::Match => "Match",
+        Token::As => "As",
+        Token::Identifier(_) => "Identifier",
+        Token::IntLiteral(_) => "IntLiteral",
+        Token::TypeInt(_) => "TypeInt",
+        Token::StringLiteral(_) => "StringLiteral",
+        Token::TypeBool => "TypeBool",
+        Token::TypeString => "TypeString",
+        Token::TypePtr => "TypePtr",
+        Token::TypeVoid => "TypeVoid",
+        Token::Plus => "Plus",
+        Token::Minus => "Minus",
+        Token::Star => "Star",
+        Token::Slash => "Slash",
+        Token::Equals => "Equals",
+        Token::EqualEqual => "EqualEqual",
+        Token::Bang => "Bang",
+        Token::BangEqual => "BangEqual",
+        Token::Less => "Less",
+        Token::LessEqual => "LessEqual",
+        Token::Greater => "Greater",
+        Token::GreaterEqual => "GreaterEqual",
+        Token::LessLess => "LessLess",
+        Token::GreaterGreater => "GreaterGreater",
+        Token::Arrow => "Arrow",
+        Token::FatArrow => "FatArrow",
+        Token::Colon => "Colon",
+        Token::ColonColon => "ColonColon",
+        Token::Semicolon => "Semicolon",
+        Token::LParen => "LParen",
+        Token::RParen => "RParen",
+        Token::LBrace => "LBrace",
+        Token::RBrace => "RBrace",
+        Token::LBracket => "LBracket",
+        Token::RBracket => "RBracket",
+        Token::Return => "Return",
+        Token::If => "If",
+        Token::Else => "Else",
+        Token::For => "For",
+        Token::While => "While",
+        Token::Defer => "Defer",
+        Token::True => "True",
+        Token::False => "False",
+        Token::Comma => "Comma",
+        Token::Dot => "Dot",
+        Token::Ellipsis => "Ellipsis",
+        Token::Pipe => "Pipe",
+        Token::Question => "Question",
+        Token::At => "At",
+        Token::Eof => "Eof",
+        Token::Error(_) => "Error",
+    }
+}
+
+fn token_value(token: &Token) -> Option<Value> {
+    match token {
+        Token::Identifier(name) => Some(Value::String(name.clone())),
+        Token::IntLiteral(value) => Some(Value::Number(i128::from(*value))),
+        Token::StringLiteral(raw) => Some(Value::String(raw.clone())),
+        Token::TypeInt(ty) => Some(Value::String(ty.name().to_string())),
+        Token::Error(message) => Some(Value::String(message.clone())),
+        _ => None,
+    }
+}
+
+pub fn tokens_to_json(tokens: &[(Token, Span)]) -> Value {
+    Value::Array(
+        tokens
+            .iter()
+            .map(|(token, span)| {
+                let mut fields = vec![("kind", Value::String(token_name(token).to_string())), ("span", span_to_json(*span))];
+                if let Some(value) = token_value(token) {
+                    fields.push(("value", value));
+                }
+                Value::object(fields)
+            })
+            .collect(),
+    )
+}
+
+pub fn tokens_to_sexp(tokens: &[(Token, Span)]) -> String {
+    tokens
+        .iter()
+        .map(|(token, span)| match token_value(token) {
+            Some(value) => format!("({} {}..{} {})", token_name(token), span.start, span.end, value),
+            None => format!("({} {}..{})", token_name(token), span.start, span.end),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn span_to_json(span: Span) -> Value {
+    Value::Array(vec![Value::Number(span.start as i128), Value::Number(span.end as i128)])
+}
+
+fn node_fields(id: NodeId, span: Span, kind: Option<&str>) -> Vec<(&'static str, Value)> {
+    let mut fields = vec![("id", Value::Number(i128::from(id.0))), ("span", span_to_json(span))];
+    if let Some(kind) = kind {
+        fields.push(("kind", Value::String(kind.to_string())));
+    }
+    fields
+}
+
+fn optional<T>(value: Option<&T>, convert: impl Fn(&T) -> Value) -> Value {
+    value.map(convert).unwrap_or(Value::Null)
+}
+
+fn unary_op_name(op: UnaryOp) -> &'static str {
+    match op {
+        UnaryOp::Neg => "Neg",
+        UnaryOp::Not => "Not",
+    }
+}
+
+fn binary_op_name(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "Add",
+        BinaryOp::Sub => "Sub",
+        BinaryOp::Mul => "Mul",
+        BinaryOp::Div => "Div",
+        BinaryOp::Shl => "Shl",
+        BinaryOp::Shr => "Shr",
+        BinaryOp::Eq => "Eq",
+        BinaryOp::Ne => "Ne",
+        BinaryOp::Lt => "Lt",
+        BinaryOp::Le => "Le",
+        BinaryOp::Gt => "Gt",
+        BinaryOp::Ge => "Ge",
+    }
+}
+
+fn type_kind_name(kind: &TypeKind) -> &'static str {
+    match kind {
+        TypeKind::Int(_) => "Int",
+        TypeKind::Bool => "Bool",
+        TypeKind::String => "String",
+        TypeKind::Ptr => "Ptr",
+        TypeKind::Void => "Void",
+        TypeKind::Array { .. } => "Array",
+        TypeKind::Named { .. } => "Named",
+        TypeKind::Tuple(_) => "Tuple",
+        TypeKind::Function { .. } => "Function",
+        TypeKind::Dyn(_) => "Dyn",
+    }
+}
+
+pub fn program_to_json(program: &Program) -> Value {
+    let mut fields = node_fields(program.id, program.span, None);
+    fields.push(("items", Value::Array(program.items.iter().map(item_to_json).collect())));
+    Value::object(fields)
+}
+
+fn item_to_json(item: &Item) -> Value {
+    let mut fields = match &item.kind {
+        ItemKind::Function(function) => {
+            let mut fields = node_fields(item.id, item.span, Some("Function"));
+            function_fields(function, &mut fields);
+            fields
+        }
+        ItemKind::Global(binding) => {
+            let mut fields = node_fields(item.id, item.span, Some("Global"));
+            let_fields(binding, &mut fields);
+            fields
+        }
+        ItemKind::Const(constant) => {
+            let mut fields = node_fields(item.id, item.span, Some("Const"));
+            fields.push(("name", Value::String(constant.name.clone())));
+            fields.push(("ty", type_to_json(&constant.ty)));
+            fields.push(("value", expr_to_json(&constant.value)));
+            fields
+        }
+        ItemKind::Struct(definition) => {
+            let mut fields = node_fields(item.id, item.span, Some("Struct"));
+            fields.push(("name", Value::String(definition.name.clone())));
+            fields.push(("type_params", Value::Array(definition.type_params.iter().map(type_param_to_json).collect())));
+            fields.push(("fields", Value::Array(definition.fields.iter().map(field_to_json).collect())));
+            fields
+        }
+        ItemKind::Enum(definition) => {
+            let mut fields = node_fields(item.id, item.span, Some("Enum"));
+            fields.push(("name", Value::String(definition.name.clone())));
+            fields.push(("type_params", Value::Array(definition.type_params.iter().map(type_param_to_json).collect())));
+            fields.push(("variants", Value::Array(definition.variants.iter().map(variant_to_json).collect())));
+            fields
+        }
+        ItemKind::Interface(definition) => {
+            let mut fields = node_fields(item.id, item.span, Some("Interface"));
+            fields.push(("name", Value::String(definition.name.clone())));
+            fields.push(("methods", Value::Array(definition.methods.iter().map(method_sig_to_json).collect())));
+            fields
+        }
+        ItemKind::Impl(definition) => {
+            let mut fields = node_fields(item.id, item.span, Some("Impl"));
+            fields.push(("interface", Value::String(definition.interface.clone())));
+            fields.push(("ty", type_to_json(&definition.ty)));
+            fields.push(("methods", Value::Array(definition.methods.iter().map(method_to_json).collect())));
+            fields
+        }
+        ItemKind::Import(import) => {
+            let mut fields = node_fields(item.id, item.span, Some("Import"));
+            fields.push(("path", Value::String(import.path.clone())));
+            fields
+        }
+        ItemKind::Extern(function) => {
+            let mut fields = node_fields(item.id, item.span, Some("Extern"));
+            fields.push(("abi", Value::String(function.abi.clone())));
+            fields.push(("name", Value::String(function.name.clone())));
+            fields.push(("params", Value::Array(function.params.iter().map(param_to_json).collect())));
+            fields.push(("variadic", Value::Bool(function.variadic)));
+            fields.push(("return_type", optional(function.return_type.as_ref(), type_to_json)));
+            fields
+        }
+    };
+    fields.push(("public", Value::Bool(item.public)));
+    fields.push(("attributes", Value::Array(item.attributes.iter().map(attribute_to_json).collect())));
+    Value::object(fields)
+}
+
+fn attribute_to_json(attribute: &Attribute) -> Value {
+    let mut fields = node_fields(attribute.id, attribute.span, None);
+    fields.push(("name", Value::String(attribute.name.clone())));
+    fields.push(("args", Value::Array(attribute.args.iter().cloned().map(Value::String).collect())));
+    Value::object(fields)
+}
+
+fn function_fields(function: &Function, fields: &mut Vec<(&'static str, Value)>) {
+    fields.push(("constant", Value::Bool(function.constant)));
+    fields.push(("name", Value::String(function.name.clone())));
+    fields.push(("type_params", Value::Array(function.type_params.iter().map(type_param_to_json).collect())));
+    fields.push(("params", Value::Array(function.params.iter().map(param_to_json).collect())));
+    fields.push(("return_type", optional(function.return_type.as_ref(), type_to_json)));
+    fields.push(("body", block_to_json(&function.body)));
+}
+
+fn type_param_to_json(param: &TypeParam) -> Value {
+    let mut fields = node_fields(param.id, param.span, None);
+    fields.push(("name", Value::String(param.name.clone())));
+    fields.push(("bounds", Value::Array(param.bounds.iter().cloned().map(Value::String).collect())));
+    Value::object(fields)
+}
+
+fn method_sig_to_json(method: &MethodSig) -> Value {
+    let mut fields = node_fields(method.id, method.span, None);
+    fields.push(("name", Value::String(method.name.clone())));
+    fields.push(("params", Value::Array(method.params.iter().map(param_to_json).collect())));
+    fields.push(("return_type", optional(method.return_type.as_ref(), type_to_json)));
+    Value::object(fields)
+}
+
+fn method_to_json(method: &Method) -> Value {
+    let mut fields = node_fields(method.id, method.span, None);
+    function_fields(&method.function, &mut fields);
+    fields.push(("attributes", Value::Array(method.attributes.iter().map(attribute_to_json).collect())));
+    Value::object(fields)
+}
+
+fn variant_to_json(variant: &Variant) -> Value {
+    let mut fields = node_fields(variant.id, variant.span, None);
+    fields.push(("name", Value::String(variant.name.clone())));
+    fields.push(("fields", Value::Array(variant.fields.iter().map(type_to_json).collect())));
+    Value::object(fields)
+}
+
+fn path_to_json(path: &[String]) -> Value {
+    Value::Array(path.iter().cloned().map(Value::String).collect())
+}
+
+fn match_arm_to_json(arm: &MatchArm) -> Value {
+    let mut fields = node_fields(arm.id, arm.span, None);
+    fields.push(("pattern", pattern_to_json(&arm.pattern)));
+    fields.push(("guard", optional(arm.guard.as_ref(), expr_to_json)));
+    fields.push(("body", arm_body_to_json(&arm.body)));
+    Value::object(fields)
+}
+
+fn arm_body_to_json(body: &ArmBody) -> Value {
+    match body {
+        ArmBody::Expr(expr) => Value::object(vec![("kind", Value::String("Expr".to_string())), ("expr", expr_to_json(expr))]),
+        ArmBody::Block(block) => {
+            Value::object(vec![("kind", Value::String("Block".to_string())), ("block", block_to_json(block))])
+        }
+    }
+}
+
+fn pattern_to_json(pattern: &Pattern) -> Value {
+    let fields = match &pattern.kind {
+        PatternKind::Wildcard => node_fields(pattern.id, pattern.span, Some("Wildcard")),
+        PatternKind::Binding { name, mutable } => {
+            let mut fields = node_fields(pattern.id, pattern.span, Some("Binding"));
+            fields.push(("name", Value::String(name.clone())));
+            fields.push(("mutable", Value::Bool(*mutable)));
+            fields
+        }
+        PatternKind::IntLiteral(value) => {
+            let mut fields = node_fields(pattern.id, pattern.span, Some("IntLiteral"));
+            fields.push(("value", Value::Number(*value)));
+            fields
+        }
+        PatternKind::BoolLiteral(value) => {
+            let mut fields = node_fields(pattern.id, pattern.span, Some("BoolLiteral"));
+            fields.push(("value", Value::Bool(*value)));
+            fields
+        }
+        PatternKind::Variant { path, fields: subpatterns } => {
+            let mut fields = node_fields(pattern.id, pattern.span, Some("Variant"));
+            fields.push(("path", path_to_json(path)));
+            fields.push(("fields", Value::Array(subpatterns.iter().map(pattern_to_json).collect())));
+            fields
+        }
+        PatternKind::Tuple(elements) => {
+            let mut fields = node_fields(pattern.id, pattern.span, Some("Tuple"));
+            fields.push(("elements", Value::Array(elements.iter().map(pattern_to_json).collect())));
+            fields
+        }
+    };
+    Value::object(fields)
+}
+
+fn field_to_json(field: &Field) -> Value {
+    let mut fields = node_fields(field.id, field.span, None);
+    fields.push(("name", Value::String(field.name.clone())));
+    fields.push(("ty", type_to_json(&field.ty)));
+    Value::object(fields)
+}
+
+fn field_init_to_json(init: &FieldInit) -> Value {
+    let mut fields = node_fields(init.id, init.span, None);
+    fields.push(("name", Value::String(init.name.clone())));
+    fields.push(("value", expr_to_json(&init.value)));
+    Value::object(fields)
+}
+
+fn let_fields(binding: &Let, fields: &mut Vec<(&'static str, Value)>) {
+    fields.push(("pattern", pattern_to_json(&binding.pattern)));
+    fields.push(("ty", optional(binding.ty.as_ref(), type_to_json)));
+    fields.push(("value", expr_to_json(&binding.value)));
+}
+
+fn param_to_json(param: &Param) -> Value {
+    let mut fields = node_fields(param.id, param.span, None);
+    fields.push(("pattern", pattern_to_json(&param.pattern)));
+    fields.push(("ty", type_to_json(&param.ty)));
+    Value::object(fields)
+}
+
+fn closure_param_to_json(param: &ClosureParam) -> Value {
+    let mut fields = node_fields(param.id, param.span, None);
+    fields.push(("pattern", pattern_to_json(&param.pattern)));
+    fields.push(("ty", optional(param.ty.as_ref(), type_to_json)));
+    Value::object(fields)
+}
+
+fn type_to_json(ty: &Type) -> Value {
+    let mut fields = node_fields(ty.id, ty.span, Some(type_kind_name(&ty.kind)));
+    match &ty.kind {
+        TypeKind::Array { element, size } => {
+            fields.push(("element", type_to_json(element)));
+            fields.push((
+                "size",
+                match size {
+                    ArraySize::Literal(size) => Value::Number(i128::from(*size)),
+                    ArraySize::Const(name) => Value::String(name.clone()),
+                },
+            ));
+        }
+        TypeKind::Named { name, args } => {
+            fields.push(("name", Value::String(name.clone())));
+            fields.push(("args", Value::Array(args.iter().map(type_to_json).collect())));
+        }
+        TypeKind::Tuple(elements) => fields.push(("elements", Value::Array(elements.iter().map(type_to_json).collect()))),
+        TypeKind::Function { params, return_type } => {
+            fields.push(("params", Value::Array(params.iter().map(type_to_json).collect())));
+            fields.push(("return_type", optional(return_type.as_deref(), type_to_json)));
+        }
+        TypeKind::Dyn(interface) => fields.push(("interface", Value::String(interface.clone()))),
+        TypeKind::Int(ty) => fields.push(("name", Value::String(ty.name().to_string()))),
+        TypeKind::Bool | TypeKind::String | TypeKind::Ptr | TypeKind::Void => {}
+    }
+    Value::object(fields)
+}
+
+fn block_to_json(block: &Block) -> Value {
+    let mut fields = node_fields(block.id, block.span, None);
+    fields.push(("stmts", Value::Array(block.stmts.iter().map(stmt_to_json).collect())));
+    Value::object(fields)
+}
+
+fn stmt_to_json(stmt: &Stmt) -> Value {
+    let fields = match &stmt.kind {
+        StmtKind::Let(binding) => {
+            let mut fields = node_fields(stmt.id, stmt.span, Some("Let"));
+            let_fields(binding, &mut fields);
+            fields
+        }
+        StmtKind::Expr(expr) => {
+            let mut fields = node_fields(stmt.id, stmt.span, Some("Expr"));
+            fields.push(("expr", expr_to_json(expr)));
+            fields
+        }
+        StmtKind::Return(value) => {
+            let mut fields = node_fields(stmt.id, stmt.span, Some("Return"));
+            fields.push(("value", optional(value.as_ref(), expr_to_json)));
+            fields
+        }
+        StmtKind::If { condition, then_branch, else_branch } => {
+            let mut fields = node_fields(stmt.id, stmt.span, Some("If"));
+            fields.push(("condition", expr_to_json(condition)));
+            fields.push(("then_branch", block_to_json(then_branch)));
+            fields.push(("else_branch", optional(else_branch.as_ref(), block_to_json)));
+            fields
+        }
+        StmtKind::For { init, condition, increment, body } => {
+            let mut fields = node_fields(stmt.id, stmt.span, Some("For"));
+            fields.push(("init", optional(init.as_deref(), stmt_to_json)));
+            fields.push(("condition", optional(condition.as_ref(), expr_to_json)));
+            fields.push(("increment", optional(increment.as_ref(), expr_to_json)));
+            fields.push(("body", block_to_json(body)));
+            fields
+        }
+        StmtKind::While { condition, body } => {
+            let mut fields = node_fields(stmt.id, stmt.span, Some("While"));
+            fields.push(("condition", expr_to_json(condition)));
+            fields.push(("body", block_to_json(body)));
+            fields
+        }
+        StmtKind::Defer(deferred) => {
+            let mut fields = node_fields(stmt.id, stmt.span, Some("Defer"));
+            fields.push(("stmt", stmt_to_json(deferred)));
+            fields
+        }
+    };
+    Value::object(fields)
+}
+
+fn expr_to_json(expr: &Expr) -> Value {
+    let fields = match &expr.kind {
+        ExprKind::Unary { op, operand } => {
+            let mut fields = node_fields(expr.id, expr.span, Some("Unary"));
+            fields.push(("op", Value::String(unary_op_name(*op).to_string())));
+            fields.push(("operand", expr_to_json(operand)));
+            fields
+        }
+        ExprKind::Binary { op, left, right } => {
+            let mut fields = node_fields(expr.id, expr.span, Some("Binary"));
+            fields.push(("op", Value::String(binary_op_name(*op).to_string())));
+            fields.push(("left", expr_to_json(left)));
+            fields.push(("right", expr_to_json(right)));
+            fields
+        }
+        ExprKind::Assign { target, value } => {
+            let mut fields = node_fields(expr.id, expr.span, Some("Assign"));
+            fields.push(("target", expr_to_json(target)));
+            fields.push(("value", expr_to_json(value)));
+            fields
+        }
+        ExprKind::Call { callee, args } => {
+            let mut fields = node_fields(expr.id, expr.span, Some("Call"));
+            fields.push(("callee", expr_to_json(callee)));
+            fields.push(("args", Value::Array(args.iter().map(expr_to_json).collect())));
+            fields
+        }
+        ExprKind::Index { base, index } => {
+            let mut fields = node_fields(expr.id, expr.span, Some("Index"));
+            fields.push(("base", expr_to_json(base)));
+            fields.push(("index", expr_to_json(index)));
+            fields
+        }
+        ExprKind::Field { base, name } => {
+            let mut fields = node_fields(expr.id, expr.span, Some("Field"));
+            fields.push(("base", expr_to_json(base)));
+            fields.push(("name", Value::String(name.clone())));
+            fields
+        }
+        ExprKind::TupleIndex { base, index } => {
+            let mut fields = node_fields(expr.id, expr.span, Some("TupleIndex"));
+            fields.push(("base", expr_to_json(base)));
+            fields.push(("index", Value::Number(i128::from(*index))));
+            fields
+        }
+        ExprKind::Try(operand) => {
+            let mut fields = node_fields(expr.id, expr.span, Some("Try"));
+            fields.push(("operand", expr_to_json(operand)));
+            fields
+        }
+        ExprKind::Cast { operand, ty } => {
+            let mut fields = node_fields(expr.id, expr.span, Some("Cast"));
+            fields.push(("operand", expr_to_json(operand)));
+            fields.push(("ty", type_to_json(ty)));
+            fields
+        }
+        ExprKind::ArrayLiteral(elements) => {
+            let mut fields = node_fields(expr.id, expr.span, Some("ArrayLiteral"));
+            fields.push(("elements", Value::Array(elements.iter().map(expr_to_json).collect())));
+            fields
+        }
+        ExprKind::Tuple(elements) => {
+            let mut fields = node_fields(expr.id, expr.span, Some("Tuple"));
+            fields.push(("elements", Value::Array(elements.iter().map(expr_to_json).collect())));
+            fields
+        }
+        ExprKind::StructLiteral { name, fields: inits } => {
+            let mut fields = node_fields(expr.id, expr.span, Some("StructLiteral"));
+            fields.push(("name", Value::String(name.clone())));
+            fields.push(("fields", Value::Array(inits.iter().map(field_init_to_json).collect())));
+            fields
+        }
+        ExprKind::Match { scrutinee, arms } => {
+            let mut fields = node_fields(expr.id, expr.span, Some("Match"));
+            fields.push(("scrutinee", expr_to_json(scrutinee)));
+            fields.push(("arms", Value::Array(arms.iter().map(match_arm_to_json).collect())));
+            fields
+        }
+        ExprKind::Closure { params, return_type, body } => {
+            let mut fields = node_fields(expr.id, expr.span, Some("Closure"));
+            fields.push(("params", Value::Array(params.iter().map(closure_param_to_json).collect())));
+            fields.push(("return_type", optional(return_type.as_ref(), type_to_json)));
+            fields.push(("body", arm_body_to_json(body)));
+            fields
+        }
+        ExprKind::Path(path) => {
+            let mut fields = node_fields(expr.id, expr.span, Some("Path"));
+            fields.push(("path", path_to_json(path)));
+            fields
+        }
+        ExprKind::Identifier(name) => {
+            let mut fields = node_fields(expr.id, expr.span, Some("Identifier"));
+            fields.push(("name", Value::String(name.clone())));
+            fields
+        }
+        ExprKind::IntLiteral(value) => {
+            let mut fields = node_fields(expr.id, expr.span, Some("IntLiteral"));
+            fields.push(("value", Value::Number(i128::from(*value))));
+            fields
+        }
+        ExprKind::BoolLiteral(value) => {
+            let mut fields = node_fields(expr.id, expr.span, Some("BoolLiteral"));
+            fields.push(("value", Value::Bool(*value)));
+            fields
+        }
+        ExprKind::StringLiteral(value) => {
+            let mut fields = node_fields(expr.id, expr.span, Some("StringLiteral"));
+            fields.push(("value", Value::String(value.clone())));
+            fields
+        }
+        ExprKind::Interpolation(parts) => {
+            let mut fields = node_fields(expr.id, expr.span, Some("Interpolation"));
+            let parts = parts
+                .iter()
+                .map(|part| match part {
+                    StringPart::Text(text) => Value::String(text.clone()),
+                    StringPart::Expr(expr) => expr_to_json(expr),
+                })
+                .collect();
+            fields.push(("parts", Value::Array(parts)));
+            fields
+        }
+    };
+    Value::object(fields)
+}
+
+pub fn program_to_sexp(program: &Program) -> String {
+    let mut out = node_head("program", program.id, program.span);
+    for item in &program.items {
+        out.push(' ');
+        out.push_str(&item_to_sexp(item));
+    }
+    out.push(')');
+    out
+}
+
+fn node_head(kind: &str, id: NodeId, span: Span) -> String {
+    format!("({} #{} {}..{}", kind, id.0, span.start, span.end)
+}
+
+fn quoted(name: &str) -> String {
+    Value::String(name.to_string()).to_string()
+}
+
+fn sexp_list(head: String, fields: Vec<String>) -> String {
+    let mut out = head;
+    for field in fields {
+        out.push(' ');
+        out.push_str(&field);
+    }
+    out.push(')');
+    out
+}
+
+fn optional_sexp<T>(value: Option<&T>, convert: impl Fn(&T) -> String) -> String {
+    value.map(convert).unwrap_or_else(|| "nil".to_string())
+}
+
+/// Appends an `(attribute ...)` node for each of `attributes` to a node head.
+fn push_attributes_sexp(head: &mut String, attributes: &[Attribute]) {
+    for attribute in attributes {
+        let mut fields = vec![quoted(&attribute.name)];
+        fields.extend(attribute.args.iter().map(|arg| quoted(arg)));
+        head.push(' ');
+        head.push_str(&sexp_list(node_head("attribute", attribute.id, attribute.span), fields));
+    }
+}
+
+fn item_to_sexp(item: &Item) -> String {
+    let head = |kind: &str| {
+        let mut head = node_head(kind, item.id, item.span);
+        if item.public {
+            head.push_str(" pub");
+        }
+        push_attributes_sexp(&mut head, &item.attributes);
+        head
+    };
+    match &item.kind {
+        ItemKind::Function(function) => sexp_list(head("function"), function_sexp_fields(function)),
+        ItemKind::Global(binding) => sexp_list(head("global"), let_sexp_fields(binding)),
+        ItemKind::Const(constant) => sexp_list(
+            head("const"),
+            vec![quoted(&constant.name), type_to_sexp(&constant.ty), expr_to_sexp(&constant.value)],
+        ),
+        ItemKind::Struct(definition) => {
+            let mut fields = vec![quoted(&definition.name)];
+            fields.extend(type_params_to_sexp(&definition.type_params));
+            fields.extend(definition.fields.iter().map(field_to_sexp));
+            sexp_list(head("struct"), fields)
+        }
+        ItemKind::Enum(definition) => {
+            let mut fields = vec![quoted(&definition.name)];
+            fields.extend(type_params_to_sexp(&definition.type_params));
+            fields.extend(definition.variants.iter().map(variant_to_sexp));
+            sexp_list(head("enum"), fields)
+        }
+        ItemKind::Interface(definition) => {
+            let mut fields = vec![quoted(&definition.name)];
+            fields.extend(definition.methods.iter().map(method_sig_to_sexp));
+            sexp_list(head("interface"), fields)
+        }
+        ItemKind::Impl(definition) => {
+            let mut fields = vec![quoted(&definition.interface), type_to_sexp(&definition.ty)];
+            fields.extend(
+                definition
+                    .methods
+                    .iter()
+                    .map(|method| {
+                        let mut head = node_head("method", method.id, method.span);
+                        push_attributes_sexp(&mut head, &method.attributes);
+                        sexp_list(head, function_sexp_fields(&method.function))
+                    }),
+            );
+            sexp_list(head("impl"), fields)
+        }
+        ItemKind::Import(import) => sexp_list(head("import"), vec![quoted(&import.path)]),
+        ItemKind::Extern(function) => {
+            let mut params: Vec<String> = function.params.iter().map(param_to_sexp).collect();
+            if function.variadic {
+                params.push("...".to_string());
+            }
+            sexp_list(
+                head("extern"),
+                vec![
+                    quoted(&function.abi),
+                    quoted(&function.name),
+                    sexp_list("(params".to_string(), params),
+                    optional_sexp(function.return_type.as_ref(), type_to_sexp),
+                ],
+            )
+        }
+    }
+}
+
+fn function_sexp_fields(function: &Function) -> Vec<String> {
+    let mut fields = Vec::new();
+    if function.constant {
+        fields.push("const".to_string());
+    }
+    fields.push(quoted(&function.name));
+    fields.extend(type_params_to_sexp(&function.type_params));
+    fields.push(sexp_list("(params".to_string(), function.params.iter().map(param_to_sexp).collect()));
+    fields.push(optional_sexp(function.return_type.as_ref(), type_to_sexp));
+    fields.push(block_to_sexp(&function.body));
+    fields
+}
+
+fn method_sig_to_sexp(method: &MethodSig) -> String {
+    sexp_list(
+        node_head("method-sig", method.id, method.span),
+        vec![
+            quoted(&method.name),
+            sexp_list("(params".to_string(), method.params.iter().map(param_to_sexp).collect()),
+            optional_sexp(method.return_type.as_ref(), type_to_sexp),
+        ],
+    )
+}
+
+/// The `(type-params ...)` list of a generic item; nothing for an ordinary
+/// one.
+fn type_params_to_sexp(params: &[TypeParam]) -> Option<String> {
+    if params.is_empty() {
+        return None;
+    }
+    let params = params
+        .iter()
+        .map(|param| {
+            let mut fields = vec![quoted(&param.name)];
+            fields.extend(param.bounds.iter().map(|bound| quoted(bound)));
+            sexp_list(node_head("type-param", param.id, param.span), fields)
+        })
+        .collect();
+    Some(sexp_list("(type-params".to_string(), params))
+}
+
+fn variant_to_sexp(variant: &Variant) -> String {
+    let mut fields = vec![quoted(&variant.name)];
+    fields.extend(variant.fields.iter().map(type_to_sexp));
+    sexp_list(node_head("variant", variant.id, variant.span), fields)
+}
+
+fn match_arm_to_sexp(arm: &MatchArm) -> String {
+    sexp_list(
+        node_head("arm", arm.id, arm.span),
+        vec![pattern_to_sexp(&arm.pattern), optional_sexp(arm.guard.as_ref(), expr_to_sexp), arm_body_to_sexp(&arm.body)],
+    )
+}
+
+fn arm_body_to_sexp(body: &ArmBody) -> String {
+    match body {
+        ArmBody::Expr(expr) => expr_to_sexp(expr),
+        ArmBody::Block(block) => block_to_sexp(block),
+    }
+}
+
+fn pattern_to_sexp(pattern: &Pattern) -> String {
+    let fields = match &pattern.kind {
+        PatternKind::Wildcard => vec!["_".to_string()],
+        PatternKind::Binding { name, mutable: false } => vec![quoted(name)],
+        PatternKind::Binding { name, mutable: true } => vec!["mut".to_string(), quoted(name)],
+        PatternKind::IntLiteral(value) => vec![value.to_string()],
+        PatternKind::BoolLiteral(value) => vec![value.to_string()],
+        PatternKind::Variant { path, fields } => {
+            let mut items = vec!["variant".to_string(), quoted(&path.join("::"))];
+            items.extend(fields.iter().map(pattern_to_sexp));
+            items
+        }
+        PatternKind::Tuple(elements) => {
+            let mut items = vec!["tuple".to_string()];
+            items.extend(elements.iter().map(pattern_to_sexp));
+            items
+        }
+    };
+    sexp_list(node_head("pattern", pattern.id, pattern.span), fields)
+}
+
+fn field_to_sexp(field: &Field) -> String {
+    sexp_list(node_head("field", field.id, field.span), vec![quoted(&field.name), type_to_sexp(&field.ty)])
+}
+
+fn field_init_to_sexp(init: &FieldInit) -> String {
+    sexp_list(node_head("field-init", init.id, init.span), vec![quoted(&init.name), expr_to_sexp(&init.value)])
+}
+
+fn let_sexp_fields(binding: &Let) -> Vec<String> {
+    vec![
+        pattern_to_sexp(&binding.pattern),
+        optional_sexp(binding.ty.as_ref(), type_to_sexp),
+        expr_to_sexp(&binding.value),
+    ]
+}
+
+fn param_to_sexp(param: &Param) -> String {
+    sexp_list(node_head("param", param.id, param.span), vec![pattern_to_sexp(&param.pattern), type_to_sexp(&param.ty)])
+}
+
+fn closure_param_to_sexp(param: &ClosureParam) -> String {
+    sexp_list(
+        node_head("param", param.id, param.span),
+        vec![pattern_to_sexp(&param.pattern), optional_sexp(param.ty.as_ref(), type_to_sexp)],
+    )
+}
+
+fn type_to_sexp(ty: &Type) -> String {
+    let fields = match &ty.kind {
+        TypeKind::Int(ty) => vec![ty.name().to_string()],
+        TypeKind::Bool => vec!["bool".to_string()],
+        TypeKind::String => vec!["string".to_string()],
+        TypeKind::Ptr => vec!["ptr".to_string()],
+        TypeKind::Void => vec!["void".to_string()],
+        TypeKind::Array { element, size } => {
+            let size = match size {
+                ArraySize::Literal(size) => size.to_string(),
+                ArraySize::Const(name) => quoted(name),
+            };
+            vec!["array".to_string(), type_to_sexp(element), size]
+        }
+        TypeKind::Named { name, args } => {
+            let mut items = vec![quoted(name)];
+            items.extend(args.iter().map(type_to_sexp));
+            items
+        }
+        TypeKind::Tuple(elements) => {
+            let mut items = vec!["tuple".to_string()];
+            items.extend(elements.iter().map(type_to_sexp));
+            items
+        }
+        TypeKind::Function { params, return_type } => vec![
+            "fn".to_string(),
+            sexp_list("(params".to_string(), params.iter().map(type_to_sexp).collect()),
+            optional_sexp(return_type.as_deref(), type_to_sexp),
+        ],
+        TypeKind::Dyn(interface) => vec!["dyn".to_string(), quoted(interface)],
+    };
+    sexp_list(node_head("type", ty.id, ty.span), fields)
+}
+
+fn block_to_sexp(block: &Block) -> String {
+    sexp_list(node_head("block", block.id, block.span), block.stmts.iter().map(stmt_to_sexp).collect())
+}
+
+fn stmt_to_sexp(stmt: &Stmt) -> String {
+    match &stmt.kind {
+        StmtKind::Let(binding) => sexp_list(node_head("let", stmt.id, stmt.span), let_sexp_fields(binding)),
+        StmtKind::Expr(expr) => sexp_list(node_head("expr", stmt.id, stmt.span), vec![expr_to_sexp(expr)]),
+        StmtKind::Return(value) => sexp_list(
+            node_head("return", stmt.id, stmt.span),
+            vec![optional_sexp(value.as_ref(), expr_to_sexp)],
+        ),
+        StmtKind::If { condition, then_branch, else_branch } => sexp_list(
+            node_head("if", stmt.id, stmt.span),
+            vec![
+                expr_to_sexp(condition),
+                block_to_sexp(then_branch),
+                optional_sexp(else_branch.as_ref(), block_to_sexp),
+            ],
+        ),
+        StmtKind::For { init, condition, increment, body } => sexp_list(
+            node_head("for", stmt.id, stmt.span),
+            vec![
+                optional_sexp(init.as_deref(), stmt_to_sexp),
+                optional_sexp(condition.as_ref(), expr_to_sexp),
+                optional_sexp(increment.as_ref(), expr_to_sexp),
+                block_to_sexp(body),
+            ],
+        ),
+        StmtKind::While { condition, body } => sexp_list(
+            node_head("while", stmt.id, stmt.span),
+            vec![expr_to_sexp(condition), block_to_sexp(body)],
+        ),
+        StmtKind::Defer(deferred) => sexp_list(node_head("defer", stmt.id, stmt.span), vec![stmt_to_sexp(deferred)]),
+    }
+}
+
+fn expr_to_sexp(expr: &Expr) -> String {
+    match &expr.kind {
+        ExprKind::Unary { op, operand } => sexp_list(
+            node_head("unary", expr.id, expr.span),
+            vec![op.symbol().to_string(), expr_to_sexp(operand)],
+        ),
+        ExprKind::Binary { op, left, right } => sexp_list(
+            node_head("binary", expr.id, expr.span),
+            vec![op.symbol().to_string(), expr_to_sexp(left), expr_to_sexp(right)],
+        ),
+        ExprKind::Assign { target, value } => sexp_list(
+            node_head("assign", expr.id, expr.span),
+            vec![expr_to_sexp(target), expr_to_sexp(value)],
+        ),
+        ExprKind::Call { callee, args } => {
+            let mut fields = vec![expr_to_sexp(callee)];
+            fields.extend(args.iter().map(expr_to_sexp));
+            sexp_list(node_head("call", expr.id, expr.span), fields)
+        }
+        ExprKind::Index { base, index } => sexp_list(
+            node_head("index", expr.id, expr.span),
+            vec![expr_to_sexp(base), expr_to_sexp(index)],
+        ),
+        ExprKind::Field { base, name } => sexp_list(
+            node_head("field-access", expr.id, expr.span),
+            vec![expr_to_sexp(base), quoted(name)],
+        ),
+        ExprKind::TupleIndex { base, index } => sexp_list(
+            node_head("tuple-index", expr.id, expr.span),
+            vec![expr_to_sexp(base), index.to_string()],
+        ),
+        ExprKind::Try(operand) => sexp_list(node_head("try", expr.id, expr.span), vec![expr_to_sexp(operand)]),
+        ExprKind::Cast { operand, ty } => sexp_list(node_head("cast", expr.id, expr.span), vec![expr_to_sexp(operand), type_to_sexp(ty)]),
+        ExprKind::ArrayLiteral(elements) => {
+            sexp_list(node_head("array", expr.id, expr.span), elements.iter().map(expr_to_sexp).collect())
+        }
+        ExprKind::Tuple(elements) => {
+            sexp_list(node_head("tuple", expr.id, expr.span), elements.iter().map(expr_to_sexp).collect())
+        }
+        ExprKind::StructLiteral { name, fields } => {
+            let mut items = vec![quoted(name)];
+            items.extend(fields.iter().map(field_init_to_sexp));
+            sexp_list(node_head("struct-literal", expr.id, expr.span), items)
+        }
+        ExprKind::Match { scrutinee, arms } => {
+            let mut fields = vec![expr_to_sexp(scrutinee)];
+            fields.extend(arms.iter().map(match_arm_to_sexp));
+            sexp_list(node_head("match", expr.id, expr.span), fields)
+        }
+        ExprKind::Closure { params, return_type, body } => sexp_list(
+            node_head("closure", expr.id, expr.span),
+            vec![
+                sexp_list("(params".to_string(), params.iter().map(closure_param_to_sexp).collect()),
+                optional_sexp(return_type.as_ref(), type_to_sexp),
+                arm_body_to_sexp(body),
+            ],
+        ),
+        ExprKind::Path(path) => sexp_list(node_head("path", expr.id, expr.span), path.iter().map(|s| quoted(s)).collect()),
+        ExprKind::Identifier(name) => sexp_list(node_head("identifier", expr.id, expr.span), vec![quoted(name)]),
+        ExprKind::IntLiteral(value) => sexp_list(node_head("int", expr.id, expr.span), vec![value.to_string()]),
+        ExprKind::BoolLiteral(value) => sexp_list(node_head("bool", expr.id, expr.span), vec![value.to_string()]),
+        ExprKind::StringLiteral(value) => sexp_list(node_head("string", expr.id, expr.span), vec![quoted(value)]),
+        ExprKind::Interpolation(parts) => sexp_list(
+            node_head("interpolation", expr.id, expr.span),
+            parts
+                .iter()
+                .map(|part| match part {
+                    StringPart::Text(text) => quoted(text),
+                    StringPart::Expr(expr) => expr_to_sexp(expr),
+                })
+                .collect(),
+        ),
+    }
+}
+
+pub fn program_from_json(text: &str) -> Result<Program, JsonError> {
+    program_from_value(&json::parse(text)?)
+}
+
+pub fn program_from_value(value: &Value) -> Result<Program, JsonError> {
+    Ok(Program {
+        id: id_from(value)?,
+        span: span_from(value)?,
+        items: value.get("items")?.as_array()?.iter().map(item_from).collect::<Result<_, _>>()?,
+    })
+}
+
+fn id_from(value: &Value) -> Result<NodeId, JsonError> {
+    let id = value.get("id")?.as_i64()?;
+    u32::try_from(id).map(NodeId).map_err(|_| JsonError::new(format!("invalid node id {}", id)))
+}
+
+fn span_from(value: &Value) -> Result<Span, JsonError> {
+    match value.get("span")?.as_array()? {
+        [start, end] => {
+            let offset = |value: &Value| {
+                let offset = value.as_i64()?;
+                usize::try_from(offset).map_err(|_| JsonError::new(format!("invalid span offset {}", offset)))
+            };
+            let (start, end) = (offset(start)?, offset(end)?);
+            if start > end {
+                return Err(JsonError::new(format!("span start {} is after its end {}", start, end)));
+            }
+            Ok(Span::new(start, end))
+        }
+        _ => Err(JsonError::new("span must be a [start, end] pair")),
+    }
+}
+
+fn kind_of(value: &Value) -> Result<&str, JsonError> {
+    value.get("kind")?.as_str()
+}
+
+fn unknown_kind(what: &str, kind: &str) -> JsonError {
+    JsonError::new(format!("unknown {} kind '{}'", what, kind))
+}
+
+fn string_field(value: &Value, key: &str) -> Result<String, JsonError> {
+    Ok(value.get(key)?.as_str()?.to_string())
+}
+
+fn name_field(value: &Value, key: &str) -> Result<String, JsonError> {
+    name_from(value.get(key)?)
+}
+
+/// An identifier, or a name qualified by modules such as `math::Point`.
+fn name_from(value: &Value) -> Result<String, JsonError> {
+    let name = value.as_str()?;
+    if !name.split("::").all(Lexer::is_identifier) {
+        return Err(JsonError::new(format!("invalid name '{}'", name)));
+    }
+    Ok(name.to_string())
+}
+
+fn optional_field<T>(
+    value: &Value,
+    key: &str,
+    convert: impl Fn(&Value) -> Result<T, JsonError>,
+) -> Result<Option<T>, JsonError> {
+    value.get(key)?.as_option().map(convert).transpose()
+}
+
+fn item_from(value: &Value) -> Result<Item, JsonError> {
+    let kind = match kind_of(value)? {
+        "Function" => ItemKind::Function(function_from(value)?),
+        "Global" => ItemKind::Global(let_from(value)?),
+        "Const" => ItemKind::Const(Const {
+            name: name_field(value, "name")?,
+            ty: type_from(value.get("ty")?)?,
+            value: expr_from(value.get("value")?)?,
+        }),
+        "Struct" => ItemKind::Struct(Struct {
+            name: name_field(value, "name")?,
+            type_params: type_params_from(value)?,
+            fields: value.get("fields")?.as_array()?.iter().map(field_from).collect::<Result<_, _>>()?,
+        }),
+        "Enum" => ItemKind::Enum(Enum {
+            name: name_field(value, "name")?,
+            type_params: type_params_from(value)?,
+            variants: value.get("variants")?.as_array()?.iter().map(variant_from).collect::<Result<_, _>>()?,
+        }),
+        "Interface" => ItemKind::Interface(Interface {
+            name: name_field(value, "name")?,
+            methods: value.get("methods")?.as_array()?.iter().map(method_sig_from).collect::<Result<_, _>>()?,
+        }),
+        "Impl" => ItemKind::Impl(Impl {
+            interface: name_field(value, "interface")?,
+            ty: type_from(value.get("ty")?)?,
+            methods: value.get("methods")?.as_array()?.iter().map(method_from).collect::<Result<_, _>>()?,
+        }),
+        "Import" => ItemKind::Import(Import {
+            path: string_field(value, "path")?,
+        }),
+        "Extern" => ItemKind::Extern(Extern {
+            abi: string_field(value, "abi")?,
+            name: name_field(value, "name")?,
+            params: value.get("params")?.as_array()?.iter().map(param_from).collect::<Result<_, _>>()?,
+            variadic: value.get("variadic")?.as_bool()?,
+            return_type: optional_field(value, "return_type", type_from)?,
+        }),
+        other => return Err(unknown_kind("item", other)),
+    };
+    Ok(Item {
+        id: id_from(value)?,
+        span: span_from(value)?,
+        attributes: value.get("attributes")?.as_array()?.iter().map(attribute_from).collect::<Result<_, _>>()?,
+        public: value.get("public")?.as_bool()?,
+        kind,
+    })
+}
+
+fn attribute_from(value: &Value) -> Result<Attribute, JsonError> {
+    Ok(Attribute {
+        id: id_from(value)?,
+        span: span_from(value)?,
+        name: name_field(value, "name")?,
+        args: value.get("args")?.as_array()?.iter().map(|arg| Ok(arg.as_str()?.to_string())).collect::<Result<_, _>>()?,
+    })
+}
+
+fn function_from(value: &Value) -> Result<Function, JsonError> {
+    Ok(Function {
+        constant: value.get("constant")?.as_bool()?,
+        name: name_field(value, "name")?,
+        type_params: type_params_from(value)?,
+        params: value.get("params")?.as_array()?.iter().map(param_from).collect::<Result<_, _>>()?,
+        return_type: optional_field(value, "return_type", type_from)?,
+        body: block_from(value.get("body")?)?,
+    })
+}
+
+fn type_params_from(value: &Value) -> Result<Vec<TypeParam>, JsonError> {
+    value
+        .get("type_params")?
+        .as_array()?
+        .iter()
+        .map(|param| {
+            Ok(TypeParam {
+                id: id_from(param)?,
+                span: span_from(param)?,
+                name: name_field(param, "name")?,
+                bounds: param.get("bounds")?.as_array()?.iter().map(name_from).collect::<Result<_, _>>()?,
+            })
+        })
+        .collect()
+}
+
+fn method_sig_from(value: &Value) -> Result<MethodSig, JsonError> {
+    Ok(MethodSig {
+        id: id_from(value)?,
+        span: span_from(value)?,
+        name: name_field(value, "name")?,
+        params: value.get("params")?.as_array()?.iter().map(param_from).collect::<Result<_, _>>()?,
+        return_type: optional_field(value, "return_type", type_from)?,
+    })
+}
+
+fn method_from(value: &Value) -> Result<Method, JsonError> {
+    Ok(Method {
+        id: id_from(value)?,
+        span: span_from(value)?,
+        attributes: value.get("attributes")?.as_array()?.iter().map(attribute_from).collect::<Result<_, _>>()?,
+        function: function_from(value)?,
+    })
+}
+
+fn let_from(value: &Value) -> Result<Let, JsonError> {
+    Ok(Let {
+        pattern: pattern_from(value.get("pattern")?)?,
+        ty: optional_field(value, "ty", type_from)?,
+        value: expr_from(value.get("value")?)?,
+    })
+}
+
+fn param_from(value: &Value) -> Result<Param, JsonError> {
+    Ok(Param {
+        id: id_from(value)?,
+        span: span_from(value)?,
+        pattern: pattern_from(value.get("pattern")?)?,
+        ty: type_from(value.get("ty")?)?,
+    })
+}
+
+fn field_from(value: &Value) -> Result<Field, JsonError> {
+    Ok(Field {
+        id: id_from(value)?,
+        span: span_from(value)?,
+        name: name_field(value, "name")?,
+        ty: type_from(value.get("ty")?)?,
+    })
+}
+
+fn field_init_from(value: &Value) -> Result<FieldInit, JsonError> {
+    Ok(FieldInit {
+        id: id_from(value)?,
+        span: span_from(value)?,
+        name: name_field(value, "name")?,
+        value: expr_from(value.get("value")?)?,
+    })
+}
+
+fn variant_from(value: &Value) -> Result<Variant, JsonError> {
+    Ok(Variant {
+        id: id_from(value)?,
+        span: span_from(value)?,
+        name: name_field(value, "name")?,
+        fields: value.get("fields")?.as_array()?.iter().map(type_from).collect::<Result<_, _>>()?,
+    })
+}
+
+fn path_from(value: &Value) -> Result<Vec<String>, JsonError> {
+    value.get("path")?.as_array()?.iter().map(name_from).collect()
+}
+
+fn match_arm_from(value: &Value) -> Result<MatchArm, JsonError> {
+    Ok(MatchArm {
+        id: id_from(value)?,
+        span: span_from(value)?,
+        pattern: pattern_from(value.get("pattern")?)?,
+        guard: optional_field(value, "guard", expr_from)?,
+        body: arm_body_from(value.get("body")?)?,
+    })
+}
+
+fn arm_body_from(body: &Value) -> Result<ArmBody, JsonError> {
+    match kind_of(body)? {
+        "Expr" => Ok(ArmBody::Expr(expr_from(body.get("expr")?)?)),
+        "Block" => Ok(ArmBody::Block(block_from(body.get("block")?)?)),
+        other => Err(unknown_kind("body", other)),
+    }
+}
+
+fn closure_param_from(value: &Value) -> Result<ClosureParam, JsonError> {
+    Ok(ClosureParam {
+        id: id_from(value)?,
+        span: span_from(value)?,
+        pattern: pattern_from(value.get("pattern")?)?,
+        ty: optional_field(value, "ty", type_from)?,
+    })
+}
+
+fn pattern_from(value: &Value) -> Result<Pattern, JsonError> {
+    let kind = match kind_of(value)? {
+        "Wildcard" => PatternKind::Wildcard,
+        "Binding" => PatternKind::Binding {
+            name: name_field(value, "name")?,
+            mutable: value.get("mutable")?.as_bool()?,
+        },
+        "IntLiteral" => PatternKind::IntLiteral(int_literal_from(value)?),
+        "BoolLiteral" => PatternKind::BoolLiteral(value.get("value")?.as_bool()?),
+        "Variant" => PatternKind::Variant {
+            path: path_from(value)?,
+            fields: value.get("fields")?.as_array()?.iter().map(pattern_from).collect::<Result<_, _>>()?,
+        },
+        "Tuple" => PatternKind::Tuple(value.get("elements")?.as_array()?.iter().map(pattern_from).collect::<Result<_, _>>()?),
+        other => return Err(unknown_kind("pattern", other)),
+    };
+    Ok(Pattern {
+        id: id_from(value)?,
+        span: span_from(value)?,
+        kind,
+    })
+}
+
+fn int_literal_from<T: TryFrom<i128>>(value: &Value) -> Result<T, JsonError> {
+    let literal = value.get("value")?.as_i128()?;
+    T::try_from(literal).map_err(|_| JsonError::new(format!("integer literal {} is out of range", literal)))
+}
+
+fn type_from(value: &Value) -> Result<Type, JsonError> {
+    let kind = match kind_of(value)? {
+        "Int" => {
+            let name = value.get("name")?.as_str()?;
+            TypeKind::Int(IntType::from_name(name).ok_or_else(|| JsonError::new(format!("unknown integer type '{}'", name)))?)
+        }
+        "Bool" => TypeKind::Bool,
+        "String" => TypeKind::String,
+        "Ptr" => TypeKind::Ptr,
+        "Void" => TypeKind::Void,
+        "Array" => {
+            let size = match value.get("size")? {
+                name @ Value::String(_) => ArraySize::Const(name_from(name)?),
+                size => {
+                    let size = size.as_i64()?;
+                    ArraySize::Literal(u32::try_from(size).map_err(|_| JsonError::new(format!("invalid array size {}", size)))?)
+                }
+            };
+            TypeKind::Array {
+                element: Box::new(type_from(value.get("element")?)?),
+                size,
+            }
+        }
+        "Named" => TypeKind::Named {
+            name: name_field(value, "name")?,
+            args: value.get("args")?.as_array()?.iter().map(type_from).collect::<Result<_, _>>()?,
+        },
+        "Tuple" => TypeKind::Tuple(value.get("elements")?.as_array()?.iter().map(type_from).collect::<Result<_, _>>()?),
+        "Function" => TypeKind::Function {
+            params: value.get("params")?.as_array()?.iter().map(type_from).collect::<Result<_, _>>()?,
+            return_type: optional_field(value, "return_type", |ty| type_from(ty).map(Box::new))?,
+        },
+        "Dyn" => TypeKind::Dyn(name_field(value, "interface")?),
+        other => return Err(unknown_kind("type", other)),
+    };
+    Ok(Type {
+        id: id_from(value)?,
+        span: span_from(value)?,
+        kind,
+    })
+}
+
+fn block_from(value: &Value) -> Result<Block, JsonError> {
+    Ok(Block {
+        id: id_from(value)?,
+        span: span_from(value)?,
+        stmts: value.get("stmts")?.as_array()?.iter().map(stmt_from).collect::<Result<_, _>>()?,
+    })
+}
+
+fn stmt_from(value: &Value) -> Result<Stmt, JsonError> {
+    let kind = match kind_of(value)? {
+        "Let" => StmtKind::Let(let_from(value)?),
+        "Expr" => StmtKind::Expr(expr_from(value.get("expr")?)?),
+        "Return" => StmtKind::Return(optional_field(value, "value", expr_from)?),
+        "If" => StmtKind::If {
+            condition: expr_from(value.get("condition")?)?,
+            then_branch: block_from(value.get("then_branch")?)?,
+            else_branch: optional_field(value, "else_branch", block_from)?,
+        },
+        "For" => StmtKind::For {
+            init: optional_field(value, "init", |init| stmt_from(init).map(Box::new))?,
+            condition: optional_field(value, "condition", expr_from)?,
+            increment: optional_field(value, "increment", expr_from)?,
+            body: block_from(value.get("body")?)?,
+        },
+        "While" => StmtKind::While {
+            condition: expr_from(value.get("condition")?)?,
+            body: block_from(value.get("body")?)?,
+        },
+        "Defer" => StmtKind::Defer(Box::new(stmt_from(value.get("stmt")?)?)),
+        other => return Err(unknown_kind("statement", other)),
+    };
+    Ok(Stmt {
+        id: id_from(value)?,
+        span: span_from(value)?,
+        kind,
+    })
+}
+
+fn boxed_expr(value: &Value, key: &str) -> Result<Box<Expr>, JsonError> {
+    expr_from(value.get(key)?).map(Box::new)
+}
+
+fn expr_from(value: &Value) -> Result<Expr, JsonError> {
+    let kind = match kind_of(value)? {
+        "Unary" => ExprKind::Unary {
+            op: match value.get("op")?.as_str()? {
+                "Neg" => UnaryOp::Neg,
+                "Not" => UnaryOp::Not,
+                other => return Err(unknown_kind("unary operator", other)),
+            },
+            operand: boxed_expr(value, "operand")?,
+        },
+        "Binary" => {
+            let op_name = value.get("op")?.as_str()?;
+            let op = [
+                BinaryOp::Add,
+                BinaryOp::Sub,
+                BinaryOp::Mul,
+                BinaryOp::Div,
+                BinaryOp::Shl,
+                BinaryOp::Shr,
+                BinaryOp::Eq,
+                BinaryOp::Ne,
+                BinaryOp::Lt,
+                BinaryOp::Le,
+                BinaryOp::Gt,
+                BinaryOp::Ge,
+            ]
+            .into_iter()
+            .find(|op| binary_op_name(*op) == op_name)
+            .ok_or_else(|| unknown_kind("binary operator", op_name))?;
+            ExprKind::Binary {
+                op,
+                left: boxed_expr(value, "left")?,
+                right: boxed_expr(value, "right")?,
+            }
+        }
+        "Assign" => ExprKind::Assign {
+            target: boxed_expr(value, "target")?,
+            value: boxed_expr(value, "value")?,
+        },
+        "Call" => ExprKind::Call {
+            callee: boxed_expr(value, "callee")?,
+            args: value.get("args")?.as_array()?.iter().map(expr_from).collect::<Result<_, _>>()?,
+        },
+        "Index" => ExprKind::Index {
+            base: boxed_expr(value, "base")?,
+            index: boxed_expr(value, "index")?,
+        },
+        "Field" => ExprKind::Field {
+            base: boxed_expr(value, "base")?,
+            name: name_field(value, "name")?,
+        },
+        "TupleIndex" => {
+            let index = value.get("index")?.as_i64()?;
+            ExprKind::TupleIndex {
+                base: boxed_expr(value, "base")?,
+                index: u32::try_from(index).map_err(|_| JsonError::new(format!("invalid tuple index {}", index)))?,
+            }
+        }
+        "Try" => ExprKind::Try(boxed_expr(value, "operand")?),
+        "Cast" => ExprKind::Cast {
+            operand: boxed_expr(value, "operand")?,
+            ty: type_from(value.get("ty")?)?,
+        },
+        "StructLiteral" => ExprKind::StructLiteral {
+            name: name_field(value, "name")?,
+            fields: value.get("fields")?.as_array()?.iter().map(field_init_from).collect::<Result<_, _>>()?,
+        },
+        "Match" => ExprKind::Match {
+            scrutinee: boxed_expr(value, "scrutinee")?,
+            arms: value.get("arms")?.as_array()?.iter().map(match_arm_from).collect::<Result<_, _>>()?,
+        },
+        "Closure" => ExprKind::Closure {
+            params: value.get("params")?.as_array()?.iter().map(closure_param_from).collect::<Result<_, _>>()?,
+            return_type: optional_field(value, "return_type", type_from)?,
+            body: Box::new(arm_body_from(value.get("body")?)?),
+        },
+        "Path" => ExprKind::Path(path_from(value)?),
+        "ArrayLiteral" => ExprKind::ArrayLiteral(
+            value.get("elements")?.as_array()?.iter().map(expr_from).collect::<Result<_, _>>()?,
+        ),
+        "Tuple" => ExprKind::Tuple(value.get("elements")?.as_array()?.iter().map(expr_from).collect::<Result<_, _>>()?),
+        "Identifier" => ExprKind::Identifier(name_field(value, "name")?),
+        "IntLiteral" => ExprKind::IntLiteral(int_literal_from(value)?),
+        "BoolLiteral" => ExprKind::BoolLiteral(value.get("value")?.as_bool()?),
+        "StringLiteral" => ExprKind::StringLiteral(string_field(value, "value")?),
+        "Interpolation" => ExprKind::Interpolation(
+            value
+                .get("parts")?
+                .as_array()?
+                .iter()
+                .map(|part| match part {
+                    Value::String(text) => Ok(StringPart::Text(text.clone())),
+                    part => Ok(StringPart::Expr(expr_from(part)?)),
+                })
+                .collect::<Result<_, JsonError>>()?,
+        ),
+        other => return Err(unknown_kind("expression", other)),
+    };
+    Ok(Expr {
+        id: id_from(value)?,
+        span: span_from(value)?,
+        kind,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(source: &str) -> Program {
+        let mut lexer = Lexer::new(source.to_string());
+        Parser::new(&mut lexer).parse_program()
+    }
+
+    const SAMPLE: &str = "import \"util/math.cl\"; enum E { A(int, E2), B } enum E2 { C } pub struct P { a: int, b: bool[N] } const N: int = 2 * 1; let x: int = 10; function main(a: int) -> bool { let y = -a; if (y < x) { return !true; } else { f(y, 2); } for (let i: int = 0; i < 10; i = i + 1) { while (i < 5) { i = i * 2; } } for (;;) {} let v: int[3][2] = [[1, 2, 3], []]; v[0][a] = v[1][2]; let p: P = P { a: 1, b: [true, false] }; p.b[0] = p.a == 1; match E::A(1, E2::C) { E::A(-1, _) if x => {} E::A(n, E2::C) => n, E::B => { return; } } return; } pub function g((q, r): (int, bool), u: ()) -> (int, (bool,)) { let (a, (_, b)) = (q.0, (u, r)); return (a, (b,)); } function h(k: fn(int) -> int) -> fn() { let c = |x, (y, _): (int, bool)| -> int { return k(x + y); }; return || c(1, (2, true)); } struct Pair<A, B> { a: A, b: B } function swap<A, B>(p: Pair<A, B>) -> Pair<B, A> { return Pair { a: p.b, b: p.a }; } interface Show { function show(self) -> int; } impl Show for P { @inline function show(self) -> int { return self.a; } } function all<T: Show>(t: T, s: dyn Show) -> int { return t.show() + s.show(); } enum Maybe<T> { No, Yes(T) } function next(m: Maybe<int>) -> Maybe<int> { return Maybe::Yes(m? + 1); } function bits(b: u8, n: i64) -> u16 { return (b as u16 << 4) + (n >> 2) as u16; } function greet(name: string) -> string { return \"hi {name}, {{{name.len() + 1}}}\\n\" + \"\\\"!\"; } @inline @deprecated(\"use \\\"g\\\"\") pub function old() {} extern \"C\" function printf(format: ptr, ...) -> int; function close(p: ptr) { defer printf(p); defer if true { printf(p, 1); } } const MAX: u64 = 18446744073709551615; function low(n: i64) -> int { return match n { -9223372036854775808 => 0, _ => 1 }; }";
+
+    #[test]
+    fn json_round_trips_including_ids_and_spans() {
+        let program = parse(SAMPLE);
+        let text = program_to_json(&program).to_string();
+        assert_eq!(program_from_json(&text).unwrap(), program);
+    }
+
+    #[test]
+    fn json_shape_is_stable() {
+        let json = program_to_json(&parse("let a = b + 1;")).to_string();
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"id":5,"span":[0,14],"items":[{"id":4,"span":[0,14],"kind":"Global","#,
+                r#""pattern":{"id":0,"span":[4,5],"kind":"Binding","name":"a","mutable":false},"ty":null,"#,
+                r#""value":{"id":3,"span":[8,13],"kind":"Binary","op":"Add","#,
+                r#""left":{"id":1,"span":[8,9],"kind":"Identifier","name":"b"},"#,
+                r#""right":{"id":2,"span":[12,13],"kind":"IntLiteral","value":1}},"public":false,"attributes":[]}]}"#
+            )
+        );
+    }
+
+    #[test]
+    fn reports_malformed_json() {
+        assert!(program_from_json("{\"id\": 0, \"span\": [0, 1]}").unwrap_err().message.contains("items"));
+        let bad_kind = r#"{"id":0,"span":[0,1],"items":[{"id":1,"span":[0,1],"kind":"Module"}]}"#;
+        assert_eq!(program_from_json(bad_kind).unwrap_err().message, "unknown item kind 'Module'");
+        assert!(program_from_json("[1, 2").is_err());
+
+        let json = program_to_json(&parse("function f(x: math::Point) {}")).to_string();
+        assert!(program_from_json(&json).is_ok());
+        let error = |from: &str, to: &str| program_from_json(&json.replacen(from, to, 1)).unwrap_err().message;
+        assert_eq!(error(r#""name":"f""#, r#""name":"f g""#), "invalid name 'f g'");
+        assert_eq!(error(r#""name":"f""#, r#""name":"let""#), "invalid name 'let'");
+        assert_eq!(error(r#""name":"math::Point""#, r#""name":"math::2""#), "invalid name 'math::2'");
+        assert_eq!(error(r#""span":[0,29]"#, r#""span":[29,0]"#), "span start 29 is after its end 0");
+    }
+
+    #[test]
+    fn sexp_includes_ids_and_spans() {
+        assert_eq!(
+            program_to_sexp(&parse("function f() { return a + 1; }")),
+            r#"(program #6 0..30 (function #5 0..30 "f" (params) nil (block #4 13..30 (return #3 15..28 (binary #2 22..27 + (identifier #0 22..23 "a") (int #1 26..27 1))))))"#
+        );
+    }
+
+    #[test]
+    fn sexp_nests_array_types() {
+        assert_eq!(
+            program_to_sexp(&parse("let a: int[2] = [1];")),
+            r#"(program #6 0..20 (global #5 0..20 (pattern #0 4..5 "a") (type #2 7..13 array (type #1 7..10 int) 2) (array #4 16..19 (int #3 17..18 1))))"#
+        );
+    }
+
+    #[test]
+    fn sexp_dumps_tuples_and_destructuring() {
+        assert_eq!(
+            program_to_sexp(&parse("let (a, _): (int,) = (b,).0;")),
+            concat!(
+                r#"(program #9 0..28 (global #8 0..28 (pattern #2 4..10 tuple (pattern #0 5..6 "a") (pattern #1 8..9 _)) "#,
+                r#"(type #4 12..18 tuple (type #3 13..16 int)) (tuple-index #7 21..27 (tuple #6 21..25 (identifier #5 22..23 "b")) 0)))"#
+            )
+        );
+    }
+
+    #[test]
+    fn sexp_dumps_function_types_and_closures() {
+        assert_eq!(
+            program_to_sexp(&parse("let f: fn(int) = |x| g(x);")),
+            concat!(
+                r#"(program #10 0..26 (global #9 0..26 (pattern #0 4..5 "f") (type #2 7..14 fn (params (type #1 10..13 int)) nil) "#,
+                r#"(closure #8 17..25 (params (param #4 18..19 (pattern #3 18..19 "x") nil)) nil "#,
+                r#"(call #7 21..25 (identifier #5 21..22 "g") (identifier #6 23..24 "x")))))"#
+            )
+        );
+    }
+
+    #[test]
+    fn sexp_dumps_type_parameters_and_arguments() {
+        assert_eq!(
+            program_to_sexp(&parse("function id<T>(x: T) -> Box<T> {}")),
+            concat!(
+                r#"(program #8 0..33 (function #7 0..33 "id" (type-params (type-param #0 12..13 "T")) "#,
+                r#"(params (param #3 15..19 (pattern #1 15..16 "x") (type #2 18..19 "T"))) "#,
+                r#"(type #5 24..30 "Box" (type #4 28..29 "T")) (block #6 31..33)))"#
+            )
+        );
+    }
+
+    #[test]
+    fn sexp_dumps_interfaces_impls_and_bounds() {
+        assert_eq!(
+            program_to_sexp(&parse("interface I { function m(self); } impl I for int {} function f<T: I>(x: dyn I) {}")),
+            concat!(
+                r#"(program #13 0..81 (interface #4 0..33 "I" (method-sig #3 14..31 "m" "#,
+                r#"(params (param #2 25..29 (pattern #0 25..29 "self") (type #1 25..29 "Self"))) nil)) "#,
+                r#"(impl #6 34..51 "I" (type #5 45..48 int)) (function #12 52..81 "f" (type-params (type-param #7 63..67 "T" "I")) "#,
+                r#"(params (param #10 69..77 (pattern #8 69..70 "x") (type #9 72..77 dyn "I"))) nil (block #11 79..81)))"#
+            )
+        );
+    }
+
+    #[test]
+    fn sexp_dumps_attributes_after_pub() {
+        assert_eq!(
+            program_to_sexp(&parse("@inline @export(\"f_c\") pub function f() {}")),
+            r#"(program #4 0..42 (function #3 0..42 pub (attribute #0 0..7 "inline") (attribute #1 8..22 "export" "f_c") "f" (params) nil (block #2 40..42)))"#
+        );
+    }
+
+    #[test]
+    fn sexp_dumps_extern_functions() {
+        assert_eq!(
+            program_to_sexp(&parse("extern \"C\" function printf(f: ptr, ...) -> int;")),
+            r#"(program #5 0..47 (extern #4 0..47 "C" "printf" (params (param #2 27..33 (pattern #0 27..28 "f") (type #1 30..33 ptr)) ...) (type #3 43..46 int)))"#
+        );
+    }
+
+    #[test]
+    fn sexp_dumps_deferred_statements() {
+        assert_eq!(
+            program_to_sexp(&parse("function f() { defer g(); }")),
+            r#"(program #6 0..27 (function #5 0..27 "f" (params) nil (block #4 13..27 (defer #3 15..25 (expr #2 21..25 (call #1 21..24 (identifier #0 21..22 "g")))))))"#
+        );
+    }
+
+    #[test]
+    fn dumps_tokens_with_spans() {
+        let tokens = Lexer::new("let n = 42;".to_string()).tokenize();
+        assert_eq!(
+            tokens_to_json(&tokens).to_string(),
+            concat!(
+                r#"[{"kind":"Let","span":[0,3]},{"kind":"Identifier","span":[4,5],"value":"n"},"#,
+                r#"{"kind":"Equals","span":[6,7]},{"kind":"IntLiteral","span":[8,10],"value":42},"#,
+                r#"{"kind":"Semicolon","span":[10,11]},{"kind":"Eof","span":[11,11]}]"#
+            )
+        );
+        assert_eq!(
+            tokens_to_sexp(&tokens),
+            "(Let 0..3)\n(Identifier 4..5 \"n\")\n(Equals 6..7)\n(IntLiteral 8..10 42)\n(Semicolon 10..11)\n(Eof 11..11)"
+        );
+    }
+}
@@ -0,0 +1,395 @@
+//! Exhaustiveness and reachability checking for `match`.
+//!
+//! Patterns are reduced to constructors applied to sub-patterns (enum
+//! variants, tuples, `true`/`false` and integer literals) or wildcards, and
+//! checked with the usefulness algorithm from Maranget's "Warnings for pattern
+//! matching": an arm is unreachable if its pattern is not useful after the
+//! arms above it, and a match is exhaustive if `_` is not useful after all of
+//! its arms. Arms with a guard may fail to match, so they never cover later
+//! arms or count towards exhaustiveness.
+//!
+//! The patterns of `let` bindings and function parameters must be
+//! irrefutable: `_` must not be useful after them.
+
+use crate::diagnostic::Diagnostic;
+use crate::parser::{ClosureParam, Enum, Expr, ExprKind, ItemKind, Let, MatchArm, Param, Pattern, PatternKind, Program};
+use crate::printer::tuple_text;
+use crate::visit::{self, Visitor};
+use std::collections::HashMap;
+
+pub fn check_program(program: &Program) -> Vec<Diagnostic> {
+    let enums = program
+        .items
+        .iter()
+        .filter_map(|item| match &item.kind {
+            ItemKind::Enum(definition) => Some((definition.name.as_str(), definition)),
+            _ => None,
+        })
+        .collect();
+    let mut checker = Checker {
+        enums,
+        diagnostics: Vec::new(),
+    };
+    checker.visit_program(program);
+    checker.diagnostics
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Ctor {
+    Variant { enum_name: String, index: usize },
+    /// The only constructor of tuples of this many elements.
+    Tuple(usize),
+    Bool(bool),
+    Int(i128),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Pat {
+    Wild,
+    Ctor(Ctor, Vec<Pat>),
+}
+
+type Row = Vec<Pat>;
+
+struct Checker<'a> {
+    enums: HashMap<&'a str, &'a Enum>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Visitor for Checker<'_> {
+    fn visit_expr(&mut self, expr: &Expr) {
+        if let ExprKind::Match { arms, .. } = &expr.kind {
+            self.check_match(expr, arms);
+        }
+        visit::walk_expr(self, expr);
+    }
+
+    fn visit_let(&mut self, binding: &Let) {
+        self.check_irrefutable(&binding.pattern, "`let` binding");
+        visit::walk_let(self, binding);
+    }
+
+    fn visit_param(&mut self, param: &Param) {
+        self.check_irrefutable(&param.pattern, "function parameter");
+        visit::walk_param(self, param);
+    }
+
+    fn visit_closure_param(&mut self, param: &ClosureParam) {
+        self.check_irrefutable(&param.pattern, "closure parameter");
+        visit::walk_closure_param(self, param);
+    }
+}
+
+impl Checker<'_> {
+    fn check_match(&mut self, expr: &Expr, arms: &[MatchArm]) {
+        let Some(patterns) = arms.iter().map(|arm| self.lower(&arm.pattern)).collect::<Option<Vec<Pat>>>() else {
+            // The pattern errors are already reported; coverage would only
+            // add noise.
+            return;
+        };
+        let mut covering: Vec<Row> = Vec::new();
+        for (arm, pattern) in arms.iter().zip(patterns) {
+            let row = vec![pattern];
+            if !self.is_useful(&covering, &row) {
+                self.diagnostics.push(Diagnostic::warning(arm.pattern.span, "unreachable match arm"));
+            }
+            if arm.guard.is_none() {
+                covering.push(row);
+            }
+        }
+        if let Some(missing) = self.witness(&covering, 1) {
+            self.diagnostics.push(Diagnostic::error(
+                expr.span,
+                format!("non-exhaustive match: `{}` not covered", self.display(&missing[0])),
+            ));
+        }
+    }
+
+    fn check_irrefutable(&mut self, pattern: &Pattern, what: &str) {
+        let Some(lowered) = self.lower(pattern) else {
+            return;
+        };
+        if let Some(missing) = self.witness(&[vec![lowered]], 1) {
+            self.diagnostics.push(Diagnostic::error(
+                pattern.span,
+                format!("refutable pattern in {}: `{}` not covered", what, self.display(&missing[0])),
+            ));
+        }
+    }
+
+    /// Reduces a pattern to constructors and wildcards, reporting unknown
+    /// variants and payload arity mismatches.
+    fn lower(&mut self, pattern: &Pattern) -> Option<Pat> {
+        match &pattern.kind {
+            PatternKind::Wildcard | PatternKind::Binding { .. } => Some(Pat::Wild),
+            PatternKind::IntLiteral(value) => Some(Pat::Ctor(Ctor::Int(*value), Vec::new())),
+            PatternKind::BoolLiteral(value) => Some(Pat::Ctor(Ctor::Bool(*value), Vec::new())),
+            PatternKind::Variant { path, fields } => {
+                let [.., enum_name, variant_name] = path.as_slice() else {
+                    self.error(pattern, format!("expected `Enum::Variant`, found `{}`", path.join("::")));
+                    return None;
+                };
+                let Some(definition) = self.enums.get(enum_name.as_str()) else {
+                    self.error(pattern, format!("unknown enum `{}`", enum_name));
+                    return None;
+                };
+                let Some(index) = definition.variants.iter().position(|variant| variant.name == *variant_name) else {
+                    self.error(pattern, format!("enum `{}` has no variant `{}`", enum_name, variant_name));
+                    return None;
+                };
+                let expected = definition.variants[index].fields.len();
+                if fields.len() != expected {
+                    self.error(
+                        pattern,
+                        format!(
+                            "`{}::{}` has {} payload field(s) but the pattern has {}",
+                            enum_name,
+                            variant_name,
+                            expected,
+                            fields.len()
+                        ),
+                    );
+                    return None;
+                }
+                let fields = fields.iter().map(|field| self.lower(field)).collect::<Option<Vec<Pat>>>()?;
+                Some(Pat::Ctor(
+                    Ctor::Variant {
+                        enum_name: enum_name.clone(),
+                        index,
+                    },
+                    fields,
+                ))
+            }
+            PatternKind::Tuple(elements) => {
+                let elements = elements.iter().map(|element| self.lower(element)).collect::<Option<Vec<Pat>>>()?;
+                Some(Pat::Ctor(Ctor::Tuple(elements.len()), elements))
+            }
+        }
+    }
+
+    fn error(&mut self, pattern: &Pattern, message: String) {
+        self.diagnostics.push(Diagnostic::error(pattern.span, message));
+    }
+
+    fn arity(&self, ctor: &Ctor) -> usize {
+        match ctor {
+            Ctor::Variant { enum_name, index } => self.enums[enum_name.as_str()].variants[*index].fields.len(),
+            Ctor::Tuple(arity) => *arity,
+            Ctor::Bool(_) | Ctor::Int(_) => 0,
+        }
+    }
+
+    /// Every constructor of the type `ctor` belongs to, or `None` when there
+    /// are too many to list.
+    fn all_ctors(&self, ctor: &Ctor) -> Option<Vec<Ctor>> {
+        match ctor {
+            Ctor::Variant { enum_name, .. } => Some(
+                (0..self.enums[enum_name.as_str()].variants.len())
+                    .map(|index| Ctor::Variant {
+                        enum_name: enum_name.clone(),
+                        index,
+                    })
+                    .collect(),
+            ),
+            Ctor::Tuple(arity) => Some(vec![Ctor::Tuple(*arity)]),
+            Ctor::Bool(_) => Some(vec![Ctor::Bool(false), Ctor::Bool(true)]),
+            Ctor::Int(_) => None,
+        }
+    }
+
+    /// The constructors that must be tried when the first column is a
+    /// wildcard, if the rows name every constructor of its type.
+    fn complete_signature(&self, rows: &[Row]) -> Option<Vec<Ctor>> {
+        let heads = head_ctors(rows);
+        let all = self.all_ctors(heads.first()?)?;
+        all.iter().all(|ctor| heads.contains(ctor)).then_some(all)
+    }
+
+    /// Rows that match `ctor` in the first column, with its arguments spliced
+    /// in place of that column.
+    fn specialize(&self, rows: &[Row], ctor: &Ctor) -> Vec<Row> {
+        rows.iter()
+            .filter_map(|row| {
+                let (head, rest) = row.split_first()?;
+                let mut specialized = match head {
+                    Pat::Ctor(head, args) if head == ctor => args.clone(),
+                    Pat::Ctor(..) => return None,
+                    Pat::Wild => vec![Pat::Wild; self.arity(ctor)],
+                };
+                specialized.extend_from_slice(rest);
+                Some(specialized)
+            })
+            .collect()
+    }
+
+    /// Whether some value matched by `row` is matched by none of `rows`.
+    fn is_useful(&self, rows: &[Row], row: &[Pat]) -> bool {
+        let Some((head, rest)) = row.split_first() else {
+            return rows.is_empty();
+        };
+        match head {
+            Pat::Ctor(ctor, args) => {
+                let mut specialized = args.clone();
+                specialized.extend_from_slice(rest);
+                self.is_useful(&self.specialize(rows, ctor), &specialized)
+            }
+            Pat::Wild => match self.complete_signature(rows) {
+                Some(all) => all.iter().any(|ctor| {
+                    let mut specialized = vec![Pat::Wild; self.arity(ctor)];
+                    specialized.extend_from_slice(rest);
+                    self.is_useful(&self.specialize(rows, ctor), &specialized)
+                }),
+                None => self.is_useful(&default_rows(rows), rest),
+            },
+        }
+    }
+
+    /// A row of `width` patterns matched by none of `rows`, if there is one.
+    fn witness(&self, rows: &[Row], width: usize) -> Option<Row> {
+        if width == 0 {
+            return rows.is_empty().then(Vec::new);
+        }
+        if let Some(all) = self.complete_signature(rows) {
+            return all.into_iter().find_map(|ctor| {
+                let arity = self.arity(&ctor);
+                let mut args = self.witness(&self.specialize(rows, &ctor), arity + width - 1)?;
+                let rest = args.split_off(arity);
+                let mut row = vec![Pat::Ctor(ctor, args)];
+                row.extend(rest);
+                Some(row)
+            });
+        }
+        let mut row = self.witness(&default_rows(rows), width - 1)?;
+        let heads = head_ctors(rows);
+        let missing = heads
+            .first()
+            .and_then(|ctor| self.all_ctors(ctor))
+            .and_then(|all| all.into_iter().find(|ctor| !heads.contains(ctor)));
+        let head = match missing {
+            Some(ctor) => {
+                let arity = self.arity(&ctor);
+                Pat::Ctor(ctor, vec![Pat::Wild; arity])
+            }
+            None => Pat::Wild,
+        };
+        row.insert(0, head);
+        Some(row)
+    }
+
+    fn display(&self, pat: &Pat) -> String {
+        match pat {
+            Pat::Wild => "_".to_string(),
+            Pat::Ctor(Ctor::Bool(value), _) => value.to_string(),
+            Pat::Ctor(Ctor::Int(value), _) => value.to_string(),
+            Pat::Ctor(Ctor::Tuple(_), elements) => tuple_text(elements.iter().map(|element| self.display(element)).collect()),
+            Pat::Ctor(Ctor::Variant { enum_name, index }, args) => {
+                let name = format!("{}::{}", enum_name, self.enums[enum_name.as_str()].variants[*index].name);
+                if args.is_empty() {
+                    name
+                } else {
+                    let args: Vec<String> = args.iter().map(|arg| self.display(arg)).collect();
+                    format!("{}({})", name, args.join(", "))
+                }
+            }
+        }
+    }
+}
+
+/// The distinct constructors heading `rows`.
+fn head_ctors(rows: &[Row]) -> Vec<Ctor> {
+    let mut ctors: Vec<Ctor> = Vec::new();
+    for row in rows {
+        if let Some(Pat::Ctor(ctor, _)) = row.first() {
+            if !ctors.contains(ctor) {
+                ctors.push(ctor.clone());
+            }
+        }
+    }
+    ctors
+}
+
+/// Rows whose first column is a wildcard, without that column.
+fn default_rows(rows: &[Row]) -> Vec<Row> {
+    rows.iter()
+        .filter(|row| row.first() == Some(&Pat::Wild))
+        .map(|row| row[1..].to_vec())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::Severity;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    const ENUMS: &str = "enum Shape { Circle(int), Rect(int, int), Empty } enum Opt { Some(Shape), None }";
+
+    fn check(body: &str) -> Vec<(Severity, String)> {
+        let source = format!("{} function f(s: Shape, o: Opt, b: bool, n: int) {{ {} }}", ENUMS, body);
+        let mut lexer = Lexer::new(source);
+        let program = Parser::new(&mut lexer).parse_program();
+        check_program(&program).into_iter().map(|diagnostic| (diagnostic.severity, diagnostic.message)).collect()
+    }
+
+    fn missing(pattern: &str) -> Vec<(Severity, String)> {
+        vec![(Severity::Error, format!("non-exhaustive match: `{}` not covered", pattern))]
+    }
+
+    #[test]
+    fn accepts_exhaustive_matches() {
+        assert!(check("match s { Shape::Circle(_) => 1, Shape::Rect(w, h) => w, Shape::Empty => 0 }").is_empty());
+        assert!(check("match o { Opt::Some(Shape::Circle(r)) if r > 0 => r, Opt::Some(_) => 1, Opt::None => 0 }").is_empty());
+        assert!(check("match b { true => 1, false => 0 }").is_empty());
+        assert!(check("match n { 0 => 1, x => x }").is_empty());
+        assert!(check("match (b, n) { (true, _) => 1, (false, 0) => 2, (false, _) => 3 }").is_empty());
+    }
+
+    #[test]
+    fn reports_a_missing_pattern() {
+        assert_eq!(check("match s { Shape::Circle(_) => 1, Shape::Empty => 0 }"), missing("Shape::Rect(_, _)"));
+        assert_eq!(
+            check("match o { Opt::Some(Shape::Circle(_)) => 1, Opt::Some(Shape::Empty) => 2, Opt::None => 0 }"),
+            missing("Opt::Some(Shape::Rect(_, _))")
+        );
+        assert_eq!(check("match b { true => 1 }"), missing("false"));
+        assert_eq!(check("match n { 0 => 1, 1 => 2 }"), missing("_"));
+        assert_eq!(check("match (b, o) { (true, _) => 1, (_, Opt::None) => 2 }"), missing("(false, Opt::Some(_))"));
+        // A guarded arm may not match, so it does not make the match exhaustive.
+        assert_eq!(check("match b { true => 1, false if n > 0 => 0 }"), missing("false"));
+    }
+
+    #[test]
+    fn warns_about_unreachable_arms() {
+        assert_eq!(
+            check("match s { _ => 0, Shape::Empty => 1 }"),
+            [(Severity::Warning, "unreachable match arm".to_string())]
+        );
+        assert_eq!(
+            check("match o { Opt::Some(_) => 0, Opt::None => 1, Opt::Some(Shape::Empty) => 2 }"),
+            [(Severity::Warning, "unreachable match arm".to_string())]
+        );
+        assert!(check("match b { true if n > 1 => 0, true => 1, false => 2 }").is_empty());
+    }
+
+    #[test]
+    fn requires_irrefutable_let_and_parameter_patterns() {
+        assert!(check("let (q, (r, _)) = (n, (b, s));").is_empty());
+        assert_eq!(
+            check("let (0, r) = (n, b);"),
+            [(Severity::Error, "refutable pattern in `let` binding: `(_, _)` not covered".to_string())]
+        );
+        let source = format!("{} function g((x, true): (int, bool)) {{}}", ENUMS);
+        let mut lexer = Lexer::new(source);
+        let diagnostics = check_program(&Parser::new(&mut lexer).parse_program());
+        assert_eq!(diagnostics[0].message, "refutable pattern in function parameter: `(_, false)` not covered");
+        assert_eq!(check("let f = |(x, 0): (int, int)| x;")[0].1, "refutable pattern in closure parameter: `(_, _)` not covered");
+    }
+
+    #[test]
+    fn reports_malformed_variant_patterns() {
+        assert_eq!(check("match s { Shape::Rect(w) => 0, _ => 1 }")[0].1, "`Shape::Rect` has 2 payload field(s) but the pattern has 1");
+        assert_eq!(check("match s { Shape::Square => 0, _ => 1 }")[0].1, "enum `Shape` has no variant `Square`");
+        assert_eq!(check("match s { Color::Red => 0, _ => 1 }")[0].1, "unknown enum `Color`");
+    }
+}
@@ -0,0 +1,986 @@
+//! The `copilot fmt` source formatter.
+//!
+//! Formatting parses the file, then prints the tree back in one canonical
+//! layout: four-space indentation, one statement per line, a blank line
+//! between items and at most one blank line between statements. Comments
+//! collected by the lexer are re-attached by position, either on their own
+//! line before the next statement or after the statement they trail. Calls,
+//! parameter lists, array literals and tuples that would run past
+//! `MAX_WIDTH` or hold a multi-line element such as a closure with a block
+//! body are split with one element per line. An expression with a comment
+//! inside it is written as it appears in the source, only re-indented, so
+//! the comment stays where it was; a parameter list with comments is split
+//! with the comments kept between the parameters. Formatting already
+//! formatted source returns it unchanged.
+
+use crate::diagnostic::Diagnostic;
+use crate::lexer::{Comment, Lexer, Span, Token};
+use crate::parser::{
+    operator_info, parse_source, ArmBody, Attribute, Block, Enum, Expr, ExprKind, Function, Impl, Interface, Item, ItemKind, Let, MatchArm,
+    Parser, Program, Stmt, StmtKind, Struct,
+};
+use crate::printer::{
+    attribute_text, closure_params_text, condition_needs_parens, expr_binding_power, extern_text, import_text, method_sig_text, operand_binding_powers,
+    param_text, print_expr, print_pattern, print_type, starts_with_match, type_params_text,
+};
+
+use crate::visit::{walk_expr, Visitor};
+
+pub const MAX_WIDTH: usize = 100;
+
+const INDENT: &str = "    ";
+
+pub fn format_source(source: &str) -> String {
+    let mut lexer = Lexer::new(source.to_string());
+    let program = Parser::new(&mut lexer).parse_program();
+    format_program(source, &lexer, &program)
+}
+
+/// Formats `source`, or returns the syntax error that stops it from parsing.
+pub fn try_format_source(source: &str) -> Result<String, Diagnostic> {
+    let mut lexer = Lexer::new(source.to_string());
+    let program = parse_source(&mut lexer)?;
+    Ok(format_program(source, &lexer, &program))
+}
+
+/// Formats `program`, parsed from `source` by `lexer`, whose comments are
+/// put back.
+fn format_program(source: &str, lexer: &Lexer, program: &Program) -> String {
+    let mut formatter = Formatter {
+        source: source.chars().collect(),
+        comments: lexer.comments().to_vec(),
+        next_comment: 0,
+        out: String::new(),
+        indent: 0,
+        last_end: 0,
+        at_block_start: true,
+    };
+    formatter.program(program);
+    formatter.out
+}
+
+pub fn is_formatted(source: &str) -> bool {
+    format_source(source) == source
+}
+
+struct Formatter {
+    source: Vec<char>,
+    comments: Vec<Comment>,
+    next_comment: usize,
+    out: String,
+    indent: usize,
+    /// Source offset just past the last node or comment written.
+    last_end: usize,
+    /// Set right after an opening brace, where blank lines are dropped.
+    at_block_start: bool,
+}
+
+impl Formatter {
+    fn begin_line(&mut self) {
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    fn column(&self) -> usize {
+        self.out.chars().rev().take_while(|&c| c != '\n').count()
+    }
+
+    fn fits(&self, text: &str, reserve: usize) -> bool {
+        self.column() + text.chars().count() + reserve <= MAX_WIDTH
+    }
+
+    fn has_blank_line(&self, from: usize, to: usize) -> bool {
+        from < to && self.source[from..to.min(self.source.len())].iter().filter(|&&c| c == '\n').count() >= 2
+    }
+
+    /// Where the first token at or after `offset` starts, past whitespace and
+    /// comments.
+    fn token_start(&self, mut offset: usize) -> usize {
+        loop {
+            while self.source.get(offset).is_some_and(|c| c.is_whitespace()) {
+                offset += 1;
+            }
+            match self.comments[self.next_comment..].iter().find(|comment| comment.span.start == offset) {
+                Some(comment) => offset = comment.span.end,
+                None => return offset,
+            }
+        }
+    }
+
+    fn has_comment_before(&self, offset: usize) -> bool {
+        self.comments.get(self.next_comment).is_some_and(|comment| comment.span.start < offset)
+    }
+
+    /// Whether a pending comment sits inside `expr` but outside the blocks
+    /// and `match` expressions under it, which place their own comments.
+    fn has_loose_comment(&self, expr: &Expr) -> bool {
+        let inside = |span: Span, offset: usize| span.start <= offset && offset < span.end;
+        let mut comments = self.comments[self.next_comment..]
+            .iter()
+            .filter(|comment| inside(expr.span, comment.span.start))
+            .peekable();
+        if comments.peek().is_none() {
+            return false;
+        }
+        let mut scopes = CommentScopes(Vec::new());
+        walk_expr(&mut scopes, expr);
+        comments.any(|comment| !scopes.0.iter().any(|&scope| inside(scope, comment.span.start)))
+    }
+
+    /// Writes `expr` as it appears in the source, with its continuation lines
+    /// moved to the current indentation, and drops the comments inside it
+    /// from the pending ones.
+    fn verbatim(&mut self, expr: &Expr) {
+        let Span { mut start, end } = expr.span;
+        // A postfix expression on a parenthesised operand starts inside the
+        // parentheses; take in the ones it closes.
+        let tokens = Lexer::with_offset(self.source[start..end].iter().collect(), start).tokenize();
+        let depth = tokens.iter().fold(0i32, |depth, (token, _)| match token {
+            Token::LParen => depth + 1,
+            Token::RParen => depth - 1,
+            _ => depth,
+        });
+        let mut unopened = -depth;
+        while unopened > 0 && start > 0 {
+            start -= 1;
+            if self.source[start] == '(' {
+                unopened -= 1;
+            }
+        }
+        let line_start = self.source[..start].iter().rposition(|&c| c == '\n').map_or(0, |newline| newline + 1);
+        let base: String = self.source[line_start..].iter().take_while(|&&c| c == ' ' || c == '\t').collect();
+        let in_string = |offset: usize| {
+            tokens
+                .iter()
+                .any(|(token, span)| matches!(token, Token::StringLiteral(_)) && span.start < offset && offset < span.end)
+        };
+        let mut offset = start;
+        for (index, line) in self.source[start..end].iter().collect::<String>().split('\n').enumerate() {
+            if index > 0 {
+                self.out.push('\n');
+                if in_string(offset - 1) {
+                    self.out.push_str(line);
+                } else if !line.trim().is_empty() {
+                    self.begin_line();
+                    self.out.push_str(line.strip_prefix(base.as_str()).unwrap_or(line.trim_start()));
+                }
+            } else {
+                self.out.push_str(line);
+            }
+            offset += line.chars().count() + 1;
+        }
+        let first = self.next_comment + self.comments[self.next_comment..].iter().take_while(|comment| comment.span.start < start).count();
+        let count = self.comments[first..].iter().take_while(|comment| comment.span.start < end).count();
+        self.comments.drain(first..first + count);
+        self.last_end = end;
+    }
+
+    /// Writes a blank line before the next thing if the source had one there
+    /// or `force` asks for one.
+    fn separator(&mut self, next_start: usize, force: bool) {
+        if !self.at_block_start && !self.out.is_empty() && (force || self.has_blank_line(self.last_end, next_start)) {
+            self.out.push('\n');
+        }
+        self.at_block_start = false;
+    }
+
+    /// Writes every pending comment that starts before `offset`. A comment on
+    /// the same source line as the previous node is appended to that line;
+    /// others get a line of their own. Returns whether a forced blank line is
+    /// still owed to whatever comes next.
+    fn comments_until(&mut self, offset: usize, mut force_blank: bool) -> bool {
+        while self.has_comment_before(offset) {
+            let comment = self.comments[self.next_comment].clone();
+            self.next_comment += 1;
+            let trails_previous = self.last_end > 0
+                && self.last_end <= comment.span.start
+                && self.out.ends_with('\n')
+                && !self.source[self.last_end..comment.span.start].contains(&'\n');
+            if trails_previous {
+                self.out.pop();
+                self.out.push(' ');
+            } else {
+                self.separator(comment.span.start, force_blank);
+                force_blank = false;
+                self.begin_line();
+            }
+            self.out.push_str(&comment.text);
+            self.out.push('\n');
+            self.last_end = self.last_end.max(comment.span.end);
+        }
+        force_blank
+    }
+
+    fn program(&mut self, program: &Program) {
+        let mut previous: Option<&Item> = None;
+        for item in &program.items {
+            let force_blank = previous.is_some_and(|previous| {
+                let multiline = |item: &Item| !matches!(item.kind, ItemKind::Global(_) | ItemKind::Const(_) | ItemKind::Import(_) | ItemKind::Extern(_));
+                multiline(previous) || multiline(item)
+            });
+            let force_blank = self.comments_until(item.span.start, force_blank);
+            self.separator(item.span.start, force_blank);
+            self.item(item);
+            self.last_end = item.span.end;
+            previous = Some(item);
+        }
+        self.comments_until(usize::MAX, false);
+    }
+
+    fn item(&mut self, item: &Item) {
+        self.attributes(&item.attributes);
+        self.begin_line();
+        if item.public {
+            self.out.push_str("pub ");
+        }
+        match &item.kind {
+            ItemKind::Function(function) => self.function(function),
+            ItemKind::Global(binding) => {
+                self.let_binding(binding);
+                self.out.push('\n');
+            }
+            ItemKind::Const(constant) => {
+                self.out.push_str(&format!("const {}: {} = ", constant.name, print_type(&constant.ty)));
+                self.expr(&constant.value, 0, 1);
+                self.out.push_str(";\n");
+            }
+            ItemKind::Struct(definition) => self.struct_definition(definition, item.span.end),
+            ItemKind::Enum(definition) => self.enum_definition(definition, item.span.end),
+            ItemKind::Interface(definition) => self.interface(definition, item.span.end),
+            ItemKind::Impl(definition) => self.impl_block(definition, item.span.end),
+            ItemKind::Import(import) => {
+                self.out.push_str(&import_text(import));
+                self.out.push('\n');
+            }
+            ItemKind::Extern(function) => {
+                self.out.push_str(&extern_text(function));
+                self.out.push('\n');
+            }
+        }
+    }
+
+    /// Writes each attribute on a line of its own, with the comments before
+    /// and after them.
+    fn attributes(&mut self, attributes: &[Attribute]) {
+        for attribute in attributes {
+            self.comments_until(attribute.span.start, false);
+            self.begin_line();
+            self.out.push_str(&attribute_text(attribute));
+            self.out.push('\n');
+            self.last_end = attribute.span.end;
+        }
+        if let Some(last) = attributes.last() {
+            let start = self.token_start(last.span.end);
+            self.comments_until(start, false);
+        }
+    }
+
+    fn struct_definition(&mut self, definition: &Struct, end: usize) {
+        let fields = definition
+            .fields
+            .iter()
+            .map(|field| (field.span, format!("{}: {}", field.name, print_type(&field.ty))))
+            .collect();
+        let header = format!("struct {}{}", definition.name, type_params_text(&definition.type_params));
+        self.members(&header, fields, ",", end);
+    }
+
+    fn enum_definition(&mut self, definition: &Enum, end: usize) {
+        let variants = definition
+            .variants
+            .iter()
+            .map(|variant| {
+                let text = if variant.fields.is_empty() {
+                    variant.name.clone()
+                } else {
+                    let fields: Vec<String> = variant.fields.iter().map(print_type).collect();
+                    format!("{}({})", variant.name, fields.join(", "))
+                };
+                (variant.span, text)
+            })
+            .collect();
+        let header = format!("enum {}{}", definition.name, type_params_text(&definition.type_params));
+        self.members(&header, variants, ",", end);
+    }
+
+    fn interface(&mut self, definition: &Interface, end: usize) {
+        let methods = definition.methods.iter().map(|method| (method.span, method_sig_text(method))).collect();
+        self.members(&format!("interface {}", definition.name), methods, ";", end);
+    }
+
+    /// Writes `impl Interface for type {` and its methods, separated by
+    /// blank lines like top-level functions.
+    fn impl_block(&mut self, definition: &Impl, end: usize) {
+        self.out.push_str(&format!("impl {} for {}", definition.interface, print_type(&definition.ty)));
+        if definition.methods.is_empty() && !self.has_comment_before(end) {
+            self.out.push_str(" {}\n");
+            return;
+        }
+        self.out.push_str(" {\n");
+        self.indent += 1;
+        self.at_block_start = true;
+        for (index, method) in definition.methods.iter().enumerate() {
+            let force_blank = self.comments_until(method.span.start, index > 0);
+            self.separator(method.span.start, force_blank);
+            self.attributes(&method.attributes);
+            self.begin_line();
+            self.function(&method.function);
+            self.last_end = method.span.end;
+        }
+        self.comments_until(end.saturating_sub(1), false);
+        self.indent -= 1;
+        self.begin_line();
+        self.out.push_str("}\n");
+    }
+
+    /// Writes `header {` followed by one member per line, each ended by
+    /// `terminator`; `end` is the source offset just past the closing brace.
+    fn members(&mut self, header: &str, members: Vec<(Span, String)>, terminator: &str, end: usize) {
+        self.out.push_str(header);
+        if members.is_empty() && !self.has_comment_before(end) {
+            self.out.push_str(" {}\n");
+            return;
+        }
+        self.out.push_str(" {\n");
+        self.indent += 1;
+        self.at_block_start = true;
+        for (span, text) in members {
+            let force_blank = self.comments_until(span.start, false);
+            self.separator(span.start, force_blank);
+            self.begin_line();
+            self.out.push_str(&text);
+            self.out.push_str(terminator);
+            self.out.push('\n');
+            self.last_end = span.end;
+        }
+        self.comments_until(end.saturating_sub(1), false);
+        self.indent -= 1;
+        self.begin_line();
+        self.out.push_str("}\n");
+    }
+
+    fn function(&mut self, function: &Function) {
+        if function.constant {
+            self.out.push_str("const ");
+        }
+        self.out.push_str("function ");
+        self.out.push_str(&function.name);
+        self.out.push_str(&type_params_text(&function.type_params));
+        let params: Vec<String> = function.params.iter().map(param_text).collect();
+        let return_type = function
+            .return_type
+            .as_ref()
+            .map(|ty| format!(" -> {}", print_type(ty)))
+            .unwrap_or_default();
+        let flat = format!("({}){}", params.join(", "), return_type);
+        let has_comments = !params.is_empty() && self.has_comment_before(function.body.span.start);
+        if params.is_empty() || (!has_comments && self.fits(&flat, 2)) {
+            self.out.push_str(&flat);
+        } else {
+            self.out.push_str("(\n");
+            self.indent += 1;
+            self.at_block_start = true;
+            for (param, text) in function.params.iter().zip(&params) {
+                self.comments_until(param.span.start, false);
+                self.at_block_start = false;
+                self.begin_line();
+                self.out.push_str(text);
+                self.out.push_str(",\n");
+                self.last_end = param.span.end;
+            }
+            self.comments_until(function.body.span.start, false);
+            self.indent -= 1;
+            self.begin_line();
+            self.out.push(')');
+            self.out.push_str(&return_type);
+        }
+        self.block(&function.body, false);
+    }
+
+    /// Writes ` { ... }` after the header on the current line. When
+    /// `continues` is set the closing brace is left open for ` else`.
+    fn block(&mut self, block: &Block, continues: bool) {
+        if block.stmts.is_empty() && !self.has_comment_before(block.span.end) {
+            self.out.push_str(" {}");
+        } else {
+            self.out.push_str(" {\n");
+            self.last_end = block.span.start + 1;
+            self.indent += 1;
+            self.at_block_start = true;
+            for stmt in &block.stmts {
+                self.stmt(stmt);
+            }
+            self.comments_until(block.span.end.saturating_sub(1), false);
+            self.indent -= 1;
+            self.begin_line();
+            self.out.push('}');
+        }
+        self.last_end = block.span.end;
+        if !continues {
+            self.out.push('\n');
+        }
+    }
+
+    fn let_binding(&mut self, binding: &Let) {
+        self.out.push_str("let ");
+        self.out.push_str(&print_pattern(&binding.pattern));
+        if let Some(ty) = &binding.ty {
+            self.out.push_str(": ");
+            self.out.push_str(&print_type(ty));
+        }
+        self.out.push_str(" = ");
+        self.expr(&binding.value, 0, 1);
+        self.out.push(';');
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        let force_blank = self.comments_until(stmt.span.start, false);
+        self.separator(stmt.span.start, force_blank);
+        self.begin_line();
+        self.stmt_text(stmt);
+        self.last_end = self.last_end.max(stmt.span.end);
+    }
+
+    /// Writes `stmt` from the current position on its first line.
+    fn stmt_text(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Let(binding) => {
+                self.let_binding(binding);
+                self.out.push('\n');
+            }
+            StmtKind::Expr(expr @ Expr { kind: ExprKind::Match { .. }, .. }) => {
+                self.expr(expr, 0, 0);
+                self.out.push('\n');
+            }
+            StmtKind::Expr(expr) if starts_with_match(expr) => {
+                self.out.push('(');
+                self.expr(expr, 0, 2);
+                self.out.push_str(");\n");
+            }
+            StmtKind::Expr(expr) => {
+                self.expr(expr, 0, 1);
+                self.out.push_str(";\n");
+            }
+            StmtKind::Return(None) => self.out.push_str("return;\n"),
+            StmtKind::Return(Some(value)) => {
+                self.out.push_str("return ");
+                self.expr(value, 0, 1);
+                self.out.push_str(";\n");
+            }
+            StmtKind::If { .. } => self.if_chain(stmt),
+            StmtKind::For { init, condition, increment, body } => {
+                self.out.push_str("for (");
+                match init.as_deref() {
+                    Some(Stmt { kind: StmtKind::Let(binding), .. }) => self.let_binding(binding),
+                    Some(Stmt { kind: StmtKind::Expr(expr), .. }) => {
+                        self.expr(expr, 0, 1);
+                        self.out.push(';');
+                    }
+                    Some(other) => panic!("statement cannot appear in a for header: {:?}", other.kind),
+                    None => self.out.push(';'),
+                }
+                if let Some(condition) = condition {
+                    self.out.push(' ');
+                    self.expr(condition, 0, 1);
+                }
+                self.out.push(';');
+                if let Some(increment) = increment {
+                    self.out.push(' ');
+                    self.expr(increment, 0, 3);
+                }
+                self.out.push(')');
+                self.block(body, false);
+            }
+            StmtKind::While { condition, body } => {
+                self.out.push_str("while ");
+                self.condition(condition);
+                self.block(body, false);
+            }
+            StmtKind::Defer(deferred) => {
+                self.out.push_str("defer ");
+                self.stmt_text(deferred);
+            }
+        }
+    }
+
+    /// Writes an `if` and its `else` branches, folding an else block that only
+    /// holds another `if` into `else if`.
+    fn if_chain(&mut self, stmt: &Stmt) {
+        let StmtKind::If { condition, then_branch, else_branch } = &stmt.kind else {
+            unreachable!("if_chain called on a non-if statement");
+        };
+        self.out.push_str("if ");
+        self.condition(condition);
+        self.block(then_branch, else_branch.is_some());
+        if let Some(else_branch) = else_branch {
+            self.out.push_str(" else");
+            match else_branch.stmts.as_slice() {
+                [nested @ Stmt { kind: StmtKind::If { .. }, .. }] if !self.has_comment_before(nested.span.start) => {
+                    self.out.push(' ');
+                    self.if_chain(nested);
+                }
+                _ => self.block(else_branch, false),
+            }
+        }
+    }
+
+    /// Writes an `if` or `while` condition, keeping it parenthesised when a
+    /// struct literal would otherwise be read as the body.
+    fn condition(&mut self, condition: &Expr) {
+        if condition_needs_parens(condition) {
+            self.out.push('(');
+            self.expr(condition, 0, 3);
+            self.out.push(')');
+        } else {
+            self.expr(condition, 0, 2);
+        }
+    }
+
+    /// Writes `expr`, parenthesised if it binds looser than
+    /// `min_binding_power`. `reserve` is the width of the text that will
+    /// follow on the same line. Calls, array literals and tuples that do not
+    /// fit are split one element per line.
+    fn expr(&mut self, expr: &Expr, min_binding_power: u8, reserve: usize) {
+        let needs_parens = expr_binding_power(expr) < min_binding_power;
+        if !matches!(expr.kind, ExprKind::Match { .. }) && self.has_loose_comment(expr) {
+            if needs_parens {
+                self.out.push('(');
+            }
+            self.verbatim(expr);
+            if needs_parens {
+                self.out.push(')');
+            }
+            return;
+        }
+        let flat = if needs_parens {
+            format!("({})", print_expr(expr))
+        } else {
+            print_expr(expr)
+        };
+        if !flat.contains('\n') && self.fits(&flat, reserve) {
+            self.out.push_str(&flat);
+            return;
+        }
+        let reserve = if needs_parens { reserve + 1 } else { reserve };
+        if needs_parens {
+            self.out.push('(');
+        }
+        match &expr.kind {
+            ExprKind::Unary { op, operand } => {
+                self.out.push_str(op.symbol());
+                let binding_power = operator_info(&op.token()).and_then(|info| info.prefix).unwrap_or(0);
+                self.expr(operand, binding_power, reserve);
+            }
+            ExprKind::Binary { op, left, right } => self.infix(left, op.token(), op.symbol(), right, reserve),
+            ExprKind::Assign { target, value } => self.infix(target, Token::Equals, "=", value, reserve),
+            ExprKind::Call { callee, args } => {
+                let binding_power = operator_info(&Token::LParen).and_then(|info| info.postfix).unwrap_or(0);
+                self.expr(callee, binding_power, 0);
+                self.list('(', args, ')', reserve);
+            }
+            ExprKind::Index { base, index } => {
+                let binding_power = operator_info(&Token::LBracket).and_then(|info| info.postfix).unwrap_or(0);
+                self.expr(base, binding_power, 0);
+                self.out.push('[');
+                self.expr(index, 0, reserve + 1);
+                self.out.push(']');
+            }
+            ExprKind::Field { base, name } => {
+                let binding_power = operator_info(&Token::Dot).and_then(|info| info.postfix).unwrap_or(0);
+                self.expr(base, binding_power, 0);
+                self.out.push('.');
+                self.out.push_str(name);
+            }
+            ExprKind::TupleIndex { base, index } => {
+                let binding_power = operator_info(&Token::Dot).and_then(|info| info.postfix).unwrap_or(0);
+                self.expr(base, binding_power, 0);
+                self.out.push('.');
+                self.out.push_str(&index.to_string());
+            }
+            ExprKind::Try(operand) => {
+                let binding_power = operator_info(&Token::Question).and_then(|info| info.postfix).unwrap_or(0);
+                self.expr(operand, binding_power, reserve + 1);
+                self.out.push('?');
+            }
+            ExprKind::Cast { operand, ty } => {
+                let binding_power = operator_info(&Token::As).and_then(|info| info.postfix).unwrap_or(0);
+                let ty = print_type(ty);
+                self.expr(operand, binding_power, reserve + ty.len() + 4);
+                self.out.push_str(" as ");
+                self.out.push_str(&ty);
+            }
+            ExprKind::ArrayLiteral(elements) => self.list('[', elements, ']', reserve),
+            ExprKind::Tuple(elements) if elements.len() == 1 => {
+                self.out.push('(');
+                self.expr(&elements[0], 0, reserve + 2);
+                self.out.push_str(",)");
+            }
+            ExprKind::Tuple(elements) => self.list('(', elements, ')', reserve),
+            ExprKind::Match { scrutinee, arms } => self.match_expr(expr, scrutinee, arms),
+            ExprKind::Closure { params, return_type, body } => {
+                self.out.push_str(&format!("|{}|", closure_params_text(params)));
+                if let Some(return_type) = return_type {
+                    self.out.push_str(" -> ");
+                    self.out.push_str(&print_type(return_type));
+                }
+                match &**body {
+                    ArmBody::Expr(body) => {
+                        self.out.push(' ');
+                        self.expr(body, 0, reserve);
+                    }
+                    ArmBody::Block(block) => self.block(block, true),
+                }
+            }
+            ExprKind::StructLiteral { name, fields } => {
+                self.out.push_str(name);
+                self.out.push_str(" {\n");
+                self.indent += 1;
+                for init in fields {
+                    self.begin_line();
+                    self.out.push_str(&init.name);
+                    self.out.push_str(": ");
+                    self.expr(&init.value, 0, 1);
+                    self.out.push_str(",\n");
+                }
+                self.indent -= 1;
+                self.begin_line();
+                self.out.push('}');
+            }
+            ExprKind::Path(_)
+            | ExprKind::Identifier(_)
+            | ExprKind::IntLiteral(_)
+            | ExprKind::BoolLiteral(_)
+            | ExprKind::StringLiteral(_)
+            | ExprKind::Interpolation(_) => {
+                self.out.push_str(&print_expr(expr))
+            }
+        }
+        if needs_parens {
+            self.out.push(')');
+        }
+    }
+
+    fn infix(&mut self, left: &Expr, token: Token, symbol: &str, right: &Expr, reserve: usize) {
+        let (binding_power, associativity) = operator_info(&token).and_then(|info| info.infix).expect("infix operator");
+        let (left_min, right_min) = operand_binding_powers(binding_power, associativity);
+        self.expr(left, left_min, 0);
+        self.out.push(' ');
+        self.out.push_str(symbol);
+        self.out.push(' ');
+        self.expr(right, right_min, reserve);
+    }
+
+    /// Writes a `match` with one arm per line, keeping comments between
+    /// arms. The closing brace is left without a newline.
+    fn match_expr(&mut self, expr: &Expr, scrutinee: &Expr, arms: &[MatchArm]) {
+        self.out.push_str("match ");
+        self.condition(scrutinee);
+        self.out.push_str(" {\n");
+        self.last_end = scrutinee.span.end;
+        self.indent += 1;
+        self.at_block_start = true;
+        for arm in arms {
+            let force_blank = self.comments_until(arm.span.start, false);
+            self.separator(arm.span.start, force_blank);
+            self.begin_line();
+            self.out.push_str(&print_pattern(&arm.pattern));
+            if let Some(guard) = &arm.guard {
+                self.out.push_str(" if ");
+                self.expr(guard, 0, 4);
+            }
+            self.out.push_str(" =>");
+            match &arm.body {
+                ArmBody::Expr(body) => {
+                    self.out.push(' ');
+                    self.expr(body, 0, 1);
+                    self.out.push_str(",\n");
+                }
+                ArmBody::Block(block) => self.block(block, false),
+            }
+            self.last_end = arm.span.end;
+        }
+        self.comments_until(expr.span.end.saturating_sub(1), false);
+        self.indent -= 1;
+        self.begin_line();
+        self.out.push('}');
+        self.last_end = expr.span.end;
+    }
+
+    fn list(&mut self, open: char, exprs: &[Expr], close: char, reserve: usize) {
+        let flat = format!("{}{}{}", open, exprs.iter().map(print_expr).collect::<Vec<_>>().join(", "), close);
+        if exprs.is_empty() || (!flat.contains('\n') && self.fits(&flat, reserve)) {
+            self.out.push_str(&flat);
+            return;
+        }
+        self.out.push(open);
+        self.out.push('\n');
+        self.indent += 1;
+        for expr in exprs {
+            self.begin_line();
+            self.expr(expr, 0, 1);
+            self.out.push_str(",\n");
+        }
+        self.indent -= 1;
+        self.begin_line();
+        self.out.push(close);
+    }
+}
+
+/// Collects the spans of the blocks and `match` expressions under an
+/// expression, without descending into them.
+struct CommentScopes(Vec<Span>);
+
+impl Visitor for CommentScopes {
+    fn visit_block(&mut self, block: &Block) {
+        self.0.push(block.span);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr.kind {
+            ExprKind::Match { .. } => self.0.push(expr.span),
+            _ => walk_expr(self, expr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CORPUS: &[&str] = &[
+        "let x: int = 10; function main() { let y: int = 20; return y + x; if (true) { return false; } else { return true; } for (let i: int = 0; i < 10; i = i + 1) { while (i < 5) { i = i + 1; } } }",
+        "function h(n: int) -> bool { if n < 0 { return false; } else if n == 0 { return true; } else { return h(n - 1); } }",
+        "// leading\nfunction f() { // after brace\n    let a = 1; // trailing\n\n\n    /* block */ a = a + 1;\n    // before close\n}\n// at end\n",
+        "function call() { result = some_function_name(first_argument + 1, second_argument * 2, third_argument - 3, fourth_argument); }",
+        "function outer() { return first_function_name(inner_function_name(aaaaaaaaaa, bbbbbbbbbb, cccccccccc), second_argument_expression, 42); }",
+        "function long_parameter_list(first_parameter: int, second_parameter: int, third_parameter: bool, fourth: int) -> int { return 0; }",
+        "let a = 1;\nlet b = 2;\n\nlet c = 3;\nfunction empty() { /* todo */ }",
+        "struct Point { x: int, // across\n y: int }\nstruct Empty {}\nfunction f() { if (p == Point { x: 1, y: 2 }) { let moved = Point { x: first_coordinate_value_here + offset, y: second_coordinate_value_here + offset }; } }",
+        "let table: int[6] = [first_value_in_table, second_value_in_table, third_value_in_table, fourth_value];",
+        "enum Shape { Circle(int), Rect(int, int), Empty }\nfunction area(s: Shape) -> int { match s { Shape::Circle(r) => 3 * r * r, // approx\n Shape::Rect(w, h) if w > 0 => { return w * h; } _ => 0 } let n = match s { Shape::Empty => 0, _ => 1 }; }",
+        "function run(n: int) { let add = |x| x + n; apply(|x: int| -> int { return x * n; // scale\n }, 1); let f: fn() = || {}; }",
+        "struct Pair<A, B> { first: A, // left\n second: B }\nfunction swap<A, B>(pair_to_swap: Pair<A, B>, unused_parameter: Pair<Pair<int, bool>, int>) -> Pair<B, A> { return Pair { first: pair_to_swap.second, second: pair_to_swap.first }; }",
+        "interface Show { function show(self) -> int; // text\n function pad(self, width: int) -> Self; }\ninterface Marker {}\nimpl Show for Point { // first\n function show(self) -> int { return self.x; } @inline // hot\n function pad(self, width: int) -> Point { return self; } }\nfunction all<T: Show + Marker>(item: T, shown: dyn Show) -> int { return item.show() + shown.show(); }",
+        "import math;\npub let scale = 2;\npub   struct Point { x: int }\npub function f() {}",
+        "const N: int = 4;\nconst   M: int=N*2;\nlet grid: int[N][M] = [];",
+        "function f(p: ptr) { defer   free(p); // last\n defer if p == p { free(p); } for (;;) { defer match p { _ => 0 } defer (g()); } }",
+        "const   function squares()->int[4] { let t = [0, 0, 0, 0]; for (let i = 0; i < 4; i = i + 1) { t[i] = i * i; } return t; }\npub const function id(n: int) -> int { return n; }\nconst T: int[4] = squares();",
+        "function count(mut n: int, (mut a, b): (int, int)) { let  mut total = 0; for (let mut i = 0; i < n; i = i + 1) { total = total + i; } }",
+        "function bits(x: u8, y: i64) -> u64 { return (x as u64 << 3) + (y>>2) as u64 - (-(y as i16) as u64); }\nlet p: Pair<Pair<u8, i16>> = 1;",
+        "function greet(name: string) -> string { return \"// {name} /* {{not}} a comment */\"+\"\\\"{name.len() * 2}\\\"\\n\"; }",
+        "@inline // hot\n// called often\n@export( \"c_f\" ) pub  function f() {}\n@deprecated struct S {}",
+        "extern \"C\"   function puts(s:ptr)->int;\n// from libc\n@deprecated extern \"C\" function printf(f: ptr,...) -> int;\nfunction main() { puts(\"hi\".as_ptr()); }",
+        "enum Result<T,  E> { Ok(T), // value\n Err(E) }\nfunction f(r: Result<int, bool>) -> Result<int, bool> { return Result::Ok(-r? + g(r)?.x); }",
+        "function main() {\n    let x = f(1, // c\n        2) + /* mid */ 3; let y = (a /* in */ + b).len();\n}",
+        "function g(a: int, // first\n /* second */ b: int) -> int { return a; }",
+    ];
+
+    #[test]
+    fn formatting_is_idempotent() {
+        for source in CORPUS {
+            let formatted = format_source(source);
+            assert_eq!(format_source(&formatted), formatted, "not idempotent for:\n{}", formatted);
+            assert!(is_formatted(&formatted));
+        }
+    }
+
+    #[test]
+    fn normalises_indentation_and_spacing() {
+        let source = "function   f( a:int,b :int )->int{\nlet c=a*( b+1 );\n        if(c>1){return c;}else{return -c;}\n}";
+        assert_eq!(
+            format_source(source),
+            "function f(a: int, b: int) -> int {\n\
+             \x20   let c = a * (b + 1);\n\
+             \x20   if c > 1 {\n\
+             \x20       return c;\n\
+             \x20   } else {\n\
+             \x20       return -c;\n\
+             \x20   }\n\
+             }\n"
+        );
+        assert!(!is_formatted(source));
+    }
+
+    #[test]
+    fn preserves_comments_and_single_blank_lines() {
+        assert_eq!(
+            format_source(CORPUS[2]),
+            "// leading\n\
+             function f() { // after brace\n\
+             \x20   let a = 1; // trailing\n\
+             \n\
+             \x20   /* block */\n\
+             \x20   a = a + 1;\n\
+             \x20   // before close\n\
+             }\n\
+             // at end\n"
+        );
+        assert_eq!(
+            format_source(CORPUS[6]),
+            "let a = 1;\nlet b = 2;\n\nlet c = 3;\n\nfunction empty() { /* todo */\n}\n"
+        );
+    }
+
+    #[test]
+    fn keeps_comments_inside_expressions_and_parameter_lists() {
+        assert_eq!(
+            format_source(CORPUS[23]),
+            "function main() {\n\
+             \x20   let x = f(1, // c\n\
+             \x20       2) + /* mid */ 3;\n\
+             \x20   let y = (a /* in */ + b).len();\n\
+             }\n"
+        );
+        assert_eq!(
+            format_source(CORPUS[24]),
+            "function g(\n\
+             \x20   a: int, // first\n\
+             \x20   /* second */\n\
+             \x20   b: int,\n\
+             ) -> int {\n\
+             \x20   return a;\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn reports_syntax_errors_instead_of_panicking() {
+        let error = try_format_source("function f( {\n").unwrap_err();
+        assert_eq!(error.message, "Expected pattern, found LBrace");
+        assert_eq!(error.span, Span::new(12, 13));
+        assert_eq!(try_format_source("let a  =  1;").unwrap(), "let a = 1;\n");
+    }
+
+    #[test]
+    fn lays_out_structs_one_field_per_line() {
+        assert_eq!(
+            format_source(CORPUS[7]),
+            "struct Point {\n\
+             \x20   x: int, // across\n\
+             \x20   y: int,\n\
+             }\n\
+             \n\
+             struct Empty {}\n\
+             \n\
+             function f() {\n\
+             \x20   if (p == Point { x: 1, y: 2 }) {\n\
+             \x20       let moved = Point {\n\
+             \x20           x: first_coordinate_value_here + offset,\n\
+             \x20           y: second_coordinate_value_here + offset,\n\
+             \x20       };\n\
+             \x20   }\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn lays_out_enums_and_match_arms_one_per_line() {
+        assert_eq!(
+            format_source(CORPUS[9]),
+            "enum Shape {\n\
+             \x20   Circle(int),\n\
+             \x20   Rect(int, int),\n\
+             \x20   Empty,\n\
+             }\n\
+             \n\
+             function area(s: Shape) -> int {\n\
+             \x20   match s {\n\
+             \x20       Shape::Circle(r) => 3 * r * r, // approx\n\
+             \x20       Shape::Rect(w, h) if w > 0 => {\n\
+             \x20           return w * h;\n\
+             \x20       }\n\
+             \x20       _ => 0,\n\
+             \x20   }\n\
+             \x20   let n = match s {\n\
+             \x20       Shape::Empty => 0,\n\
+             \x20       _ => 1,\n\
+             \x20   };\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn splits_lists_around_closure_blocks() {
+        assert_eq!(
+            format_source(CORPUS[10]),
+            "function run(n: int) {\n\
+             \x20   let add = |x| x + n;\n\
+             \x20   apply(\n\
+             \x20       |x: int| -> int {\n\
+             \x20           return x * n; // scale\n\
+             \x20       },\n\
+             \x20       1,\n\
+             \x20   );\n\
+             \x20   let f: fn() = || {};\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn lays_out_interfaces_and_impls_one_method_per_line() {
+        assert_eq!(
+            format_source(CORPUS[12]),
+            "interface Show {\n\
+             \x20   function show(self) -> int; // text\n\
+             \x20   function pad(self, width: int) -> Self;\n\
+             }\n\
+             \n\
+             interface Marker {}\n\
+             \n\
+             impl Show for Point {\n\
+             \x20   // first\n\
+             \x20   function show(self) -> int {\n\
+             \x20       return self.x;\n\
+             \x20   }\n\
+             \n\
+             \x20   @inline // hot\n\
+             \x20   function pad(self, width: int) -> Point {\n\
+             \x20       return self;\n\
+             \x20   }\n\
+             }\n\
+             \n\
+             function all<T: Show + Marker>(item: T, shown: dyn Show) -> int {\n\
+             \x20   return item.show() + shown.show();\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn wraps_long_argument_parameter_and_element_lists() {
+        assert_eq!(
+            format_source(CORPUS[3]),
+            "function call() {\n\
+             \x20   result = some_function_name(\n\
+             \x20       first_argument + 1,\n\
+             \x20       second_argument * 2,\n\
+             \x20       third_argument - 3,\n\
+             \x20       fourth_argument,\n\
+             \x20   );\n\
+             }\n"
+        );
+        assert_eq!(
+            format_source(CORPUS[5]),
+            "function long_parameter_list(\n\
+             \x20   first_parameter: int,\n\
+             \x20   second_parameter: int,\n\
+             \x20   third_parameter: bool,\n\
+             \x20   fourth: int,\n\
+             ) -> int {\n\
+             \x20   return 0;\n\
+             }\n"
+        );
+        assert_eq!(
+            format_source(CORPUS[8]),
+            "let table: int[6] = [\n\
+             \x20   first_value_in_table,\n\
+             \x20   second_value_in_table,\n\
+             \x20   third_value_in_table,\n\
+             \x20   fourth_value,\n\
+             ];\n"
+        );
+        for line in format_source(CORPUS[4]).lines() {
+            assert!(line.len() <= MAX_WIDTH, "line too long: {}", line);
+        }
+    }
+}
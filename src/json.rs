@@ -0,0 +1,377 @@
+//! A minimal JSON value type with a reader and a compact writer, enough for
+//! the AST and token dumps in `dump`. Numbers are limited to integers, which
+//! is all the dumps contain.
+
+use std::fmt;
+
+/// How deeply arrays and objects may nest before the reader gives up, so
+/// that hostile input is an error rather than a stack overflow.
+pub const MAX_DEPTH: usize = 512;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(i128),
+    String(String),
+    Array(Vec<Value>),
+    /// Fields in insertion order, so output is stable.
+    Object(Vec<(String, Value)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonError {
+    pub message: String,
+}
+
+impl JsonError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self { message: message.into() }
+    }
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+impl Value {
+    pub fn object(fields: Vec<(&str, Value)>) -> Value {
+        Value::Object(fields.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+
+    pub fn get(&self, key: &str) -> Result<&Value, JsonError> {
+        match self {
+            Value::Object(fields) => fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value)
+                .ok_or_else(|| JsonError::new(format!("missing field '{}'", key))),
+            _ => Err(JsonError::new(format!("expected an object with field '{}'", key))),
+        }
+    }
+
+    pub fn as_str(&self) -> Result<&str, JsonError> {
+        match self {
+            Value::String(value) => Ok(value),
+            other => Err(JsonError::new(format!("expected a string, found {}", other))),
+        }
+    }
+
+    pub fn as_i128(&self) -> Result<i128, JsonError> {
+        match self {
+            Value::Number(value) => Ok(*value),
+            other => Err(JsonError::new(format!("expected a number, found {}", other))),
+        }
+    }
+
+    pub fn as_i64(&self) -> Result<i64, JsonError> {
+        let value = self.as_i128()?;
+        i64::try_from(value).map_err(|_| JsonError::new(format!("number {} is out of range", value)))
+    }
+
+    pub fn as_bool(&self) -> Result<bool, JsonError> {
+        match self {
+            Value::Bool(value) => Ok(*value),
+            other => Err(JsonError::new(format!("expected a boolean, found {}", other))),
+        }
+    }
+
+    pub fn as_array(&self) -> Result<&[Value], JsonError> {
+        match self {
+            Value::Array(values) => Ok(values),
+            other => Err(JsonError::new(format!("expected an array, found {}", other))),
+        }
+    }
+
+    /// `None` for JSON `null`, otherwise the value itself.
+    pub fn as_option(&self) -> Option<&Value> {
+        match self {
+            Value::Null => None,
+            value => Some(value),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Number(value) => write!(f, "{}", value),
+            Value::String(value) => write_string(f, value),
+            Value::Array(values) => {
+                write!(f, "[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Value::Object(fields) => {
+                write!(f, "{{")?;
+                for (index, (key, value)) in fields.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+pub fn parse(text: &str) -> Result<Value, JsonError> {
+    let mut reader = Reader {
+        chars: text.chars().collect(),
+        position: 0,
+        depth: 0,
+    };
+    let value = reader.value()?;
+    reader.skip_whitespace();
+    if reader.position != reader.chars.len() {
+        return Err(reader.error("trailing characters after JSON value"));
+    }
+    Ok(value)
+}
+
+struct Reader {
+    chars: Vec<char>,
+    position: usize,
+    /// Arrays and objects open around the current position.
+    depth: usize,
+}
+
+impl Reader {
+    fn error(&self, message: &str) -> JsonError {
+        JsonError::new(format!("{} at offset {}", message, self.position))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).cloned()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), JsonError> {
+        self.skip_whitespace();
+        if self.peek() == Some(expected) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", expected)))
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Value) -> Result<Value, JsonError> {
+        let end = self.position + word.len();
+        if end <= self.chars.len() && self.chars[self.position..end].iter().copied().eq(word.chars()) {
+            self.position = end;
+            Ok(value)
+        } else {
+            Err(self.error("invalid literal"))
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, JsonError> {
+        self.skip_whitespace();
+        if matches!(self.peek(), Some('[' | '{')) {
+            if self.depth == MAX_DEPTH {
+                return Err(self.error(&format!("nesting deeper than {} levels", MAX_DEPTH)));
+            }
+            self.depth += 1;
+            let value = self.nested();
+            self.depth -= 1;
+            return value;
+        }
+        match self.peek() {
+            Some('n') => self.keyword("null", Value::Null),
+            Some('t') => self.keyword("true", Value::Bool(true)),
+            Some('f') => self.keyword("false", Value::Bool(false)),
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            _ => Err(self.error("expected a JSON value")),
+        }
+    }
+
+    /// Reads the array or object at the current position.
+    fn nested(&mut self) -> Result<Value, JsonError> {
+        match self.peek() {
+            Some('[') => {
+                self.position += 1;
+                let mut values = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(']') {
+                    self.position += 1;
+                    return Ok(Value::Array(values));
+                }
+                loop {
+                    values.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(',') => self.position += 1,
+                        Some(']') => {
+                            self.position += 1;
+                            return Ok(Value::Array(values));
+                        }
+                        _ => return Err(self.error("expected ',' or ']'")),
+                    }
+                }
+            }
+            Some('{') => {
+                self.position += 1;
+                let mut fields = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some('}') {
+                    self.position += 1;
+                    return Ok(Value::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.expect(':')?;
+                    fields.push((key, self.value()?));
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(',') => self.position += 1,
+                        Some('}') => {
+                            self.position += 1;
+                            return Ok(Value::Object(fields));
+                        }
+                        _ => return Err(self.error("expected ',' or '}'")),
+                    }
+                }
+            }
+            _ => unreachable!("nested called away from '[' or '{{'"),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, JsonError> {
+        let start = self.position;
+        if self.peek() == Some('-') {
+            self.position += 1;
+        }
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.position += 1;
+        }
+        if matches!(self.peek(), Some('.' | 'e' | 'E')) {
+            return Err(self.error("only integer numbers are supported"));
+        }
+        let text: String = self.chars[start..self.position].iter().collect();
+        text.parse().map(Value::Number).map_err(|_| self.error("invalid number"))
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        if self.peek() != Some('"') {
+            return Err(self.error("expected a string"));
+        }
+        self.position += 1;
+        let mut out = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(self.error("unterminated string"));
+            };
+            self.position += 1;
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let Some(escape) = self.peek() else {
+                        return Err(self.error("unterminated escape"));
+                    };
+                    self.position += 1;
+                    match escape {
+                        '"' => out.push('"'),
+                        '\\' => out.push('\\'),
+                        '/' => out.push('/'),
+                        'n' => out.push('\n'),
+                        'r' => out.push('\r'),
+                        't' => out.push('\t'),
+                        'b' => out.push('\u{8}'),
+                        'f' => out.push('\u{c}'),
+                        'u' => {
+                            let mut code = self.hex_escape()?;
+                            // A character outside the Basic Multilingual Plane is
+                            // written as a UTF-16 surrogate pair of two escapes.
+                            if (0xd800..0xdc00).contains(&code) {
+                                let low = match (self.peek(), self.chars.get(self.position + 1)) {
+                                    (Some('\\'), Some('u')) => {
+                                        self.position += 2;
+                                        self.hex_escape()?
+                                    }
+                                    _ => return Err(self.error("unpaired surrogate in \\u escape")),
+                                };
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    return Err(self.error("unpaired surrogate in \\u escape"));
+                                }
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                            }
+                            out.push(char::from_u32(code).ok_or_else(|| self.error("invalid \\u escape"))?);
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+    }
+
+    /// Reads the four hex digits after `\u`.
+    fn hex_escape(&mut self) -> Result<u32, JsonError> {
+        let end = self.position + 4;
+        if end > self.chars.len() {
+            return Err(self.error("truncated \\u escape"));
+        }
+        let hex: String = self.chars[self.position..end].iter().collect();
+        let code = u32::from_str_radix(&hex, 16).map_err(|_| self.error("invalid \\u escape"))?;
+        self.position = end;
+        Ok(code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combines_surrogate_pairs() {
+        assert_eq!(parse(r#""\ud83d\ude00 \u00e9""#), Ok(Value::String("\u{1f600} \u{e9}".to_string())));
+        assert!(parse(r#""\ud83d""#).unwrap_err().message.contains("unpaired surrogate"));
+        assert!(parse(r#""\ud83d\u0041""#).unwrap_err().message.contains("unpaired surrogate"));
+        assert!(parse(r#""\ude00""#).unwrap_err().message.contains("invalid \\u escape"));
+    }
+
+    #[test]
+    fn limits_nesting_depth() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(parse(&nested(MAX_DEPTH + 1)).unwrap_err().message.contains("nesting deeper than 512 levels"));
+        assert!(parse(&"{\"a\": ".repeat(100_000)).unwrap_err().message.contains("nesting deeper"));
+    }
+}
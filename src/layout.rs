@@ -0,0 +1,537 @@
+//! Memory layout of CopilotLang types for the MLIR/LLVM lowering. It is
+//! part of the front end so that it is built and tested without the MLIR
+//! and LLVM libraries.
+//!
+//! Structs and tuples use C layout: fields are laid out in declaration order,
+//! each at the next offset that satisfies its alignment, and the total size is
+//! rounded up to the largest alignment. This matches what LLVM assumes for a
+//! non-packed struct type, so offsets computed here can be used directly for
+//! field GEPs.
+//!
+//! Generic structs have no layout of their own; `monomorphize` turns every
+//! use into a concrete struct first, and only those are laid out. It also
+//! replaces array sizes that name constants by their values.
+//!
+//! Enums are a tagged union: an `int` tag holding the variant index at offset
+//! 0, followed by a payload area big enough for the largest variant. Each
+//! variant's payload fields are laid out like the fields of a struct starting
+//! at the payload offset.
+//!
+//! A function value is a closure: a code pointer followed by a pointer to the
+//! closure's environment, which is null for named functions. The environment
+//! is a struct of the captured variables, where a variable captured by
+//! reference is stored as a pointer to its heap cell.
+//!
+//! A `dyn` value is a pointer to the value followed by a pointer to the
+//! vtable for its type and interface. A vtable holds one code pointer per
+//! method of the interface, in declaration order.
+
+use crate::captures::{Capture, CaptureMode};
+use crate::lexer::IntType;
+use crate::parser::{ArraySize, Enum, Interface, ItemKind, Program, Struct, Type, TypeKind};
+use crate::printer::print_type;
+use crate::typeck::{Ty, TypeTable};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub enum LayoutError {
+    UnknownType(String),
+    DuplicateType(String),
+    DuplicateField { name: String, field: String },
+    RecursiveType(String),
+    UnevaluatedSize(String),
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutError::UnknownType(name) => write!(f, "unknown type `{}`", name),
+            LayoutError::DuplicateType(name) => write!(f, "type `{}` is defined more than once", name),
+            LayoutError::DuplicateField { name, field } => write!(f, "field `{}` is declared more than once in struct `{}`", field, name),
+            LayoutError::RecursiveType(name) => write!(f, "type `{}` contains itself and would have infinite size", name),
+            LayoutError::UnevaluatedSize(name) => write!(f, "array size `{}` has not been evaluated", name),
+        }
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    pub size: u64,
+    pub align: u64,
+}
+
+impl Layout {
+    /// Size rounded up to alignment: the distance between consecutive
+    /// elements of an array of this type.
+    pub fn stride(self) -> u64 {
+        align_to(self.size, self.align)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructLayout {
+    pub layout: Layout,
+    /// Field names and byte offsets in declaration order.
+    pub fields: Vec<(String, u64)>,
+}
+
+impl StructLayout {
+    pub fn field_offset(&self, name: &str) -> Option<u64> {
+        self.fields.iter().find(|(field, _)| field == name).map(|(_, offset)| *offset)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumLayout {
+    pub layout: Layout,
+    /// Offset of the payload area, after the tag.
+    pub payload_offset: u64,
+    /// Variant names and the absolute byte offsets of their payload fields,
+    /// in declaration order; the position of a variant is its tag.
+    pub variants: Vec<(String, Vec<u64>)>,
+}
+
+impl EnumLayout {
+    pub fn tag(&self, variant: &str) -> Option<usize> {
+        self.variants.iter().position(|(name, _)| name == variant)
+    }
+}
+
+/// Layouts of every struct and enum declared in a program.
+#[derive(Debug, Default)]
+pub struct LayoutTable {
+    structs: HashMap<String, StructLayout>,
+    enums: HashMap<String, EnumLayout>,
+}
+
+const BOOL: Layout = Layout { size: 1, align: 1 };
+const VOID: Layout = Layout { size: 0, align: 1 };
+const TAG: Layout = Layout { size: 4, align: 4 };
+const POINTER: Layout = Layout { size: 8, align: 8 };
+/// `{ code pointer, environment pointer }`.
+const CLOSURE: Layout = Layout { size: 16, align: 8 };
+/// `{ data pointer, vtable pointer }`.
+const TRAIT_OBJECT: Layout = Layout { size: 16, align: 8 };
+/// `{ byte pointer, length }`; the bytes are never written once created.
+const STRING: Layout = Layout { size: 16, align: 8 };
+
+impl LayoutTable {
+    pub fn compute(program: &Program) -> Result<Self, LayoutError> {
+        let mut definitions = HashMap::new();
+        for item in &program.items {
+            let definition = match &item.kind {
+                ItemKind::Struct(definition) if definition.type_params.is_empty() => Definition::Struct(definition),
+                ItemKind::Enum(definition) if definition.type_params.is_empty() => Definition::Enum(definition),
+                _ => continue,
+            };
+            if definitions.insert(definition.name(), definition).is_some() {
+                return Err(LayoutError::DuplicateType(definition.name().to_string()));
+            }
+        }
+        let mut builder = Builder {
+            definitions,
+            table: LayoutTable::default(),
+            in_progress: Vec::new(),
+        };
+        for item in &program.items {
+            match &item.kind {
+                ItemKind::Struct(definition) if definition.type_params.is_empty() => builder.layout(Definition::Struct(definition))?,
+                ItemKind::Enum(definition) if definition.type_params.is_empty() => builder.layout(Definition::Enum(definition))?,
+                _ => continue,
+            };
+        }
+        Ok(builder.table)
+    }
+
+    pub fn struct_layout(&self, name: &str) -> Option<&StructLayout> {
+        self.structs.get(name)
+    }
+
+    pub fn enum_layout(&self, name: &str) -> Option<&EnumLayout> {
+        self.enums.get(name)
+    }
+
+    pub fn type_layout(&self, ty: &Type) -> Result<Layout, LayoutError> {
+        match &ty.kind {
+            TypeKind::Int(ty) => Ok(int_layout(*ty)),
+            TypeKind::Bool => Ok(BOOL),
+            TypeKind::String => Ok(STRING),
+            TypeKind::Ptr => Ok(POINTER),
+            TypeKind::Void => Ok(VOID),
+            TypeKind::Array { element, size } => Ok(array(self.type_layout(element)?, array_size(size)?)),
+            TypeKind::Tuple(elements) => {
+                let elements = elements.iter().map(|element| self.type_layout(element)).collect::<Result<Vec<_>, _>>()?;
+                Ok(sequence(&elements).0)
+            }
+            TypeKind::Function { .. } => Ok(CLOSURE),
+            TypeKind::Dyn(_) => Ok(TRAIT_OBJECT),
+            TypeKind::Named { name, args } if args.is_empty() => self.named_layout(name),
+            TypeKind::Named { .. } => Err(LayoutError::UnknownType(print_type(ty))),
+        }
+    }
+
+    /// Layout of a checked type, as recorded in the `TypeTable`.
+    pub fn ty_layout(&self, ty: &Ty) -> Result<Layout, LayoutError> {
+        match ty {
+            Ty::Int(ty) => Ok(int_layout(*ty)),
+            Ty::Bool => Ok(BOOL),
+            Ty::String => Ok(STRING),
+            Ty::Ptr => Ok(POINTER),
+            Ty::Void => Ok(VOID),
+            Ty::Array(element, size) => Ok(array(self.ty_layout(element)?, *size)),
+            Ty::Tuple(elements) => Ok(self.tuple_layout(elements)?.layout),
+            Ty::Function(..) => Ok(CLOSURE),
+            Ty::Dyn(_) => Ok(TRAIT_OBJECT),
+            Ty::Struct(name, args) | Ty::Enum(name, args) if args.is_empty() => self.named_layout(name),
+            Ty::Struct(..) | Ty::Enum(..) | Ty::Param(_) | Ty::Error | Ty::Infer => Err(LayoutError::UnknownType(ty.to_string())),
+        }
+    }
+
+    /// Layout of a tuple type; its fields are named by their index.
+    pub fn tuple_layout(&self, elements: &[Ty]) -> Result<StructLayout, LayoutError> {
+        let elements = elements.iter().map(|element| self.ty_layout(element)).collect::<Result<Vec<_>, _>>()?;
+        let (layout, offsets) = sequence(&elements);
+        Ok(StructLayout {
+            layout,
+            fields: offsets.into_iter().enumerate().map(|(index, offset)| (index.to_string(), offset)).collect(),
+        })
+    }
+
+    /// Layout of a closure's environment; its fields are named after the
+    /// captured variables, whose types are looked up by binding.
+    pub fn environment_layout(&self, captures: &[Capture], types: &TypeTable) -> Result<StructLayout, LayoutError> {
+        let fields = captures
+            .iter()
+            .map(|capture| match capture.mode {
+                CaptureMode::ByValue => self.ty_layout(types.type_of(capture.binding).unwrap_or(&Ty::Error)),
+                CaptureMode::ByReference => Ok(POINTER),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let (layout, offsets) = sequence(&fields);
+        Ok(StructLayout {
+            layout,
+            fields: captures.iter().map(|capture| capture.name.clone()).zip(offsets).collect(),
+        })
+    }
+
+    /// Layout of the vtables of an interface; its fields are named after the
+    /// methods.
+    pub fn vtable_layout(&self, interface: &Interface) -> StructLayout {
+        let (layout, offsets) = sequence(&vec![POINTER; interface.methods.len()]);
+        StructLayout {
+            layout,
+            fields: interface.methods.iter().map(|method| method.name.clone()).zip(offsets).collect(),
+        }
+    }
+
+    fn named_layout(&self, name: &str) -> Result<Layout, LayoutError> {
+        self.structs
+            .get(name)
+            .map(|layout| layout.layout)
+            .or_else(|| self.enums.get(name).map(|layout| layout.layout))
+            .ok_or_else(|| LayoutError::UnknownType(name.to_string()))
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Definition<'a> {
+    Struct(&'a Struct),
+    Enum(&'a Enum),
+}
+
+impl<'a> Definition<'a> {
+    fn name(self) -> &'a str {
+        match self {
+            Definition::Struct(definition) => &definition.name,
+            Definition::Enum(definition) => &definition.name,
+        }
+    }
+}
+
+struct Builder<'a> {
+    definitions: HashMap<&'a str, Definition<'a>>,
+    table: LayoutTable,
+    /// Types whose layout is being computed, to detect by-value cycles.
+    in_progress: Vec<&'a str>,
+}
+
+impl<'a> Builder<'a> {
+    fn layout(&mut self, definition: Definition<'a>) -> Result<Layout, LayoutError> {
+        let name = definition.name();
+        if let Some(done) = self.table.structs.get(name) {
+            return Ok(done.layout);
+        }
+        if let Some(done) = self.table.enums.get(name) {
+            return Ok(done.layout);
+        }
+        if self.in_progress.contains(&name) {
+            return Err(LayoutError::RecursiveType(name.to_string()));
+        }
+        self.in_progress.push(name);
+        let layout = match definition {
+            Definition::Struct(definition) => self.struct_layout(definition)?,
+            Definition::Enum(definition) => self.enum_layout(definition)?,
+        };
+        self.in_progress.pop();
+        Ok(layout)
+    }
+
+    fn struct_layout(&mut self, definition: &'a Struct) -> Result<Layout, LayoutError> {
+        let mut layouts = Vec::new();
+        for (index, field) in definition.fields.iter().enumerate() {
+            if definition.fields[..index].iter().any(|earlier| earlier.name == field.name) {
+                return Err(LayoutError::DuplicateField {
+                    name: definition.name.clone(),
+                    field: field.name.clone(),
+                });
+            }
+            layouts.push(self.type_layout(&field.ty)?);
+        }
+
+        let (layout, offsets) = sequence(&layouts);
+        let fields = definition.fields.iter().map(|field| field.name.clone()).zip(offsets).collect();
+        self.table.structs.insert(definition.name.clone(), StructLayout { layout, fields });
+        Ok(layout)
+    }
+
+    fn enum_layout(&mut self, definition: &'a Enum) -> Result<Layout, LayoutError> {
+        // The payload area starts at the first offset after the tag that
+        // suits every payload field, so it is shared by all variants.
+        let mut payloads = Vec::new();
+        let mut payload_align = 1;
+        for variant in &definition.variants {
+            if payloads.iter().any(|(name, _)| *name == variant.name) {
+                return Err(LayoutError::DuplicateField {
+                    name: definition.name.clone(),
+                    field: variant.name.clone(),
+                });
+            }
+            let fields = variant.fields.iter().map(|ty| self.type_layout(ty)).collect::<Result<Vec<_>, _>>()?;
+            payload_align = fields.iter().fold(payload_align, |align, field| align.max(field.align));
+            payloads.push((variant.name.clone(), fields));
+        }
+
+        // Each payload is laid out like a tuple placed at the payload offset,
+        // which is a multiple of every field's alignment.
+        let payload_offset = align_to(TAG.size, payload_align);
+        let mut end = payload_offset;
+        let variants = payloads
+            .into_iter()
+            .map(|(name, fields)| {
+                let (layout, offsets) = sequence(&fields);
+                end = end.max(payload_offset + layout.size);
+                (name, offsets.into_iter().map(|offset| payload_offset + offset).collect())
+            })
+            .collect();
+
+        let align = TAG.align.max(payload_align);
+        let layout = Layout {
+            size: align_to(end, align),
+            align,
+        };
+        self.table.enums.insert(
+            definition.name.clone(),
+            EnumLayout {
+                layout,
+                payload_offset,
+                variants,
+            },
+        );
+        Ok(layout)
+    }
+
+    /// Like `LayoutTable::type_layout`, but lays out named types on demand so
+    /// declaration order does not matter.
+    fn type_layout(&mut self, ty: &'a Type) -> Result<Layout, LayoutError> {
+        match &ty.kind {
+            TypeKind::Named { name, args } if args.is_empty() => match self.definitions.get(name.as_str()) {
+                Some(definition) => self.layout(*definition),
+                None => Err(LayoutError::UnknownType(name.clone())),
+            },
+            TypeKind::Array { element, size } => Ok(array(self.type_layout(element)?, array_size(size)?)),
+            TypeKind::Tuple(elements) => {
+                let elements = elements.iter().map(|element| self.type_layout(element)).collect::<Result<Vec<_>, _>>()?;
+                Ok(sequence(&elements).0)
+            }
+            _ => self.table.type_layout(ty),
+        }
+    }
+}
+
+/// Integers are as wide as their type and aligned to their size.
+fn int_layout(ty: IntType) -> Layout {
+    let size = u64::from(ty.bits() / 8);
+    Layout { size, align: size }
+}
+
+fn array(element: Layout, size: u32) -> Layout {
+    Layout {
+        size: element.stride() * u64::from(size),
+        align: element.align,
+    }
+}
+
+/// C layout of consecutive fields: the overall layout and each field's
+/// offset.
+fn sequence(fields: &[Layout]) -> (Layout, Vec<u64>) {
+    let mut offset = 0;
+    let mut align = 1;
+    let mut offsets = Vec::new();
+    for field in fields {
+        offset = align_to(offset, field.align);
+        offsets.push(offset);
+        offset += field.size;
+        align = align.max(field.align);
+    }
+    let layout = Layout {
+        size: align_to(offset, align),
+        align,
+    };
+    (layout, offsets)
+}
+
+fn align_to(offset: u64, align: u64) -> u64 {
+    offset.div_ceil(align) * align
+}
+
+fn array_size(size: &ArraySize) -> Result<u32, LayoutError> {
+    match size {
+        ArraySize::Literal(size) => Ok(*size),
+        ArraySize::Const(name) => Err(LayoutError::UnevaluatedSize(name.clone())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn layouts(source: &str) -> Result<LayoutTable, LayoutError> {
+        let mut lexer = Lexer::new(source.to_string());
+        LayoutTable::compute(&Parser::new(&mut lexer).parse_program())
+    }
+
+    #[test]
+    fn pads_fields_to_their_alignment() {
+        let table = layouts("struct Mixed { flag: bool, count: int, bits: bool[3], inner: Pair } struct Pair { a: bool, b: int }").unwrap();
+        let mixed = table.struct_layout("Mixed").unwrap();
+        assert_eq!(mixed.layout, Layout { size: 20, align: 4 });
+        assert_eq!(mixed.fields, [("flag".to_string(), 0), ("count".to_string(), 4), ("bits".to_string(), 8), ("inner".to_string(), 12)]);
+        assert_eq!(table.struct_layout("Pair").unwrap().field_offset("b"), Some(4));
+    }
+
+    #[test]
+    fn sizes_integers_by_their_width() {
+        let table = layouts("struct Sized { a: u8, b: i64, c: i16, d: u32 }").unwrap();
+        let sized = table.struct_layout("Sized").unwrap();
+        assert_eq!(sized.layout, Layout { size: 24, align: 8 });
+        assert_eq!(sized.fields.iter().map(|(_, offset)| *offset).collect::<Vec<_>>(), [0, 8, 16, 20]);
+    }
+
+    #[test]
+    fn lays_out_strings_as_pointer_and_length() {
+        let table = layouts("struct Named { tag: u8, name: string }").unwrap();
+        let named = table.struct_layout("Named").unwrap();
+        assert_eq!(named.layout, Layout { size: 24, align: 8 });
+        assert_eq!(named.field_offset("name"), Some(8));
+        let table = layouts("struct Buffer { len: u32, data: ptr }").unwrap();
+        assert_eq!(table.struct_layout("Buffer").unwrap().field_offset("data"), Some(8));
+    }
+
+    #[test]
+    fn lays_out_enums_as_tag_and_largest_payload() {
+        let table = layouts("enum Shape { Circle(int), Rect(int, int), Empty } enum Flag { On(bool), Off } struct Holder { s: Shape }").unwrap();
+        let shape = table.enum_layout("Shape").unwrap();
+        assert_eq!(shape.layout, Layout { size: 12, align: 4 });
+        assert_eq!(shape.payload_offset, 4);
+        assert_eq!(shape.variants[1], ("Rect".to_string(), vec![4, 8]));
+        assert_eq!(shape.tag("Empty"), Some(2));
+        assert_eq!(table.enum_layout("Flag").unwrap().layout, Layout { size: 8, align: 4 });
+        assert_eq!(table.struct_layout("Holder").unwrap().layout.size, 12);
+        assert_eq!(layouts("enum List { Cons(int, List), Nil }").unwrap_err(), LayoutError::RecursiveType("List".to_string()));
+    }
+
+    #[test]
+    fn lays_out_tuples_like_structs() {
+        let table = layouts("struct Pair { a: bool, b: (bool, int) }").unwrap();
+        assert_eq!(table.struct_layout("Pair").unwrap().layout, Layout { size: 12, align: 4 });
+        let tuple = table.tuple_layout(&[Ty::Bool, Ty::Struct("Pair".to_string(), Vec::new()), Ty::Tuple(Vec::new())]).unwrap();
+        assert_eq!(tuple.layout, Layout { size: 16, align: 4 });
+        assert_eq!(tuple.field_offset("1"), Some(4));
+        assert_eq!(tuple.field_offset("2"), Some(16));
+    }
+
+    #[test]
+    fn lays_out_closures_and_their_environments() {
+        let source = "struct Holder { flag: bool, f: fn(int) -> int } function f(n: int) { let b = true; let t = (n, n); let c = |x: int| { n = x; return t.0 + x; }; let d = || b; }";
+        let mut lexer = Lexer::new(source.to_string());
+        let program = Parser::new(&mut lexer).parse_program();
+        let table = LayoutTable::compute(&program).unwrap();
+        assert_eq!(table.struct_layout("Holder").unwrap().field_offset("f"), Some(8));
+        assert_eq!(table.ty_layout(&Ty::Function(vec![Ty::INT], Box::new(Ty::Void))).unwrap(), Layout { size: 16, align: 8 });
+
+        let captures = crate::captures::analyze(&program);
+        let (types, _) = crate::typeck::check_program(&program);
+        let [.., c, d] = closures(&program)[..] else { panic!("expected two closures") };
+        let environment = table.environment_layout(captures.captures(c), &types).unwrap();
+        assert_eq!(environment.fields, [("n".to_string(), 0), ("t".to_string(), 8)]);
+        assert_eq!(environment.layout, Layout { size: 16, align: 8 });
+        assert_eq!(table.environment_layout(captures.captures(d), &types).unwrap().layout, Layout { size: 1, align: 1 });
+    }
+
+    fn closures(program: &Program) -> Vec<crate::parser::NodeId> {
+        use crate::parser::{Expr, ExprKind};
+        use crate::visit::{self, Visitor};
+
+        struct Closures(Vec<crate::parser::NodeId>);
+
+        impl Visitor for Closures {
+            fn visit_expr(&mut self, expr: &Expr) {
+                if let ExprKind::Closure { .. } = expr.kind {
+                    self.0.push(expr.id);
+                }
+                visit::walk_expr(self, expr);
+            }
+        }
+
+        let mut closures = Closures(Vec::new());
+        closures.visit_program(program);
+        closures.0
+    }
+
+    #[test]
+    fn lays_out_trait_objects_and_vtables() {
+        let source = "interface Shape { function area(self) -> int; function scale(self, by: int) -> Self; } struct Holder { flag: bool, s: dyn Shape }";
+        let mut lexer = Lexer::new(source.to_string());
+        let program = Parser::new(&mut lexer).parse_program();
+        let table = LayoutTable::compute(&program).unwrap();
+        assert_eq!(table.struct_layout("Holder").unwrap().layout, Layout { size: 24, align: 8 });
+        assert_eq!(table.ty_layout(&Ty::Dyn("Shape".to_string())).unwrap(), Layout { size: 16, align: 8 });
+        let ItemKind::Interface(interface) = &program.items[0].kind else { panic!("expected interface") };
+        let vtable = table.vtable_layout(interface);
+        assert_eq!(vtable.fields, [("area".to_string(), 0), ("scale".to_string(), 8)]);
+        assert_eq!(vtable.layout, Layout { size: 16, align: 8 });
+    }
+
+    #[test]
+    fn skips_generic_structs_and_enums() {
+        let table = layouts("struct Pair<A, B> { a: A, b: B } enum Maybe<T> { No, Yes(T) } struct Holder { n: int }").unwrap();
+        assert!(table.struct_layout("Pair").is_none());
+        assert!(table.enum_layout("Maybe").is_none());
+        assert_eq!(layouts("struct Holder { p: Pair<int, int> } struct Pair<A, B> { a: A, b: B }").unwrap_err(), LayoutError::UnknownType("Pair<int, int>".to_string()));
+    }
+
+    #[test]
+    fn rejects_recursive_and_unknown_types() {
+        assert_eq!(layouts("struct A { b: B } struct B { a: A[1] }").unwrap_err(), LayoutError::RecursiveType("A".to_string()));
+        assert_eq!(layouts("struct A { b: Missing }").unwrap_err(), LayoutError::UnknownType("Missing".to_string()));
+        assert_eq!(
+            layouts("struct A { x: int, x: bool }").unwrap_err(),
+            LayoutError::DuplicateField { name: "A".to_string(), field: "x".to_string() }
+        );
+    }
+}
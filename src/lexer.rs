@@ -1,53 +1,243 @@
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
     Let,
+    Mut,
+    Const,
     Function,
+    Fn,
+    Struct,
+    Enum,
+    Interface,
+    Impl,
+    Dyn,
+    Import,
+    Extern,
+    Pub,
+    Match,
+    As,
     Identifier(String),
-    IntLiteral(i32),
-    TypeInt,
+    IntLiteral(u64),
+    /// The text between the quotes of a string literal as written, with
+    /// escapes and `{...}` holes still in it.
+    StringLiteral(String),
+    TypeInt(IntType),
+    TypeBool,
+    TypeString,
+    /// `ptr`, an untyped pointer passed to and from C.
+    TypePtr,
     TypeVoid,
     Plus,
     Minus,
     Star,
     Slash,
     Equals,
+    EqualEqual,
+    Bang,
+    BangEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    LessLess,
+    GreaterGreater,
+    Arrow,
+    FatArrow,
     Colon,
+    ColonColon,
     Semicolon,
     LParen,
     RParen,
     LBrace,
     RBrace,
+    LBracket,
+    RBracket,
     Return,
     If,
     Else,
     For,
     While,
+    Defer,
     True,
     False,
     Comma,
+    Dot,
+    /// `...`, which ends the parameters of a variadic extern function.
+    Ellipsis,
+    Pipe,
+    Question,
+    /// `@`, which starts an attribute.
+    At,
     Eof,
     Error(String),
 }
 
+/// The width and signedness of an integer type. `int` is another name for
+/// `i32`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IntType {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+}
+
+impl IntType {
+    pub fn from_name(name: &str) -> Option<IntType> {
+        match name {
+            "i8" => Some(IntType::I8),
+            "i16" => Some(IntType::I16),
+            "i32" | "int" => Some(IntType::I32),
+            "i64" => Some(IntType::I64),
+            "u8" => Some(IntType::U8),
+            "u16" => Some(IntType::U16),
+            "u32" => Some(IntType::U32),
+            "u64" => Some(IntType::U64),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            IntType::I8 => "i8",
+            IntType::I16 => "i16",
+            IntType::I32 => "int",
+            IntType::I64 => "i64",
+            IntType::U8 => "u8",
+            IntType::U16 => "u16",
+            IntType::U32 => "u32",
+            IntType::U64 => "u64",
+        }
+    }
+
+    pub fn bits(self) -> u32 {
+        match self {
+            IntType::I8 | IntType::U8 => 8,
+            IntType::I16 | IntType::U16 => 16,
+            IntType::I32 | IntType::U32 => 32,
+            IntType::I64 | IntType::U64 => 64,
+        }
+    }
+
+    pub fn signed(self) -> bool {
+        matches!(self, IntType::I8 | IntType::I16 | IntType::I32 | IntType::I64)
+    }
+
+    /// Whether `value` is one of the values of this type.
+    pub fn contains(self, value: i128) -> bool {
+        let (min, max) = if self.signed() {
+            (-(1 << (self.bits() - 1)), (1 << (self.bits() - 1)) - 1)
+        } else {
+            (0, (1 << self.bits()) - 1)
+        };
+        (min..=max).contains(&value)
+    }
+
+    /// `value` converted as `as` does: truncated to the low `bits()` bits,
+    /// which are then read as a signed or unsigned number.
+    pub fn wrap(self, value: i128) -> i128 {
+        let modulus = 1i128 << self.bits();
+        let low = value.rem_euclid(modulus);
+        if self.signed() && low >= modulus / 2 {
+            low - modulus
+        } else {
+            low
+        }
+    }
+}
+
+/// A half-open range of character offsets into the source text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+}
+
+/// A `//` or `/* */` comment skipped by the lexer, kept for tools such as
+/// the formatter that need to reproduce it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    pub span: Span,
+    pub text: String,
+}
+
 pub struct Lexer {
     input: Vec<char>,
     position: usize,
     nesting_level: usize,
+    token_start: usize,
+    comments: Vec<Comment>,
+    /// Added to every span, so that the files of a multi-file program have
+    /// disjoint spans.
+    offset: usize,
 }
 
 impl Lexer {
     pub fn new(input: String) -> Self {
+        Self::with_offset(input, 0)
+    }
+
+    /// A lexer whose spans start at `offset` rather than 0.
+    pub fn with_offset(input: String, offset: usize) -> Self {
         Self {
             input: input.chars().collect(),
             position: 0,
             nesting_level: 0,
+            token_start: 0,
+            comments: Vec::new(),
+            offset,
+        }
+    }
+
+    /// Lexes the rest of the input, returning every token with its span. The
+    /// last entry is always `Token::Eof`.
+    pub fn tokenize(&mut self) -> Vec<(Token, Span)> {
+        let mut tokens = Vec::new();
+        loop {
+            let token = self.next_token();
+            let done = token == Token::Eof;
+            tokens.push((token, self.span()));
+            if done {
+                return tokens;
+            }
         }
     }
 
+    /// Comments seen so far, in source order.
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
+    }
+
+    fn record_comment(&mut self) {
+        let text = self.input[self.token_start..self.position].iter().collect::<String>();
+        self.comments.push(Comment {
+            span: self.span(),
+            text: text.trim_end().to_string(),
+        });
+    }
+
+    /// The span of the token most recently returned by `next_token`.
+    pub fn span(&self) -> Span {
+        Span::new(self.offset + self.token_start, self.offset + self.position)
+    }
+
     fn get_char(&self) -> Option<char> {
         self.input.get(self.position).cloned()
     }
 
+    fn peek_char(&self) -> Option<char> {
+        self.input.get(self.position + 1).cloned()
+    }
+
     fn advance(&mut self) {
         self.position += 1;
     }
@@ -61,77 +251,38 @@ impl Lexer {
     }
 
     fn is_operator(c: char) -> bool {
-        matches!(c, '+' | '-' | '*' | '/' | '=' | ':' | ';' | '(' | ')' | '{' | '}' | ',')
+        matches!(c, '+' | '-' | '*' | '/' | ';' | '(' | ')' | '{' | '}' | '[' | ']' | ',' | '.' | '|' | '?' | '@')
+    }
+
+    /// Whether `s` lexes as a single identifier.
+    pub fn is_identifier(s: &str) -> bool {
+        s.chars().next().is_some_and(|c| !c.is_ascii_digit()) && s.chars().all(Lexer::is_identifier_char) && !Lexer::is_keyword(s)
     }
 
     fn is_keyword(s: &str) -> bool {
-        matches!(s, "let" | "function" | "int" | "void" | "return" | "if" | "else" | "for" | "while" | "true" | "false")
+        matches!(s, "let" | "mut" | "const" | "function" | "fn" | "struct" | "enum" | "interface" | "impl" | "dyn" | "import" | "extern" | "pub" | "match" | "as" | "bool" | "string" | "ptr" | "void" | "return" | "if" | "else" | "for" | "while" | "defer" | "true" | "false")
+            || IntType::from_name(s).is_some()
     }
 
     pub fn next_token(&mut self) -> Token {
         while let Some(current_char) = self.get_char() {
+            self.token_start = self.position;
             match current_char {
                 c if Lexer::is_whitespace(c) => self.advance(),
-                'l' => {
-                    self.advance();
-                    if self.input[self.position..self.position + 2] == ['e', 't'] {
-                        self.position += 2;
-                        return Token::Let;
-                    }
-                }
-                'f' => {
-                    self.advance();
-                    if self.input[self.position..self.position + 7] == ['u', 'n', 'c', 't', 'i', 'o', 'n'] {
-                        self.position += 7;
-                        return Token::Function;
-                    }
-                }
-                'i' => {
-                    self.advance();
-                    if self.input[self.position..self.position + 1] == ['f'] {
-                        self.position += 1;
-                        return Token::If;
-                    }
-                }
-                'e' => {
-                    self.advance();
-                    if self.input[self.position..self.position + 3] == ['l', 's', 'e'] {
-                        self.position += 3;
-                        return Token::Else;
-                    }
-                }
-                'w' => {
-                    self.advance();
-                    if self.input[self.position..self.position + 4] == ['h', 'i', 'l', 'e'] {
-                        self.position += 4;
-                        return Token::While;
-                    }
-                }
-                't' => {
-                    self.advance();
-                    if self.input[self.position..self.position + 3] == ['r', 'u', 'e'] {
-                        self.position += 3;
-                        return Token::True;
-                    }
-                }
-                'f' => {
-                    self.advance();
-                    if self.input[self.position..self.position + 4] == ['a', 'l', 's', 'e'] {
-                        self.position += 4;
-                        return Token::False;
-                    }
-                }
                 '0'..='9' => {
                     let start = self.position;
                     while let Some(ch) = self.get_char() {
-                        if ch.is_digit(10) {
+                        if ch.is_ascii_digit() {
                             self.advance();
                         } else {
                             break;
                         }
                     }
-                    let value: i32 = self.input[start..self.position].iter().collect::<String>().parse().unwrap();
-                    return Token::IntLiteral(value);
+                    let text = self.input[start..self.position].iter().collect::<String>();
+                    return match text.parse() {
+                        Ok(value) => Token::IntLiteral(value),
+                        Err(_) => Token::Error(format!("Integer literal too large: {}", text)),
+                    };
                 }
                 '"' => {
                     self.advance();
@@ -140,7 +291,11 @@ impl Lexer {
                         if ch == '"' {
                             let value = self.input[start..self.position].iter().collect::<String>();
                             self.advance();
-                            return Token::Identifier(value);
+                            return Token::StringLiteral(value);
+                        } else if ch == '\\' {
+                            // Skip the escaped character, which may be a quote.
+                            self.advance();
+                            self.advance();
                         } else {
                             self.advance();
                         }
@@ -158,6 +313,7 @@ impl Lexer {
                                     self.advance();
                                 }
                             }
+                            self.record_comment();
                         } else if next_char == '*' {
                             self.advance();
                             self.nesting_level += 1;
@@ -185,6 +341,7 @@ impl Lexer {
                                     self.advance();
                                 }
                             }
+                            self.record_comment();
                         } else {
                             return Token::Slash;
                         }
@@ -203,22 +360,89 @@ impl Lexer {
                     if Lexer::is_keyword(&value) {
                         match value.as_str() {
                             "let" => return Token::Let,
+                            "mut" => return Token::Mut,
+                            "const" => return Token::Const,
                             "function" => return Token::Function,
-                            "int" => return Token::TypeInt,
+                            "fn" => return Token::Fn,
+                            "struct" => return Token::Struct,
+                            "enum" => return Token::Enum,
+                            "interface" => return Token::Interface,
+                            "impl" => return Token::Impl,
+                            "dyn" => return Token::Dyn,
+                            "import" => return Token::Import,
+                            "extern" => return Token::Extern,
+                            "pub" => return Token::Pub,
+                            "match" => return Token::Match,
+                            "as" => return Token::As,
+                            "bool" => return Token::TypeBool,
+                            "string" => return Token::TypeString,
+                            "ptr" => return Token::TypePtr,
                             "void" => return Token::TypeVoid,
                             "return" => return Token::Return,
                             "if" => return Token::If,
                             "else" => return Token::Else,
                             "for" => return Token::For,
                             "while" => return Token::While,
+                            "defer" => return Token::Defer,
                             "true" => return Token::True,
                             "false" => return Token::False,
-                            _ => {}
+                            name => {
+                                if let Some(ty) = IntType::from_name(name) {
+                                    return Token::TypeInt(ty);
+                                }
+                            }
                         }
                     } else {
                         return Token::Identifier(value);
                     }
                 }
+                '=' if self.peek_char() == Some('>') => {
+                    self.advance();
+                    self.advance();
+                    return Token::FatArrow;
+                }
+                ':' => {
+                    self.advance();
+                    if self.get_char() == Some(':') {
+                        self.advance();
+                        return Token::ColonColon;
+                    }
+                    return Token::Colon;
+                }
+                '<' | '>' if self.peek_char() == Some(current_char) => {
+                    self.advance();
+                    self.advance();
+                    return if current_char == '<' { Token::LessLess } else { Token::GreaterGreater };
+                }
+                '=' | '!' | '<' | '>' => {
+                    self.advance();
+                    let followed_by_equals = self.get_char() == Some('=');
+                    if followed_by_equals {
+                        self.advance();
+                    }
+                    return match (current_char, followed_by_equals) {
+                        ('=', false) => Token::Equals,
+                        ('=', true) => Token::EqualEqual,
+                        ('!', false) => Token::Bang,
+                        ('!', true) => Token::BangEqual,
+                        ('<', false) => Token::Less,
+                        ('<', true) => Token::LessEqual,
+                        ('>', false) => Token::Greater,
+                        _ => Token::GreaterEqual,
+                    };
+                }
+                '.' if self.peek_char() == Some('.') && self.input.get(self.position + 2) == Some(&'.') => {
+                    self.position += 3;
+                    return Token::Ellipsis;
+                }
+                '-' => {
+                    self.advance();
+                    if self.get_char() == Some('>') {
+                        self.advance();
+                        return Token::Arrow;
+                    }
+                    return Token::Minus;
+                }
                 c if Lexer::is_operator(c) => {
                     self.advance();
                     match c {
@@ -226,14 +450,18 @@ impl Lexer {
                         '-' => return Token::Minus,
                         '*' => return Token::Star,
                         '/' => return Token::Slash,
-                        '=' => return Token::Equals,
-                        ':' => return Token::Colon,
                         ';' => return Token::Semicolon,
                         '(' => return Token::LParen,
                         ')' => return Token::RParen,
                         '{' => return Token::LBrace,
                         '}' => return Token::RBrace,
+                        '[' => return Token::LBracket,
+                        ']' => return Token::RBracket,
                         ',' => return Token::Comma,
+                        '.' => return Token::Dot,
+                        '|' => return Token::Pipe,
+                        '?' => return Token::Question,
+                        '@' => return Token::At,
                         _ => {}
                     }
                 }
@@ -243,6 +471,7 @@ impl Lexer {
                 }
             }
         }
+        self.token_start = self.position;
         Token::Eof
     }
 }
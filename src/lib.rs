@@ -0,0 +1,18 @@
+pub mod attributes;
+pub mod captures;
+pub mod consts;
+pub mod diagnostic;
+pub mod dump;
+pub mod exhaustiveness;
+pub mod formatter;
+pub mod json;
+pub mod layout;
+pub mod lexer;
+pub mod modules;
+pub mod monomorphize;
+pub mod mutability;
+pub mod parser;
+pub mod prelude;
+pub mod printer;
+pub mod typeck;
+pub mod visit;
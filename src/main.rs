@@ -1,11 +1,201 @@
-mod lexer;
-mod parser;
+use copilot_lang::{attributes, dump, exhaustiveness, formatter, lexer, modules, monomorphize, mutability, parser, printer, typeck};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::{env, fs, process};
 
 fn main() {
-    let input = "let x: int = 10; function main() { let y: int = 20; return y + x; if (true) { return false; } else { return true; } for (let i: int = 0; i < 10; i = i + 1) { while (i < 5) { i = i + 1; } } }";
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("fmt") => process::exit(run_fmt(&args[1..])),
+        Some("dump") => process::exit(run_dump(&args[1..])),
+        Some("check") => process::exit(run_check(&args[1..])),
+        Some("from-json") => process::exit(run_from_json(&args[1..])),
+        _ => run_sample(),
+    }
+}
+
+fn run_sample() {
+    let input = "const x: int = 10; function main() { let y: int = 20; return y + x; if (true) { return false; } else { return true; } for (let mut i: int = 0; i < 10; i = i + 1) { while (i < 5) { i = i + 1; } } }";
     let mut lexer = lexer::Lexer::new(input.to_string());
 
     let mut parser = parser::Parser::new(&mut lexer);
     let ast = parser.parse_program();
-    println!("{:?}", ast);
+    print!("{}", printer::print_program(&ast));
+}
+
+/// `copilot fmt [--check] [FILE...]`
+///
+/// Formats each file in place, or stdin to stdout when no file is given.
+/// With `--check` nothing is written; the exit code is 1 if any input is not
+/// already formatted. A syntax error is reported at the token where parsing
+/// stopped and exits with 2.
+fn run_fmt(args: &[String]) -> i32 {
+    let check = args.iter().any(|arg| arg == "--check");
+    let files: Vec<&String> = args.iter().filter(|arg| *arg != "--check").collect();
+
+    if files.is_empty() {
+        let mut source = String::new();
+        if let Err(err) = io::stdin().read_to_string(&mut source) {
+            eprintln!("error: failed to read stdin: {}", err);
+            return 2;
+        }
+        let formatted = match formatter::try_format_source(&source) {
+            Ok(formatted) => formatted,
+            Err(diagnostic) => {
+                eprint!("{}", diagnostic.render("<stdin>", &source));
+                return 2;
+            }
+        };
+        if check {
+            return if formatted == source { 0 } else { 1 };
+        }
+        if let Err(err) = io::stdout().write_all(formatted.as_bytes()) {
+            eprintln!("error: failed to write stdout: {}", err);
+            return 2;
+        }
+        return 0;
+    }
+
+    let mut status = 0;
+    for path in files {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("error: failed to read {}: {}", path, err);
+                return 2;
+            }
+        };
+        let formatted = match formatter::try_format_source(&source) {
+            Ok(formatted) => formatted,
+            Err(diagnostic) => {
+                eprint!("{}", diagnostic.render(path, &source));
+                return 2;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+        if check {
+            println!("{} is not formatted", path);
+            status = 1;
+        } else if let Err(err) = fs::write(path, formatted) {
+            eprintln!("error: failed to write {}: {}", path, err);
+            return 2;
+        }
+    }
+    status
+}
+
+/// `copilot dump [--tokens] [--sexp] FILE`
+///
+/// Prints the syntax tree of FILE as JSON, or its token stream with
+/// `--tokens`. `--sexp` switches either dump to S-expressions. The formats are
+/// documented in `src/dump.rs`. A syntax error is reported at the token
+/// where parsing stopped and exits with 2.
+fn run_dump(args: &[String]) -> i32 {
+    let tokens = args.iter().any(|arg| arg == "--tokens");
+    let sexp = args.iter().any(|arg| arg == "--sexp");
+    let Some(path) = args.iter().find(|arg| !arg.starts_with("--")) else {
+        eprintln!("usage: copilot dump [--tokens] [--sexp] FILE");
+        return 2;
+    };
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("error: failed to read {}: {}", path, err);
+            return 2;
+        }
+    };
+    let mut lexer = lexer::Lexer::new(source.clone());
+    let output = if tokens {
+        let tokens = lexer.tokenize();
+        if sexp {
+            dump::tokens_to_sexp(&tokens)
+        } else {
+            dump::tokens_to_json(&tokens).to_string()
+        }
+    } else {
+        let program = match parser::parse_source(&mut lexer) {
+            Ok(program) => program,
+            Err(diagnostic) => {
+                eprint!("{}", diagnostic.render(path, &source));
+                return 2;
+            }
+        };
+        if sexp {
+            dump::program_to_sexp(&program)
+        } else {
+            dump::program_to_json(&program).to_string()
+        }
+    };
+    println!("{}", output);
+    0
+}
+
+/// `copilot check FILE`
+///
+/// Loads FILE and the modules it imports, type checks the program and its
+/// attributes, runs the `match` checks and, if those pass, instantiates its
+/// generic items, printing the diagnostics to stderr in source order. The
+/// exit code is 1 if any of them is an error; warnings alone pass.
+fn run_check(args: &[String]) -> i32 {
+    let [path] = args else {
+        eprintln!("usage: copilot check FILE");
+        return 2;
+    };
+    let (program, sources, mut diagnostics) = match modules::load(Path::new(path), |file| fs::read_to_string(file)) {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            eprintln!("error: failed to read {}: {}", path, err);
+            return 2;
+        }
+    };
+    // Names that failed to resolve would only be reported again.
+    if diagnostics.is_empty() {
+        let types;
+        (types, diagnostics) = typeck::check_program(&program);
+        diagnostics.extend(attributes::check_program(&program));
+        diagnostics.extend(exhaustiveness::check_program(&program));
+        diagnostics.extend(mutability::check_program(&program));
+        if !diagnostics.iter().any(|diagnostic| diagnostic.is_error()) {
+            diagnostics.extend(monomorphize::monomorphize(&program, &types).1);
+        }
+    }
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+    for diagnostic in &diagnostics {
+        eprint!("{}", sources.render(diagnostic));
+    }
+    if diagnostics.iter().any(|diagnostic| diagnostic.is_error()) {
+        1
+    } else {
+        0
+    }
+}
+
+/// `copilot from-json FILE`
+///
+/// Reads a JSON syntax tree produced by `copilot dump` (or another tool) and
+/// prints it as CopilotLang source.
+fn run_from_json(args: &[String]) -> i32 {
+    let [path] = args else {
+        eprintln!("usage: copilot from-json FILE");
+        return 2;
+    };
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("error: failed to read {}: {}", path, err);
+            return 2;
+        }
+    };
+    match dump::program_from_json(&text) {
+        Ok(program) => {
+            print!("{}", printer::print_program(&program));
+            0
+        }
+        Err(err) => {
+            eprintln!("error: {}: {}", path, err);
+            1
+        }
+    }
 }
@@ -1,86 +1,129 @@
-use crate::parser::ASTNode;
+use crate::parser::{Block, Expr, ExprKind, Item, ItemKind, Program, Stmt, StmtKind};
 use mlir_sys::{MlirContext, MlirModule, MlirOperation, mlirContextCreate, mlirModuleCreateEmpty, mlirOperationCreate};
 use thiserror::Error;
 use log::{error, info};
 
 #[derive(Error, Debug)]
 pub enum ConversionError {
-    #[error("Expected a program node")]
-    ExpectedProgramNode,
     #[error("Unsupported AST node")]
     UnsupportedASTNode,
 }
 
-pub fn ast_to_mlir(ast: &ASTNode) -> Result<MlirModule, ConversionError> {
+pub fn ast_to_mlir(program: &Program) -> Result<MlirModule, ConversionError> {
     let context = unsafe { mlirContextCreate() };
     let module = unsafe { mlirModuleCreateEmpty(context) };
 
-    match ast {
-        ASTNode::Program(nodes) => {
-            for node in nodes {
-                let operation = ast_node_to_mlir_operation(node, context)?;
-                unsafe {
-                    mlirModuleAppendOperation(module, operation);
-                }
-            }
-        }
-        _ => {
-            error!("Expected a program node");
-            return Err(ConversionError::ExpectedProgramNode);
+    for item in &program.items {
+        let operation = item_to_mlir_operation(item, context)?;
+        unsafe {
+            mlirModuleAppendOperation(module, operation);
         }
     }
 
     Ok(module)
 }
 
-fn ast_node_to_mlir_operation(node: &ASTNode, context: MlirContext) -> Result<MlirOperation, ConversionError> {
-    match node {
-        ASTNode::Function { name, params, body } => {
+fn item_to_mlir_operation(item: &Item, context: MlirContext) -> Result<MlirOperation, ConversionError> {
+    match &item.kind {
+        ItemKind::Function(function) => {
             // Create MLIR operation for function
-            // Pseudocode: mlirOperationCreateFunction(name, params, body)
+            // Pseudocode: mlirOperationCreateFunction(function.name, function.params, function.return_type)
+            let body = block_to_mlir_operations(&function.body, context)?;
+            unimplemented!()
+        }
+        ItemKind::Global(global) => {
+            // Create MLIR operation for global variable
+            // Pseudocode: mlirOperationCreateGlobal(global.name, global.ty, global.value)
             unimplemented!()
         }
-        ASTNode::Let { name, value } => {
+        ItemKind::Import(_) => {
+            // `modules::load` merges the imported modules into the program
+            // and drops the imports, so none reach the lowering
+            unreachable!("imports are resolved before lowering")
+        }
+        _ => {
+            error!("Unsupported AST node");
+            return Err(ConversionError::UnsupportedASTNode);
+        }
+    }
+}
+
+fn block_to_mlir_operations(block: &Block, context: MlirContext) -> Result<Vec<MlirOperation>, ConversionError> {
+    block
+        .stmts
+        .iter()
+        .map(|stmt| stmt_to_mlir_operation(stmt, context))
+        .collect()
+}
+
+fn stmt_to_mlir_operation(stmt: &Stmt, context: MlirContext) -> Result<MlirOperation, ConversionError> {
+    match &stmt.kind {
+        StmtKind::Let(binding) => {
             // Create MLIR operation for let statement
-            // Pseudocode: mlirOperationCreateLet(name, value)
+            // Pseudocode: mlirOperationCreateLet(binding.pattern, binding.value)
             unimplemented!()
         }
-        ASTNode::Return(value) => {
+        StmtKind::Expr(expr) => expr_to_mlir_operation(expr, context),
+        StmtKind::Return(value) => {
             // Create MLIR operation for return statement
             // Pseudocode: mlirOperationCreateReturn(value)
             unimplemented!()
         }
-        ASTNode::If { condition, then_branch, else_branch } => {
+        StmtKind::If { condition, then_branch, else_branch } => {
             // Create MLIR operation for if statement
             // Pseudocode: mlirOperationCreateIf(condition, then_branch, else_branch)
             unimplemented!()
         }
-        ASTNode::For { init, condition, increment, body } => {
+        StmtKind::For { init, condition, increment, body } => {
             // Create MLIR operation for for loop
             // Pseudocode: mlirOperationCreateFor(init, condition, increment, body)
             unimplemented!()
         }
-        ASTNode::While { condition, body } => {
+        StmtKind::While { condition, body } => {
             // Create MLIR operation for while loop
             // Pseudocode: mlirOperationCreateWhile(condition, body)
             unimplemented!()
         }
-        ASTNode::BinaryOp { op, left, right } => {
+        _ => {
+            error!("Unsupported AST node");
+            return Err(ConversionError::UnsupportedASTNode);
+        }
+    }
+}
+
+fn expr_to_mlir_operation(expr: &Expr, context: MlirContext) -> Result<MlirOperation, ConversionError> {
+    match &expr.kind {
+        ExprKind::Unary { op, operand } => {
+            // Create MLIR operation for unary operation
+            // Pseudocode: mlirOperationCreateUnaryOp(op, operand)
+            unimplemented!()
+        }
+        ExprKind::Binary { op, left, right } => {
             // Create MLIR operation for binary operation
             // Pseudocode: mlirOperationCreateBinaryOp(op, left, right)
             unimplemented!()
         }
-        ASTNode::Identifier(name) => {
+        ExprKind::Assign { target, value } => {
+            // Create MLIR operation for assignment
+            // Pseudocode: mlirOperationCreateStore(target, value)
+            unimplemented!()
+        }
+        ExprKind::Call { callee, args } => {
+            // Create MLIR operation for function call
+            // Pseudocode: mlirOperationCreateCall(callee, args)
+            unimplemented!()
+        }
+        ExprKind::Identifier(name) => {
             // Create MLIR operation for identifier
             // Pseudocode: mlirOperationCreateIdentifier(name)
             unimplemented!()
         }
-        ASTNode::IntLiteral(value) => {
+        ExprKind::IntLiteral(value) => {
             // Create MLIR operation for integer literal
             // Pseudocode: mlirOperationCreateIntLiteral(value)
             unimplemented!()
         }
-        ASTNode::BoolLiteral(value) => {
+        ExprKind::BoolLiteral(value) => {
             // Create MLIR operation for boolean literal
             // Pseudocode: mlirOperationCreateBoolLiteral(value)
             unimplemented!()
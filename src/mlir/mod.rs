@@ -1,3 +1,7 @@
+//! A sketch of the MLIR and LLVM lowering. The conversions are pseudocode
+//! that end in `unimplemented!()`; the crate does not declare this module,
+//! so none of it is built.
+
 pub mod ast_to_mlir;
 pub mod optimizations;
 pub mod mlir_to_llvm;
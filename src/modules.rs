@@ -0,0 +1,596 @@
+//! Multi-file programs.
+//!
+//! `load` reads the root file and, depth first, every module it imports.
+//! Module files are found relative to the directory of the root file, and
+//! each is parsed once however many modules import it. An import that leads
+//! back to a file that is still being loaded is a cycle and is reported.
+//!
+//! The modules are merged into one `Program`, dependencies first. The items
+//! of an imported module are renamed to their qualified names, such as
+//! `math::sqrt`, which is how the modules importing it refer to them; inside
+//! the module they are referred to unqualified, and the root module's items
+//! keep their names, as do those of the prelude, which comes first and is
+//! visible everywhere. A module only sees the `pub` items of the modules it
+//! imports itself. Afterwards every name is one the checking passes can look up as it
+//! is, so they know nothing about modules.
+//!
+//! The spans of each file are offset by the lengths of the files loaded
+//! before it, so a span identifies its file through the `SourceMap`.
+
+use crate::diagnostic::{render_span, Diagnostic};
+use crate::lexer::{Lexer, Span};
+use crate::parser::{
+    parse_source, ArraySize, Block, Enum, Expr, ExprKind, Function, Import, Item, ItemKind, Let, MatchArm, MethodSig, NodeId, Pattern, PatternKind, Program, Stmt,
+    StmtKind, Struct, Type, TypeKind, TypeParam,
+};
+use crate::prelude;
+use crate::visit::{self, VisitorMut};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A source file and the offset its spans start at.
+#[derive(Debug)]
+pub struct SourceFile {
+    pub path: String,
+    pub source: String,
+    pub offset: usize,
+}
+
+/// The source files of a program, in the order they were loaded.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    /// Adds a file and returns the offset its spans start at.
+    pub fn add(&mut self, path: String, source: String) -> usize {
+        // One past the end of the previous file, so that a span at the very
+        // end of a file is not mistaken for the start of the next.
+        let offset = self.files.last().map_or(0, |file| file.offset + file.source.chars().count() + 1);
+        self.files.push(SourceFile { path, source, offset });
+        offset
+    }
+
+    /// The file that the character offset `offset` falls in.
+    pub fn file(&self, offset: usize) -> Option<&SourceFile> {
+        self.files.iter().rev().find(|file| file.offset <= offset)
+    }
+
+    /// Renders a diagnostic against the file its span is in, and each of
+    /// its notes against the file the note's span is in.
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let mut out = self.render_span(diagnostic.span, &diagnostic.severity.to_string(), &diagnostic.message);
+        for note in &diagnostic.notes {
+            out.push_str(&self.render_span(note.span, "note", &note.message));
+        }
+        out
+    }
+
+    fn render_span(&self, span: Span, label: &str, message: &str) -> String {
+        let file = self.file(span.start).expect("diagnostic outside every source file");
+        let local = Span::new(span.start - file.offset, span.end - file.offset);
+        render_span(&file.path, &file.source, local, label, message)
+    }
+}
+
+/// Loads the program whose root file is `root` together with the modules it
+/// imports, reading files with `read`. Only a root file that cannot be read
+/// is an `Err`; every other problem is reported as a diagnostic.
+pub fn load(root: &Path, mut read: impl FnMut(&Path) -> io::Result<String>) -> io::Result<(Program, SourceMap, Vec<Diagnostic>)> {
+    let source = read(root)?;
+    let mut loader = Loader {
+        directory: root.parent().map(Path::to_path_buf).unwrap_or_default(),
+        read: &mut read,
+        sources: SourceMap::default(),
+        modules: Vec::new(),
+        loaded: HashMap::new(),
+        names: HashMap::new(),
+        loading: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+    let file = root.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+    loader.module(prelude::PATH.to_string(), None, prelude::PATH.to_string(), prelude::SOURCE.to_string());
+    loader.module(file, None, root.display().to_string(), source);
+
+    let Loader { modules, sources, mut diagnostics, .. } = loader;
+    let exports: Vec<HashMap<String, Export>> = modules.iter().map(|module| exports(&module.program)).collect();
+    let mut items = Vec::new();
+    let mut span = Span::default();
+    for (index, module) in modules.into_iter().enumerate() {
+        let mut program = module.program;
+        Qualifier {
+            prefix: module.name.as_deref(),
+            own: &exports[index],
+            imports: module.imports.iter().map(|(name, index)| (name.as_str(), &exports[*index])).collect(),
+            scopes: Vec::new(),
+            type_params: Vec::new(),
+            diagnostics: &mut diagnostics,
+        }
+        .visit_program_mut(&mut program);
+        items.extend(program.items.into_iter().filter(|item| !matches!(item.kind, ItemKind::Import(_))));
+        span = program.span;
+    }
+    // The root module comes last, and its span reaches back to offset 0.
+    let mut program = Program { id: NodeId(0), span, items };
+    visit::renumber(&mut program);
+    Ok((program, sources, diagnostics))
+}
+
+struct Module {
+    /// The qualifier of the module's items; `None` for the root module.
+    name: Option<String>,
+    program: Program,
+    /// The modules it imports, by name, as indices into `Loader::modules`.
+    imports: HashMap<String, usize>,
+}
+
+struct Loader<'r> {
+    directory: PathBuf,
+    read: &'r mut dyn FnMut(&Path) -> io::Result<String>,
+    sources: SourceMap,
+    /// Every module loaded so far, dependencies before the modules that
+    /// import them.
+    modules: Vec<Module>,
+    /// Indices into `modules` by file.
+    loaded: HashMap<String, usize>,
+    /// The file that each module name was taken by.
+    names: HashMap<String, String>,
+    /// The files being loaded, outermost first.
+    loading: Vec<String>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Loader<'_> {
+    /// Parses a module and loads its imports, returning its index. A module
+    /// with a syntax error is reported and loaded without any items.
+    fn module(&mut self, file: String, name: Option<String>, path: String, source: String) -> usize {
+        let offset = self.sources.add(path, source.clone());
+        let end = offset + source.chars().count();
+        let mut lexer = Lexer::with_offset(source, offset);
+        let program = parse_source(&mut lexer).unwrap_or_else(|diagnostic| {
+            self.diagnostics.push(diagnostic);
+            Program {
+                id: NodeId(0),
+                span: Span::new(offset, end),
+                items: Vec::new(),
+            }
+        });
+        self.loading.push(file.clone());
+        let mut imports = HashMap::new();
+        for item in &program.items {
+            if let ItemKind::Import(import) = &item.kind {
+                if let Some(index) = self.import(item.span, import) {
+                    imports.insert(import.module_name().to_string(), index);
+                }
+            }
+        }
+        self.loading.pop();
+        self.modules.push(Module { name, program, imports });
+        self.loaded.insert(file, self.modules.len() - 1);
+        self.modules.len() - 1
+    }
+
+    fn import(&mut self, span: Span, import: &Import) -> Option<usize> {
+        let file = import.file();
+        if let Some(start) = self.loading.iter().position(|loading| *loading == file) {
+            let cycle: Vec<&str> = self.loading[start..].iter().map(String::as_str).chain([file.as_str()]).collect();
+            self.error(span, format!("import cycle: {}", cycle.join(" -> ")));
+            return None;
+        }
+        let name = import.module_name();
+        match self.names.get(name) {
+            Some(other) if *other != file => {
+                self.error(span, format!("modules `{}` and `{}` are both named `{}`", other, file, name));
+                return None;
+            }
+            _ => {}
+        }
+        if let Some(&index) = self.loaded.get(&file) {
+            return Some(index);
+        }
+        let path = self.directory.join(&file);
+        match (self.read)(&path) {
+            Ok(source) => {
+                self.names.insert(name.to_string(), file.clone());
+                Some(self.module(file, Some(name.to_string()), path.display().to_string(), source))
+            }
+            Err(err) => {
+                self.error(span, format!("cannot read module `{}`: {}", file, err));
+                None
+            }
+        }
+    }
+
+    fn error(&mut self, span: Span, message: String) {
+        self.diagnostics.push(Diagnostic::error(span, message));
+    }
+}
+
+/// An item a module declares.
+struct Export {
+    public: bool,
+    /// Where it is defined, for the error when it is private.
+    span: Span,
+}
+
+/// The items a module declares, by unqualified name.
+fn exports(program: &Program) -> HashMap<String, Export> {
+    let mut exports = HashMap::new();
+    for item in &program.items {
+        let mut names = Vec::new();
+        match &item.kind {
+            ItemKind::Function(function) => names.push(function.name.clone()),
+            ItemKind::Extern(function) => names.push(function.name.clone()),
+            ItemKind::Const(constant) => names.push(constant.name.clone()),
+            ItemKind::Struct(definition) => names.push(definition.name.clone()),
+            ItemKind::Enum(definition) => names.push(definition.name.clone()),
+            ItemKind::Interface(definition) => names.push(definition.name.clone()),
+            ItemKind::Global(binding) => collect_bindings(&binding.pattern, &mut names),
+            ItemKind::Impl(_) | ItemKind::Import(_) => {}
+        }
+        for name in names {
+            exports.insert(name, Export { public: item.public, span: item.span });
+        }
+    }
+    exports
+}
+
+fn collect_bindings(pattern: &Pattern, names: &mut Vec<String>) {
+    match &pattern.kind {
+        PatternKind::Binding { name, .. } => names.push(name.clone()),
+        PatternKind::Wildcard | PatternKind::IntLiteral(_) | PatternKind::BoolLiteral(_) => {}
+        PatternKind::Variant { fields: elements, .. } | PatternKind::Tuple(elements) => {
+            for element in elements {
+                collect_bindings(element, names);
+            }
+        }
+    }
+}
+
+/// Rewrites the names in one module to the names of the merged program.
+struct Qualifier<'a> {
+    /// `math` for the module `math`; `None` for the root module.
+    prefix: Option<&'a str>,
+    /// The module's own items.
+    own: &'a HashMap<String, Export>,
+    /// The items of each module it imports, by module name.
+    imports: HashMap<&'a str, &'a HashMap<String, Export>>,
+    /// Local variables in scope, innermost last; empty at top level.
+    scopes: Vec<HashSet<String>>,
+    /// The type parameters of the generic item being rewritten.
+    type_params: Vec<String>,
+    diagnostics: &'a mut Vec<Diagnostic>,
+}
+
+impl Qualifier<'_> {
+    /// The name of one of the module's own items in the merged program.
+    fn own_name(&self, name: &str) -> String {
+        match self.prefix {
+            Some(prefix) => format!("{}::{}", prefix, name),
+            None => name.to_string(),
+        }
+    }
+
+    /// Resolves the name of a type, struct or interface: a name qualified by
+    /// a module must name one of the items of an imported module, and an
+    /// unqualified name of one of the module's own items is qualified.
+    fn resolve(&mut self, name: &mut String, span: Span) {
+        match name.split_once("::") {
+            Some((module, item)) => {
+                let (module, item) = (module.to_string(), item.to_string());
+                self.check_import(&module, &item, span);
+            }
+            None if self.own.contains_key(name.as_str()) => *name = self.own_name(name),
+            None => {}
+        }
+    }
+
+    /// Resolves the path of an enum variant or of an item of another module,
+    /// merging a leading module into the item name: `math::Shape::Circle`
+    /// becomes `math::Shape` followed by `Circle`.
+    fn resolve_path(&mut self, path: &mut Vec<String>, span: Span) {
+        if path.len() > 1 && self.imports.contains_key(path[0].as_str()) {
+            let module = path.remove(0);
+            self.check_import(&module, &path[0], span);
+            path[0] = format!("{}::{}", module, path[0]);
+        } else if self.own.contains_key(path[0].as_str()) {
+            path[0] = self.own_name(&path[0]);
+        }
+    }
+
+    /// Reports `module::item` unless it names a `pub` item of an imported
+    /// module.
+    fn check_import(&mut self, module: &str, item: &str, span: Span) {
+        let diagnostic = match self.imports.get(module).map(|items| items.get(item)) {
+            Some(Some(export)) if export.public => return,
+            Some(Some(export)) => Diagnostic::error(span, format!("item `{}::{}` is private", module, item))
+                .with_note(export.span, format!("`{}` is defined here without `pub`", item)),
+            Some(None) => Diagnostic::error(span, format!("module `{}` has no item `{}`", module, item)),
+            None => Diagnostic::error(span, format!("unknown module `{}`", module)),
+        };
+        self.diagnostics.push(diagnostic);
+    }
+
+    fn in_scope(&mut self, walk: impl FnOnce(&mut Self)) {
+        self.scopes.push(HashSet::new());
+        walk(self);
+        self.scopes.pop();
+    }
+
+    fn with_type_params(&mut self, params: &[TypeParam], walk: impl FnOnce(&mut Self)) {
+        let names = params.iter().map(|param| param.name.clone()).collect();
+        let enclosing = std::mem::replace(&mut self.type_params, names);
+        walk(self);
+        self.type_params = enclosing;
+    }
+}
+
+impl VisitorMut for Qualifier<'_> {
+    fn visit_item_mut(&mut self, item: &mut Item) {
+        match &mut item.kind {
+            ItemKind::Function(function) => function.name = self.own_name(&function.name),
+            // Only the name in the program is qualified; the lowering links
+            // against the symbol without the module.
+            ItemKind::Extern(function) => function.name = self.own_name(&function.name),
+            ItemKind::Const(constant) => constant.name = self.own_name(&constant.name),
+            ItemKind::Struct(definition) => definition.name = self.own_name(&definition.name),
+            ItemKind::Enum(definition) => definition.name = self.own_name(&definition.name),
+            ItemKind::Interface(definition) => definition.name = self.own_name(&definition.name),
+            ItemKind::Impl(definition) => self.resolve(&mut definition.interface, item.span),
+            ItemKind::Global(_) | ItemKind::Import(_) => {}
+        }
+        visit::walk_item_mut(self, item);
+    }
+
+    fn visit_function_mut(&mut self, function: &mut Function) {
+        let params = function.type_params.clone();
+        self.with_type_params(&params, |qualifier| {
+            qualifier.in_scope(|qualifier| visit::walk_function_mut(qualifier, function))
+        });
+    }
+
+    fn visit_struct_mut(&mut self, definition: &mut Struct) {
+        let params = definition.type_params.clone();
+        self.with_type_params(&params, |qualifier| visit::walk_struct_mut(qualifier, definition));
+    }
+
+    fn visit_enum_mut(&mut self, definition: &mut Enum) {
+        let params = definition.type_params.clone();
+        self.with_type_params(&params, |qualifier| visit::walk_enum_mut(qualifier, definition));
+    }
+
+    fn visit_type_param_mut(&mut self, param: &mut TypeParam) {
+        for bound in &mut param.bounds {
+            self.resolve(bound, param.span);
+        }
+    }
+
+    fn visit_method_sig_mut(&mut self, method: &mut MethodSig) {
+        self.in_scope(|qualifier| visit::walk_method_sig_mut(qualifier, method));
+    }
+
+    /// The value is resolved before the pattern binds, so `let x = x;` refers
+    /// to the outer `x`.
+    fn visit_let_mut(&mut self, binding: &mut Let) {
+        self.visit_expr_mut(&mut binding.value);
+        if let Some(ty) = &mut binding.ty {
+            self.visit_type_mut(ty);
+        }
+        self.visit_pattern_mut(&mut binding.pattern);
+    }
+
+    fn visit_block_mut(&mut self, block: &mut Block) {
+        self.in_scope(|qualifier| visit::walk_block_mut(qualifier, block));
+    }
+
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        match &stmt.kind {
+            StmtKind::For { .. } => self.in_scope(|qualifier| visit::walk_stmt_mut(qualifier, stmt)),
+            _ => visit::walk_stmt_mut(self, stmt),
+        }
+    }
+
+    fn visit_match_arm_mut(&mut self, arm: &mut MatchArm) {
+        self.in_scope(|qualifier| visit::walk_match_arm_mut(qualifier, arm));
+    }
+
+    fn visit_pattern_mut(&mut self, pattern: &mut Pattern) {
+        match &mut pattern.kind {
+            PatternKind::Binding { name, .. } => match self.scopes.last_mut() {
+                Some(scope) => {
+                    scope.insert(name.clone());
+                }
+                // Outside every scope the binding is a global of the module.
+                None => *name = self.own_name(name),
+            },
+            PatternKind::Variant { path, .. } => self.resolve_path(path, pattern.span),
+            _ => {}
+        }
+        visit::walk_pattern_mut(self, pattern);
+    }
+
+    fn visit_type_mut(&mut self, ty: &mut Type) {
+        visit::walk_type_mut(self, ty);
+        match &mut ty.kind {
+            TypeKind::Named { name, .. } if !self.type_params.contains(name) => self.resolve(name, ty.span),
+            TypeKind::Dyn(interface) => self.resolve(interface, ty.span),
+            TypeKind::Array { size: ArraySize::Const(name), .. } => self.resolve(name, ty.span),
+            _ => {}
+        }
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        let span = expr.span;
+        match &mut expr.kind {
+            ExprKind::Identifier(name) if self.own.contains_key(name.as_str()) && !self.scopes.iter().any(|scope| scope.contains(name.as_str())) => {
+                *name = self.own_name(name);
+            }
+            ExprKind::Path(path) => {
+                self.resolve_path(path, span);
+                // `math::sqrt` names a function or global, not a variant.
+                if path.len() == 1 {
+                    let name = path.remove(0);
+                    expr.kind = ExprKind::Identifier(name);
+                }
+            }
+            ExprKind::StructLiteral { name, .. } => self.resolve(name, span),
+            ExprKind::Closure { .. } => {
+                self.in_scope(|qualifier| visit::walk_expr_mut(qualifier, expr));
+                return;
+            }
+            _ => {}
+        }
+        visit::walk_expr_mut(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::print_program;
+    use crate::typeck::check_program;
+
+    /// Loads `main.cl` from the given files.
+    fn load_files(files: &[(&str, &str)]) -> (Program, SourceMap, Vec<Diagnostic>) {
+        let files: HashMap<&str, &str> = files.iter().copied().collect();
+        load(Path::new("main.cl"), |path| {
+            let path = path.to_str().expect("test paths are UTF-8");
+            files.get(path).map(|source| source.to_string()).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "not found"))
+        })
+        .unwrap()
+    }
+
+    fn messages(diagnostics: &[Diagnostic]) -> Vec<&str> {
+        diagnostics.iter().map(|diagnostic| diagnostic.message.as_str()).collect()
+    }
+
+    #[test]
+    fn merges_modules_under_qualified_names() {
+        let (program, _, diagnostics) = load_files(&[
+            (
+                "main.cl",
+                "import math; import \"util/geometry.cl\"; \
+                 function main() -> int { let sqrt: int[math::SIZE] = [4, 4]; return math::sqrt(sqrt[1]) + geometry::area(geometry::Point { x: math::scale }); }",
+            ),
+            ("math.cl", "pub const SIZE: int = 2; const HALF: int = SIZE / 2; pub let scale = 2; pub function sqrt(x: int) -> int { return half(x) * scale; } function half(x: int) -> int { return x / 2; }"),
+            (
+                "util/geometry.cl",
+                "import math; pub struct Point { x: int } enum Shape { Square(Point), Empty } \
+                 pub function area(p: Point) -> int { let s: Shape = Shape::Square(p); return match s { Shape::Square(q) => math::sqrt(q.x), Shape::Empty => 0 }; }",
+            ),
+        ]);
+        assert_eq!(messages(&diagnostics), Vec::<&str>::new());
+        assert_eq!(
+            print_program(&program),
+            prelude::SOURCE.to_string()
+                + "\n\
+             pub const math::SIZE: int = 2;\n\
+             \n\
+             const math::HALF: int = math::SIZE / 2;\n\
+             \n\
+             pub let math::scale = 2;\n\
+             \n\
+             pub function math::sqrt(x: int) -> int {\n\
+             \x20   return math::half(x) * math::scale;\n\
+             }\n\
+             \n\
+             function math::half(x: int) -> int {\n\
+             \x20   return x / 2;\n\
+             }\n\
+             \n\
+             pub struct geometry::Point {\n\
+             \x20   x: int,\n\
+             }\n\
+             \n\
+             enum geometry::Shape {\n\
+             \x20   Square(geometry::Point),\n\
+             \x20   Empty,\n\
+             }\n\
+             \n\
+             pub function geometry::area(p: geometry::Point) -> int {\n\
+             \x20   let s: geometry::Shape = geometry::Shape::Square(p);\n\
+             \x20   return match s {\n\
+             \x20       geometry::Shape::Square(q) => math::sqrt(q.x),\n\
+             \x20       geometry::Shape::Empty => 0,\n\
+             \x20   };\n\
+             }\n\
+             \n\
+             function main() -> int {\n\
+             \x20   let sqrt: int[math::SIZE] = [4, 4];\n\
+             \x20   return math::sqrt(sqrt[1]) + geometry::area(geometry::Point { x: math::scale });\n\
+             }\n"
+        );
+        assert!(check_program(&program).1.is_empty());
+    }
+
+    #[test]
+    fn reports_syntax_errors_in_imported_modules() {
+        let (_, sources, diagnostics) = load_files(&[("main.cl", "import a; function main() {}"), ("a.cl", "pub function f() { let = 1; }")]);
+        assert_eq!(messages(&diagnostics), ["Expected pattern, found Equals"]);
+        assert!(sources.render(&diagnostics[0]).contains("a.cl:1:24"), "{}", sources.render(&diagnostics[0]));
+    }
+
+    #[test]
+    fn reports_cycles_and_unreadable_modules() {
+        let (_, _, diagnostics) = load_files(&[
+            ("main.cl", "import a; import missing; function main() {}"),
+            ("a.cl", "import b; function f() {}"),
+            ("b.cl", "import a; import main; function g() {}"),
+        ]);
+        assert_eq!(
+            messages(&diagnostics),
+            ["import cycle: a.cl -> b.cl -> a.cl", "import cycle: main.cl -> a.cl -> b.cl -> main.cl", "cannot read module `missing.cl`: not found"]
+        );
+    }
+
+    #[test]
+    fn reports_unknown_modules_and_items() {
+        let (_, _, diagnostics) = load_files(&[
+            ("main.cl", "import math; import \"lib/math.cl\"; function main() { math::cube(1); other::f(); let p: other::Point = math::Point {}; }"),
+            ("math.cl", "pub function sqrt(x: int) -> int { return x; }"),
+            ("lib/math.cl", ""),
+        ]);
+        assert_eq!(
+            messages(&diagnostics),
+            [
+                "modules `math.cl` and `lib/math.cl` are both named `math`",
+                "module `math` has no item `cube`",
+                "module `math` has no item `Point`",
+                "unknown module `other`"
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_private_items_with_their_definition() {
+        let (_, sources, diagnostics) = load_files(&[
+            ("main.cl", "import math;\nfunction main() -> int { return math::helper(math::Point { x: 1 }.x); }"),
+            ("math.cl", "pub struct Point { x: int }\nfunction helper(x: int) -> int { return x; }"),
+        ]);
+        assert_eq!(messages(&diagnostics), ["item `math::helper` is private"]);
+        assert_eq!(
+            sources.render(&diagnostics[0]),
+            "main.cl:2:33: error: item `math::helper` is private\n\
+             \x20   function main() -> int { return math::helper(math::Point { x: 1 }.x); }\n\
+             \x20                                   ^^^^^^^^^^^^\n\
+             math.cl:2:1: note: `helper` is defined here without `pub`\n\
+             \x20   function helper(x: int) -> int { return x; }\n\
+             \x20   ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^\n"
+        );
+    }
+
+    #[test]
+    fn renders_diagnostics_against_their_file() {
+        let (program, sources, _) = load_files(&[
+            ("main.cl", "import math;\nfunction main() { math::f(); }"),
+            ("math.cl", "pub function f() {\n    let x: bool = 1;\n}"),
+        ]);
+        let (_, diagnostics) = check_program(&program);
+        assert_eq!(
+            sources.render(&diagnostics[0]),
+            "math.cl:2:19: error: mismatched types: expected `bool`, found `int`\n        let x: bool = 1;\n                      ^\n"
+        );
+    }
+}
@@ -0,0 +1,494 @@
+//! Monomorphisation of generic functions, structs and enums.
+//!
+//! Starting from the non-generic items, every generic function, struct or
+//! enum is copied once for each list of type arguments it is used with, so
+//! the lowering only ever sees concrete types. A copy is named after its
+//! definition and arguments, such as `max<int>` or `Pair<int, bool>`, keeps
+//! the spans of the definition and is appended after the ordinary items. Node
+//! ids are reassigned at the end so that they stay unique. Array sizes that
+//! name a constant are replaced by its value.
+//!
+//! Impls and the bodies of their methods are never generic; a call to a
+//! method on a value of a type parameter becomes a call on the concrete type
+//! in each copy, dispatched statically once the copy is checked again.
+//!
+//! The program must have type checked without errors: the type arguments of
+//! calls, struct literals and enum variants are read from its `TypeTable`.
+
+use crate::consts::ConstValue;
+use crate::diagnostic::Diagnostic;
+use crate::lexer::Span;
+use crate::parser::{ArraySize, Expr, ExprKind, Item, ItemKind, NodeId, Pattern, PatternKind, Program, Type, TypeKind};
+use crate::printer::print_type;
+use crate::typeck::{Ty, TypeTable};
+use crate::visit::{self, VisitorMut};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// The largest number of type nodes in the arguments of one instantiation.
+/// Instantiation can only fail to terminate by using ever larger types, as in
+/// a generic function that calls itself with `(x, x)`.
+const TYPE_SIZE_LIMIT: usize = 256;
+
+pub fn monomorphize(program: &Program, types: &TypeTable) -> (Program, Vec<Diagnostic>) {
+    let mut generics = HashMap::new();
+    for item in &program.items {
+        match &item.kind {
+            ItemKind::Function(function) if !function.type_params.is_empty() => {
+                generics.insert(function.name.as_str(), item);
+            }
+            ItemKind::Struct(definition) if !definition.type_params.is_empty() => {
+                generics.insert(definition.name.as_str(), item);
+            }
+            ItemKind::Enum(definition) if !definition.type_params.is_empty() => {
+                generics.insert(definition.name.as_str(), item);
+            }
+            _ => {}
+        }
+    }
+    let mut monomorphizer = Monomorphizer {
+        types,
+        generics,
+        instances: HashSet::new(),
+        queue: VecDeque::new(),
+        diagnostics: Vec::new(),
+    };
+
+    let mut items = Vec::new();
+    for item in &program.items {
+        if !is_generic(item) {
+            let mut item = item.clone();
+            Rewriter {
+                monomorphizer: &mut monomorphizer,
+                bindings: HashMap::new(),
+            }
+            .visit_item_mut(&mut item);
+            items.push(item);
+        }
+    }
+    while let Some(instance) = monomorphizer.queue.pop_front() {
+        items.push(monomorphizer.instantiate(instance));
+    }
+
+    let mut program = Program {
+        id: program.id,
+        span: program.span,
+        items,
+    };
+    visit::renumber(&mut program);
+    (program, monomorphizer.diagnostics)
+}
+
+fn is_generic(item: &Item) -> bool {
+    match &item.kind {
+        ItemKind::Function(function) => !function.type_params.is_empty(),
+        ItemKind::Struct(definition) => !definition.type_params.is_empty(),
+        ItemKind::Enum(definition) => !definition.type_params.is_empty(),
+        ItemKind::Global(_) | ItemKind::Const(_) | ItemKind::Interface(_) | ItemKind::Impl(_) | ItemKind::Import(_) | ItemKind::Extern(_) => false,
+    }
+}
+
+/// A generic item to copy for concrete type arguments.
+struct Instance {
+    generic: String,
+    name: String,
+    args: Vec<Type>,
+}
+
+struct Monomorphizer<'a> {
+    types: &'a TypeTable,
+    generics: HashMap<&'a str, &'a Item>,
+    /// Names of the instances requested so far.
+    instances: HashSet<String>,
+    queue: VecDeque<Instance>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Monomorphizer<'_> {
+    /// The name of the instance of `generic` for `args`, which are concrete,
+    /// queueing it if it is new.
+    fn request(&mut self, generic: &str, args: Vec<Type>, span: Span) -> String {
+        let printed: Vec<String> = args.iter().map(print_type).collect();
+        let name = format!("{}<{}>", generic, printed.join(", "));
+        if self.instances.insert(name.clone()) {
+            if args.iter().map(type_size).sum::<usize>() > TYPE_SIZE_LIMIT {
+                let message = format!("instantiating `{}` does not terminate: its type arguments grow without bound", generic);
+                self.diagnostics.push(Diagnostic::error(span, message));
+            } else {
+                self.queue.push_back(Instance {
+                    generic: generic.to_string(),
+                    name: name.clone(),
+                    args,
+                });
+            }
+        }
+        name
+    }
+
+    fn instantiate(&mut self, instance: Instance) -> Item {
+        let mut item = self.generics[instance.generic.as_str()].clone();
+        let type_params = match &mut item.kind {
+            ItemKind::Function(function) => {
+                function.name = instance.name;
+                std::mem::take(&mut function.type_params)
+            }
+            ItemKind::Struct(definition) => {
+                definition.name = instance.name;
+                std::mem::take(&mut definition.type_params)
+            }
+            ItemKind::Enum(definition) => {
+                definition.name = instance.name;
+                std::mem::take(&mut definition.type_params)
+            }
+            ItemKind::Global(_) | ItemKind::Const(_) | ItemKind::Interface(_) | ItemKind::Impl(_) | ItemKind::Import(_) | ItemKind::Extern(_) => {
+                unreachable!("only functions, structs and enums are generic")
+            }
+        };
+        let bindings = type_params.into_iter().map(|param| param.name).zip(instance.args).collect();
+        Rewriter {
+            monomorphizer: self,
+            bindings,
+        }
+        .visit_item_mut(&mut item);
+        item
+    }
+}
+
+/// Rewrites a copy of an item for concrete type arguments: type parameters
+/// are replaced by their arguments and uses of generic items by the names of
+/// their instances.
+struct Rewriter<'m, 'a> {
+    monomorphizer: &'m mut Monomorphizer<'a>,
+    bindings: HashMap<String, Type>,
+}
+
+impl Rewriter<'_, '_> {
+    /// The concrete type written for a checked type from inside the item.
+    fn concrete(&mut self, ty: &Ty, span: Span) -> Type {
+        let kind = match ty {
+            Ty::Int(ty) => TypeKind::Int(*ty),
+            Ty::Bool => TypeKind::Bool,
+            Ty::String => TypeKind::String,
+            Ty::Ptr => TypeKind::Ptr,
+            Ty::Void => TypeKind::Void,
+            Ty::Array(element, size) => TypeKind::Array {
+                element: Box::new(self.concrete(element, span)),
+                size: ArraySize::Literal(*size),
+            },
+            Ty::Tuple(elements) => TypeKind::Tuple(elements.iter().map(|element| self.concrete(element, span)).collect()),
+            Ty::Struct(name, args) | Ty::Enum(name, args) => TypeKind::Named {
+                name: name.clone(),
+                args: args.iter().map(|arg| self.concrete(arg, span)).collect(),
+            },
+            Ty::Param(name) => TypeKind::Named {
+                name: name.clone(),
+                args: Vec::new(),
+            },
+            Ty::Function(params, return_type) => TypeKind::Function {
+                params: params.iter().map(|param| self.concrete(param, span)).collect(),
+                return_type: Some(Box::new(self.concrete(return_type, span))),
+            },
+            Ty::Dyn(interface) => TypeKind::Dyn(interface.clone()),
+            Ty::Error | Ty::Infer => unreachable!("monomorphize requires a program without type errors"),
+        };
+        let mut ty = Type {
+            id: NodeId(0),
+            span,
+            kind,
+        };
+        self.visit_type_mut(&mut ty);
+        ty
+    }
+
+    /// Renames the enum of the variant path `path` to its instance for the
+    /// type `ty` of the value, if the enum is generic.
+    fn variant_path(&mut self, path: &mut [String], ty: Option<&Ty>, span: Span) {
+        let [.., enum_name, _] = path else {
+            return;
+        };
+        if !self.monomorphizer.generics.contains_key(enum_name.as_str()) {
+            return;
+        }
+        if let Some(Ty::Enum(_, args)) = ty {
+            let args = args.iter().map(|arg| self.concrete(arg, span)).collect();
+            *enum_name = self.monomorphizer.request(enum_name, args, span);
+        }
+    }
+}
+
+impl VisitorMut for Rewriter<'_, '_> {
+    fn visit_type_mut(&mut self, ty: &mut Type) {
+        visit::walk_type_mut(self, ty);
+        if let TypeKind::Array { size, .. } = &mut ty.kind {
+            if let ArraySize::Const(name) = size {
+                let Some(ConstValue::Int(value)) = self.monomorphizer.types.constant(name) else {
+                    unreachable!("monomorphize requires a program without type errors")
+                };
+                *size = ArraySize::Literal(value as u32);
+            }
+            return;
+        }
+        let TypeKind::Named { name, args } = &mut ty.kind else {
+            return;
+        };
+        if let Some(arg) = self.bindings.get(name).filter(|_| args.is_empty()) {
+            ty.kind = arg.kind.clone();
+        } else if self.monomorphizer.generics.contains_key(name.as_str()) {
+            *name = self.monomorphizer.request(name, std::mem::take(args), ty.span);
+        }
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        let types = self.monomorphizer.types;
+        match &mut expr.kind {
+            ExprKind::Identifier(name) => {
+                if let Some(args) = types.instantiation(expr.id) {
+                    let args = args.iter().map(|arg| self.concrete(arg, expr.span)).collect();
+                    *name = self.monomorphizer.request(name, args, expr.span);
+                }
+            }
+            ExprKind::Path(path) => self.variant_path(path, types.type_of(expr.id), expr.span),
+            ExprKind::Call { callee, .. } => {
+                if let ExprKind::Path(path) = &mut callee.kind {
+                    self.variant_path(path, types.type_of(expr.id), expr.span);
+                }
+            }
+            ExprKind::StructLiteral { name, .. } if self.monomorphizer.generics.contains_key(name.as_str()) => {
+                if let Some(Ty::Struct(_, args)) = types.type_of(expr.id) {
+                    let args = args.iter().map(|arg| self.concrete(arg, expr.span)).collect();
+                    *name = self.monomorphizer.request(name, args, expr.span);
+                }
+            }
+            _ => {}
+        }
+        visit::walk_expr_mut(self, expr);
+    }
+
+    fn visit_pattern_mut(&mut self, pattern: &mut Pattern) {
+        if let PatternKind::Variant { path, .. } = &mut pattern.kind {
+            self.variant_path(path, self.monomorphizer.types.type_of(pattern.id), pattern.span);
+        }
+        visit::walk_pattern_mut(self, pattern);
+    }
+}
+
+fn type_size(ty: &Type) -> usize {
+    1 + match &ty.kind {
+        TypeKind::Int(_) | TypeKind::Bool | TypeKind::String | TypeKind::Ptr | TypeKind::Void | TypeKind::Dyn(_) => 0,
+        TypeKind::Array { element, .. } => type_size(element),
+        TypeKind::Named { args: types, .. } | TypeKind::Tuple(types) => types.iter().map(type_size).sum(),
+        TypeKind::Function { params, return_type } => {
+            params.iter().map(type_size).sum::<usize>() + return_type.as_deref().map_or(0, type_size)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::prelude;
+    use crate::printer::print_program;
+    use crate::typeck::check_program;
+
+    /// The monomorphised program and the diagnostics of checking it again.
+    fn monomorphized(source: &str) -> (Program, Vec<String>) {
+        let mut lexer = Lexer::new(source.to_string());
+        let program = Parser::new(&mut lexer).parse_program();
+        let (types, diagnostics) = check_program(&program);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let (program, mut diagnostics) = monomorphize(&program, &types);
+        if diagnostics.is_empty() {
+            diagnostics = check_program(&program).1;
+        }
+        (program, diagnostics.into_iter().map(|diagnostic| diagnostic.message).collect())
+    }
+
+    #[test]
+    fn instantiates_generic_items_for_each_use() {
+        let (program, diagnostics) = monomorphized(
+            "struct Pair<A, B> { first: A, second: B } \
+             function swap<A, B>(p: Pair<A, B>) -> Pair<B, A> { return Pair { first: p.second, second: p.first }; } \
+             function pick<T>(first: bool, a: T, b: T) -> T { if first { return a; } return b; } \
+             function main() { let p = swap(Pair { first: 1, second: true }); let f: fn(bool, int, int) -> int = pick; pick(true, p, swap(swap(p))); }",
+        );
+        assert_eq!(diagnostics, Vec::<String>::new());
+        assert_eq!(
+            print_program(&program),
+            "function main() {\n\
+             \x20   let p = swap<int, bool>(Pair<int, bool> { first: 1, second: true });\n\
+             \x20   let f: fn(bool, int, int) -> int = pick<int>;\n\
+             \x20   pick<Pair<bool, int>>(true, p, swap<int, bool>(swap<bool, int>(p)));\n\
+             }\n\
+             \n\
+             function swap<int, bool>(p: Pair<int, bool>) -> Pair<bool, int> {\n\
+             \x20   return Pair<bool, int> { first: p.second, second: p.first };\n\
+             }\n\
+             \n\
+             struct Pair<int, bool> {\n\
+             \x20   first: int,\n\
+             \x20   second: bool,\n\
+             }\n\
+             \n\
+             function pick<int>(first: bool, a: int, b: int) -> int {\n\
+             \x20   if first {\n\
+             \x20       return a;\n\
+             \x20   }\n\
+             \x20   return b;\n\
+             }\n\
+             \n\
+             struct Pair<bool, int> {\n\
+             \x20   first: bool,\n\
+             \x20   second: int,\n\
+             }\n\
+             \n\
+             function pick<Pair<bool, int>>(first: bool, a: Pair<bool, int>, b: Pair<bool, int>) -> Pair<bool, int> {\n\
+             \x20   if first {\n\
+             \x20       return a;\n\
+             \x20   }\n\
+             \x20   return b;\n\
+             }\n\
+             \n\
+             function swap<bool, int>(p: Pair<bool, int>) -> Pair<int, bool> {\n\
+             \x20   return Pair<int, bool> { first: p.second, second: p.first };\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn instantiates_generic_bodies_transitively_with_fresh_ids() {
+        let (program, diagnostics) = monomorphized(
+            "struct Box<T> { value: T } function wrap<T>(value: T) -> Box<T> { let f = |x: T| Box { value: x }; return f(value); } \
+             function twice<T>(value: T) -> Box<Box<T>> { return wrap(wrap(value)); } function main() { twice((1, true)).value.value.0; }",
+        );
+        assert_eq!(diagnostics, Vec::<String>::new());
+        let names: Vec<String> = program
+            .items
+            .iter()
+            .map(|item| match &item.kind {
+                ItemKind::Function(function) => function.name.clone(),
+                ItemKind::Struct(definition) => definition.name.clone(),
+                _ => String::new(),
+            })
+            .collect();
+        assert_eq!(
+            names,
+            ["main", "twice<(int, bool)>", "Box<(int, bool)>", "Box<Box<(int, bool)>>", "wrap<Box<(int, bool)>>", "wrap<(int, bool)>"]
+        );
+
+        struct Ids(Vec<NodeId>);
+        impl VisitorMut for Ids {
+            fn visit_expr_mut(&mut self, expr: &mut Expr) {
+                self.0.push(expr.id);
+                visit::walk_expr_mut(self, expr);
+            }
+        }
+        let mut ids = Ids(Vec::new());
+        ids.visit_program_mut(&mut program.clone());
+        let unique: HashSet<NodeId> = ids.0.iter().copied().collect();
+        assert_eq!(unique.len(), ids.0.len());
+    }
+
+    #[test]
+    fn dispatches_bounded_calls_statically_in_each_copy() {
+        let (program, diagnostics) = monomorphized(
+            "interface Show { function show(self) -> int; } struct P { x: int } \
+             impl Show for P { function show(self) -> int { return self.x; } } \
+             function describe<T: Show>(x: T) -> int { return x.show(); } function main() { let d: dyn Show = P { x: 1 }; describe(d) + describe(P { x: 2 }); }",
+        );
+        assert_eq!(diagnostics, Vec::<String>::new());
+        let printed = print_program(&program);
+        assert!(printed.contains("function describe<dyn Show>(x: dyn Show) -> int {"), "{}", printed);
+        assert!(printed.contains("function describe<P>(x: P) -> int {"), "{}", printed);
+        let (types, _) = check_program(&program);
+        let mut calls = Vec::new();
+        struct Methods<'t>(&'t TypeTable, &'t mut Vec<String>);
+        impl VisitorMut for Methods<'_> {
+            fn visit_expr_mut(&mut self, expr: &mut Expr) {
+                if let (ExprKind::Field { base, .. }, Some(interface)) = (&expr.kind, self.0.method_interface(expr.id)) {
+                    self.1.push(format!("{} for {}", interface, self.0.type_of(base.id).map_or(String::new(), Ty::to_string)));
+                }
+                visit::walk_expr_mut(self, expr);
+            }
+        }
+        Methods(&types, &mut calls).visit_program_mut(&mut program.clone());
+        assert_eq!(calls, ["Show for dyn Show", "Show for P"]);
+    }
+
+    #[test]
+    fn stops_instantiations_that_do_not_terminate() {
+        let (_, diagnostics) = monomorphized("function grow<T>(x: T) { grow((x, x)); } function main() { grow(1); }");
+        assert_eq!(diagnostics, ["instantiating `grow` does not terminate: its type arguments grow without bound"]);
+    }
+
+    #[test]
+    fn replaces_constant_array_sizes_by_their_values() {
+        let (program, diagnostics) = monomorphized(
+            "const N: int = 1 + 1; struct Stack<T> { items: T[N] } \
+             function main() -> int { let s: Stack<bool> = Stack { items: [true, false] }; let c: int[N] = [N, N]; return c[0]; }",
+        );
+        assert_eq!(diagnostics, Vec::<String>::new());
+        assert_eq!(
+            print_program(&program),
+            "const N: int = 1 + 1;\n\
+             \n\
+             function main() -> int {\n\
+             \x20   let s: Stack<bool> = Stack<bool> { items: [true, false] };\n\
+             \x20   let c: int[2] = [N, N];\n\
+             \x20   return c[0];\n\
+             }\n\
+             \n\
+             struct Stack<bool> {\n\
+             \x20   items: bool[2],\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn instantiates_generic_enums_and_their_variants() {
+        let (program, diagnostics) = monomorphized(&format!(
+            "{} function half(n: int) -> Option<int> {{ if n > 1 {{ return Option::Some(n / 2); }} return Option::None; }} \
+             function quarter(n: int) -> Option<int> {{ return half(half(n)?); }} \
+             function main() -> int {{ match quarter(8) {{ Option::Some(q) => q, Option::None => 0 }} }}",
+            prelude::SOURCE
+        ));
+        assert_eq!(diagnostics, Vec::<String>::new());
+        assert_eq!(
+            print_program(&program),
+            "function half(n: int) -> Option<int> {\n\
+             \x20   if n > 1 {\n\
+             \x20       return Option<int>::Some(n / 2);\n\
+             \x20   }\n\
+             \x20   return Option<int>::None;\n\
+             }\n\
+             \n\
+             function quarter(n: int) -> Option<int> {\n\
+             \x20   return half(half(n)?);\n\
+             }\n\
+             \n\
+             function main() -> int {\n\
+             \x20   match quarter(8) {\n\
+             \x20       Option<int>::Some(q) => q,\n\
+             \x20       Option<int>::None => 0,\n\
+             \x20   }\n\
+             }\n\
+             \n\
+             pub enum Option<int> {\n\
+             \x20   None,\n\
+             \x20   Some(int),\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn instantiates_variants_nested_in_generic_calls_and_variants() {
+        let (program, diagnostics) = monomorphized(&format!(
+            "{} function id<T>(t: T) -> T {{ return t; }} function main() {{ let x = id(Option::Some(1)); let y = Option::Some(Option::Some(1)); }}",
+            prelude::SOURCE
+        ));
+        assert_eq!(diagnostics, Vec::<String>::new());
+        let printed = print_program(&program);
+        assert!(printed.contains("let x = id<Option<int>>(Option<int>::Some(1));"), "{}", printed);
+        assert!(printed.contains("let y = Option<Option<int>>::Some(Option<int>::Some(1));"), "{}", printed);
+    }
+}
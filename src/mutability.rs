@@ -0,0 +1,225 @@
+//! Mutability checking.
+//!
+//! Bindings are immutable unless declared `mut`: assigning to a variable, or
+//! to an element or field of one, is an error unless the binding that
+//! declared it is `mut`. Globals are no exception, and constants can never be
+//! assigned to. A `mut` binding that is never assigned to gets a warning, as
+//! the `mut` can be removed.
+
+use crate::captures::assigned_variable;
+use crate::diagnostic::Diagnostic;
+use crate::lexer::Span;
+use crate::parser::{
+    ArmBody, Block, ClosureParam, Expr, ExprKind, Function, ItemKind, Let, MatchArm, MethodSig, Param, Pattern, PatternKind,
+    Program, Stmt, StmtKind,
+};
+use crate::visit::{self, Visitor};
+use std::collections::HashSet;
+
+pub fn check_program(program: &Program) -> Vec<Diagnostic> {
+    let constants = program
+        .items
+        .iter()
+        .filter_map(|item| match &item.kind {
+            ItemKind::Const(constant) => Some(constant.name.clone()),
+            _ => None,
+        })
+        .collect();
+    let mut checker = Checker {
+        scopes: Vec::new(),
+        constants,
+        diagnostics: Vec::new(),
+    };
+    checker.in_scope(|checker| visit::walk_program(checker, program));
+    checker.diagnostics
+}
+
+struct Binding {
+    name: String,
+    span: Span,
+    mutable: bool,
+    assigned: bool,
+}
+
+struct Checker {
+    /// Variables in scope, innermost last; the first scope holds the globals.
+    scopes: Vec<Vec<Binding>>,
+    constants: HashSet<String>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker {
+    fn bind(&mut self, pattern: &Pattern) {
+        match &pattern.kind {
+            PatternKind::Binding { name, mutable } => self.scopes.last_mut().expect("no scope to bind in").push(Binding {
+                name: name.clone(),
+                span: pattern.span,
+                mutable: *mutable,
+                assigned: false,
+            }),
+            PatternKind::Wildcard | PatternKind::IntLiteral(_) | PatternKind::BoolLiteral(_) => {}
+            PatternKind::Variant { fields: elements, .. } | PatternKind::Tuple(elements) => {
+                for element in elements {
+                    self.bind(element);
+                }
+            }
+        }
+    }
+
+    /// Walks in a new scope, then warns about its `mut` bindings that were
+    /// never assigned to.
+    fn in_scope(&mut self, walk: impl FnOnce(&mut Self)) {
+        self.scopes.push(Vec::new());
+        walk(self);
+        for binding in self.scopes.pop().expect("pushed above") {
+            if binding.mutable && !binding.assigned {
+                let message = format!("variable `{}` is declared `mut` but never assigned to", binding.name);
+                self.diagnostics.push(Diagnostic::warning(binding.span, message));
+            }
+        }
+    }
+
+    fn assign(&mut self, name: &str, span: Span) {
+        let binding = self.scopes.iter_mut().rev().find_map(|scope| scope.iter_mut().rev().find(|binding| binding.name == name));
+        let diagnostic = match binding {
+            Some(binding) if binding.mutable => {
+                binding.assigned = true;
+                return;
+            }
+            Some(binding) => Diagnostic::error(span, format!("cannot assign to immutable variable `{}`", name))
+                .with_note(binding.span, format!("declare it mutable: `mut {}`", name)),
+            None if self.constants.contains(name) => Diagnostic::error(span, format!("cannot assign to constant `{}`", name)),
+            // Unknown names are reported by the type checker.
+            None => return,
+        };
+        self.diagnostics.push(diagnostic);
+    }
+}
+
+impl Visitor for Checker {
+    fn visit_function(&mut self, function: &Function) {
+        self.in_scope(|checker| visit::walk_function(checker, function));
+    }
+
+    fn visit_param(&mut self, param: &Param) {
+        self.bind(&param.pattern);
+    }
+
+    /// An interface's method signatures have no body to bind their
+    /// parameters in.
+    fn visit_method_sig(&mut self, _: &MethodSig) {}
+
+    fn visit_closure_param(&mut self, param: &ClosureParam) {
+        self.bind(&param.pattern);
+    }
+
+    /// The value is walked before the pattern binds, so `let x = x;` refers
+    /// to the outer `x`.
+    fn visit_let(&mut self, binding: &Let) {
+        self.visit_expr(&binding.value);
+        self.bind(&binding.pattern);
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        self.in_scope(|checker| visit::walk_block(checker, block));
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::For { .. } => self.in_scope(|checker| visit::walk_stmt(checker, stmt)),
+            _ => visit::walk_stmt(self, stmt),
+        }
+    }
+
+    fn visit_match_arm(&mut self, arm: &MatchArm) {
+        self.in_scope(|checker| {
+            checker.bind(&arm.pattern);
+            if let Some(guard) = &arm.guard {
+                checker.visit_expr(guard);
+            }
+            match &arm.body {
+                ArmBody::Expr(body) => checker.visit_expr(body),
+                ArmBody::Block(block) => checker.visit_block(block),
+            }
+        });
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Assign { target, .. } => {
+                if let Some(name) = assigned_variable(target) {
+                    self.assign(name, target.span);
+                }
+                visit::walk_expr(self, expr);
+            }
+            ExprKind::Closure { .. } => self.in_scope(|checker| visit::walk_expr(checker, expr)),
+            _ => visit::walk_expr(self, expr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn messages(source: &str) -> Vec<String> {
+        let mut lexer = Lexer::new(source.to_string());
+        let program = Parser::new(&mut lexer).parse_program();
+        check_program(&program).into_iter().map(|diagnostic| format!("{}: {}", diagnostic.severity, diagnostic.message)).collect()
+    }
+
+    #[test]
+    fn allows_assignments_to_mut_bindings() {
+        assert_eq!(
+            messages(
+                "let mut total = 0; function f(mut n: int, (mut a, b): (int, int)) { let mut p = (n, b); p.0 = a; a = n; \
+                 for (let mut i = 0; i < n; i = i + 1) { total = total + i; } match p { (mut x, _) => { x = x + 1; n = x; } } \
+                 let add = |mut y: int| { y = y + b; }; }"
+            ),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn reports_assignments_to_immutable_bindings() {
+        assert_eq!(
+            messages(
+                "const N: int = 1; let g = 0; function f(n: int) { let t = (n, n); t.0 = 1; n = 2; g = 3; N = 4; \
+                 let mut m = 0; let c = || { m = n; let m = 1; m = 2; }; }"
+            ),
+            [
+                "error: cannot assign to immutable variable `t`",
+                "error: cannot assign to immutable variable `n`",
+                "error: cannot assign to immutable variable `g`",
+                "error: cannot assign to constant `N`",
+                "error: cannot assign to immutable variable `m`"
+            ]
+        );
+    }
+
+    #[test]
+    fn suggests_declaring_the_binding_mutable() {
+        let source = "function f() {\n    let count = 0;\n    count = count + 1;\n}";
+        let mut lexer = Lexer::new(source.to_string());
+        let diagnostics = check_program(&Parser::new(&mut lexer).parse_program());
+        assert_eq!(
+            diagnostics[0].render("main.cl", source),
+            "main.cl:3:5: error: cannot assign to immutable variable `count`\n        count = count + 1;\n        ^^^^^\n\
+             main.cl:2:9: note: declare it mutable: `mut count`\n        let count = 0;\n            ^^^^^\n"
+        );
+    }
+
+    #[test]
+    fn warns_about_mut_bindings_that_are_never_assigned() {
+        assert_eq!(
+            messages("let mut g = 0; function f(mut n: int) { let (mut a, b) = (n, g); let mut used = a; used = b; }"),
+            [
+                "warning: variable `a` is declared `mut` but never assigned to",
+                "warning: variable `n` is declared `mut` but never assigned to",
+                "warning: variable `g` is declared `mut` but never assigned to"
+            ]
+        );
+    }
+}
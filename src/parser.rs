@@ -1,288 +1,2654 @@
-use crate::lexer::{Lexer, Token};
+use crate::diagnostic::Diagnostic;
+use crate::lexer::{IntType, Lexer, Span, Token};
+use std::fmt;
 
-#[derive(Debug)]
-pub enum ASTNode {
-    Program(Vec<ASTNode>),
-    Function {
-        name: String,
-        params: Vec<String>,
-        body: Box<ASTNode>,
+/// Identifies a node within one parsed program. Ids are handed out in the
+/// order the parser finishes nodes, so the same source always yields the same
+/// ids and later passes can key side tables by them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(pub u32);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub id: NodeId,
+    pub span: Span,
+    pub items: Vec<Item>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    pub id: NodeId,
+    pub span: Span,
+    /// The attributes before the item, in source order.
+    pub attributes: Vec<Attribute>,
+    /// Declared `pub`, so other modules may refer to it.
+    pub public: bool,
+    pub kind: ItemKind,
+}
+
+impl Item {
+    /// The attribute `@name` of the item, if it has one.
+    pub fn attribute(&self, name: &str) -> Option<&Attribute> {
+        self.attributes.iter().find(|attribute| attribute.name == name)
+    }
+}
+
+/// `@name` or `@name("argument", ...)` before an item or an impl method,
+/// such as `@inline` or `@deprecated("use g")`. `attributes::check_program` checks them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attribute {
+    pub id: NodeId,
+    pub span: Span,
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ItemKind {
+    Function(Function),
+    Global(Let),
+    Const(Const),
+    Struct(Struct),
+    Enum(Enum),
+    Interface(Interface),
+    Impl(Impl),
+    Import(Import),
+    Extern(Extern),
+}
+
+/// `extern "C" function name(param: type, ...) -> type;`, a function defined
+/// outside the program, such as in libc, and called with the C calling
+/// convention.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Extern {
+    /// The calling convention as written; the type checker only accepts `"C"`.
+    pub abi: String,
+    pub name: String,
+    pub params: Vec<Param>,
+    /// The parameters end with `...`, so calls may pass more arguments.
+    pub variadic: bool,
+    pub return_type: Option<Type>,
+}
+
+/// `import math;` or `import "util/math.cl";`. Names from the module are
+/// written qualified by its name, as in `math::sqrt`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    /// The module name or file path as written.
+    pub path: String,
+}
+
+impl Import {
+    /// The module's file, relative to the directory of the root file:
+    /// `math.cl` for `import math;`.
+    pub fn file(&self) -> String {
+        if self.path.ends_with(".cl") {
+            self.path.clone()
+        } else {
+            format!("{}.cl", self.path)
+        }
+    }
+
+    /// The name that qualifies the module's items: its file name without
+    /// the `.cl` extension.
+    pub fn module_name(&self) -> &str {
+        let file = self.path.rsplit('/').next().unwrap_or(&self.path);
+        file.strip_suffix(".cl").unwrap_or(file)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    /// Declared `const function`, so constant initializers can call it.
+    pub constant: bool,
+    pub name: String,
+    /// `<T, ...>` after the name; empty for an ordinary function.
+    pub type_params: Vec<TypeParam>,
+    pub params: Vec<Param>,
+    pub return_type: Option<Type>,
+    pub body: Block,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub id: NodeId,
+    pub span: Span,
+    /// A name, or a pattern that destructures the argument. A bare `self`
+    /// parameter is the binding `self` with the type `Self`.
+    pub pattern: Pattern,
+    pub ty: Type,
+}
+
+/// A type parameter such as the `T` of `function max<T>(a: T, b: T) -> T`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeParam {
+    pub id: NodeId,
+    pub span: Span,
+    pub name: String,
+    /// The interfaces named by `T: Show + Eq`.
+    pub bounds: Vec<String>,
+}
+
+/// `interface Name { function method(self, ...) -> type; ... }`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interface {
+    pub name: String,
+    pub methods: Vec<MethodSig>,
+}
+
+/// A method declared by an interface, without a body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MethodSig {
+    pub id: NodeId,
+    pub span: Span,
+    pub name: String,
+    pub params: Vec<Param>,
+    pub return_type: Option<Type>,
+}
+
+/// `impl Interface for type { function method(self, ...) { ... } ... }`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Impl {
+    pub interface: String,
+    pub ty: Type,
+    pub methods: Vec<Method>,
+}
+
+/// A method defined by an `impl`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Method {
+    pub id: NodeId,
+    pub span: Span,
+    /// The attributes before the method, in source order.
+    pub attributes: Vec<Attribute>,
+    pub function: Function,
+}
+
+impl Method {
+    /// The attribute `@name` of the method, if it has one.
+    pub fn attribute(&self, name: &str) -> Option<&Attribute> {
+        self.attributes.iter().find(|attribute| attribute.name == name)
+    }
+}
+
+/// `struct Name<T, ...> { field: type, ... }`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Struct {
+    pub name: String,
+    pub type_params: Vec<TypeParam>,
+    pub fields: Vec<Field>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub id: NodeId,
+    pub span: Span,
+    pub name: String,
+    pub ty: Type,
+}
+
+/// `enum Name<T, ...> { Variant, Variant(type, ...), ... }`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Enum {
+    pub name: String,
+    pub type_params: Vec<TypeParam>,
+    pub variants: Vec<Variant>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub id: NodeId,
+    pub span: Span,
+    pub name: String,
+    /// Payload types; empty for a variant without parentheses.
+    pub fields: Vec<Type>,
+}
+
+/// A `let` binding, either a global item or a local statement.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Let {
+    /// A name, or a pattern such as `(q, r)` that destructures the value.
+    pub pattern: Pattern,
+    pub ty: Option<Type>,
+    pub value: Expr,
+}
+
+/// `const NAME: type = value;`, a global whose value the compiler computes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Const {
+    pub name: String,
+    pub ty: Type,
+    pub value: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Type {
+    pub id: NodeId,
+    pub span: Span,
+    pub kind: TypeKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeKind {
+    /// `int` or a sized integer type such as `u8`.
+    Int(IntType),
+    Bool,
+    String,
+    /// `ptr`, an untyped pointer for passing data to and from C.
+    Ptr,
+    Void,
+    /// `element[size]`; `int[4][2]` is an array of two `int[4]`s.
+    Array {
+        element: Box<Type>,
+        size: ArraySize,
     },
-    Let {
+    /// A user-defined type or type parameter referred to by name, with the
+    /// type arguments of a generic struct such as `Pair<int, bool>`.
+    Named {
         name: String,
-        value: Box<ASTNode>,
+        args: Vec<Type>,
     },
-    Return(Box<ASTNode>),
+    /// `(type, ...)`; `()` is the empty tuple and `(int,)` has one element.
+    Tuple(Vec<Type>),
+    /// `fn(type, ...) -> type`; without an arrow the function returns `void`.
+    Function {
+        params: Vec<Type>,
+        return_type: Option<Box<Type>>,
+    },
+    /// `dyn Interface`, a value of any type that implements the interface.
+    Dyn(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArraySize {
+    Literal(u32),
+    /// A constant, such as `N` in `int[N]`.
+    Const(String),
+}
+
+impl fmt::Display for ArraySize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArraySize::Literal(size) => write!(f, "{}", size),
+            ArraySize::Const(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub id: NodeId,
+    pub span: Span,
+    pub stmts: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub id: NodeId,
+    pub span: Span,
+    pub kind: StmtKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
+    Let(Let),
+    Expr(Expr),
+    Return(Option<Expr>),
     If {
-        condition: Box<ASTNode>,
-        then_branch: Box<ASTNode>,
-        else_branch: Option<Box<ASTNode>>,
+        condition: Expr,
+        then_branch: Block,
+        else_branch: Option<Block>,
     },
     For {
-        init: Box<ASTNode>,
-        condition: Box<ASTNode>,
-        increment: Box<ASTNode>,
-        body: Box<ASTNode>,
+        init: Option<Box<Stmt>>,
+        condition: Option<Expr>,
+        increment: Option<Expr>,
+        body: Block,
     },
     While {
-        condition: Box<ASTNode>,
-        body: Box<ASTNode>,
+        condition: Expr,
+        body: Block,
+    },
+    /// `defer stmt`, which runs `stmt` when the enclosing block is left,
+    /// after any statements deferred later in it.
+    Defer(Box<Stmt>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub id: NodeId,
+    pub span: Span,
+    pub kind: ExprKind,
+}
+
+/// `pattern [if guard] => body` in a `match`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchArm {
+    pub id: NodeId,
+    pub span: Span,
+    pub pattern: Pattern,
+    pub guard: Option<Expr>,
+    pub body: ArmBody,
+}
+
+/// The body of a match arm or closure.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArmBody {
+    Expr(Expr),
+    Block(Block),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    pub id: NodeId,
+    pub span: Span,
+    pub kind: PatternKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatternKind {
+    /// `_`
+    Wildcard,
+    /// `name`, or `mut name` for a variable that may be assigned to.
+    Binding {
+        name: String,
+        mutable: bool,
+    },
+    /// An integer, negative if written with a leading `-`.
+    IntLiteral(i128),
+    BoolLiteral(bool),
+    /// `Enum::Variant` or `Enum::Variant(pattern, ...)`.
+    Variant {
+        path: Vec<String>,
+        fields: Vec<Pattern>,
+    },
+    /// `(pattern, ...)`.
+    Tuple(Vec<Pattern>),
+}
+
+/// A closure parameter; the type may be left out when it can be inferred
+/// from the function type the closure is used as.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClosureParam {
+    pub id: NodeId,
+    pub span: Span,
+    pub pattern: Pattern,
+    pub ty: Option<Type>,
+}
+
+/// One `field: value` entry of a struct literal.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldInit {
+    pub id: NodeId,
+    pub span: Span,
+    pub name: String,
+    pub value: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Unary {
+        op: UnaryOp,
+        operand: Box<Expr>,
+    },
+    Binary {
+        op: BinaryOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    Assign {
+        target: Box<Expr>,
+        value: Box<Expr>,
+    },
+    Call {
+        callee: Box<Expr>,
+        args: Vec<Expr>,
+    },
+    Index {
+        base: Box<Expr>,
+        index: Box<Expr>,
+    },
+    Field {
+        base: Box<Expr>,
+        name: String,
+    },
+    /// `tuple.0`.
+    TupleIndex {
+        base: Box<Expr>,
+        index: u32,
+    },
+    ArrayLiteral(Vec<Expr>),
+    /// `(a, b)`; `()` is the empty tuple and `(a,)` has one element.
+    Tuple(Vec<Expr>),
+    /// `Name { field: value, ... }`.
+    StructLiteral {
+        name: String,
+        fields: Vec<FieldInit>,
     },
-    BinaryOp {
-        op: Token,
-        left: Box<ASTNode>,
-        right: Box<ASTNode>,
+    /// `match scrutinee { arm, ... }`.
+    Match {
+        scrutinee: Box<Expr>,
+        arms: Vec<MatchArm>,
+    },
+    /// `|param, ...| body`, or `|param, ...| -> type { ... }`. An expression
+    /// body extends as far to the right as possible.
+    Closure {
+        params: Vec<ClosureParam>,
+        return_type: Option<Type>,
+        body: Box<ArmBody>,
+    },
+    /// A qualified name such as `Shape::Circle`.
+    Path(Vec<String>),
+    /// `value?`: the payload of an `Option::Some` or `Result::Ok`, or else
+    /// an early return of the `Option::None` or `Result::Err`.
+    Try(Box<Expr>),
+    /// `value as type`, which converts between integer types: a narrower
+    /// type keeps the low bits, a wider one sign-extends a signed value and
+    /// zero-extends an unsigned one.
+    Cast {
+        operand: Box<Expr>,
+        ty: Type,
     },
     Identifier(String),
-    IntLiteral(i32),
+    /// An integer as written; `-1` is the negation of the literal `1`.
+    IntLiteral(u64),
     BoolLiteral(bool),
+    /// A string literal without holes, with its escapes decoded.
+    StringLiteral(String),
+    /// `"x = {x}"`: a string literal with the values of expressions
+    /// formatted into it.
+    Interpolation(Vec<StringPart>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringPart {
+    Text(String),
+    /// A `{...}` hole.
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    /// `<<`. Shifting by a negative amount, or by at least the width of
+    /// the left operand, is an error.
+    Shl,
+    /// `>>`, which is arithmetic for signed and logical for unsigned types.
+    Shr,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl UnaryOp {
+    fn from_token(token: &Token) -> Option<UnaryOp> {
+        match token {
+            Token::Minus => Some(UnaryOp::Neg),
+            Token::Bang => Some(UnaryOp::Not),
+            _ => None,
+        }
+    }
+
+    pub fn token(self) -> Token {
+        match self {
+            UnaryOp::Neg => Token::Minus,
+            UnaryOp::Not => Token::Bang,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            UnaryOp::Neg => "-",
+            UnaryOp::Not => "!",
+        }
+    }
+}
+
+impl BinaryOp {
+    fn from_token(token: &Token) -> Option<BinaryOp> {
+        match token {
+            Token::Plus => Some(BinaryOp::Add),
+            Token::Minus => Some(BinaryOp::Sub),
+            Token::Star => Some(BinaryOp::Mul),
+            Token::Slash => Some(BinaryOp::Div),
+            Token::LessLess => Some(BinaryOp::Shl),
+            Token::GreaterGreater => Some(BinaryOp::Shr),
+            Token::EqualEqual => Some(BinaryOp::Eq),
+            Token::BangEqual => Some(BinaryOp::Ne),
+            Token::Less => Some(BinaryOp::Lt),
+            Token::LessEqual => Some(BinaryOp::Le),
+            Token::Greater => Some(BinaryOp::Gt),
+            Token::GreaterEqual => Some(BinaryOp::Ge),
+            _ => None,
+        }
+    }
+
+    pub fn token(self) -> Token {
+        match self {
+            BinaryOp::Add => Token::Plus,
+            BinaryOp::Sub => Token::Minus,
+            BinaryOp::Mul => Token::Star,
+            BinaryOp::Div => Token::Slash,
+            BinaryOp::Shl => Token::LessLess,
+            BinaryOp::Shr => Token::GreaterGreater,
+            BinaryOp::Eq => Token::EqualEqual,
+            BinaryOp::Ne => Token::BangEqual,
+            BinaryOp::Lt => Token::Less,
+            BinaryOp::Le => Token::LessEqual,
+            BinaryOp::Gt => Token::Greater,
+            BinaryOp::Ge => Token::GreaterEqual,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Shl => "<<",
+            BinaryOp::Shr => ">>",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Associativity {
+    Left,
+    Right,
+}
+
+/// How an operator token binds when it appears in prefix, infix or postfix
+/// position. A `None` slot means the token cannot be used in that position.
+pub struct OperatorInfo {
+    pub token: Token,
+    pub prefix: Option<u8>,
+    pub infix: Option<(u8, Associativity)>,
+    pub postfix: Option<u8>,
+}
+
+const fn infix(token: Token, binding_power: u8, associativity: Associativity) -> OperatorInfo {
+    OperatorInfo { token, prefix: None, infix: Some((binding_power, associativity)), postfix: None }
+}
+
+/// Binding powers for every operator the expression parser understands.
+/// Higher numbers bind tighter; adding an operator only requires a new row.
+pub const OPERATOR_TABLE: &[OperatorInfo] = &[
+    infix(Token::Equals, 1, Associativity::Right),
+    infix(Token::EqualEqual, 2, Associativity::Left),
+    infix(Token::BangEqual, 2, Associativity::Left),
+    infix(Token::Less, 3, Associativity::Left),
+    infix(Token::LessEqual, 3, Associativity::Left),
+    infix(Token::Greater, 3, Associativity::Left),
+    infix(Token::GreaterEqual, 3, Associativity::Left),
+    infix(Token::LessLess, 4, Associativity::Left),
+    infix(Token::GreaterGreater, 4, Associativity::Left),
+    infix(Token::Plus, 5, Associativity::Left),
+    OperatorInfo { token: Token::Minus, prefix: Some(8), infix: Some((5, Associativity::Left)), postfix: None },
+    infix(Token::Star, 6, Associativity::Left),
+    infix(Token::Slash, 6, Associativity::Left),
+    // `-x as u8` casts `-x`, and `a * b as u8` casts `b`.
+    OperatorInfo { token: Token::As, prefix: None, infix: None, postfix: Some(7) },
+    OperatorInfo { token: Token::Bang, prefix: Some(8), infix: None, postfix: None },
+    OperatorInfo { token: Token::LParen, prefix: None, infix: None, postfix: Some(9) },
+    OperatorInfo { token: Token::LBracket, prefix: None, infix: None, postfix: Some(9) },
+    OperatorInfo { token: Token::Dot, prefix: None, infix: None, postfix: Some(9) },
+    OperatorInfo { token: Token::Question, prefix: None, infix: None, postfix: Some(9) },
+];
+
+pub fn operator_info(token: &Token) -> Option<&'static OperatorInfo> {
+    OPERATOR_TABLE.iter().find(|info| info.token == *token)
+}
+
+/// Parses the program `lexer` reads, reporting a syntax error at the token
+/// where parsing stopped.
+pub fn parse_source(lexer: &mut Lexer) -> Result<Program, Diagnostic> {
+    Parser::new(lexer).try_parse_program()
+}
+
+enum Parenthesized<T> {
+    Single(T),
+    Tuple(Vec<T>),
 }
 
 pub struct Parser<'a> {
     lexer: &'a mut Lexer,
     current_token: Token,
+    current_span: Span,
+    previous_end: usize,
+    next_id: u32,
+    /// Cleared while parsing an `if` or `while` condition, where `{` opens the
+    /// body rather than a struct literal. Restored inside brackets.
+    struct_literals: bool,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(lexer: &'a mut Lexer) -> Self {
         let current_token = lexer.next_token();
-        Self { lexer, current_token }
+        let current_span = lexer.span();
+        Self {
+            lexer,
+            current_token,
+            current_span,
+            previous_end: 0,
+            next_id: 0,
+            struct_literals: true,
+        }
     }
 
     fn advance(&mut self) {
+        self.previous_end = self.current_span.end;
         self.current_token = self.lexer.next_token();
+        self.current_span = self.lexer.span();
     }
 
-    pub fn parse_program(&mut self) -> ASTNode {
-        let mut nodes = Vec::new();
-        while self.current_token != Token::Eof {
-            nodes.push(self.parse_statement());
-        }
-        ASTNode::Program(nodes)
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, Diagnostic> {
+        Err(Diagnostic::error(self.current_span, message))
     }
 
-    fn parse_statement(&mut self) -> ASTNode {
-        match self.current_token {
-            Token::Let => self.parse_let(),
-            Token::Function => self.parse_function(),
-            Token::Return => self.parse_return(),
-            Token::If => self.parse_if(),
-            Token::For => self.parse_for(),
-            Token::While => self.parse_while(),
-            _ => self.parse_expression(),
+    fn expect(&mut self, token: Token, message: &str) -> Result<(), Diagnostic> {
+        if self.current_token == token {
+            self.advance();
+            Ok(())
+        } else {
+            self.error(format!("{}, found {:?}", message, self.current_token))
         }
     }
 
-    fn parse_let(&mut self) -> ASTNode {
-        self.advance();
-        if let Token::Identifier(name) = &self.current_token {
+    fn expect_identifier(&mut self, message: &str) -> Result<String, Diagnostic> {
+        if let Token::Identifier(name) = self.current_token.clone() {
             self.advance();
-            if self.current_token == Token::Equals {
-                self.advance();
-                let value = self.parse_expression();
-                ASTNode::Let {
-                    name: name.clone(),
-                    value: Box::new(value),
-                }
-            } else {
-                panic!("Expected '=' after let declaration");
-            }
+            Ok(name)
         } else {
-            panic!("Expected identifier after let");
+            self.error(format!("{}, found {:?}", message, self.current_token))
         }
     }
 
-    fn parse_function(&mut self) -> ASTNode {
-        self.advance();
-        if let Token::Identifier(name) = &self.current_token {
+    fn next_id(&mut self) -> NodeId {
+        let id = NodeId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    /// The span from `start` to the end of the last consumed token.
+    fn span_from(&self, start: usize) -> Span {
+        Span::new(start, self.previous_end.max(start))
+    }
+
+    /// Parses the whole program, panicking with the message of a syntax
+    /// error. For sources that are known to parse, such as the prelude.
+    pub fn parse_program(&mut self) -> Program {
+        self.try_parse_program().unwrap_or_else(|diagnostic| panic!("{}", diagnostic.message))
+    }
+
+    pub fn try_parse_program(&mut self) -> Result<Program, Diagnostic> {
+        let mut items = Vec::new();
+        while self.current_token != Token::Eof {
+            items.push(self.parse_item()?);
+        }
+        Ok(Program {
+            id: self.next_id(),
+            span: self.span_from(0),
+            items,
+        })
+    }
+
+    fn parse_item(&mut self) -> Result<Item, Diagnostic> {
+        let start = self.current_span.start;
+        let attributes = self.parse_attributes()?;
+        let public = self.current_token == Token::Pub;
+        if public {
             self.advance();
-            if self.current_token == Token::LParen {
-                self.advance();
-                let mut params = Vec::new();
-                while self.current_token != Token::RParen {
-                    if let Token::Identifier(param) = &self.current_token {
-                        params.push(param.clone());
-                        self.advance();
-                        if self.current_token == Token::Comma {
-                            self.advance();
-                        }
-                    } else {
-                        panic!("Expected identifier in function parameters");
-                    }
-                }
+            if matches!(self.current_token, Token::Impl | Token::Import) {
+                return self.error(format!("Expected 'function', 'struct', 'enum', 'interface', 'extern', 'let' or 'const' after 'pub', found {:?}", self.current_token));
+            }
+        }
+        let kind = match self.current_token {
+            Token::Function => ItemKind::Function(self.parse_function()?),
+            Token::Let => ItemKind::Global(self.parse_let()?),
+            Token::Const => {
                 self.advance();
-                if self.current_token == Token::LBrace {
-                    self.advance();
-                    let body = self.parse_block();
-                    ASTNode::Function {
-                        name: name.clone(),
-                        params,
-                        body: Box::new(body),
-                    }
+                if self.current_token == Token::Function {
+                    let mut function = self.parse_function()?;
+                    function.constant = true;
+                    ItemKind::Function(function)
                 } else {
-                    panic!("Expected '{{' after function parameters");
+                    ItemKind::Const(self.parse_const()?)
                 }
-            } else {
-                panic!("Expected '(' after function name");
             }
-        } else {
-            panic!("Expected identifier after function");
-        }
+            Token::Struct => ItemKind::Struct(self.parse_struct()?),
+            Token::Enum => ItemKind::Enum(self.parse_enum()?),
+            Token::Interface => ItemKind::Interface(self.parse_interface()?),
+            Token::Impl => ItemKind::Impl(self.parse_impl()?),
+            Token::Import => ItemKind::Import(self.parse_import()?),
+            Token::Extern => ItemKind::Extern(self.parse_extern()?),
+            _ => return self.error(format!(
+                "Expected 'function', 'struct', 'enum', 'interface', 'impl', 'import', 'extern', 'pub', 'let' or 'const' at top level, found {:?}",
+                self.current_token
+            )),
+        };
+        Ok(Item {
+            id: self.next_id(),
+            span: self.span_from(start),
+            attributes,
+            public,
+            kind,
+        })
     }
 
-    fn parse_return(&mut self) -> ASTNode {
-        self.advance();
-        let value = self.parse_expression();
-        ASTNode::Return(Box::new(value))
+    /// Parses the attributes before an item or an impl method.
+    fn parse_attributes(&mut self) -> Result<Vec<Attribute>, Diagnostic> {
+        let mut attributes = Vec::new();
+        while self.current_token == Token::At {
+            attributes.push(self.parse_attribute()?);
+        }
+        Ok(attributes)
     }
 
-    fn parse_if(&mut self) -> ASTNode {
+    fn parse_attribute(&mut self) -> Result<Attribute, Diagnostic> {
+        let start = self.current_span.start;
         self.advance();
-        let condition = self.parse_expression();
-        if self.current_token == Token::LBrace {
+        let name = self.expect_identifier("Expected attribute name after '@'")?;
+        let mut args = Vec::new();
+        if self.current_token == Token::LParen {
             self.advance();
-            let then_branch = self.parse_block();
-            let else_branch = if self.current_token == Token::Else {
-                self.advance();
-                if self.current_token == Token::LBrace {
+            while self.current_token != Token::RParen {
+                args.push(self.expect_plain_string("Expected string argument in attribute")?);
+                if self.current_token == Token::Comma {
                     self.advance();
-                    Some(Box::new(self.parse_block()))
-                } else {
-                    panic!("Expected '{{' after else");
+                } else if self.current_token != Token::RParen {
+                    return self.error(format!("Expected ',' or ')' in attribute arguments, found {:?}", self.current_token));
                 }
-            } else {
-                None
-            };
-            ASTNode::If {
-                condition: Box::new(condition),
-                then_branch: Box::new(then_branch),
-                else_branch,
             }
-        } else {
-            panic!("Expected '{{' after if condition");
+            self.advance();
         }
+        Ok(Attribute {
+            id: self.next_id(),
+            span: self.span_from(start),
+            name,
+            args,
+        })
     }
 
-    fn parse_for(&mut self) -> ASTNode {
+    fn parse_function(&mut self) -> Result<Function, Diagnostic> {
         self.advance();
-        let init = self.parse_statement();
-        let condition = self.parse_expression();
-        let increment = self.parse_statement();
-        if self.current_token == Token::LBrace {
-            self.advance();
-            let body = self.parse_block();
-            ASTNode::For {
-                init: Box::new(init),
-                condition: Box::new(condition),
-                increment: Box::new(increment),
-                body: Box::new(body),
+        let name = self.expect_identifier("Expected identifier after function")?;
+        let type_params = self.parse_type_params()?;
+        let params = self.parse_params()?;
+        let return_type = self.parse_return_type()?;
+        let body = self.parse_block()?;
+        Ok(Function {
+            constant: false,
+            name,
+            type_params,
+            params,
+            return_type,
+            body,
+        })
+    }
+
+    /// Parses `(param, ...)` after a function or method name.
+    fn parse_params(&mut self) -> Result<Vec<Param>, Diagnostic> {
+        self.expect(Token::LParen, "Expected '(' after function name")?;
+        let mut params = Vec::new();
+        while self.current_token != Token::RParen {
+            params.push(self.parse_param()?);
+            if self.current_token == Token::Comma {
+                self.advance();
+            } else if self.current_token != Token::RParen {
+                return self.error(format!("Expected ',' or ')' in function parameters, found {:?}", self.current_token));
             }
-        } else {
-            panic!("Expected '{{' after for loop");
         }
+        self.advance();
+        Ok(params)
     }
 
-    fn parse_while(&mut self) -> ASTNode {
-        self.advance();
-        let condition = self.parse_expression();
-        if self.current_token == Token::LBrace {
+    /// Parses an optional `-> type`.
+    fn parse_return_type(&mut self) -> Result<Option<Type>, Diagnostic> {
+        Ok(if self.current_token == Token::Arrow {
             self.advance();
-            let body = self.parse_block();
-            ASTNode::While {
-                condition: Box::new(condition),
-                body: Box::new(body),
-            }
+            Some(self.parse_type()?)
         } else {
-            panic!("Expected '{{' after while condition");
-        }
+            None
+        })
     }
 
-    fn parse_block(&mut self) -> ASTNode {
-        let mut nodes = Vec::new();
-        while self.current_token != Token::RBrace {
-            nodes.push(self.parse_statement());
-        }
-        self.advance();
-        ASTNode::Program(nodes)
+    fn parse_param(&mut self) -> Result<Param, Diagnostic> {
+        let start = self.current_span.start;
+        let pattern = self.parse_pattern()?;
+        // A bare `self` stands for `self: Self`.
+        let ty = if matches!(&pattern.kind, PatternKind::Binding { name, .. } if name == "self") && self.current_token != Token::Colon {
+            Type {
+                id: self.next_id(),
+                span: pattern.span,
+                kind: TypeKind::Named {
+                    name: "Self".to_string(),
+                    args: Vec::new(),
+                },
+            }
+        } else {
+            self.expect(Token::Colon, "Expected ':' after parameter name")?;
+            self.parse_type()?
+        };
+        Ok(Param {
+            id: self.next_id(),
+            span: self.span_from(start),
+            pattern,
+            ty,
+        })
     }
 
-    fn parse_expression(&mut self) -> ASTNode {
-        self.parse_precedence(0)
+    fn parse_import(&mut self) -> Result<Import, Diagnostic> {
+        self.advance();
+        let path = match self.current_token {
+            Token::StringLiteral(_) => self.expect_plain_string("Expected a path without interpolation after import")?,
+            _ => self.expect_identifier("Expected module name or path after import")?,
+        };
+        self.expect(Token::Semicolon, "Expected ';' after import")?;
+        Ok(Import { path })
     }
 
-    fn parse_precedence(&mut self, min_precedence: u8) -> ASTNode {
-        let mut left = self.parse_primary();
-        while let Some(op) = self.get_operator() {
-            let precedence = self.get_precedence(&op);
-            if precedence < min_precedence {
+    fn parse_extern(&mut self) -> Result<Extern, Diagnostic> {
+        self.advance();
+        let abi = self.expect_plain_string("Expected calling convention such as \"C\" after extern")?;
+        self.expect(Token::Function, "Expected 'function' after extern calling convention")?;
+        let name = self.expect_identifier("Expected identifier after function")?;
+        self.expect(Token::LParen, "Expected '(' after function name")?;
+        let mut params = Vec::new();
+        let mut variadic = false;
+        while self.current_token != Token::RParen {
+            if self.current_token == Token::Ellipsis {
+                self.advance();
+                variadic = true;
+                if self.current_token != Token::RParen {
+                    return self.error(format!("Expected ')' after '...', found {:?}", self.current_token));
+                }
                 break;
             }
-            self.advance();
-            let mut right = self.parse_primary();
-            while let Some(next_op) = self.get_operator() {
-                let next_precedence = self.get_precedence(&next_op);
-                if next_precedence > precedence {
-                    right = self.parse_precedence(precedence + 1);
-                } else {
-                    break;
-                }
+            params.push(self.parse_param()?);
+            if self.current_token == Token::Comma {
+                self.advance();
+            } else if self.current_token != Token::RParen {
+                return self.error(format!("Expected ',' or ')' in function parameters, found {:?}", self.current_token));
             }
-            left = ASTNode::BinaryOp {
-                op,
-                left: Box::new(left),
-                right: Box::new(right),
-            };
         }
-        left
+        self.advance();
+        let return_type = self.parse_return_type()?;
+        self.expect(Token::Semicolon, "Expected ';' after extern function declaration")?;
+        Ok(Extern {
+            abi,
+            name,
+            params,
+            variadic,
+            return_type,
+        })
     }
 
-    fn parse_primary(&mut self) -> ASTNode {
-        match &self.current_token {
-            Token::Identifier(name) => {
-                let name = name.clone();
-                self.advance();
-                ASTNode::Identifier(name)
-            }
-            Token::IntLiteral(value) => {
-                let value = *value;
+    /// Parses a string literal without `{...}` holes and returns its text.
+    fn expect_plain_string(&mut self, message: &str) -> Result<String, Diagnostic> {
+        let Token::StringLiteral(raw) = self.current_token.clone() else {
+            return self.error(format!("{}, found {:?}", message, self.current_token));
+        };
+        Ok(match self.parse_string(&raw, self.current_span.start + 1)? {
+            ExprKind::StringLiteral(text) => {
                 self.advance();
-                ASTNode::IntLiteral(value)
+                text
             }
-            Token::True => {
-                self.advance();
-                ASTNode::BoolLiteral(true)
+            _ => return self.error(format!("{}, found an interpolated string", message)),
+        })
+    }
+
+    /// Parses a name that may be qualified by a module, such as
+    /// `math::Point`.
+    fn parse_qualified_name(&mut self, message: &str) -> Result<String, Diagnostic> {
+        let first = self.expect_identifier(message)?;
+        Ok(self.parse_path(first)?.join("::"))
+    }
+
+    fn parse_interface(&mut self) -> Result<Interface, Diagnostic> {
+        self.advance();
+        let name = self.expect_identifier("Expected identifier after interface")?;
+        self.expect(Token::LBrace, "Expected '{' after interface name")?;
+        let mut methods = Vec::new();
+        while self.current_token != Token::RBrace {
+            let start = self.current_span.start;
+            self.expect(Token::Function, "Expected 'function' in interface")?;
+            let method_name = self.expect_identifier("Expected identifier after function")?;
+            let params = self.parse_params()?;
+            let return_type = self.parse_return_type()?;
+            self.expect(Token::Semicolon, "Expected ';' after interface method")?;
+            methods.push(MethodSig {
+                id: self.next_id(),
+                span: self.span_from(start),
+                name: method_name,
+                params,
+                return_type,
+            });
+        }
+        self.advance();
+        Ok(Interface { name, methods })
+    }
+
+    fn parse_impl(&mut self) -> Result<Impl, Diagnostic> {
+        self.advance();
+        let interface = self.parse_qualified_name("Expected interface name after impl")?;
+        self.expect(Token::For, "Expected 'for' after interface name")?;
+        let ty = self.parse_type()?;
+        self.expect(Token::LBrace, "Expected '{' after impl type")?;
+        let mut methods = Vec::new();
+        while self.current_token != Token::RBrace {
+            let start = self.current_span.start;
+            let attributes = self.parse_attributes()?;
+            if self.current_token != Token::Function {
+                return self.error(format!("Expected 'function' in impl, found {:?}", self.current_token));
             }
-            Token::False => {
+            let function = self.parse_function()?;
+            methods.push(Method {
+                id: self.next_id(),
+                span: self.span_from(start),
+                attributes,
+                function,
+            });
+        }
+        self.advance();
+        Ok(Impl { interface, ty, methods })
+    }
+
+    fn parse_struct(&mut self) -> Result<Struct, Diagnostic> {
+        self.advance();
+        let name = self.expect_identifier("Expected identifier after struct")?;
+        let type_params = self.parse_type_params()?;
+        self.expect(Token::LBrace, "Expected '{' after struct name")?;
+        let mut fields = Vec::new();
+        while self.current_token != Token::RBrace {
+            let start = self.current_span.start;
+            let field_name = self.expect_identifier("Expected field name in struct")?;
+            self.expect(Token::Colon, "Expected ':' after field name")?;
+            let ty = self.parse_type()?;
+            fields.push(Field {
+                id: self.next_id(),
+                span: self.span_from(start),
+                name: field_name,
+                ty,
+            });
+            if self.current_token == Token::Comma {
                 self.advance();
-                ASTNode::BoolLiteral(false)
+            } else if self.current_token != Token::RBrace {
+                return self.error(format!("Expected ',' or '}}' in struct fields, found {:?}", self.current_token));
             }
-            Token::LParen => {
+        }
+        self.advance();
+        Ok(Struct { name, type_params, fields })
+    }
+
+    /// Parses an optional `<T, U: Bound + ...>` list of type parameters.
+    fn parse_type_params(&mut self) -> Result<Vec<TypeParam>, Diagnostic> {
+        let mut params = Vec::new();
+        if self.current_token != Token::Less {
+            return Ok(params);
+        }
+        self.advance();
+        while !self.at_closing_angle() {
+            let start = self.current_span.start;
+            let name = self.expect_identifier("Expected type parameter name")?;
+            let mut bounds = Vec::new();
+            if self.current_token == Token::Colon {
                 self.advance();
-                let expr = self.parse_expression();
-                if self.current_token == Token::RParen {
+                bounds.push(self.parse_qualified_name("Expected interface name in bound")?);
+                while self.current_token == Token::Plus {
                     self.advance();
-                    expr
-                } else {
-                    panic!("Expected ')' after expression");
+                    bounds.push(self.parse_qualified_name("Expected interface name in bound")?);
                 }
             }
-            _ => panic!("Unexpected token: {:?}", self.current_token),
+            params.push(TypeParam {
+                id: self.next_id(),
+                span: self.span_from(start),
+                name,
+                bounds,
+            });
+            if self.current_token == Token::Comma {
+                self.advance();
+            } else if !self.at_closing_angle() {
+                return self.error(format!("Expected ',' or '>' in type parameters, found {:?}", self.current_token));
+            }
         }
+        self.close_angle();
+        Ok(params)
     }
 
-    fn get_operator(&self) -> Option<Token> {
-        match self.current_token {
-            Token::Plus | Token::Minus | Token::Star | Token::Slash | Token::Equals => Some(self.current_token.clone()),
-            _ => None,
+    /// Whether the current token closes a `<...>` list. A `>>` closes two
+    /// nested lists, as in `Pair<Pair<int, bool>>`.
+    fn at_closing_angle(&self) -> bool {
+        matches!(self.current_token, Token::Greater | Token::GreaterGreater)
+    }
+
+    /// Consumes one `>`, leaving the second `>` of a `>>` as the current
+    /// token.
+    fn close_angle(&mut self) {
+        if self.current_token == Token::GreaterGreater {
+            self.current_token = Token::Greater;
+            self.current_span.start += 1;
+            self.previous_end = self.current_span.start;
+        } else {
+            self.advance();
         }
     }
 
-    fn get_precedence(&self, token: &Token) -> u8 {
-        match token {
-            Token::Equals => 1,
-            Token::Plus | Token::Minus => 2,
-            Token::Star | Token::Slash => 3,
-            _ => 0,
+    fn parse_enum(&mut self) -> Result<Enum, Diagnostic> {
+        self.advance();
+        let name = self.expect_identifier("Expected identifier after enum")?;
+        let type_params = self.parse_type_params()?;
+        self.expect(Token::LBrace, "Expected '{' after enum name")?;
+        let mut variants = Vec::new();
+        while self.current_token != Token::RBrace {
+            let start = self.current_span.start;
+            let variant_name = self.expect_identifier("Expected variant name in enum")?;
+            let mut fields = Vec::new();
+            if self.current_token == Token::LParen {
+                self.advance();
+                while self.current_token != Token::RParen {
+                    fields.push(self.parse_type()?);
+                    if self.current_token == Token::Comma {
+                        self.advance();
+                    } else if self.current_token != Token::RParen {
+                        return self.error(format!("Expected ',' or ')' in variant payload, found {:?}", self.current_token));
+                    }
+                }
+                self.advance();
+            }
+            variants.push(Variant {
+                id: self.next_id(),
+                span: self.span_from(start),
+                name: variant_name,
+                fields,
+            });
+            if self.current_token == Token::Comma {
+                self.advance();
+            } else if self.current_token != Token::RBrace {
+                return self.error(format!("Expected ',' or '}}' in enum variants, found {:?}", self.current_token));
+            }
+        }
+        self.advance();
+        Ok(Enum { name, type_params, variants })
+    }
+
+    fn parse_type(&mut self) -> Result<Type, Diagnostic> {
+        let start = self.current_span.start;
+        let kind = match &self.current_token {
+            Token::TypeInt(ty) => TypeKind::Int(*ty),
+            Token::TypeBool => TypeKind::Bool,
+            Token::TypeString => TypeKind::String,
+            Token::TypePtr => TypeKind::Ptr,
+            Token::TypeVoid => TypeKind::Void,
+            Token::Identifier(_) => {
+                let name = self.parse_qualified_name("Expected type")?;
+                let mut args = Vec::new();
+                if self.current_token == Token::Less {
+                    self.advance();
+                    while !self.at_closing_angle() {
+                        args.push(self.parse_type()?);
+                        if self.current_token == Token::Comma {
+                            self.advance();
+                        } else if !self.at_closing_angle() {
+                            return self.error(format!("Expected ',' or '>' in type arguments, found {:?}", self.current_token));
+                        }
+                    }
+                    self.close_angle();
+                }
+                let ty = Type {
+                    id: self.next_id(),
+                    span: self.span_from(start),
+                    kind: TypeKind::Named { name, args },
+                };
+                return self.parse_array_suffixes(start, ty);
+            }
+            Token::LParen => {
+                self.advance();
+                let ty = match self.parse_parenthesized(Self::parse_type, "tuple type")? {
+                    Parenthesized::Single(ty) => ty,
+                    Parenthesized::Tuple(elements) => Type {
+                        id: self.next_id(),
+                        span: self.span_from(start),
+                        kind: TypeKind::Tuple(elements),
+                    },
+                };
+                return self.parse_array_suffixes(start, ty);
+            }
+            // An array of functions is written `(fn() -> int)[2]`, so a
+            // function type takes no suffixes of its own.
+            Token::Fn => return self.parse_function_type(),
+            Token::Dyn => {
+                self.advance();
+                let name = self.parse_qualified_name("Expected interface name after dyn")?;
+                let ty = Type {
+                    id: self.next_id(),
+                    span: self.span_from(start),
+                    kind: TypeKind::Dyn(name),
+                };
+                return self.parse_array_suffixes(start, ty);
+            }
+            _ => return self.error(format!("Expected type, found {:?}", self.current_token)),
+        };
+        self.advance();
+        let ty = Type {
+            id: self.next_id(),
+            span: self.span_from(start),
+            kind,
+        };
+        self.parse_array_suffixes(start, ty)
+    }
+
+    /// Parses `fn(type, ...) [-> type]`.
+    fn parse_function_type(&mut self) -> Result<Type, Diagnostic> {
+        let start = self.current_span.start;
+        self.advance();
+        self.expect(Token::LParen, "Expected '(' after fn")?;
+        let mut params = Vec::new();
+        while self.current_token != Token::RParen {
+            params.push(self.parse_type()?);
+            if self.current_token == Token::Comma {
+                self.advance();
+            } else if self.current_token != Token::RParen {
+                return self.error(format!("Expected ',' or ')' in function type, found {:?}", self.current_token));
+            }
+        }
+        self.advance();
+        let return_type = if self.current_token == Token::Arrow {
+            self.advance();
+            Some(Box::new(self.parse_type()?))
+        } else {
+            None
+        };
+        Ok(Type {
+            id: self.next_id(),
+            span: self.span_from(start),
+            kind: TypeKind::Function { params, return_type },
+        })
+    }
+
+    /// Parses any `[size]` suffixes after the element type `ty`.
+    fn parse_array_suffixes(&mut self, start: usize, mut ty: Type) -> Result<Type, Diagnostic> {
+        while self.current_token == Token::LBracket {
+            self.advance();
+            let size = match self.current_token.clone() {
+                Token::IntLiteral(size) if u32::try_from(size).is_ok() => {
+                    self.advance();
+                    ArraySize::Literal(size as u32)
+                }
+                Token::Identifier(_) => ArraySize::Const(self.parse_qualified_name("Expected array size")?),
+                _ => return self.error(format!("Expected array size, found {:?}", self.current_token)),
+            };
+            self.expect(Token::RBracket, "Expected ']' after array size")?;
+            ty = Type {
+                id: self.next_id(),
+                span: self.span_from(start),
+                kind: TypeKind::Array {
+                    element: Box::new(ty),
+                    size,
+                },
+            };
+        }
+        Ok(ty)
+    }
+
+    /// Parses the rest of `(a, b, ...)` after the opening parenthesis. One
+    /// element without a trailing comma is just parenthesised.
+    fn parse_parenthesized<T>(&mut self, parse: impl Fn(&mut Self) -> Result<T, Diagnostic>, what: &str) -> Result<Parenthesized<T>, Diagnostic> {
+        let mut elements = Vec::new();
+        let mut trailing_comma = false;
+        while self.current_token != Token::RParen {
+            elements.push(parse(self)?);
+            trailing_comma = self.current_token == Token::Comma;
+            if trailing_comma {
+                self.advance();
+            } else if self.current_token != Token::RParen {
+                return self.error(format!("Expected ',' or ')' in {}, found {:?}", what, self.current_token));
+            }
+        }
+        self.advance();
+        Ok(if elements.len() == 1 && !trailing_comma {
+            Parenthesized::Single(elements.remove(0))
+        } else {
+            Parenthesized::Tuple(elements)
+        })
+    }
+
+    /// Parses `let pattern[: type] = value;`.
+    fn parse_let(&mut self) -> Result<Let, Diagnostic> {
+        self.advance();
+        let pattern = self.parse_pattern()?;
+        let ty = if self.current_token == Token::Colon {
+            self.advance();
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+        self.expect(Token::Equals, "Expected '=' after let declaration")?;
+        let value = self.parse_expression()?;
+        self.expect(Token::Semicolon, "Expected ';' after let declaration")?;
+        Ok(Let { pattern, ty, value })
+    }
+
+    /// Parses a constant after its `const`, which `parse_item` has consumed
+    /// to tell it from a `const function`.
+    fn parse_const(&mut self) -> Result<Const, Diagnostic> {
+        let name = self.expect_identifier("Expected identifier or 'function' after const")?;
+        self.expect(Token::Colon, "Expected ':' and a type after const name")?;
+        let ty = self.parse_type()?;
+        self.expect(Token::Equals, "Expected '=' after const declaration")?;
+        let value = self.parse_expression()?;
+        self.expect(Token::Semicolon, "Expected ';' after const declaration")?;
+        Ok(Const { name, ty, value })
+    }
+
+    /// Parses `{ ... }`; the current token must be the opening brace.
+    fn parse_block(&mut self) -> Result<Block, Diagnostic> {
+        let start = self.current_span.start;
+        self.expect(Token::LBrace, "Expected '{'")?;
+        let mut stmts = Vec::new();
+        while self.current_token != Token::RBrace {
+            if self.current_token == Token::Eof {
+                return self.error("Expected '}' before end of input");
+            }
+            stmts.push(self.parse_statement()?);
+        }
+        self.advance();
+        Ok(Block {
+            id: self.next_id(),
+            span: self.span_from(start),
+            stmts,
+        })
+    }
+
+    fn parse_statement(&mut self) -> Result<Stmt, Diagnostic> {
+        let start = self.current_span.start;
+        let kind = match self.current_token {
+            Token::Let => StmtKind::Let(self.parse_let()?),
+            Token::Return => self.parse_return()?,
+            Token::If => self.parse_if()?,
+            Token::For => self.parse_for()?,
+            Token::While => self.parse_while()?,
+            Token::Defer => self.parse_defer()?,
+            Token::Function => return self.error("Functions can only be declared at top level"),
+            Token::Struct => return self.error("Structs can only be declared at top level"),
+            Token::Enum => return self.error("Enums can only be declared at top level"),
+            Token::Interface => return self.error("Interfaces can only be declared at top level"),
+            Token::Impl => return self.error("Impls can only be declared at top level"),
+            Token::Import => return self.error("Imports can only be declared at top level"),
+            Token::Extern => return self.error("Extern functions can only be declared at top level"),
+            Token::Pub => return self.error("Only top-level items can be 'pub'"),
+            Token::Const => return self.error("Constants can only be declared at top level"),
+            // Like a block, a `match` statement needs no ';', and it ends at
+            // its closing brace rather than continuing as an operand.
+            Token::Match => {
+                let expr = self.parse_match()?;
+                if self.current_token == Token::Semicolon {
+                    self.advance();
+                }
+                StmtKind::Expr(expr)
+            }
+            _ => {
+                let expr = self.parse_expression()?;
+                self.expect(Token::Semicolon, "Expected ';' after expression")?;
+                StmtKind::Expr(expr)
+            }
+        };
+        Ok(Stmt {
+            id: self.next_id(),
+            span: self.span_from(start),
+            kind,
+        })
+    }
+
+    fn parse_return(&mut self) -> Result<StmtKind, Diagnostic> {
+        self.advance();
+        let value = if self.current_token == Token::Semicolon {
+            None
+        } else {
+            Some(self.parse_expression()?)
+        };
+        self.expect(Token::Semicolon, "Expected ';' after return")?;
+        Ok(StmtKind::Return(value))
+    }
+
+    fn parse_if(&mut self) -> Result<StmtKind, Diagnostic> {
+        self.advance();
+        let condition = self.parse_condition()?;
+        if self.current_token != Token::LBrace {
+            return self.error("Expected '{' after if condition");
+        }
+        let then_branch = self.parse_block()?;
+        let else_branch = if self.current_token == Token::Else {
+            self.advance();
+            match self.current_token {
+                Token::LBrace => Some(self.parse_block()?),
+                // `else if` is sugar for an else block holding a single if statement.
+                Token::If => {
+                    let stmt = self.parse_statement()?;
+                    Some(Block {
+                        id: self.next_id(),
+                        span: stmt.span,
+                        stmts: vec![stmt],
+                    })
+                }
+                _ => return self.error("Expected '{' after else"),
+            }
+        } else {
+            None
+        };
+        Ok(StmtKind::If {
+            condition,
+            then_branch,
+            else_branch,
+        })
+    }
+
+    /// Parses `for (init; condition; increment) { ... }`; each clause may be empty.
+    fn parse_for(&mut self) -> Result<StmtKind, Diagnostic> {
+        self.advance();
+        self.expect(Token::LParen, "Expected '(' after for")?;
+        let init = match self.current_token {
+            Token::Semicolon => {
+                self.advance();
+                None
+            }
+            // Both `let` and expression statements consume their own ';'.
+            _ => Some(Box::new(self.parse_statement()?)),
+        };
+        let condition = if self.current_token == Token::Semicolon {
+            None
+        } else {
+            Some(self.parse_expression()?)
+        };
+        self.expect(Token::Semicolon, "Expected ';' after for condition")?;
+        let increment = if self.current_token == Token::RParen {
+            None
+        } else {
+            Some(self.parse_expression()?)
+        };
+        self.expect(Token::RParen, "Expected ')' after for clauses")?;
+        if self.current_token != Token::LBrace {
+            return self.error("Expected '{' after for loop");
+        }
+        let body = self.parse_block()?;
+        Ok(StmtKind::For {
+            init,
+            condition,
+            increment,
+            body,
+        })
+    }
+
+    fn parse_while(&mut self) -> Result<StmtKind, Diagnostic> {
+        self.advance();
+        let condition = self.parse_condition()?;
+        if self.current_token != Token::LBrace {
+            return self.error("Expected '{' after while condition");
+        }
+        let body = self.parse_block()?;
+        Ok(StmtKind::While { condition, body })
+    }
+
+    fn parse_defer(&mut self) -> Result<StmtKind, Diagnostic> {
+        self.advance();
+        Ok(match self.current_token {
+            Token::Let => return self.error("Expected statement after defer; a deferred 'let' would go out of scope at once"),
+            Token::Defer => return self.error("Expected statement after defer, found another 'defer'"),
+            _ => StmtKind::Defer(Box::new(self.parse_statement()?)),
+        })
+    }
+
+    fn parse_expression(&mut self) -> Result<Expr, Diagnostic> {
+        self.parse_expression_bp(0)
+    }
+
+    /// Parses an `if` or `while` condition, which is followed by a block.
+    fn parse_condition(&mut self) -> Result<Expr, Diagnostic> {
+        self.with_struct_literals(false, Self::parse_expression)
+    }
+
+    /// Parses an expression between brackets, where struct literals are
+    /// always allowed.
+    fn parse_nested_expression(&mut self) -> Result<Expr, Diagnostic> {
+        self.with_struct_literals(true, Self::parse_expression)
+    }
+
+    fn with_struct_literals(&mut self, allowed: bool, parse: impl FnOnce(&mut Self) -> Result<Expr, Diagnostic>) -> Result<Expr, Diagnostic> {
+        let saved = std::mem::replace(&mut self.struct_literals, allowed);
+        let expr = parse(self);
+        self.struct_literals = saved;
+        expr
+    }
+
+    fn parse_expression_bp(&mut self, min_binding_power: u8) -> Result<Expr, Diagnostic> {
+        let start = self.current_span.start;
+        let mut left = match operator_info(&self.current_token).and_then(|info| info.prefix) {
+            Some(binding_power) => {
+                let op = UnaryOp::from_token(&self.current_token).expect("prefix operator without a UnaryOp");
+                self.advance();
+                let operand = self.parse_expression_bp(binding_power)?;
+                Expr {
+                    id: self.next_id(),
+                    span: self.span_from(start),
+                    kind: ExprKind::Unary {
+                        op,
+                        operand: Box::new(operand),
+                    },
+                }
+            }
+            None => self.parse_primary()?,
+        };
+
+        while let Some(info) = operator_info(&self.current_token) {
+            if let Some(binding_power) = info.postfix {
+                if binding_power < min_binding_power {
+                    break;
+                }
+                left = self.parse_postfix(left)?;
+            } else if let Some((binding_power, associativity)) = info.infix {
+                if binding_power < min_binding_power {
+                    break;
+                }
+                let token = self.current_token.clone();
+                self.advance();
+                let right = match associativity {
+                    Associativity::Left => self.parse_expression_bp(binding_power + 1)?,
+                    Associativity::Right => self.parse_expression_bp(binding_power)?,
+                };
+                let kind = match BinaryOp::from_token(&token) {
+                    Some(op) => ExprKind::Binary {
+                        op,
+                        left: Box::new(left),
+                        right: Box::new(right),
+                    },
+                    None if token == Token::Equals => ExprKind::Assign {
+                        target: Box::new(left),
+                        value: Box::new(right),
+                    },
+                    None => panic!("infix operator without a BinaryOp: {:?}", token),
+                };
+                left = Expr {
+                    id: self.next_id(),
+                    span: self.span_from(start),
+                    kind,
+                };
+            } else {
+                break;
+            }
+        }
+        Ok(left)
+    }
+
+    fn parse_postfix(&mut self, operand: Expr) -> Result<Expr, Diagnostic> {
+        let start = operand.span.start;
+        let kind = match self.current_token {
+            Token::LParen => {
+                self.advance();
+                let mut args = Vec::new();
+                while self.current_token != Token::RParen {
+                    args.push(self.parse_nested_expression()?);
+                    if self.current_token == Token::Comma {
+                        self.advance();
+                    } else if self.current_token != Token::RParen {
+                        return self.error("Expected ',' or ')' in argument list");
+                    }
+                }
+                self.advance();
+                ExprKind::Call {
+                    callee: Box::new(operand),
+                    args,
+                }
+            }
+            Token::LBracket => {
+                self.advance();
+                let index = self.parse_nested_expression()?;
+                self.expect(Token::RBracket, "Expected ']' after index")?;
+                ExprKind::Index {
+                    base: Box::new(operand),
+                    index: Box::new(index),
+                }
+            }
+            Token::Dot => {
+                self.advance();
+                match self.current_token {
+                    Token::IntLiteral(index) if u32::try_from(index).is_ok() => {
+                        self.advance();
+                        ExprKind::TupleIndex {
+                            base: Box::new(operand),
+                            index: index as u32,
+                        }
+                    }
+                    _ => {
+                        let name = self.expect_identifier("Expected field name or tuple index after '.'")?;
+                        ExprKind::Field {
+                            base: Box::new(operand),
+                            name,
+                        }
+                    }
+                }
+            }
+            Token::Question => {
+                self.advance();
+                ExprKind::Try(Box::new(operand))
+            }
+            Token::As => {
+                self.advance();
+                ExprKind::Cast {
+                    operand: Box::new(operand),
+                    ty: self.parse_type()?,
+                }
+            }
+            _ => panic!("Unexpected postfix operator: {:?}", self.current_token),
+        };
+        Ok(Expr {
+            id: self.next_id(),
+            span: self.span_from(start),
+            kind,
+        })
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.current_span.start;
+        let kind = match &self.current_token {
+            Token::Identifier(name) => {
+                let name = name.clone();
+                self.advance();
+                let kind = if self.current_token == Token::ColonColon {
+                    let path = self.parse_path(name)?;
+                    // `math::Point { ... }` names a struct from another module.
+                    if self.current_token == Token::LBrace && self.struct_literals {
+                        self.parse_struct_literal(path.join("::"))?
+                    } else {
+                        ExprKind::Path(path)
+                    }
+                } else if self.current_token == Token::LBrace && self.struct_literals {
+                    self.parse_struct_literal(name)?
+                } else {
+                    ExprKind::Identifier(name)
+                };
+                return Ok(Expr {
+                    id: self.next_id(),
+                    span: self.span_from(start),
+                    kind,
+                });
+            }
+            Token::IntLiteral(value) => ExprKind::IntLiteral(*value),
+            Token::StringLiteral(raw) => {
+                let raw = raw.clone();
+                self.parse_string(&raw, self.current_span.start + 1)?
+            }
+            Token::True => ExprKind::BoolLiteral(true),
+            Token::False => ExprKind::BoolLiteral(false),
+            Token::LParen => {
+                self.advance();
+                return Ok(match self.parse_parenthesized(Self::parse_nested_expression, "tuple")? {
+                    Parenthesized::Single(expr) => expr,
+                    Parenthesized::Tuple(elements) => Expr {
+                        id: self.next_id(),
+                        span: self.span_from(start),
+                        kind: ExprKind::Tuple(elements),
+                    },
+                });
+            }
+            Token::Match => return self.parse_match(),
+            Token::Pipe => return self.parse_closure(),
+            Token::LBracket => {
+                self.advance();
+                let mut elements = Vec::new();
+                while self.current_token != Token::RBracket {
+                    elements.push(self.parse_nested_expression()?);
+                    if self.current_token == Token::Comma {
+                        self.advance();
+                    } else if self.current_token != Token::RBracket {
+                        return self.error("Expected ',' or ']' in array literal");
+                    }
+                }
+                self.advance();
+                return Ok(Expr {
+                    id: self.next_id(),
+                    span: self.span_from(start),
+                    kind: ExprKind::ArrayLiteral(elements),
+                });
+            }
+            _ => return self.error(format!("Unexpected token: {:?}", self.current_token)),
+        };
+        self.advance();
+        Ok(Expr {
+            id: self.next_id(),
+            span: self.span_from(start),
+            kind,
+        })
+    }
+
+    /// Decodes the text of a string literal, which starts at `start`, and
+    /// parses the expressions in its `{...}` holes. `{{` and `}}` stand for
+    /// literal braces; a hole cannot contain a string literal.
+    fn parse_string(&mut self, raw: &str, start: usize) -> Result<ExprKind, Diagnostic> {
+        let chars: Vec<char> = raw.chars().collect();
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '\\' => {
+                    text.push(match chars.get(i + 1) {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('0') => '\0',
+                        Some('\\') => '\\',
+                        Some('"') => '"',
+                        other => return self.error(format!("Invalid escape sequence in string literal: \\{}", other.map_or(String::new(), char::to_string))),
+                    });
+                    i += 2;
+                }
+                brace @ ('{' | '}') if chars.get(i + 1) == Some(&brace) => {
+                    text.push(brace);
+                    i += 2;
+                }
+                '}' => return self.error("Unmatched '}' in string literal; write '}}' for a literal brace"),
+                '{' => {
+                    let mut end = i + 1;
+                    let mut depth = 1;
+                    while depth > 0 {
+                        match chars.get(end) {
+                            Some('{') => depth += 1,
+                            Some('}') => depth -= 1,
+                            Some(_) => {}
+                            None => return self.error("Expected '}' after interpolated expression"),
+                        }
+                        end += 1;
+                    }
+                    if !text.is_empty() {
+                        parts.push(StringPart::Text(std::mem::take(&mut text)));
+                    }
+                    let source = chars[i + 1..end - 1].iter().collect();
+                    parts.push(StringPart::Expr(self.parse_hole(source, start + i + 1)?));
+                    i = end;
+                }
+                other => {
+                    text.push(other);
+                    i += 1;
+                }
+            }
+        }
+        if parts.is_empty() {
+            return Ok(ExprKind::StringLiteral(text));
+        }
+        if !text.is_empty() {
+            parts.push(StringPart::Text(text));
+        }
+        Ok(ExprKind::Interpolation(parts))
+    }
+
+    /// Parses the expression in a `{...}` hole of a string literal, whose
+    /// source starts at `offset`.
+    fn parse_hole(&mut self, source: String, offset: usize) -> Result<Expr, Diagnostic> {
+        let mut lexer = Lexer::with_offset(source, offset);
+        let mut parser = Parser::new(&mut lexer);
+        if parser.current_token == Token::Eof {
+            return self.error("Expected expression in string interpolation");
+        }
+        parser.next_id = self.next_id;
+        let expr = parser.parse_expression()?;
+        if parser.current_token != Token::Eof {
+            return parser.error(format!("Expected '}}' after interpolated expression, found {:?}", parser.current_token));
+        }
+        self.next_id = parser.next_id;
+        Ok(expr)
+    }
+
+    fn parse_closure(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.current_span.start;
+        self.advance();
+        let mut params = Vec::new();
+        while self.current_token != Token::Pipe {
+            let param_start = self.current_span.start;
+            let pattern = self.parse_pattern()?;
+            let ty = if self.current_token == Token::Colon {
+                self.advance();
+                Some(self.parse_type()?)
+            } else {
+                None
+            };
+            params.push(ClosureParam {
+                id: self.next_id(),
+                span: self.span_from(param_start),
+                pattern,
+                ty,
+            });
+            if self.current_token == Token::Comma {
+                self.advance();
+            } else if self.current_token != Token::Pipe {
+                return self.error(format!("Expected ',' or '|' in closure parameters, found {:?}", self.current_token));
+            }
+        }
+        self.advance();
+        let return_type = if self.current_token == Token::Arrow {
+            self.advance();
+            let ty = self.parse_type()?;
+            if self.current_token != Token::LBrace {
+                return self.error(format!("Expected '{{' after closure return type, found {:?}", self.current_token));
+            }
+            Some(ty)
+        } else {
+            None
+        };
+        let body = if self.current_token == Token::LBrace {
+            ArmBody::Block(self.parse_block()?)
+        } else {
+            ArmBody::Expr(self.parse_expression()?)
+        };
+        Ok(Expr {
+            id: self.next_id(),
+            span: self.span_from(start),
+            kind: ExprKind::Closure {
+                params,
+                return_type,
+                body: Box::new(body),
+            },
+        })
+    }
+
+    /// Parses the `::segment...` rest of a path whose first segment has
+    /// already been consumed.
+    fn parse_path(&mut self, first: String) -> Result<Vec<String>, Diagnostic> {
+        let mut path = vec![first];
+        while self.current_token == Token::ColonColon {
+            self.advance();
+            path.push(self.expect_identifier("Expected identifier after '::'")?);
         }
+        Ok(path)
+    }
+
+    fn parse_match(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.current_span.start;
+        self.advance();
+        let scrutinee = self.parse_condition()?;
+        self.expect(Token::LBrace, "Expected '{' after match scrutinee")?;
+        let mut arms = Vec::new();
+        while self.current_token != Token::RBrace {
+            let arm_start = self.current_span.start;
+            let pattern = self.parse_pattern()?;
+            let guard = if self.current_token == Token::If {
+                self.advance();
+                Some(self.parse_nested_expression()?)
+            } else {
+                None
+            };
+            self.expect(Token::FatArrow, "Expected '=>' after match pattern")?;
+            let body = if self.current_token == Token::LBrace {
+                let block = self.parse_block()?;
+                if self.current_token == Token::Comma {
+                    self.advance();
+                }
+                ArmBody::Block(block)
+            } else {
+                let expr = self.parse_nested_expression()?;
+                if self.current_token == Token::Comma {
+                    self.advance();
+                } else if self.current_token != Token::RBrace {
+                    return self.error(format!("Expected ',' or '}}' after match arm, found {:?}", self.current_token));
+                }
+                ArmBody::Expr(expr)
+            };
+            arms.push(MatchArm {
+                id: self.next_id(),
+                span: self.span_from(arm_start),
+                pattern,
+                guard,
+                body,
+            });
+        }
+        self.advance();
+        Ok(Expr {
+            id: self.next_id(),
+            span: self.span_from(start),
+            kind: ExprKind::Match {
+                scrutinee: Box::new(scrutinee),
+                arms,
+            },
+        })
+    }
+
+    fn parse_pattern(&mut self) -> Result<Pattern, Diagnostic> {
+        let start = self.current_span.start;
+        let kind = match self.current_token.clone() {
+            Token::Identifier(name) => {
+                self.advance();
+                if self.current_token == Token::ColonColon {
+                    let path = self.parse_path(name)?;
+                    let mut fields = Vec::new();
+                    if self.current_token == Token::LParen {
+                        self.advance();
+                        while self.current_token != Token::RParen {
+                            fields.push(self.parse_pattern()?);
+                            if self.current_token == Token::Comma {
+                                self.advance();
+                            } else if self.current_token != Token::RParen {
+                                return self.error(format!("Expected ',' or ')' in variant pattern, found {:?}", self.current_token));
+                            }
+                        }
+                        self.advance();
+                    }
+                    PatternKind::Variant { path, fields }
+                } else if name == "_" {
+                    PatternKind::Wildcard
+                } else {
+                    PatternKind::Binding { name, mutable: false }
+                }
+            }
+            Token::Mut => {
+                self.advance();
+                let name = self.expect_identifier("Expected identifier after 'mut'")?;
+                PatternKind::Binding { name, mutable: true }
+            }
+            Token::IntLiteral(value) => {
+                self.advance();
+                PatternKind::IntLiteral(i128::from(value))
+            }
+            Token::Minus => {
+                self.advance();
+                match self.current_token {
+                    Token::IntLiteral(value) => {
+                        self.advance();
+                        PatternKind::IntLiteral(-i128::from(value))
+                    }
+                    _ => return self.error(format!("Expected integer after '-' in pattern, found {:?}", self.current_token)),
+                }
+            }
+            Token::True => {
+                self.advance();
+                PatternKind::BoolLiteral(true)
+            }
+            Token::False => {
+                self.advance();
+                PatternKind::BoolLiteral(false)
+            }
+            Token::LParen => {
+                self.advance();
+                match self.parse_parenthesized(Self::parse_pattern, "tuple pattern")? {
+                    Parenthesized::Single(pattern) => return Ok(pattern),
+                    Parenthesized::Tuple(elements) => PatternKind::Tuple(elements),
+                }
+            }
+            _ => return self.error(format!("Expected pattern, found {:?}", self.current_token)),
+        };
+        Ok(Pattern {
+            id: self.next_id(),
+            span: self.span_from(start),
+            kind,
+        })
+    }
+
+    /// Parses the `{ field: value, ... }` part of a struct literal.
+    fn parse_struct_literal(&mut self, name: String) -> Result<ExprKind, Diagnostic> {
+        self.advance();
+        let mut fields = Vec::new();
+        while self.current_token != Token::RBrace {
+            let start = self.current_span.start;
+            let field_name = self.expect_identifier("Expected field name in struct literal")?;
+            self.expect(Token::Colon, "Expected ':' after field name")?;
+            let value = self.parse_nested_expression()?;
+            fields.push(FieldInit {
+                id: self.next_id(),
+                span: self.span_from(start),
+                name: field_name,
+                value,
+            });
+            if self.current_token == Token::Comma {
+                self.advance();
+            } else if self.current_token != Token::RBrace {
+                return self.error(format!("Expected ',' or '}}' in struct literal, found {:?}", self.current_token));
+            }
+        }
+        self.advance();
+        Ok(ExprKind::StructLiteral { name, fields })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Expr {
+        let mut lexer = Lexer::new(source.to_string());
+        let mut parser = Parser::new(&mut lexer);
+        let expr = parser.parse_expression().unwrap_or_else(|diagnostic| panic!("{}", diagnostic.message));
+        assert_eq!(parser.current_token, Token::Eof, "trailing input in {:?}", source);
+        expr
+    }
+
+    fn op_text(op: &Token) -> &'static str {
+        match op {
+            Token::Equals => "=",
+            Token::EqualEqual => "==",
+            Token::BangEqual => "!=",
+            Token::Less => "<",
+            Token::LessEqual => "<=",
+            Token::Greater => ">",
+            Token::GreaterEqual => ">=",
+            Token::LessLess => "<<",
+            Token::GreaterGreater => ">>",
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Star => "*",
+            Token::Slash => "/",
+            Token::Bang => "!",
+            other => panic!("no spelling for {:?}", other),
+        }
+    }
+
+    /// Renders an expression as a fully parenthesised S-expression.
+    fn sexp(expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Identifier(name) => name.clone(),
+            ExprKind::IntLiteral(value) => value.to_string(),
+            ExprKind::BoolLiteral(value) => value.to_string(),
+            ExprKind::StringLiteral(value) => format!("{:?}", value),
+            ExprKind::Interpolation(parts) => {
+                let mut out = "(str".to_string();
+                for part in parts {
+                    out.push(' ');
+                    match part {
+                        StringPart::Text(text) => out.push_str(&format!("{:?}", text)),
+                        StringPart::Expr(expr) => out.push_str(&sexp(expr)),
+                    }
+                }
+                out.push(')');
+                out
+            }
+            ExprKind::Unary { op, operand } => format!("({} {})", op.symbol(), sexp(operand)),
+            ExprKind::Binary { op, left, right } => format!("({} {} {})", op.symbol(), sexp(left), sexp(right)),
+            ExprKind::Assign { target, value } => format!("(= {} {})", sexp(target), sexp(value)),
+            ExprKind::Call { callee, args } => {
+                let mut out = format!("(call {}", sexp(callee));
+                for arg in args {
+                    out.push(' ');
+                    out.push_str(&sexp(arg));
+                }
+                out.push(')');
+                out
+            }
+            ExprKind::Index { base, index } => format!("(index {} {})", sexp(base), sexp(index)),
+            ExprKind::Field { base, name } => format!("(. {} {})", sexp(base), name),
+            ExprKind::TupleIndex { base, index } => format!("(. {} {})", sexp(base), index),
+            ExprKind::Try(operand) => format!("(? {})", sexp(operand)),
+            ExprKind::Cast { operand, ty } => match &ty.kind {
+                TypeKind::Int(ty) => format!("(as {} {})", sexp(operand), ty.name()),
+                other => panic!("no spelling for {:?}", other),
+            },
+            ExprKind::ArrayLiteral(elements) => {
+                let elements: Vec<String> = elements.iter().map(sexp).collect();
+                format!("[{}]", elements.join(" "))
+            }
+            ExprKind::Tuple(elements) => {
+                let elements: Vec<String> = elements.iter().map(|element| format!(" {}", sexp(element))).collect();
+                format!("(tuple{})", elements.concat())
+            }
+            ExprKind::StructLiteral { name, fields } => {
+                let mut out = format!("({}", name);
+                for init in fields {
+                    out.push_str(&format!(" {}: {}", init.name, sexp(&init.value)));
+                }
+                out.push(')');
+                out
+            }
+            ExprKind::Match { scrutinee, arms } => {
+                let mut out = format!("(match {}", sexp(scrutinee));
+                for arm in arms {
+                    out.push_str(&format!(" [{}", pattern_sexp(&arm.pattern)));
+                    if let Some(guard) = &arm.guard {
+                        out.push_str(&format!(" if {}", sexp(guard)));
+                    }
+                    match &arm.body {
+                        ArmBody::Expr(body) => out.push_str(&format!(" {}]", sexp(body))),
+                        ArmBody::Block(block) => out.push_str(&format!(" {{{}}}]", block.stmts.len())),
+                    }
+                }
+                out.push(')');
+                out
+            }
+            ExprKind::Closure { params, body, .. } => {
+                let params: Vec<String> = params.iter().map(|param| pattern_sexp(&param.pattern)).collect();
+                match &**body {
+                    ArmBody::Expr(body) => format!("(closure [{}] {})", params.join(" "), sexp(body)),
+                    ArmBody::Block(block) => format!("(closure [{}] {{{}}})", params.join(" "), block.stmts.len()),
+                }
+            }
+            ExprKind::Path(path) => path.join("::"),
+        }
+    }
+
+    fn pattern_sexp(pattern: &Pattern) -> String {
+        match &pattern.kind {
+            PatternKind::Wildcard => "_".to_string(),
+            PatternKind::Binding { name, mutable: false } => name.clone(),
+            PatternKind::Binding { name, mutable: true } => format!("mut {}", name),
+            PatternKind::IntLiteral(value) => value.to_string(),
+            PatternKind::BoolLiteral(value) => value.to_string(),
+            PatternKind::Variant { path, fields } => {
+                let fields: Vec<String> = fields.iter().map(pattern_sexp).collect();
+                format!("({}{})", path.join("::"), fields.iter().map(|field| format!(" {}", field)).collect::<String>())
+            }
+            PatternKind::Tuple(elements) => {
+                let elements: Vec<String> = elements.iter().map(|element| format!(" {}", pattern_sexp(element))).collect();
+                format!("(tuple{})", elements.concat())
+            }
+        }
+    }
+
+    fn infix_operators() -> Vec<(&'static str, u8, Associativity)> {
+        OPERATOR_TABLE
+            .iter()
+            .filter_map(|info| info.infix.map(|(bp, assoc)| (op_text(&info.token), bp, assoc)))
+            .collect()
+    }
+
+    #[test]
+    fn subtraction_is_left_associative() {
+        assert_eq!(sexp(&parse("a - b - c")), "(- (- a b) c)");
+    }
+
+    #[test]
+    fn assignment_is_right_associative() {
+        assert_eq!(sexp(&parse("a = b = c")), "(= a (= b c))");
+    }
+
+    #[test]
+    fn every_infix_operator_associates_per_table() {
+        for (op, _, assoc) in infix_operators() {
+            let expected = match assoc {
+                Associativity::Left => format!("({op} ({op} a b) c)"),
+                Associativity::Right => format!("({op} a ({op} b c))"),
+            };
+            assert_eq!(sexp(&parse(&format!("a {op} b {op} c"))), expected, "operator {op}");
+        }
+    }
+
+    #[test]
+    fn every_pair_of_infix_operators_respects_binding_power() {
+        for (outer, outer_bp, outer_assoc) in infix_operators() {
+            for (inner, inner_bp, _) in infix_operators() {
+                let source = format!("a {outer} b {inner} c");
+                let inner_binds_tighter = inner_bp > outer_bp
+                    || (inner_bp == outer_bp && outer_assoc == Associativity::Right);
+                let expected = if inner_binds_tighter {
+                    format!("({outer} a ({inner} b c))")
+                } else {
+                    format!("({inner} ({outer} a b) c)")
+                };
+                assert_eq!(sexp(&parse(&source)), expected, "source {source}");
+            }
+        }
+    }
+
+    #[test]
+    fn higher_precedence_after_lower_is_reclimbed() {
+        assert_eq!(sexp(&parse("a + b * c - d")), "(- (+ a (* b c)) d)");
+        assert_eq!(sexp(&parse("a * b + c * d")), "(+ (* a b) (* c d))");
+        assert_eq!(sexp(&parse("x = a + b * c < d")), "(= x (< (+ a (* b c)) d))");
+    }
+
+    #[test]
+    fn prefix_operators_bind_tighter_than_infix() {
+        assert_eq!(sexp(&parse("-a * b")), "(* (- a) b)");
+        assert_eq!(sexp(&parse("- - a")), "(- (- a))");
+        assert_eq!(sexp(&parse("!a == b")), "(== (! a) b)");
+        assert_eq!(sexp(&parse("a - -b")), "(- a (- b))");
+    }
+
+    #[test]
+    fn calls_bind_tightest() {
+        assert_eq!(sexp(&parse("f(a, b + c)")), "(call f a (+ b c))");
+        assert_eq!(sexp(&parse("-f(a)(b)")), "(- (call (call f a) b))");
+        assert_eq!(sexp(&parse("g() * h(1)")), "(* (call g) (call h 1))");
+    }
+
+    #[test]
+    fn indexing_is_postfix_and_works_as_assignment_target() {
+        assert_eq!(sexp(&parse("a[i][j + 1] = -b[0] * 2")), "(= (index (index a i) (+ j 1)) (* (- (index b 0)) 2))");
+        assert_eq!(sexp(&parse("[1, x, f(y),][2]")), "(index [1 x (call f y)] 2)");
+        assert_eq!(sexp(&parse("f(a)[0](b)")), "(call (index (call f a) 0) b)");
+        assert_eq!(sexp(&parse("[]")), "[]");
+    }
+
+    #[test]
+    fn field_access_is_postfix() {
+        assert_eq!(sexp(&parse("p.x = -a.b.c[0] + f(q).y")), "(= (. p x) (+ (- (index (. (. a b) c) 0)) (. (call f q) y)))");
+        assert_eq!(sexp(&parse("Point { x: 1, y: a + 2, }.x")), "(. (Point x: 1 y: (+ a 2)) x)");
+        assert_eq!(sexp(&parse("Empty {}")), "(Empty)");
+    }
+
+    #[test]
+    fn casts_bind_tighter_than_infix_and_looser_than_prefix() {
+        assert_eq!(sexp(&parse("-x as u8 * y as i64 + 1")), "(+ (* (as (- x) u8) (as y i64)) 1)");
+        assert_eq!(sexp(&parse("a << b as u32 >> 2 < c")), "(< (>> (<< a (as b u32)) 2) c)");
+        assert_eq!(sexp(&parse("f(x)[0] as int as u16")), "(as (as (index (call f x) 0) int) u16)");
+    }
+
+    #[test]
+    fn try_is_postfix() {
+        assert_eq!(sexp(&parse("-f(x)?.y + a[0]??")), "(+ (- (. (? (call f x)) y)) (? (? (index a 0))))");
+        assert_eq!(sexp(&parse("x = Option::Some(1)?")), "(= x (? (call Option::Some 1)))");
+    }
+
+    #[test]
+    fn parses_match_with_nested_patterns_and_guards() {
+        assert_eq!(
+            sexp(&parse("match s { Shape::Rect(0, -1) => 0, Shape::Nested(Opt::Some(x), _) if x > 1 => { }, Shape::Empty => Shape::Circle(1), n => n, }")),
+            "(match s [(Shape::Rect 0 -1) 0] [(Shape::Nested (Opt::Some x) _) if (> x 1) {0}] [(Shape::Empty) (call Shape::Circle 1)] [n n])"
+        );
+        assert_eq!(sexp(&parse("1 + match b { true => 1, false => 2 } * 2")), "(+ 1 (* (match b [true 1] [false 2]) 2))");
+    }
+
+    #[test]
+    fn parentheses_override_binding_power() {
+        assert_eq!(sexp(&parse("(a - b) * c")), "(* (- a b) c)");
+        assert_eq!(sexp(&parse("a - (b - c)")), "(- a (- b c))");
+        assert_eq!(sexp(&parse("(a = b) = c")), "(= (= a b) c)");
+    }
+
+    fn parse_source(source: &str) -> Program {
+        let mut lexer = Lexer::new(source.to_string());
+        Parser::new(&mut lexer).parse_program()
+    }
+
+    fn text(source: &str, span: Span) -> String {
+        source.chars().skip(span.start).take(span.end - span.start).collect()
+    }
+
+    #[test]
+    fn expression_spans_cover_their_source() {
+        let source = "foo + bar(1, x) * -y";
+        let expr = parse(source);
+        assert_eq!(text(source, expr.span), source);
+        let ExprKind::Binary { left, right, .. } = &expr.kind else { panic!("expected binary op") };
+        assert_eq!(text(source, left.span), "foo");
+        assert_eq!(text(source, right.span), "bar(1, x) * -y");
+        let ExprKind::Binary { left: call, right: negation, .. } = &right.kind else { panic!("expected binary op") };
+        assert_eq!(text(source, call.span), "bar(1, x)");
+        assert_eq!(text(source, negation.span), "-y");
+    }
+
+    #[test]
+    fn statement_spans_start_at_their_keyword() {
+        let source = "function main(a: int) { let y = a; while y { y = y - 1; } }";
+        let program = parse_source(source);
+        assert_eq!(text(source, program.items[0].span), source);
+        let ItemKind::Function(function) = &program.items[0].kind else { panic!("expected function") };
+        assert_eq!(text(source, function.params[0].span), "a: int");
+        assert_eq!(text(source, function.body.span), "{ let y = a; while y { y = y - 1; } }");
+        assert_eq!(text(source, function.body.stmts[0].span), "let y = a;");
+        assert_eq!(text(source, function.body.stmts[1].span), "while y { y = y - 1; }");
+    }
+
+    #[test]
+    fn node_ids_are_unique_and_deterministic() {
+        use crate::visit::{self, Visitor};
+
+        #[derive(Default)]
+        struct Ids(Vec<NodeId>);
+
+        impl Visitor for Ids {
+            fn visit_program(&mut self, program: &Program) {
+                self.0.push(program.id);
+                visit::walk_program(self, program);
+            }
+            fn visit_item(&mut self, item: &Item) {
+                self.0.push(item.id);
+                visit::walk_item(self, item);
+            }
+            fn visit_attribute(&mut self, attribute: &Attribute) {
+                self.0.push(attribute.id);
+            }
+            fn visit_type_param(&mut self, param: &TypeParam) {
+                self.0.push(param.id);
+            }
+            fn visit_param(&mut self, param: &Param) {
+                self.0.push(param.id);
+                visit::walk_param(self, param);
+            }
+            fn visit_field(&mut self, field: &Field) {
+                self.0.push(field.id);
+                visit::walk_field(self, field);
+            }
+            fn visit_field_init(&mut self, init: &FieldInit) {
+                self.0.push(init.id);
+                visit::walk_field_init(self, init);
+            }
+            fn visit_variant(&mut self, variant: &Variant) {
+                self.0.push(variant.id);
+                visit::walk_variant(self, variant);
+            }
+            fn visit_method_sig(&mut self, method: &MethodSig) {
+                self.0.push(method.id);
+                visit::walk_method_sig(self, method);
+            }
+            fn visit_method(&mut self, method: &Method) {
+                self.0.push(method.id);
+                visit::walk_method(self, method);
+            }
+            fn visit_match_arm(&mut self, arm: &MatchArm) {
+                self.0.push(arm.id);
+                visit::walk_match_arm(self, arm);
+            }
+            fn visit_closure_param(&mut self, param: &ClosureParam) {
+                self.0.push(param.id);
+                visit::walk_closure_param(self, param);
+            }
+            fn visit_pattern(&mut self, pattern: &Pattern) {
+                self.0.push(pattern.id);
+                visit::walk_pattern(self, pattern);
+            }
+            fn visit_type(&mut self, ty: &Type) {
+                self.0.push(ty.id);
+            }
+            fn visit_block(&mut self, block: &Block) {
+                self.0.push(block.id);
+                visit::walk_block(self, block);
+            }
+            fn visit_stmt(&mut self, stmt: &Stmt) {
+                self.0.push(stmt.id);
+                visit::walk_stmt(self, stmt);
+            }
+            fn visit_expr(&mut self, expr: &Expr) {
+                self.0.push(expr.id);
+                visit::walk_expr(self, expr);
+            }
+        }
+
+        fn program_ids(program: &Program) -> Vec<NodeId> {
+            let mut ids = Ids::default();
+            ids.visit_program(program);
+            ids.0
+        }
+
+        let source = "struct P { x: int, y: int[2] } function f(a: int, b: P) { let c = P { x: a, y: [a, b.x] }; while c.x { c.x = g(c) - 1; } return match c { E::V(x, _) if x => 1, _ => {} }; } function t((a, b): (int, bool)) { let (c, _) = (a, (b,)).1; } enum E { V(int, P) } function k(f: fn(int) -> bool) -> fn() { return |x, y: int| -> int { return f(x + y); }; } struct Q<T, U> { t: T } function m<T>(q: Q<T, int[2]>) {} interface I { function i(self, x: int) -> Self; } impl I for Q<int, bool> { function i(self, x: int) -> Q<int, bool> { return self; } } @inline @export(\"d\") function d<T: I + J>(x: dyn I[2]) {}";
+        let ids = program_ids(&parse_source(source));
+        let mut unique = ids.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), ids.len());
+        assert_eq!(ids, program_ids(&parse_source(source)));
+    }
+
+    #[test]
+    fn parses_array_types() {
+        let program = parse_source("let grid: int[4][2] = [[1, 2, 3, 4], [5, 6, 7, 8]];");
+        let ItemKind::Global(global) = &program.items[0].kind else { panic!("expected global") };
+        let Some(Type { kind: TypeKind::Array { element, size: ArraySize::Literal(2) }, .. }) = &global.ty else { panic!("expected int[4][2]") };
+        let TypeKind::Array { element, size: ArraySize::Literal(4) } = &element.kind else { panic!("expected int[4]") };
+        assert_eq!(element.kind, TypeKind::Int(IntType::I32));
+    }
+
+    #[test]
+    fn parses_struct_declarations_and_named_types() {
+        let program = parse_source("struct Point { x: int, y: int } struct Line { from: Point, to: Point, } function origin() -> Point { return Point { x: 0, y: 0 }; }");
+        let ItemKind::Struct(point) = &program.items[0].kind else { panic!("expected struct") };
+        assert_eq!(point.name, "Point");
+        let fields: Vec<&str> = point.fields.iter().map(|field| field.name.as_str()).collect();
+        assert_eq!(fields, ["x", "y"]);
+        let ItemKind::Struct(line) = &program.items[1].kind else { panic!("expected struct") };
+        assert_eq!(line.fields[1].ty.kind, TypeKind::Named { name: "Point".to_string(), args: Vec::new() });
+        let ItemKind::Function(origin) = &program.items[2].kind else { panic!("expected function") };
+        assert_eq!(origin.return_type.as_ref().map(|ty| &ty.kind), Some(&TypeKind::Named { name: "Point".to_string(), args: Vec::new() }));
+    }
+
+    #[test]
+    fn struct_literals_are_not_parsed_in_bare_conditions() {
+        let program = parse_source("function f() { if p == q { g(); } while (p == Q { x: 1 }) {} if f(Q {}) {} }");
+        let ItemKind::Function(function) = &program.items[0].kind else { panic!("expected function") };
+        let StmtKind::If { condition, then_branch, .. } = &function.body.stmts[0].kind else { panic!("expected if") };
+        assert_eq!(sexp(condition), "(== p q)");
+        assert_eq!(then_branch.stmts.len(), 1);
+        let StmtKind::While { condition, .. } = &function.body.stmts[1].kind else { panic!("expected while") };
+        assert_eq!(sexp(condition), "(== p (Q x: 1))");
+        let StmtKind::If { condition, .. } = &function.body.stmts[2].kind else { panic!("expected if") };
+        assert_eq!(sexp(condition), "(call f (Q))");
+    }
+
+    #[test]
+    fn parses_enums_and_match_statements() {
+        let program = parse_source("enum Shape { Circle(int), Rect(int, int), Empty, } function f(s: Shape) { match s { _ => {} } (a) = 1; }");
+        let ItemKind::Enum(shape) = &program.items[0].kind else { panic!("expected enum") };
+        let arities: Vec<(&str, usize)> = shape.variants.iter().map(|variant| (variant.name.as_str(), variant.fields.len())).collect();
+        assert_eq!(arities, [("Circle", 1), ("Rect", 2), ("Empty", 0)]);
+        assert!(shape.type_params.is_empty());
+        let ItemKind::Function(function) = &program.items[1].kind else { panic!("expected function") };
+        // A statement-level match ends at its closing brace.
+        assert_eq!(function.body.stmts.len(), 2);
+        let StmtKind::Expr(assign) = &function.body.stmts[1].kind else { panic!("expected expression") };
+        assert_eq!(sexp(assign), "(= a 1)");
+    }
+
+    #[test]
+    fn parses_tuples_and_destructuring() {
+        let program = parse_source("function f((a, (b,)): (int, (bool,)), u: ()) -> (int, int)[2] { let (q, _) = (a, (a)).1.0; return ((q), ()); }");
+        let ItemKind::Function(function) = &program.items[0].kind else { panic!("expected function") };
+        assert_eq!(pattern_sexp(&function.params[0].pattern), "(tuple a (tuple b))");
+        let TypeKind::Tuple(elements) = &function.params[0].ty.kind else { panic!("expected tuple type") };
+        assert!(matches!(&elements[1].kind, TypeKind::Tuple(inner) if inner.len() == 1 && inner[0].kind == TypeKind::Bool));
+        assert_eq!(function.params[1].ty.kind, TypeKind::Tuple(Vec::new()));
+        let Some(Type { kind: TypeKind::Array { element, size: ArraySize::Literal(2) }, .. }) = &function.return_type else { panic!("expected array of tuples") };
+        assert!(matches!(&element.kind, TypeKind::Tuple(elements) if elements.len() == 2));
+        let StmtKind::Let(binding) = &function.body.stmts[0].kind else { panic!("expected let") };
+        assert_eq!(pattern_sexp(&binding.pattern), "(tuple q _)");
+        // `(a)` is only parenthesised; `.1.0` is two tuple indexes.
+        assert_eq!(sexp(&binding.value), "(. (. (tuple a a) 1) 0)");
+        let StmtKind::Return(Some(value)) = &function.body.stmts[1].kind else { panic!("expected return") };
+        assert_eq!(sexp(value), "(tuple q (tuple))");
+    }
+
+    #[test]
+    fn parses_mut_bindings() {
+        let program = parse_source("let mut g = 0; function f(mut n: int, (mut a, b): (int, int)) { let mut c = |mut x: int| x; }");
+        let ItemKind::Global(global) = &program.items[0].kind else { panic!("expected global") };
+        assert_eq!(global.pattern.kind, PatternKind::Binding { name: "g".to_string(), mutable: true });
+        let ItemKind::Function(function) = &program.items[1].kind else { panic!("expected function") };
+        assert_eq!(pattern_sexp(&function.params[0].pattern), "mut n");
+        assert_eq!(pattern_sexp(&function.params[1].pattern), "(tuple mut a b)");
+        let StmtKind::Let(binding) = &function.body.stmts[0].kind else { panic!("expected let") };
+        assert_eq!(pattern_sexp(&binding.pattern), "mut c");
+        assert_eq!(sexp(&binding.value), "(closure [mut x] x)");
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected identifier after 'mut'")]
+    fn rejects_mut_before_a_tuple_pattern() {
+        parse_source("function f() { let mut (a, b) = (1, 2); }");
+    }
+
+    #[test]
+    fn parses_strings_and_interpolation() {
+        assert_eq!(sexp(&parse(r#""a\"b\\c\n" + s"#)), r#"(+ "a\"b\\c\n" s)"#);
+        assert_eq!(sexp(&parse(r#""x = {x}, {{y}} = {f(y, P { a: 1 }.a)}!""#)), r#"(str "x = " x ", {y} = " (call f y (. (P a: 1) a)) "!")"#);
+        assert_eq!(sexp(&parse(r#""{a}{b[0] + 1}""#)), "(str a (+ (index b 0) 1))");
+        let source = r#"s + "n: {n + 1}""#;
+        let expr = parse(source);
+        let ExprKind::Binary { right, .. } = &expr.kind else { panic!("expected binary op") };
+        let ExprKind::Interpolation(parts) = &right.kind else { panic!("expected interpolation") };
+        let StringPart::Expr(hole) = &parts[1] else { panic!("expected hole") };
+        assert_eq!(text(source, hole.span), "n + 1");
+        let program = parse_source("import \"util/a b.cl\"; let t: string = \"\";");
+        let ItemKind::Import(import) = &program.items[0].kind else { panic!("expected import") };
+        assert_eq!(import.path, "util/a b.cl");
+        let ItemKind::Global(global) = &program.items[1].kind else { panic!("expected global") };
+        assert_eq!(global.ty.as_ref().unwrap().kind, TypeKind::String);
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected '}' after interpolated expression")]
+    fn rejects_unclosed_interpolation() {
+        parse(r#""{x""#);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unmatched '}' in string literal")]
+    fn rejects_unmatched_closing_brace_in_string() {
+        parse(r#""a}b""#);
+    }
+
+    #[test]
+    fn returns_syntax_errors_at_the_offending_token() {
+        let error = |source: &str| {
+            let diagnostic = super::parse_source(&mut Lexer::new(source.to_string())).unwrap_err();
+            (diagnostic.message, text(source, diagnostic.span))
+        };
+        assert_eq!(error("function f() { let = 1; }"), ("Expected pattern, found Equals".to_string(), "=".to_string()));
+        assert_eq!(error("let s = \"{a b}\";"), ("Expected '}' after interpolated expression, found Identifier(\"b\")".to_string(), "b".to_string()));
+    }
+
+    #[test]
+    fn parses_closures_with_open_ended_bodies() {
+        assert_eq!(sexp(&parse("|x| x + 1")), "(closure [x] (+ x 1))");
+        assert_eq!(sexp(&parse("f(|a, (b, _)| a = b, || g)")), "(call f (closure [a (tuple b _)] (= a b)) (closure [] g))");
+        assert_eq!(sexp(&parse("(|x| x)(1) + 2")), "(+ (call (closure [x] x) 1) 2)");
+        assert_eq!(sexp(&parse("|x| |y| x * y")), "(closure [x] (closure [y] (* x y)))");
+        // A block body ends the closure.
+        assert_eq!(sexp(&parse("|x: int| -> int { return x; }(2)")), "(call (closure [x] {1}) 2)");
+    }
+
+    #[test]
+    fn parses_function_types() {
+        let program = parse_source("let f: fn(int, (bool,)) -> fn() = g; let a: (fn() -> int)[2] = h;");
+        let ItemKind::Global(global) = &program.items[0].kind else { panic!("expected global") };
+        let Some(Type { kind: TypeKind::Function { params, return_type: Some(return_type) }, .. }) = &global.ty else {
+            panic!("expected function type")
+        };
+        assert_eq!(params.len(), 2);
+        assert!(matches!(&return_type.kind, TypeKind::Function { params, return_type: None } if params.is_empty()));
+        let ItemKind::Global(global) = &program.items[1].kind else { panic!("expected global") };
+        let Some(Type { kind: TypeKind::Array { element, size: ArraySize::Literal(2) }, .. }) = &global.ty else { panic!("expected array") };
+        assert!(matches!(&element.kind, TypeKind::Function { return_type: Some(_), .. }));
+    }
+
+    #[test]
+    fn parses_type_parameters_and_arguments() {
+        let program = parse_source("struct Pair<A, B> { first: A, second: B } function max<T>(a: T, b: T) -> Pair<T, Pair<int, bool>>[2] {}");
+        let ItemKind::Struct(pair) = &program.items[0].kind else { panic!("expected struct") };
+        let names: Vec<&str> = pair.type_params.iter().map(|param| param.name.as_str()).collect();
+        assert_eq!(names, ["A", "B"]);
+        let ItemKind::Function(max) = &program.items[1].kind else { panic!("expected function") };
+        assert_eq!(max.type_params[0].name, "T");
+        assert_eq!(max.params[1].ty.kind, TypeKind::Named { name: "T".to_string(), args: Vec::new() });
+        let Some(Type { kind: TypeKind::Array { element, size: ArraySize::Literal(2) }, .. }) = &max.return_type else { panic!("expected array") };
+        let TypeKind::Named { name, args } = &element.kind else { panic!("expected named type") };
+        assert_eq!((name.as_str(), args.len()), ("Pair", 2));
+        assert!(matches!(&args[1].kind, TypeKind::Named { args, .. } if args.len() == 2));
+        let program = parse_source("enum Result<T, E> { Ok(T), Err(E) }");
+        let ItemKind::Enum(result) = &program.items[0].kind else { panic!("expected enum") };
+        let names: Vec<&str> = result.type_params.iter().map(|param| param.name.as_str()).collect();
+        assert_eq!(names, ["T", "E"]);
+        // `>>` closes two lists of type arguments.
+        let program = parse_source("let p: Pair<Pair<u8, i64>> = q >> 1;");
+        let ItemKind::Global(binding) = &program.items[0].kind else { panic!("expected global") };
+        let Some(TypeKind::Named { args, .. }) = binding.ty.as_ref().map(|ty| &ty.kind) else { panic!("expected named type") };
+        let TypeKind::Named { args, .. } = &args[0].kind else { panic!("expected named type") };
+        assert_eq!(args.iter().map(|arg| &arg.kind).collect::<Vec<_>>(), [&TypeKind::Int(IntType::U8), &TypeKind::Int(IntType::I64)]);
+        assert_eq!(args[1].span.end + 2, binding.ty.as_ref().unwrap().span.end);
+    }
+
+    #[test]
+    fn parses_interfaces_impls_and_bounds() {
+        let program = parse_source(
+            "interface Show { function show(self) -> int; function pad(self, width: int) -> Self; } \
+             impl Show for Point[2] { @inline function show(self) -> int { return self[0].x.show(); } } function f<T: Show + Eq, U>(x: T, y: dyn Show) {}",
+        );
+        let ItemKind::Interface(show) = &program.items[0].kind else { panic!("expected interface") };
+        assert_eq!(show.name, "Show");
+        let self_type = TypeKind::Named { name: "Self".to_string(), args: Vec::new() };
+        assert_eq!(show.methods[1].params.len(), 2);
+        assert_eq!(show.methods[1].params[0].pattern.kind, PatternKind::Binding { name: "self".to_string(), mutable: false });
+        assert_eq!(show.methods[1].params[0].ty.kind, self_type);
+        assert_eq!(show.methods[1].return_type.as_ref().map(|ty| &ty.kind), Some(&self_type));
+        let ItemKind::Impl(implementation) = &program.items[1].kind else { panic!("expected impl") };
+        assert_eq!(implementation.interface, "Show");
+        assert!(matches!(implementation.ty.kind, TypeKind::Array { size: ArraySize::Literal(2), .. }));
+        assert!(implementation.methods[0].attribute("inline").is_some());
+        assert_eq!(implementation.methods[0].span.start, implementation.methods[0].attributes[0].span.start);
+        let StmtKind::Return(Some(value)) = &implementation.methods[0].function.body.stmts[0].kind else { panic!("expected return") };
+        assert_eq!(sexp(value), "(call (. (. (index self 0) x) show))");
+        let ItemKind::Function(f) = &program.items[2].kind else { panic!("expected function") };
+        assert_eq!(f.type_params[0].bounds, ["Show", "Eq"]);
+        assert!(f.type_params[1].bounds.is_empty());
+        assert_eq!(f.params[1].ty.kind, TypeKind::Dyn("Show".to_string()));
+    }
+
+    #[test]
+    fn parses_imports_and_qualified_names() {
+        let program = parse_source(
+            "import math; import \"util/geometry.cl\"; impl math::Show for geometry::Point { } \
+             function f<T: math::Show>(p: geometry::Point, s: dyn math::Show) { math::sqrt(geometry::Point { x: 1 }.x); }",
+        );
+        let imports: Vec<(String, &str)> = program.items[..2]
+            .iter()
+            .map(|item| match &item.kind {
+                ItemKind::Import(import) => (import.file(), import.module_name()),
+                _ => panic!("expected import"),
+            })
+            .collect();
+        assert_eq!(imports, [("math.cl".to_string(), "math"), ("util/geometry.cl".to_string(), "geometry")]);
+        let ItemKind::Impl(implementation) = &program.items[2].kind else { panic!("expected impl") };
+        assert_eq!(implementation.interface, "math::Show");
+        let ItemKind::Function(f) = &program.items[3].kind else { panic!("expected function") };
+        assert_eq!(f.type_params[0].bounds, ["math::Show"]);
+        assert_eq!(f.params[0].ty.kind, TypeKind::Named { name: "geometry::Point".to_string(), args: Vec::new() });
+        assert_eq!(f.params[1].ty.kind, TypeKind::Dyn("math::Show".to_string()));
+        let StmtKind::Expr(call) = &f.body.stmts[0].kind else { panic!("expected expression") };
+        assert_eq!(sexp(call), "(call math::sqrt (. (geometry::Point x: 1) x))");
+    }
+
+    #[test]
+    fn parses_constants_and_named_array_sizes() {
+        let program = parse_source("const N: int = 2 * M; let a: int[N][math::M] = [];");
+        let ItemKind::Const(constant) = &program.items[0].kind else { panic!("expected const") };
+        assert_eq!((constant.name.as_str(), &constant.ty.kind), ("N", &TypeKind::Int(IntType::I32)));
+        assert_eq!(sexp(&constant.value), "(* 2 M)");
+        let ItemKind::Global(global) = &program.items[1].kind else { panic!("expected global") };
+        let Some(Type { kind: TypeKind::Array { element, size: ArraySize::Const(outer) }, .. }) = &global.ty else { panic!("expected array") };
+        let TypeKind::Array { size: ArraySize::Const(inner), .. } = &element.kind else { panic!("expected array") };
+        assert_eq!((inner.as_str(), outer.as_str()), ("N", "math::M"));
+    }
+
+    #[test]
+    fn parses_const_functions() {
+        let program = parse_source("const function square(n: int) -> int { return n * n; } pub const function f() {} function g() {}");
+        let constant: Vec<bool> = program
+            .items
+            .iter()
+            .map(|item| match &item.kind {
+                ItemKind::Function(function) => function.constant,
+                _ => panic!("expected function"),
+            })
+            .collect();
+        assert_eq!(constant, [true, true, false]);
+        assert!(program.items[1].public);
+    }
+
+    #[test]
+    fn parses_pub_items() {
+        let program = parse_source("pub function f() {} pub let x = 1; struct S {} pub enum E { A } import m;");
+        let public: Vec<bool> = program.items.iter().map(|item| item.public).collect();
+        assert_eq!(public, [true, true, false, true, false]);
+        assert_eq!(program.items[0].span, Span::new(0, 19));
+    }
+
+    #[test]
+    fn parses_attributes_before_items() {
+        let source = "@inline @export(\"c_f\", \"x\") pub function f() {} @deprecated(\"use \\\"T\\\"\") struct S {} let y = 1;";
+        let program = parse_source(source);
+        let attributes: Vec<(&str, &[String])> =
+            program.items[0].attributes.iter().map(|attribute| (attribute.name.as_str(), attribute.args.as_slice())).collect();
+        assert_eq!(attributes, [("inline", &[][..]), ("export", &["c_f".to_string(), "x".to_string()][..])]);
+        assert!(program.items[0].public);
+        assert_eq!(text(source, program.items[0].span), "@inline @export(\"c_f\", \"x\") pub function f() {}");
+        assert_eq!(program.items[1].attribute("deprecated").unwrap().args, ["use \"T\""]);
+        assert!(program.items[2].attributes.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected string argument in attribute")]
+    fn rejects_attribute_arguments_that_are_not_strings() {
+        parse_source("@export(name) function f() {}");
+    }
+
+    #[test]
+    fn parses_deferred_statements() {
+        let program = parse_source("function f(p: ptr) { defer free(p); defer if p == p { log(1); } let x = 1; }");
+        let ItemKind::Function(function) = &program.items[0].kind else { panic!("expected function") };
+        let StmtKind::Defer(first) = &function.body.stmts[0].kind else { panic!("expected defer") };
+        let StmtKind::Expr(call) = &first.kind else { panic!("expected expression statement") };
+        assert_eq!(sexp(call), "(call free p)");
+        let StmtKind::Defer(second) = &function.body.stmts[1].kind else { panic!("expected defer") };
+        assert!(matches!(second.kind, StmtKind::If { .. }));
+        assert_eq!(function.body.stmts.len(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "a deferred 'let' would go out of scope at once")]
+    fn rejects_deferred_lets() {
+        parse_source("function f() { defer let x = 1; }");
+    }
+
+    #[test]
+    fn parses_extern_functions() {
+        let source = "extern \"C\" function puts(s: ptr) -> int; pub extern \"C\" function printf(format: ptr, ...) -> int; extern \"C\" function abort();";
+        let program = parse_source(source);
+        let ItemKind::Extern(puts) = &program.items[0].kind else { panic!("expected extern") };
+        assert_eq!((puts.abi.as_str(), puts.name.as_str(), puts.variadic), ("C", "puts", false));
+        assert_eq!(puts.params[0].ty.kind, TypeKind::Ptr);
+        assert_eq!(puts.return_type.as_ref().map(|ty| &ty.kind), Some(&TypeKind::Int(IntType::I32)));
+        let ItemKind::Extern(printf) = &program.items[1].kind else { panic!("expected extern") };
+        assert_eq!((printf.params.len(), printf.variadic), (1, true));
+        assert!(program.items[1].public);
+        assert_eq!(text(source, program.items[1].span), "pub extern \"C\" function printf(format: ptr, ...) -> int;");
+        let ItemKind::Extern(abort) = &program.items[2].kind else { panic!("expected extern") };
+        assert!(abort.params.is_empty() && abort.return_type.is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected ')' after '...'")]
+    fn rejects_parameters_after_ellipsis() {
+        parse_source("extern \"C\" function printf(..., format: ptr) -> int;");
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected ';' after extern function declaration")]
+    fn rejects_extern_functions_with_a_body() {
+        parse_source("extern \"C\" function f() {}");
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected 'function', 'struct', 'enum', 'interface', 'extern', 'let' or 'const' after 'pub'")]
+    fn rejects_pub_impls() {
+        parse_source("pub impl Show for P {}");
+    }
+
+    #[test]
+    fn parses_items_statements_and_typed_declarations() {
+        let source = "let x: int = 10; function main() -> int { let y: int = 20; if (true) { return y; } else if x < y { return x; } for (let i: int = 0; i < 10; i = i + 1) { } return y + x; }";
+        let program = parse_source(source);
+        assert_eq!(program.items.len(), 2);
+        let ItemKind::Global(global) = &program.items[0].kind else { panic!("expected global") };
+        assert_eq!(global.pattern.kind, PatternKind::Binding { name: "x".to_string(), mutable: false });
+        assert_eq!(global.ty.as_ref().map(|ty| &ty.kind), Some(&TypeKind::Int(IntType::I32)));
+        let ItemKind::Function(main) = &program.items[1].kind else { panic!("expected function") };
+        assert_eq!(main.return_type.as_ref().map(|ty| &ty.kind), Some(&TypeKind::Int(IntType::I32)));
+        let kinds: Vec<&str> = main
+            .body
+            .stmts
+            .iter()
+            .map(|stmt| match &stmt.kind {
+                StmtKind::Let(_) => "let",
+                StmtKind::Expr(_) => "expr",
+                StmtKind::Return(_) => "return",
+                StmtKind::If { .. } => "if",
+                StmtKind::For { .. } => "for",
+                StmtKind::While { .. } => "while",
+                StmtKind::Defer(_) => "defer",
+            })
+            .collect();
+        assert_eq!(kinds, ["let", "if", "for", "return"]);
+        let StmtKind::If { else_branch: Some(else_branch), .. } = &main.body.stmts[1].kind else { panic!("expected else") };
+        assert!(matches!(else_branch.stmts[0].kind, StmtKind::If { .. }));
     }
 }
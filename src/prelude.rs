@@ -0,0 +1,24 @@
+//! Items every program can use without importing them.
+//!
+//! `modules::load` parses the prelude as a module of its own before the root
+//! file. Its items keep their names, so `Option` and `Result` can be used as
+//! they are from every module; a module that defines an item of the same name
+//! uses its own.
+
+/// The name the prelude goes by in diagnostics.
+pub const PATH: &str = "<prelude>";
+
+/// `Option` holds a value that may be absent and `Result` the value of an
+/// operation that may fail. Applying `?` to either returns the `None` or
+/// `Err` from the enclosing function.
+pub const SOURCE: &str = "\
+pub enum Option<T> {
+    None,
+    Some(T),
+}
+
+pub enum Result<T, E> {
+    Ok(T),
+    Err(E),
+}
+";
@@ -0,0 +1,904 @@
+//! Turns an AST back into CopilotLang source.
+//!
+//! Parentheses are only emitted where the operator table says they are
+//! needed, so printing a freshly parsed program and parsing the result again
+//! yields the same tree (ignoring node ids and spans).
+
+use crate::lexer::Token;
+use crate::parser::{
+    operator_info, ArmBody, Associativity, Attribute, Block, ClosureParam, Enum, Expr, ExprKind, Extern, FieldInit, Function, Impl, Import,
+    Interface, Item, ItemKind, Let, MatchArm, MethodSig, Param, Pattern, PatternKind, Program, Stmt, StmtKind, StringPart, Struct, Type,
+    TypeKind, TypeParam,
+};
+
+/// Binding power of atoms such as identifiers and literals; they never need
+/// parentheses.
+const ATOM_BINDING_POWER: u8 = u8::MAX;
+
+pub fn print_program(program: &Program) -> String {
+    let mut printer = Printer::default();
+    printer.program(program);
+    printer.out
+}
+
+pub fn print_expr(expr: &Expr) -> String {
+    let mut printer = Printer::default();
+    printer.expr(expr, 0);
+    printer.out
+}
+
+pub fn print_type(ty: &Type) -> String {
+    match &ty.kind {
+        TypeKind::Int(ty) => ty.name().to_string(),
+        TypeKind::Bool => "bool".to_string(),
+        TypeKind::String => "string".to_string(),
+        TypeKind::Ptr => "ptr".to_string(),
+        TypeKind::Void => "void".to_string(),
+        // A function type takes no array suffixes of its own.
+        TypeKind::Array { element, size } if matches!(element.kind, TypeKind::Function { .. }) => {
+            format!("({})[{}]", print_type(element), size)
+        }
+        TypeKind::Array { element, size } => format!("{}[{}]", print_type(element), size),
+        TypeKind::Named { name, args } if args.is_empty() => name.clone(),
+        TypeKind::Named { name, args } => {
+            let args: Vec<String> = args.iter().map(print_type).collect();
+            format!("{}<{}>", name, args.join(", "))
+        }
+        TypeKind::Tuple(elements) => tuple_text(elements.iter().map(print_type).collect()),
+        TypeKind::Function { params, return_type } => {
+            let params: Vec<String> = params.iter().map(print_type).collect();
+            match return_type {
+                Some(return_type) => format!("fn({}) -> {}", params.join(", "), print_type(return_type)),
+                None => format!("fn({})", params.join(", ")),
+            }
+        }
+        TypeKind::Dyn(interface) => format!("dyn {}", interface),
+    }
+}
+
+pub fn print_pattern(pattern: &Pattern) -> String {
+    match &pattern.kind {
+        PatternKind::Wildcard => "_".to_string(),
+        PatternKind::Binding { name, mutable: false } => name.clone(),
+        PatternKind::Binding { name, mutable: true } => format!("mut {}", name),
+        PatternKind::IntLiteral(value) => value.to_string(),
+        PatternKind::BoolLiteral(value) => value.to_string(),
+        PatternKind::Variant { path, fields } if fields.is_empty() => path.join("::"),
+        PatternKind::Variant { path, fields } => {
+            let fields: Vec<String> = fields.iter().map(print_pattern).collect();
+            format!("{}({})", path.join("::"), fields.join(", "))
+        }
+        PatternKind::Tuple(elements) => tuple_text(elements.iter().map(print_pattern).collect()),
+    }
+}
+
+/// `<T, U: Show>` after the name of a generic item, or nothing.
+pub fn type_params_text(params: &[TypeParam]) -> String {
+    if params.is_empty() {
+        return String::new();
+    }
+    let params: Vec<String> = params
+        .iter()
+        .map(|param| match param.bounds.as_slice() {
+            [] => param.name.clone(),
+            bounds => format!("{}: {}", param.name, bounds.join(" + ")),
+        })
+        .collect();
+    format!("<{}>", params.join(", "))
+}
+
+/// `a: int`, or `self` for the receiver of a method.
+pub fn param_text(param: &Param) -> String {
+    if is_self_param(param) {
+        return print_pattern(&param.pattern);
+    }
+    format!("{}: {}", print_pattern(&param.pattern), print_type(&param.ty))
+}
+
+/// Whether `param` is `self: Self`, which is written as a bare `self`.
+fn is_self_param(param: &Param) -> bool {
+    matches!(&param.pattern.kind, PatternKind::Binding { name, .. } if name == "self")
+        && matches!(&param.ty.kind, TypeKind::Named { name, args } if name == "Self" && args.is_empty())
+}
+
+/// `function name(params) -> type`, the header of an interface method.
+pub fn method_sig_text(method: &MethodSig) -> String {
+    let params: Vec<String> = method.params.iter().map(param_text).collect();
+    let mut text = format!("function {}({})", method.name, params.join(", "));
+    if let Some(return_type) = &method.return_type {
+        text.push_str(" -> ");
+        text.push_str(&print_type(return_type));
+    }
+    text
+}
+
+/// `extern "C" function name(params, ...) -> type;`.
+pub fn extern_text(function: &Extern) -> String {
+    let mut params: Vec<String> = function.params.iter().map(param_text).collect();
+    if function.variadic {
+        params.push("...".to_string());
+    }
+    let mut text = format!("extern \"{}\" function {}({})", string_text(&function.abi), function.name, params.join(", "));
+    if let Some(return_type) = &function.return_type {
+        text.push_str(" -> ");
+        text.push_str(&print_type(return_type));
+    }
+    text.push(';');
+    text
+}
+
+/// `import math;`, or `import "util/math.cl";` for a path that is not a
+/// plain name.
+pub fn import_text(import: &Import) -> String {
+    if import.path.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        format!("import {};", import.path)
+    } else {
+        format!("import \"{}\";", string_text(&import.path))
+    }
+}
+
+/// `x, y: int`, the parameters of a closure without the bars.
+pub fn closure_params_text(params: &[ClosureParam]) -> String {
+    let params: Vec<String> = params
+        .iter()
+        .map(|param| match &param.ty {
+            Some(ty) => format!("{}: {}", print_pattern(&param.pattern), print_type(ty)),
+            None => print_pattern(&param.pattern),
+        })
+        .collect();
+    params.join(", ")
+}
+
+/// `(a, b)`, with the trailing comma that marks a one-element tuple.
+pub fn tuple_text(elements: Vec<String>) -> String {
+    match elements.as_slice() {
+        [single] => format!("({},)", single),
+        _ => format!("({})", elements.join(", ")),
+    }
+}
+
+/// Whether an `if` or `while` condition (or `match` scrutinee) must keep its
+/// parentheses: a struct literal outside any brackets would be read as the
+/// start of the body.
+pub fn condition_needs_parens(expr: &Expr) -> bool {
+    match &expr.kind {
+        // A closure body runs to the end of the condition and may itself
+        // hold a block.
+        ExprKind::StructLiteral { .. } | ExprKind::Closure { .. } => true,
+        ExprKind::Unary { operand, .. } | ExprKind::Try(operand) | ExprKind::Cast { operand, .. } => condition_needs_parens(operand),
+        ExprKind::Binary { left, right, .. } => condition_needs_parens(left) || condition_needs_parens(right),
+        ExprKind::Assign { target, value } => condition_needs_parens(target) || condition_needs_parens(value),
+        ExprKind::Call { callee: base, .. }
+        | ExprKind::Index { base, .. }
+        | ExprKind::Field { base, .. }
+        | ExprKind::TupleIndex { base, .. } => condition_needs_parens(base),
+        ExprKind::ArrayLiteral(_)
+        | ExprKind::Tuple(_)
+        | ExprKind::Match { .. }
+        | ExprKind::Path(_)
+        | ExprKind::Identifier(_)
+        | ExprKind::IntLiteral(_)
+        | ExprKind::BoolLiteral(_)
+        | ExprKind::StringLiteral(_)
+        | ExprKind::Interpolation(_) => false,
+    }
+}
+
+/// Whether an expression statement begins with `match` without being one.
+/// The parser ends a statement-level `match` at its closing brace, so such a
+/// statement has to be parenthesised.
+pub fn starts_with_match(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Match { .. } => true,
+        ExprKind::Binary { left: first, .. }
+        | ExprKind::Assign { target: first, .. }
+        | ExprKind::Call { callee: first, .. }
+        | ExprKind::Index { base: first, .. }
+        | ExprKind::Field { base: first, .. }
+        | ExprKind::TupleIndex { base: first, .. }
+        | ExprKind::Try(first)
+        | ExprKind::Cast { operand: first, .. } => starts_with_match(first),
+        _ => false,
+    }
+}
+
+/// `@name`, or `@name("argument", ...)` if the attribute has arguments.
+pub fn attribute_text(attribute: &Attribute) -> String {
+    if attribute.args.is_empty() {
+        return format!("@{}", attribute.name);
+    }
+    let args: Vec<String> = attribute.args.iter().map(|arg| format!("\"{}\"", string_text(arg))).collect();
+    format!("@{}({})", attribute.name, args.join(", "))
+}
+
+/// `text` as written between the quotes of a string literal.
+fn string_text(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '\0' => out.push_str("\\0"),
+            '{' => out.push_str("{{"),
+            '}' => out.push_str("}}"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn field_init_text(init: &FieldInit) -> String {
+    format!("{}: {}", init.name, print_expr(&init.value))
+}
+
+/// The binding power an expression's outermost operator binds with.
+pub fn expr_binding_power(expr: &Expr) -> u8 {
+    match &expr.kind {
+        ExprKind::Unary { op, .. } => prefix_binding_power(&op.token()),
+        ExprKind::Binary { op, .. } => infix_binding_power(&op.token()).0,
+        ExprKind::Assign { .. } => infix_binding_power(&Token::Equals).0,
+        ExprKind::Call { .. } => postfix_binding_power(&Token::LParen),
+        ExprKind::Index { .. } => postfix_binding_power(&Token::LBracket),
+        ExprKind::Field { .. } | ExprKind::TupleIndex { .. } => postfix_binding_power(&Token::Dot),
+        ExprKind::Try(_) => postfix_binding_power(&Token::Question),
+        ExprKind::Cast { .. } => postfix_binding_power(&Token::As),
+        // A closure body extends as far right as possible, so like the right
+        // operand of `=` a closure only goes without parentheses at the end
+        // of an expression.
+        ExprKind::Closure { .. } => infix_binding_power(&Token::Equals).0,
+        ExprKind::ArrayLiteral(_)
+        | ExprKind::Tuple(_)
+        | ExprKind::StructLiteral { .. }
+        | ExprKind::Match { .. }
+        | ExprKind::Path(_)
+        | ExprKind::Identifier(_)
+        | ExprKind::IntLiteral(_)
+        | ExprKind::BoolLiteral(_)
+        | ExprKind::StringLiteral(_)
+        | ExprKind::Interpolation(_) => ATOM_BINDING_POWER,
+    }
+}
+
+fn prefix_binding_power(token: &Token) -> u8 {
+    operator_info(token).and_then(|info| info.prefix).expect("operator has no prefix binding power")
+}
+
+fn infix_binding_power(token: &Token) -> (u8, Associativity) {
+    operator_info(token).and_then(|info| info.infix).expect("operator has no infix binding power")
+}
+
+fn postfix_binding_power(token: &Token) -> u8 {
+    operator_info(token).and_then(|info| info.postfix).expect("operator has no postfix binding power")
+}
+
+/// Minimum binding powers the left and right operands of an infix operator
+/// must have to be printed without parentheses.
+pub fn operand_binding_powers(binding_power: u8, associativity: Associativity) -> (u8, u8) {
+    match associativity {
+        Associativity::Left => (binding_power, binding_power + 1),
+        Associativity::Right => (binding_power + 1, binding_power),
+    }
+}
+
+#[derive(Default)]
+struct Printer {
+    out: String,
+    indent: usize,
+}
+
+impl Printer {
+    fn indentation(&mut self) {
+        for _ in 0..self.indent {
+            self.out.push_str("    ");
+        }
+    }
+
+    fn line(&mut self, text: &str) {
+        self.indentation();
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    /// Prints `expr` at the current indentation, for expressions such as
+    /// `match` that span several lines.
+    fn expr_text(&self, expr: &Expr) -> String {
+        let mut printer = Printer {
+            out: String::new(),
+            indent: self.indent,
+        };
+        printer.expr(expr, 0);
+        printer.out
+    }
+
+    fn condition_text(&self, expr: &Expr) -> String {
+        if condition_needs_parens(expr) {
+            format!("({})", self.expr_text(expr))
+        } else {
+            self.expr_text(expr)
+        }
+    }
+
+    fn program(&mut self, program: &Program) {
+        for (index, item) in program.items.iter().enumerate() {
+            if index > 0 {
+                self.out.push('\n');
+            }
+            self.item(item);
+        }
+    }
+
+    fn item(&mut self, item: &Item) {
+        for attribute in &item.attributes {
+            self.line(&attribute_text(attribute));
+        }
+        // Items are never indented, so the keyword can go straight in.
+        if item.public {
+            self.out.push_str("pub ");
+        }
+        match &item.kind {
+            ItemKind::Function(function) => self.function(function),
+            ItemKind::Global(binding) => {
+                let text = self.let_text(binding);
+                self.line(&text);
+            }
+            ItemKind::Const(constant) => {
+                let text = format!("const {}: {} = {};", constant.name, print_type(&constant.ty), self.expr_text(&constant.value));
+                self.line(&text);
+            }
+            ItemKind::Struct(definition) => self.struct_definition(definition),
+            ItemKind::Enum(definition) => self.enum_definition(definition),
+            ItemKind::Interface(definition) => self.interface(definition),
+            ItemKind::Impl(definition) => self.impl_block(definition),
+            ItemKind::Import(import) => self.line(&import_text(import)),
+            ItemKind::Extern(function) => self.line(&extern_text(function)),
+        }
+    }
+
+    fn interface(&mut self, definition: &Interface) {
+        if definition.methods.is_empty() {
+            self.line(&format!("interface {} {{}}", definition.name));
+            return;
+        }
+        self.line(&format!("interface {} {{", definition.name));
+        self.indent += 1;
+        for method in &definition.methods {
+            self.line(&format!("{};", method_sig_text(method)));
+        }
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    fn impl_block(&mut self, definition: &Impl) {
+        let header = format!("impl {} for {}", definition.interface, print_type(&definition.ty));
+        if definition.methods.is_empty() {
+            self.line(&format!("{} {{}}", header));
+            return;
+        }
+        self.line(&format!("{} {{", header));
+        self.indent += 1;
+        for (index, method) in definition.methods.iter().enumerate() {
+            if index > 0 {
+                self.out.push('\n');
+            }
+            for attribute in &method.attributes {
+                self.line(&attribute_text(attribute));
+            }
+            self.function(&method.function);
+        }
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    fn enum_definition(&mut self, definition: &Enum) {
+        let name = format!("{}{}", definition.name, type_params_text(&definition.type_params));
+        if definition.variants.is_empty() {
+            self.line(&format!("enum {} {{}}", name));
+            return;
+        }
+        self.line(&format!("enum {} {{", name));
+        self.indent += 1;
+        for variant in &definition.variants {
+            if variant.fields.is_empty() {
+                self.line(&format!("{},", variant.name));
+            } else {
+                let fields: Vec<String> = variant.fields.iter().map(print_type).collect();
+                self.line(&format!("{}({}),", variant.name, fields.join(", ")));
+            }
+        }
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    fn struct_definition(&mut self, definition: &Struct) {
+        let name = format!("{}{}", definition.name, type_params_text(&definition.type_params));
+        if definition.fields.is_empty() {
+            self.line(&format!("struct {} {{}}", name));
+            return;
+        }
+        self.line(&format!("struct {} {{", name));
+        self.indent += 1;
+        for field in &definition.fields {
+            self.line(&format!("{}: {},", field.name, print_type(&field.ty)));
+        }
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    fn function(&mut self, function: &Function) {
+        let params: Vec<String> = function.params.iter().map(param_text).collect();
+        let keyword = if function.constant { "const function" } else { "function" };
+        let mut header = format!("{} {}{}({})", keyword, function.name, type_params_text(&function.type_params), params.join(", "));
+        if let Some(return_type) = &function.return_type {
+            header.push_str(" -> ");
+            header.push_str(&print_type(return_type));
+        }
+        self.block_after(header, &function.body);
+    }
+
+    /// Prints `header {`, the block's statements and the closing brace.
+    fn block_after(&mut self, header: String, block: &Block) {
+        if block.stmts.is_empty() {
+            self.line(&format!("{} {{}}", header));
+            return;
+        }
+        self.line(&format!("{} {{", header));
+        self.block_body(block);
+        self.line("}");
+    }
+
+    fn block_body(&mut self, block: &Block) {
+        self.indent += 1;
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+        self.indent -= 1;
+    }
+
+    fn let_text(&mut self, binding: &Let) -> String {
+        let mut text = format!("let {}", print_pattern(&binding.pattern));
+        if let Some(ty) = &binding.ty {
+            text.push_str(": ");
+            text.push_str(&print_type(ty));
+        }
+        text.push_str(" = ");
+        text.push_str(&self.expr_text(&binding.value));
+        text.push(';');
+        text
+    }
+
+    /// A statement that fits on one line, as used in a `for` header.
+    fn simple_stmt_text(&mut self, stmt: &Stmt) -> String {
+        match &stmt.kind {
+            StmtKind::Let(binding) => self.let_text(binding),
+            StmtKind::Expr(expr) if starts_with_match(expr) && !matches!(expr.kind, ExprKind::Match { .. }) => {
+                format!("({});", self.expr_text(expr))
+            }
+            StmtKind::Expr(expr) => format!("{};", self.expr_text(expr)),
+            _ => panic!("statement cannot appear in a for header: {:?}", stmt.kind),
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Expr(expr @ Expr { kind: ExprKind::Match { .. }, .. }) => {
+                let text = self.expr_text(expr);
+                self.line(&text);
+            }
+            StmtKind::Let(_) | StmtKind::Expr(_) => {
+                let text = self.simple_stmt_text(stmt);
+                self.line(&text);
+            }
+            StmtKind::Return(None) => self.line("return;"),
+            StmtKind::Return(Some(value)) => {
+                let text = format!("return {};", self.expr_text(value));
+                self.line(&text);
+            }
+            StmtKind::If { .. } => self.if_chain(stmt, "if"),
+            StmtKind::For { init, condition, increment, body } => {
+                let mut header = String::from("for (");
+                match init {
+                    Some(init) => header.push_str(&self.simple_stmt_text(init)),
+                    None => header.push(';'),
+                }
+                if let Some(condition) = condition {
+                    header.push(' ');
+                    header.push_str(&self.expr_text(condition));
+                }
+                header.push(';');
+                if let Some(increment) = increment {
+                    header.push(' ');
+                    header.push_str(&self.expr_text(increment));
+                }
+                header.push(')');
+                self.block_after(header, body);
+            }
+            StmtKind::While { condition, body } => {
+                let header = format!("while {}", self.condition_text(condition));
+                self.block_after(header, body);
+            }
+            StmtKind::Defer(deferred) => {
+                // `defer` goes after the indentation of the deferred
+                // statement's first line.
+                let start = self.out.len() + 4 * self.indent;
+                self.stmt(deferred);
+                self.out.insert_str(start, "defer ");
+            }
+        }
+    }
+
+    /// Prints an `if`, folding an else block that only holds another `if`
+    /// back into `else if`.
+    fn if_chain(&mut self, stmt: &Stmt, keyword: &str) {
+        let StmtKind::If { condition, then_branch, else_branch } = &stmt.kind else {
+            unreachable!("if_chain called on a non-if statement");
+        };
+        let header = format!("{} {}", keyword, self.condition_text(condition));
+        let Some(else_branch) = else_branch else {
+            self.block_after(header, then_branch);
+            return;
+        };
+        self.line(&format!("{} {{", header));
+        self.block_body(then_branch);
+        match else_branch.stmts.as_slice() {
+            [nested @ Stmt { kind: StmtKind::If { .. }, .. }] => self.if_chain(nested, "} else if"),
+            _ if else_branch.stmts.is_empty() => self.line("} else {}"),
+            _ => {
+                self.line("} else {");
+                self.block_body(else_branch);
+                self.line("}");
+            }
+        }
+    }
+
+    /// Prints `expr`, wrapping it in parentheses if it binds more loosely than
+    /// `min_binding_power`.
+    fn expr(&mut self, expr: &Expr, min_binding_power: u8) {
+        let needs_parens = expr_binding_power(expr) < min_binding_power;
+        if needs_parens {
+            self.out.push('(');
+        }
+        match &expr.kind {
+            ExprKind::Unary { op, operand } => {
+                self.out.push_str(op.symbol());
+                self.expr(operand, prefix_binding_power(&op.token()));
+            }
+            ExprKind::Binary { op, left, right } => {
+                let (binding_power, associativity) = infix_binding_power(&op.token());
+                self.infix(left, op.symbol(), right, binding_power, associativity);
+            }
+            ExprKind::Assign { target, value } => {
+                let (binding_power, associativity) = infix_binding_power(&Token::Equals);
+                self.infix(target, "=", value, binding_power, associativity);
+            }
+            ExprKind::Call { callee, args } => {
+                self.expr(callee, postfix_binding_power(&Token::LParen));
+                self.list('(', args, ')');
+            }
+            ExprKind::Index { base, index } => {
+                self.expr(base, postfix_binding_power(&Token::LBracket));
+                self.out.push('[');
+                self.expr(index, 0);
+                self.out.push(']');
+            }
+            ExprKind::Field { base, name } => {
+                self.expr(base, postfix_binding_power(&Token::Dot));
+                self.out.push('.');
+                self.out.push_str(name);
+            }
+            ExprKind::TupleIndex { base, index } => {
+                self.expr(base, postfix_binding_power(&Token::Dot));
+                self.out.push('.');
+                self.out.push_str(&index.to_string());
+            }
+            ExprKind::Try(operand) => {
+                self.expr(operand, postfix_binding_power(&Token::Question));
+                self.out.push('?');
+            }
+            ExprKind::Cast { operand, ty } => {
+                self.expr(operand, postfix_binding_power(&Token::As));
+                self.out.push_str(" as ");
+                self.out.push_str(&print_type(ty));
+            }
+            ExprKind::ArrayLiteral(elements) => self.list('[', elements, ']'),
+            ExprKind::Tuple(elements) => {
+                let elements: Vec<String> = elements.iter().map(|element| self.expr_text(element)).collect();
+                self.out.push_str(&tuple_text(elements));
+            }
+            ExprKind::StructLiteral { name, fields } => {
+                self.out.push_str(name);
+                if fields.is_empty() {
+                    self.out.push_str(" {}");
+                } else {
+                    let fields: Vec<String> = fields.iter().map(field_init_text).collect();
+                    self.out.push_str(&format!(" {{ {} }}", fields.join(", ")));
+                }
+            }
+            ExprKind::Match { scrutinee, arms } => self.match_expr(scrutinee, arms),
+            ExprKind::Closure { params, return_type, body } => {
+                self.out.push_str(&format!("|{}| ", closure_params_text(params)));
+                if let Some(return_type) = return_type {
+                    self.out.push_str(&format!("-> {} ", print_type(return_type)));
+                }
+                match &**body {
+                    ArmBody::Expr(body) => self.expr(body, 0),
+                    ArmBody::Block(block) => self.inline_block(block),
+                }
+            }
+            ExprKind::Path(path) => self.out.push_str(&path.join("::")),
+            ExprKind::Identifier(name) => self.out.push_str(name),
+            ExprKind::IntLiteral(value) => self.out.push_str(&value.to_string()),
+            ExprKind::BoolLiteral(value) => self.out.push_str(&value.to_string()),
+            ExprKind::StringLiteral(value) => self.out.push_str(&format!("\"{}\"", string_text(value))),
+            ExprKind::Interpolation(parts) => {
+                self.out.push('"');
+                for part in parts {
+                    match part {
+                        StringPart::Text(text) => self.out.push_str(&string_text(text)),
+                        StringPart::Expr(expr) => self.out.push_str(&format!("{{{}}}", print_expr(expr))),
+                    }
+                }
+                self.out.push('"');
+            }
+        }
+        if needs_parens {
+            self.out.push(')');
+        }
+    }
+
+    /// Prints a `match` with one arm per line; the closing brace is left at
+    /// the current indentation without a newline.
+    fn match_expr(&mut self, scrutinee: &Expr, arms: &[MatchArm]) {
+        let scrutinee = self.condition_text(scrutinee);
+        self.out.push_str(&format!("match {} {{\n", scrutinee));
+        self.indent += 1;
+        for arm in arms {
+            self.indentation();
+            self.out.push_str(&print_pattern(&arm.pattern));
+            if let Some(guard) = &arm.guard {
+                self.out.push_str(" if ");
+                self.expr(guard, 0);
+            }
+            self.out.push_str(" => ");
+            match &arm.body {
+                ArmBody::Expr(body) => {
+                    self.expr(body, 0);
+                    self.out.push_str(",\n");
+                }
+                ArmBody::Block(block) if block.stmts.is_empty() => self.out.push_str("{}\n"),
+                ArmBody::Block(block) => {
+                    self.out.push_str("{\n");
+                    self.block_body(block);
+                    self.line("}");
+                }
+            }
+        }
+        self.indent -= 1;
+        self.indentation();
+        self.out.push('}');
+    }
+
+    /// Prints a block inside an expression; the closing brace is left at
+    /// the current indentation without a newline.
+    fn inline_block(&mut self, block: &Block) {
+        if block.stmts.is_empty() {
+            self.out.push_str("{}");
+            return;
+        }
+        self.out.push_str("{\n");
+        self.block_body(block);
+        self.indentation();
+        self.out.push('}');
+    }
+
+    fn list(&mut self, open: char, exprs: &[Expr], close: char) {
+        self.out.push(open);
+        for (index, expr) in exprs.iter().enumerate() {
+            if index > 0 {
+                self.out.push_str(", ");
+            }
+            self.expr(expr, 0);
+        }
+        self.out.push(close);
+    }
+
+    fn infix(&mut self, left: &Expr, symbol: &str, right: &Expr, binding_power: u8, associativity: Associativity) {
+        let (left_min, right_min) = operand_binding_powers(binding_power, associativity);
+        self.expr(left, left_min);
+        self.out.push(' ');
+        self.out.push_str(symbol);
+        self.out.push(' ');
+        self.expr(right, right_min);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{Lexer, Span};
+    use crate::parser::{Field, Method, NodeId, Param, Parser, Variant};
+    use crate::visit::{self, VisitorMut};
+
+    fn parse(source: &str) -> Program {
+        let mut lexer = Lexer::new(source.to_string());
+        Parser::new(&mut lexer).parse_program()
+    }
+
+    /// Clears ids and spans so trees parsed from differently laid out source
+    /// compare equal.
+    struct Normalize;
+
+    impl VisitorMut for Normalize {
+        fn visit_program_mut(&mut self, program: &mut Program) {
+            (program.id, program.span) = (NodeId(0), Span::default());
+            visit::walk_program_mut(self, program);
+        }
+        fn visit_item_mut(&mut self, item: &mut Item) {
+            (item.id, item.span) = (NodeId(0), Span::default());
+            visit::walk_item_mut(self, item);
+        }
+        fn visit_attribute_mut(&mut self, attribute: &mut Attribute) {
+            (attribute.id, attribute.span) = (NodeId(0), Span::default());
+        }
+        fn visit_type_param_mut(&mut self, param: &mut TypeParam) {
+            (param.id, param.span) = (NodeId(0), Span::default());
+        }
+        fn visit_param_mut(&mut self, param: &mut Param) {
+            (param.id, param.span) = (NodeId(0), Span::default());
+            visit::walk_param_mut(self, param);
+        }
+        fn visit_field_mut(&mut self, field: &mut Field) {
+            (field.id, field.span) = (NodeId(0), Span::default());
+            visit::walk_field_mut(self, field);
+        }
+        fn visit_field_init_mut(&mut self, init: &mut FieldInit) {
+            (init.id, init.span) = (NodeId(0), Span::default());
+            visit::walk_field_init_mut(self, init);
+        }
+        fn visit_variant_mut(&mut self, variant: &mut Variant) {
+            (variant.id, variant.span) = (NodeId(0), Span::default());
+            visit::walk_variant_mut(self, variant);
+        }
+        fn visit_method_sig_mut(&mut self, method: &mut MethodSig) {
+            (method.id, method.span) = (NodeId(0), Span::default());
+            visit::walk_method_sig_mut(self, method);
+        }
+        fn visit_method_mut(&mut self, method: &mut Method) {
+            (method.id, method.span) = (NodeId(0), Span::default());
+            visit::walk_method_mut(self, method);
+        }
+        fn visit_match_arm_mut(&mut self, arm: &mut MatchArm) {
+            (arm.id, arm.span) = (NodeId(0), Span::default());
+            visit::walk_match_arm_mut(self, arm);
+        }
+        fn visit_closure_param_mut(&mut self, param: &mut ClosureParam) {
+            (param.id, param.span) = (NodeId(0), Span::default());
+            visit::walk_closure_param_mut(self, param);
+        }
+        fn visit_pattern_mut(&mut self, pattern: &mut Pattern) {
+            (pattern.id, pattern.span) = (NodeId(0), Span::default());
+            visit::walk_pattern_mut(self, pattern);
+        }
+        fn visit_type_mut(&mut self, ty: &mut Type) {
+            (ty.id, ty.span) = (NodeId(0), Span::default());
+            visit::walk_type_mut(self, ty);
+        }
+        fn visit_block_mut(&mut self, block: &mut Block) {
+            (block.id, block.span) = (NodeId(0), Span::default());
+            visit::walk_block_mut(self, block);
+        }
+        fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+            (stmt.id, stmt.span) = (NodeId(0), Span::default());
+            visit::walk_stmt_mut(self, stmt);
+        }
+        fn visit_expr_mut(&mut self, expr: &mut Expr) {
+            (expr.id, expr.span) = (NodeId(0), Span::default());
+            visit::walk_expr_mut(self, expr);
+        }
+    }
+
+    fn normalized(mut program: Program) -> Program {
+        Normalize.visit_program_mut(&mut program);
+        program
+    }
+
+    const CORPUS: &[&str] = &[
+        "let x: int = 10; function main() { let y: int = 20; return y + x; if (true) { return false; } else { return true; } for (let i: int = 0; i < 10; i = i + 1) { while (i < 5) { i = i + 1; } } }",
+        "function add(a: int, b: int) -> int { return a + b; }",
+        "function nothing() -> void { return; } function empty() {}",
+        "function f(x: int) -> int { return -(x - 1) * -x + !(x == 2) - f(f(x) / (x + 1)); }",
+        "function g(a: int, b: int, c: int) { a = b = c; (a = b) = c; a - (b - c); (a - b) - c; a * (b + c) / (a / b); }",
+        "function h(n: int) -> bool { if n < 0 { return false; } else if n == 0 { return true; } else if n >= 10 { return n != 11; } else { return h(n - 1); } }",
+        "function loops() { for (;;) {} for (; true;) { loops(); } for (x = 0; x <= 3; x = x + 1) { if x > 1 {} else {} } while !false { } }",
+        "let flag: bool = !true; let neg = - -3; function k() { -f(x)(y); (-f)(x); }",
+        "struct Point { x: int, y: int } struct Empty {} function mid(a: Point, b: Point) -> Point { let m = Point { x: (a.x + b.x) / 2, y: f(Point { x: 1, y: 2 }).y }; m.x = -m.y; if (a == Point { x: 0, y: 0 }) { return Empty {}; } while !(Point { x: 1, y: 1 }).x {} return m; }",
+        "enum Shape { Circle(int), Rect(int, int), Empty } enum Never {} function area(s: Shape, t: Shape) -> int { let a = match s { Shape::Circle(r) if r > 0 => 3 * r * r, Shape::Rect(w, h) => { return w * h; } Shape::Circle(_) => 0 }; match t { Shape::Empty => {} _ => f(match a { 0 => true, -1 => false, n => n == 1 }), }; (match a { x => x }).y = 2; return Shape::Circle(a); }",
+        "let grid: int[4][2] = [[1, 2, 3, 4], [5, 6, 7, 8]]; function sum(a: int[3]) -> int { a[0] = -a[1] * a[(2)]; return (a)[0] + grid[1][f(a)[0]] + [1, 2][0]; }",
+        "let origin: (int, int) = (0, 0); function divmod(a: int, b: int) -> (int, int) { return (a / b, a - a / b * b); } function swap((a, b): (int, bool), unit: ()) -> (bool, int) { let (q, (r, _)) = (divmod(a, 2), (1,)); let t: (int,)[2] = [(q.0,), (r,)]; t[0].0 = (q).1 + (-q).0; (a, b); return (b, t[1].0); }",
+        "function apply(f: fn(int) -> int, x: int) -> int { return f(x); } function run(n: int) -> fn() { let add = |x| x + n; let g: fn(int, bool) = |a: int, (b): bool| {}; let h = |x: int| -> int { if x > n { return x; } return (|y| y * n)(x); }; let fs: (fn() -> int)[1] = [|| n]; apply(|x| x + 1, add(n)); g = |a, b| { apply(add, a); }; if (|| true)() {} return || {}; }",
+        "struct Pair<A, B> { first: A, second: B } struct Grid<T> {} function swap<A, B>(p: Pair<A, B>) -> Pair<B, A> { return Pair { first: p.second, second: p.first }; } function nest(p: Pair<Pair<int, bool>, (int, bool)[2]>, f: fn(Grid<int>) -> Grid<bool>) { let q: Pair<bool, int> = swap(Pair { first: 1, second: true }); }",
+        "interface Show { function show(self) -> int; function pad(self: Self, width: int) -> Self; } interface Marker {} struct Point { x: int } impl Show for Point { function show(self) -> int { return self.x; } @inline @noinline function pad(self, width: int) -> Point { return Point { x: self.x + width }; } } impl Marker for (int, bool) {} function all<T: Show + Marker, U>(items: T[2], shown: dyn Show, more: dyn Show[2]) -> int { return items[0].pad(1).show() + shown.show(); }",
+        "import math; import \"util/geometry.cl\"; impl math::Show for geometry::Point {} function f<T: math::Show>(p: geometry::Point, s: dyn math::Show) -> math::Shape { math::sqrt(geometry::Point { x: 1 }.x); return math::Shape::Circle(math::origin.x); }",
+        "pub struct P { x: int } pub enum E { A } pub interface I {} pub let (a, b) = (1, 2); pub function f() -> P { return P { x: a }; } function g() {}",
+        "function f(p: ptr) { defer free(p); defer if p == p { free(p); } else { free(p); } while true { defer match p { _ => g(), }; return; } }",
+        "const function squares() -> int[4] { let t = [0, 0, 0, 0]; while i < 4 { t[i] = i * i; } return t; } pub const function id(n: int) -> int { return n; } const T: int[4] = squares();",
+        "const N: int = 2 * (1 + 1); pub const ON: bool = !(N > 3); struct Grid { cells: int[N][math::M] } function f(a: (fn() -> int)[N]) -> bool[N] { return [ON, N == 4]; }",
+        "let mut g = 0; function f(mut n: int) { let (mut a, b) = (n, |mut x: int| x); match a { mut y => y = 1 } }",
+        "function bits(x: u8, y: i64, z: (u16, u32)[2]) -> u64 { let s: i8 = -1; return (x as u64 << 3) + (y >> 2) as u64 - -(s as i16) as u64 * (z[0].0 as i32 + -y as i32) as u64; } let p: Pair<Pair<u8, i16>> = f(a >> b, c < d);",
+        "enum Result<T, E> { Ok(T), Err(E) } function f(r: Result<int, bool>, o: Option<(int, int)>) -> Option<int> { let x = -r? * (o?).0; if g()? {} return Option::Some(h(x)?.y[0]?); }",
+        "@test function t() {} @inline @deprecated(\"use \\\"t\\\"\") pub function f() {} @export(\"c_g\") function g() {}",
+        "extern \"C\" function puts(s: ptr) -> int; pub extern \"C\" function printf(f: ptr, ...) -> int; extern \"C\" function abort(); function main() { printf(\"%d\\n\".as_ptr(), 1, true); }",
+        "import \"lib/a \\\"b\\\".cl\"; function greet(name: string, n: u8) -> string { let s: string = \"hi\\t\\\"{name}\\\"\\n\" + \"{{{n + 1}}} {f(P { x: 1 }.x)[0] == s[0]}\"; return s; }",
+    ];
+
+    #[test]
+    fn parse_print_round_trips_corpus() {
+        for source in CORPUS {
+            let ast = normalized(parse(source));
+            let printed = print_program(&ast);
+            assert_eq!(normalized(parse(&printed)), ast, "round trip changed the tree for:\n{}", printed);
+        }
+    }
+
+    #[test]
+    fn printing_is_a_fixed_point() {
+        for source in CORPUS {
+            let printed = print_program(&parse(source));
+            assert_eq!(print_program(&parse(&printed)), printed);
+        }
+    }
+
+    fn reprint_expr(source: &str) -> String {
+        let program = parse(&format!("function f() {{ {}; }}", source));
+        let ItemKind::Function(function) = &program.items[0].kind else { panic!("expected function") };
+        let StmtKind::Expr(expr) = &function.body.stmts[0].kind else { panic!("expected expression statement") };
+        print_expr(expr)
+    }
+
+    #[test]
+    fn emits_only_necessary_parentheses() {
+        assert_eq!(reprint_expr("(a + b) * c"), "(a + b) * c");
+        assert_eq!(reprint_expr("a + (b * c)"), "a + b * c");
+        assert_eq!(reprint_expr("(a - b) - c"), "a - b - c");
+        assert_eq!(reprint_expr("a - (b - c)"), "a - (b - c)");
+        assert_eq!(reprint_expr("a = (b = c)"), "a = b = c");
+        assert_eq!(reprint_expr("(a = b) = c"), "(a = b) = c");
+        assert_eq!(reprint_expr("(-a) * (f)(b)"), "-a * f(b)");
+        assert_eq!(reprint_expr("-(a * b)"), "-(a * b)");
+        assert_eq!(reprint_expr("((a < b)) == (c < d)"), "a < b == c < d");
+        assert_eq!(reprint_expr("(-a)[(i + 1)]"), "(-a)[i + 1]");
+        assert_eq!(reprint_expr("(p.x).y = (-q).z"), "p.x.y = (-q).z");
+        assert_eq!(reprint_expr("(f(x))[0]([1, (2)])"), "f(x)[0]([1, 2])");
+        assert_eq!(reprint_expr("((a), (b,), ()).0.1"), "(a, (b,), ()).0.1");
+        assert_eq!(reprint_expr("f = (|x| (x + 1))"), "f = |x| x + 1");
+        assert_eq!(reprint_expr("(|x| x)(1) + (|y| y)"), "(|x| x)(1) + (|y| y)");
+        assert_eq!(reprint_expr("|x| (|y| (x = y))"), "|x| |y| x = y");
+        assert_eq!(reprint_expr("(f = |x| x) = g"), "(f = |x| x) = g");
+    }
+
+    #[test]
+    fn prints_readable_layout() {
+        let printed = print_program(&parse(
+            "let x: int = 1; function f(a: int) -> int { if a < x { return 0; } else if a == x { return 1; } else { while a > 0 { a = a - 1; } } return a; }",
+        ));
+        assert_eq!(
+            printed,
+            "let x: int = 1;\n\
+             \n\
+             function f(a: int) -> int {\n\
+             \x20   if a < x {\n\
+             \x20       return 0;\n\
+             \x20   } else if a == x {\n\
+             \x20       return 1;\n\
+             \x20   } else {\n\
+             \x20       while a > 0 {\n\
+             \x20           a = a - 1;\n\
+             \x20       }\n\
+             \x20   }\n\
+             \x20   return a;\n\
+             }\n"
+        );
+    }
+}
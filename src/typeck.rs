@@ -0,0 +1,2215 @@
+//! Type checking.
+//!
+//! Every expression and pattern is given a type, recorded by node id in a
+//! `TypeTable` for the lowering. Types are inferred bottom-up; the only place
+//! an expected type flows down is into literals that cannot be typed on their
+//! own, such as `[]` or a closure whose parameters have no annotations. There
+//! are no implicit conversions.
+//!
+//! An expression that fails to check gets the `Error` type, which is
+//! compatible with every type, so a mistake is reported once rather than at
+//! every use of its result.
+//!
+//! Generic functions, structs and enums are checked once, with each type
+//! parameter standing for an unknown type that supports no operations of its
+//! own. At a call the type arguments are inferred from the argument values,
+//! falling back to the expected type; a struct literal or enum variant takes
+//! them from the expected type first. Those of calls are recorded so that
+//! `monomorphize` can instantiate the definition for them.
+//!
+//! `value?` requires an `Option` or `Result` from the prelude, and an
+//! enclosing function that returns the same kind of enum.
+//!
+//! An interface's method signatures are resolved with `Self` standing for
+//! an unknown type, and each `impl` is checked against them with `Self`
+//! replaced by the implementing type. A call `x.m()` where `x` has no field
+//! `m` calls the method of an interface implemented by the type of `x`, or,
+//! for a type parameter, of one of its bounds. A value is converted to
+//! `dyn I` wherever that type is expected and its own type implements `I`;
+//! the conversion is recorded so that the lowering can pair the value with
+//! the vtable for its type.
+//!
+//! Constants are evaluated by `consts` before any type is resolved, since
+//! array sizes may name them; their values are kept in the `TypeTable`.
+//!
+//! An extern function is called with the C calling convention, so its
+//! parameters and result are limited to integers, `bool` and `ptr`. The
+//! extra arguments of a variadic one may have any of those types, which the
+//! lowering promotes as C does.
+//!
+//! Enum variant patterns that name an unknown variant or have the wrong
+//! number of fields are reported by `exhaustiveness`, not here.
+
+use crate::consts::{self, ConstValue};
+use crate::diagnostic::Diagnostic;
+use crate::lexer::{IntType, Span};
+use crate::parser::{
+    ArmBody, ArraySize, BinaryOp, Block, ClosureParam, Const, Enum, Expr, ExprKind, Extern, Field, FieldInit, Function, Impl, Interface, ItemKind,
+    Let, MatchArm, NodeId, Param, Pattern, PatternKind, Program, Stmt, StmtKind, StringPart, Struct, Type, TypeKind, TypeParam,
+    UnaryOp,
+};
+use crate::printer::{print_pattern, tuple_text};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ty {
+    Int(IntType),
+    Bool,
+    /// An immutable sequence of bytes.
+    String,
+    /// An untyped pointer passed to and from C.
+    Ptr,
+    Void,
+    Array(Box<Ty>, u32),
+    Tuple(Vec<Ty>),
+    /// A struct with its type arguments; empty unless the struct is generic.
+    Struct(String, Vec<Ty>),
+    /// An enum with its type arguments; empty unless the enum is generic.
+    Enum(String, Vec<Ty>),
+    /// A named function or closure taking the given parameters.
+    Function(Vec<Ty>, Box<Ty>),
+    /// A type parameter of the generic function or struct being checked, or
+    /// `Self` in an interface.
+    Param(String),
+    /// A value of any type that implements the interface.
+    Dyn(String),
+    /// The type of something that failed to check.
+    Error,
+    /// A type argument that is not inferred yet, in a hint built by
+    /// `open_params`. Like `Error` it asks nothing of a value, but it is
+    /// never the type of one.
+    Infer,
+}
+
+impl Ty {
+    /// `int`, the type of an integer literal that its context does not give
+    /// another integer type.
+    pub const INT: Ty = Ty::Int(IntType::I32);
+
+    /// Whether a value of type `self` can be used where `other` is expected.
+    /// `Error` is compatible with everything.
+    pub fn compatible(&self, other: &Ty) -> bool {
+        match (self, other) {
+            (Ty::Error | Ty::Infer, _) | (_, Ty::Error | Ty::Infer) => true,
+            (Ty::Array(a, n), Ty::Array(b, m)) => n == m && a.compatible(b),
+            (Ty::Tuple(a), Ty::Tuple(b)) => a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.compatible(b)),
+            (Ty::Struct(n, a), Ty::Struct(m, b)) | (Ty::Enum(n, a), Ty::Enum(m, b)) => {
+                n == m && a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.compatible(b))
+            }
+            (Ty::Function(a, r), Ty::Function(b, s)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.compatible(b)) && r.compatible(s)
+            }
+            _ => self == other,
+        }
+    }
+
+    /// Replaces the type parameters named in `bindings`; others are kept.
+    pub fn substitute(&self, bindings: &HashMap<String, Ty>) -> Ty {
+        let all = |types: &[Ty]| types.iter().map(|ty| ty.substitute(bindings)).collect();
+        match self {
+            Ty::Param(name) => bindings.get(name).cloned().unwrap_or_else(|| self.clone()),
+            Ty::Array(element, size) => Ty::Array(Box::new(element.substitute(bindings)), *size),
+            Ty::Tuple(elements) => Ty::Tuple(all(elements)),
+            Ty::Struct(name, args) => Ty::Struct(name.clone(), all(args)),
+            Ty::Enum(name, args) => Ty::Enum(name.clone(), all(args)),
+            Ty::Function(params, return_type) => Ty::Function(all(params), Box::new(return_type.substitute(bindings))),
+            Ty::Int(_) | Ty::Bool | Ty::String | Ty::Ptr | Ty::Void | Ty::Dyn(_) | Ty::Error | Ty::Infer => self.clone(),
+        }
+    }
+
+    /// Whether `self` mentions `Infer`, so that it is only a partial hint and
+    /// cannot be the type of a value.
+    fn is_open(&self) -> bool {
+        match self {
+            Ty::Infer => true,
+            Ty::Array(element, _) => element.is_open(),
+            Ty::Tuple(types) | Ty::Struct(_, types) | Ty::Enum(_, types) => types.iter().any(Ty::is_open),
+            Ty::Function(params, return_type) => params.iter().any(Ty::is_open) || return_type.is_open(),
+            Ty::Int(_) | Ty::Bool | Ty::String | Ty::Ptr | Ty::Void | Ty::Param(_) | Ty::Dyn(_) | Ty::Error => false,
+        }
+    }
+}
+
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ty::Int(ty) => write!(f, "{}", ty.name()),
+            Ty::Bool => write!(f, "bool"),
+            Ty::String => write!(f, "string"),
+            Ty::Ptr => write!(f, "ptr"),
+            Ty::Void => write!(f, "void"),
+            Ty::Array(element, size) => write!(f, "{}[{}]", element, size),
+            Ty::Tuple(elements) => write!(f, "{}", tuple_text(elements.iter().map(Ty::to_string).collect())),
+            Ty::Struct(name, args) | Ty::Enum(name, args) if !args.is_empty() => {
+                let args: Vec<String> = args.iter().map(Ty::to_string).collect();
+                write!(f, "{}<{}>", name, args.join(", "))
+            }
+            Ty::Struct(name, _) | Ty::Enum(name, _) | Ty::Param(name) => write!(f, "{}", name),
+            Ty::Dyn(interface) => write!(f, "dyn {}", interface),
+            Ty::Function(params, return_type) => {
+                let params: Vec<String> = params.iter().map(Ty::to_string).collect();
+                write!(f, "fn({})", params.join(", "))?;
+                match **return_type {
+                    Ty::Void => Ok(()),
+                    ref return_type => write!(f, " -> {}", return_type),
+                }
+            }
+            Ty::Error => write!(f, "{{error}}"),
+            Ty::Infer => write!(f, "_"),
+        }
+    }
+}
+
+/// The types of the expressions and patterns of a checked program.
+#[derive(Debug, Default)]
+pub struct TypeTable {
+    types: HashMap<NodeId, Ty>,
+    instantiations: HashMap<NodeId, Vec<Ty>>,
+    methods: HashMap<NodeId, String>,
+    coercions: HashMap<NodeId, String>,
+    constants: HashMap<String, ConstValue>,
+}
+
+impl TypeTable {
+    pub fn type_of(&self, id: NodeId) -> Option<&Ty> {
+        self.types.get(&id)
+    }
+
+    /// The inferred type arguments of the generic function named by the
+    /// identifier expression `id`.
+    pub fn instantiation(&self, id: NodeId) -> Option<&[Ty]> {
+        self.instantiations.get(&id).map(Vec::as_slice)
+    }
+
+    /// The interface that declares the method named by the field expression
+    /// `id`, the callee of a method call such as `x.show()`.
+    pub fn method_interface(&self, id: NodeId) -> Option<&str> {
+        self.methods.get(&id).map(String::as_str)
+    }
+
+    /// The interface whose `dyn` type the value of expression `id` is
+    /// converted to; the value itself keeps the type recorded for it.
+    pub fn coercion(&self, id: NodeId) -> Option<&str> {
+        self.coercions.get(&id).map(String::as_str)
+    }
+
+    /// The value of the constant `name`, if it could be evaluated.
+    pub fn constant(&self, name: &str) -> Option<ConstValue> {
+        self.constants.get(name).cloned()
+    }
+}
+
+pub fn check_program(program: &Program) -> (TypeTable, Vec<Diagnostic>) {
+    let mut checker = Checker {
+        structs: HashMap::new(),
+        enums: HashMap::new(),
+        consts: HashMap::new(),
+        functions: HashMap::new(),
+        interfaces: HashMap::new(),
+        impls: Vec::new(),
+        deprecated: HashMap::new(),
+        type_params: Vec::new(),
+        bounds: HashMap::new(),
+        self_type: None,
+        scopes: Vec::new(),
+        return_type: Ty::Void,
+        deferred: false,
+        table: TypeTable::default(),
+        diagnostics: Vec::new(),
+    };
+    checker.program(program);
+    (checker.table, checker.diagnostics)
+}
+
+#[derive(Clone)]
+struct Signature {
+    type_params: Vec<String>,
+    /// The interfaces each type parameter must implement.
+    bounds: HashMap<String, Vec<String>>,
+    params: Vec<Ty>,
+    return_type: Ty,
+    /// An extern function whose parameters end with `...`.
+    variadic: bool,
+}
+
+impl Signature {
+    /// The type of the function used as a value.
+    fn ty(&self) -> Ty {
+        Ty::Function(self.params.clone(), Box::new(self.return_type.clone()))
+    }
+}
+
+struct Checker<'a> {
+    structs: HashMap<&'a str, &'a Struct>,
+    enums: HashMap<&'a str, &'a Enum>,
+    consts: HashMap<&'a str, &'a Const>,
+    functions: HashMap<&'a str, Signature>,
+    /// The methods of each interface, without their `self` parameter.
+    interfaces: HashMap<&'a str, Vec<(String, Signature)>>,
+    /// Each implemented interface and the type implementing it.
+    impls: Vec<(String, Ty)>,
+    /// The items marked `@deprecated`, with their messages.
+    deprecated: HashMap<&'a str, Option<&'a str>>,
+    /// The type parameters of the generic item being checked.
+    type_params: Vec<String>,
+    /// The bounds of the type parameters in `type_params`.
+    bounds: HashMap<String, Vec<String>>,
+    /// What `Self` stands for in the interface or impl being checked.
+    self_type: Option<Ty>,
+    /// Variables in scope, innermost last; the first scope holds the globals.
+    scopes: Vec<HashMap<String, Ty>>,
+    /// The return type of the innermost function or closure being checked.
+    return_type: Ty,
+    /// Whether a deferred statement of that function or closure is being
+    /// checked; it runs while the block is being left, so it cannot return.
+    deferred: bool,
+    table: TypeTable,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Checker<'a> {
+    fn program(&mut self, program: &'a Program) {
+        let (constants, diagnostics) = consts::evaluate(program);
+        self.table.constants = constants;
+        self.diagnostics.extend(diagnostics);
+        let mut defined = HashSet::new();
+        for item in &program.items {
+            let name = match &item.kind {
+                ItemKind::Struct(definition) => {
+                    self.structs.insert(&definition.name, definition);
+                    &definition.name
+                }
+                ItemKind::Enum(definition) => {
+                    self.enums.insert(&definition.name, definition);
+                    &definition.name
+                }
+                ItemKind::Interface(definition) => {
+                    self.interfaces.insert(&definition.name, Vec::new());
+                    &definition.name
+                }
+                ItemKind::Const(constant) => {
+                    self.consts.insert(&constant.name, constant);
+                    &constant.name
+                }
+                ItemKind::Function(function) => &function.name,
+                ItemKind::Extern(function) => &function.name,
+                ItemKind::Global(binding) => {
+                    if let (PatternKind::Binding { name, .. }, Some(attribute)) = (&binding.pattern.kind, item.attribute("deprecated")) {
+                        self.deprecated.insert(name, attribute.args.first().map(String::as_str));
+                    }
+                    continue;
+                }
+                ItemKind::Impl(_) | ItemKind::Import(_) => continue,
+            };
+            if let Some(attribute) = item.attribute("deprecated") {
+                self.deprecated.insert(name, attribute.args.first().map(String::as_str));
+            }
+            if !defined.insert(name) {
+                self.error(item.span, format!("`{}` is defined more than once", name));
+            }
+        }
+        for item in &program.items {
+            if let ItemKind::Interface(definition) = &item.kind {
+                self.interface(definition);
+            }
+        }
+        for item in &program.items {
+            match &item.kind {
+                ItemKind::Struct(definition) => {
+                    for param in definition.type_params.iter().filter(|param| !param.bounds.is_empty()) {
+                        self.error(param.span, "bounds are only allowed on the type parameters of functions".to_string());
+                    }
+                    self.with_type_params(&definition.type_params, true, |checker| {
+                        for field in &definition.fields {
+                            checker.resolve_type(&field.ty);
+                        }
+                    })
+                }
+                ItemKind::Enum(definition) => {
+                    for param in definition.type_params.iter().filter(|param| !param.bounds.is_empty()) {
+                        self.error(param.span, "bounds are only allowed on the type parameters of functions".to_string());
+                    }
+                    self.with_type_params(&definition.type_params, true, |checker| {
+                        for variant in &definition.variants {
+                            for ty in &variant.fields {
+                                checker.resolve_type(ty);
+                            }
+                        }
+                    })
+                }
+                ItemKind::Function(function) => {
+                    let signature = self.with_type_params(&function.type_params, true, |checker| Signature {
+                        type_params: checker.type_params.clone(),
+                        bounds: checker.bounds.clone(),
+                        params: function.params.iter().map(|param| checker.resolve_type(&param.ty)).collect(),
+                        return_type: function.return_type.as_ref().map_or(Ty::Void, |ty| checker.resolve_type(ty)),
+                        variadic: false,
+                    });
+                    self.functions.insert(&function.name, signature);
+                }
+                ItemKind::Extern(function) => {
+                    let signature = self.extern_signature(item.span, function);
+                    self.functions.insert(&function.name, signature);
+                }
+                ItemKind::Global(_) | ItemKind::Const(_) | ItemKind::Interface(_) | ItemKind::Impl(_) | ItemKind::Import(_) => {}
+            }
+        }
+        for item in &program.items {
+            if let ItemKind::Impl(definition) = &item.kind {
+                self.impl_signatures(item.span, definition);
+            }
+        }
+
+        // Globals are visible to the initializers of later globals and to
+        // every function; constants are visible everywhere.
+        self.scopes.push(HashMap::new());
+        let mut constants = Vec::new();
+        for item in &program.items {
+            if let ItemKind::Const(constant) = &item.kind {
+                let ty = self.resolve_type(&constant.ty);
+                if !is_constant_type(&ty) {
+                    self.error(constant.ty.span, format!("constants must be integers, `bool` or arrays of them, found `{}`", ty));
+                }
+                self.scopes[0].insert(constant.name.clone(), ty.clone());
+                constants.push((constant, ty));
+            }
+        }
+        for (constant, ty) in constants {
+            self.expect(&constant.value, &ty);
+        }
+        for item in &program.items {
+            if let ItemKind::Global(binding) = &item.kind {
+                self.let_binding(binding);
+            }
+        }
+        for item in &program.items {
+            match &item.kind {
+                ItemKind::Function(function) => self.function(function),
+                ItemKind::Impl(definition) => {
+                    self.self_type = Some(self.declared_type(&definition.ty));
+                    for method in &definition.methods {
+                        self.function(&method.function);
+                    }
+                    self.self_type = None;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Resolves the method signatures of an interface.
+    fn interface(&mut self, definition: &'a Interface) {
+        self.self_type = Some(Ty::Param("Self".to_string()));
+        let mut methods: Vec<(String, Signature)> = Vec::new();
+        for method in &definition.methods {
+            let signature = self.method_signature(&method.name, method.span, &method.params, method.return_type.as_ref());
+            if methods.iter().any(|(name, _)| *name == method.name) {
+                let message = format!("method `{}` is declared more than once in interface `{}`", method.name, definition.name);
+                self.error(method.span, message);
+            } else {
+                methods.push((method.name.clone(), signature));
+            }
+        }
+        self.self_type = None;
+        self.interfaces.insert(&definition.name, methods);
+    }
+
+    /// Checks the method signatures of an impl against its interface and
+    /// records the implementation.
+    fn impl_signatures(&mut self, span: Span, definition: &Impl) {
+        let ty = self.resolve_type(&definition.ty);
+        let Some(declared) = self.interfaces.get(definition.interface.as_str()).cloned() else {
+            self.error(span, format!("unknown interface `{}`", definition.interface));
+            return;
+        };
+        self.deprecated(span, "interface", &definition.interface);
+        if ty != Ty::Error {
+            if self.impls.contains(&(definition.interface.clone(), ty.clone())) {
+                self.error(span, format!("interface `{}` is already implemented for `{}`", definition.interface, ty));
+            } else {
+                self.impls.push((definition.interface.clone(), ty.clone()));
+            }
+        }
+        self.self_type = Some(ty.clone());
+        let bindings = HashMap::from([("Self".to_string(), ty.clone())]);
+        for (index, method) in definition.methods.iter().enumerate() {
+            let function = &method.function;
+            if definition.methods[..index].iter().any(|earlier| earlier.function.name == function.name) {
+                self.error(method.span, format!("method `{}` is defined more than once", function.name));
+                continue;
+            }
+            if !function.type_params.is_empty() {
+                self.error(method.span, format!("method `{}` cannot have type parameters", function.name));
+            }
+            let signature = self.method_signature(&function.name, method.span, &function.params, function.return_type.as_ref());
+            match declared.iter().find(|(name, _)| *name == function.name) {
+                Some((_, expected)) => {
+                    let (expected, found) = (expected.ty().substitute(&bindings), signature.ty());
+                    if !found.compatible(&expected) {
+                        let message = format!(
+                            "method `{}` has type `{}` but interface `{}` declares `{}`",
+                            function.name, found, definition.interface, expected
+                        );
+                        self.error(method.span, message);
+                    }
+                }
+                None => {
+                    let message = format!("method `{}` is not a member of interface `{}`", function.name, definition.interface);
+                    self.error(method.span, message);
+                }
+            }
+        }
+        self.self_type = None;
+        let missing: Vec<String> = declared
+            .iter()
+            .filter(|(name, _)| !definition.methods.iter().any(|method| method.function.name == *name))
+            .map(|(name, _)| format!("`{}`", name))
+            .collect();
+        if !missing.is_empty() {
+            let message = format!("missing method(s) {} in implementation of `{}` for `{}`", missing.join(", "), definition.interface, ty);
+            self.error(span, message);
+        }
+    }
+
+    /// The signature of a method without its `self` parameter, which must
+    /// come first and have the type `Self`.
+    fn method_signature(&mut self, name: &str, span: Span, params: &[Param], return_type: Option<&Type>) -> Signature {
+        let mut types: Vec<Ty> = params.iter().map(|param| self.resolve_type(&param.ty)).collect();
+        let takes_self = params.first().is_some_and(|param| matches!(&param.pattern.kind, PatternKind::Binding { name, .. } if name == "self"));
+        if takes_self && types.first() == self.self_type.as_ref() {
+            types.remove(0);
+        } else {
+            self.error(span, format!("the first parameter of method `{}` must be `self`", name));
+        }
+        Signature {
+            type_params: Vec::new(),
+            bounds: HashMap::new(),
+            params: types,
+            return_type: return_type.map_or(Ty::Void, |ty| self.resolve_type(ty)),
+            variadic: false,
+        }
+    }
+
+    /// The signature of an extern function, reporting types that cannot be
+    /// passed to or from C.
+    fn extern_signature(&mut self, span: Span, function: &Extern) -> Signature {
+        if function.abi != "C" {
+            self.error(span, format!("unsupported calling convention \"{}\"; only \"C\" is supported", function.abi));
+        }
+        let mut params = Vec::new();
+        for param in &function.params {
+            let ty = self.resolve_type(&param.ty);
+            if !matches!(ty, Ty::Int(_) | Ty::Bool | Ty::Ptr | Ty::Error) {
+                self.error(param.ty.span, format!("extern functions can only take integers, `bool` and `ptr`, found `{}`", ty));
+            }
+            params.push(ty);
+        }
+        let return_type = match &function.return_type {
+            Some(ty) => {
+                let resolved = self.resolve_type(ty);
+                if !matches!(resolved, Ty::Int(_) | Ty::Bool | Ty::Ptr | Ty::Void | Ty::Error) {
+                    self.error(ty.span, format!("extern functions can only return integers, `bool` and `ptr`, found `{}`", resolved));
+                }
+                resolved
+            }
+            None => Ty::Void,
+        };
+        Signature {
+            type_params: Vec::new(),
+            bounds: HashMap::new(),
+            params,
+            return_type,
+            variadic: function.variadic,
+        }
+    }
+
+    fn function(&mut self, function: &Function) {
+        self.with_type_params(&function.type_params, false, |checker| checker.function_body(function));
+    }
+
+    fn function_body(&mut self, function: &Function) {
+        // Unknown types in the signature were reported with the signature.
+        self.return_type = function.return_type.as_ref().map_or(Ty::Void, |ty| self.declared_type(ty));
+        self.scopes.push(HashMap::new());
+        for param in &function.params {
+            let ty = self.declared_type(&param.ty);
+            self.bind(&param.pattern, &ty);
+        }
+        self.block(&function.body);
+        self.scopes.pop();
+    }
+
+    /// Runs `check` with the type parameters of a generic item and their
+    /// bounds in scope, reporting duplicates and unknown interfaces if
+    /// `report` is set.
+    fn with_type_params<T>(&mut self, params: &[TypeParam], report: bool, check: impl FnOnce(&mut Self) -> T) -> T {
+        let mut names = Vec::new();
+        let mut bounds: HashMap<String, Vec<String>> = HashMap::new();
+        for param in params {
+            if names.contains(&param.name) {
+                if report {
+                    self.error(param.span, format!("type parameter `{}` is declared more than once", param.name));
+                }
+                continue;
+            }
+            names.push(param.name.clone());
+            for bound in &param.bounds {
+                if self.interfaces.contains_key(bound.as_str()) {
+                    if report {
+                        self.deprecated(param.span, "interface", bound);
+                    }
+                    bounds.entry(param.name.clone()).or_default().push(bound.clone());
+                } else if report {
+                    self.error(param.span, format!("unknown interface `{}`", bound));
+                }
+            }
+        }
+        let enclosing = (std::mem::replace(&mut self.type_params, names), std::mem::replace(&mut self.bounds, bounds));
+        let result = check(self);
+        (self.type_params, self.bounds) = enclosing;
+        result
+    }
+
+    /// Resolves a type written in the source, reporting unknown names.
+    fn resolve_type(&mut self, ty: &Type) -> Ty {
+        match &ty.kind {
+            TypeKind::Named { name, args } => {
+                for arg in args {
+                    self.resolve_type(arg);
+                }
+                let expected = if self.type_params.contains(name) || self.is_self(name) {
+                    0
+                } else if let Some(definition) = self.structs.get(name.as_str()) {
+                    let expected = definition.type_params.len();
+                    self.deprecated(ty.span, "struct", name);
+                    expected
+                } else if let Some(definition) = self.enums.get(name.as_str()) {
+                    let expected = definition.type_params.len();
+                    self.deprecated(ty.span, "enum", name);
+                    expected
+                } else {
+                    return self.error(ty.span, format!("unknown type `{}`", name));
+                };
+                if args.len() != expected {
+                    let message = format!("type `{}` takes {} type argument(s) but {} were given", name, expected, args.len());
+                    return self.error(ty.span, message);
+                }
+                self.declared_type(ty)
+            }
+            TypeKind::Array { element, size } => {
+                self.resolve_type(element);
+                if let ArraySize::Const(name) = size {
+                    match self.table.constant(name) {
+                        Some(ConstValue::Int(value)) if value < 0 => {
+                            return self.error(ty.span, format!("array size `{}` is negative: {}", name, value));
+                        }
+                        Some(ConstValue::Int(value)) if u32::try_from(value).is_err() => {
+                            return self.error(ty.span, format!("array size `{}` is too large: {}", name, value));
+                        }
+                        Some(ConstValue::Int(_)) => {}
+                        Some(ConstValue::Bool(_) | ConstValue::Array(_)) => {
+                            let found = self.declared_type(&self.consts[name.as_str()].ty);
+                            return self.error(ty.span, format!("array size `{}` must be an integer constant, found `{}`", name, found));
+                        }
+                        // The constant failed to evaluate, which has been reported.
+                        None if self.consts.contains_key(name.as_str()) => return Ty::Error,
+                        None => return self.error(ty.span, format!("unknown constant `{}`", name)),
+                    }
+                }
+                self.declared_type(ty)
+            }
+            TypeKind::Tuple(elements) => {
+                for element in elements {
+                    self.resolve_type(element);
+                }
+                self.declared_type(ty)
+            }
+            TypeKind::Function { params, return_type } => {
+                for param in params.iter().chain(return_type.as_deref()) {
+                    self.resolve_type(param);
+                }
+                self.declared_type(ty)
+            }
+            TypeKind::Dyn(interface) if !self.interfaces.contains_key(interface.as_str()) => {
+                self.error(ty.span, format!("unknown interface `{}`", interface))
+            }
+            TypeKind::Dyn(interface) => {
+                self.deprecated(ty.span, "interface", interface);
+                self.declared_type(ty)
+            }
+            _ => self.declared_type(ty),
+        }
+    }
+
+    /// Whether `name` is `Self` inside an interface or impl.
+    fn is_self(&self, name: &str) -> bool {
+        name == "Self" && self.self_type.is_some()
+    }
+
+    /// Resolves a type from a declaration that has already been checked by
+    /// `resolve_type`; unknown names silently become `Error`.
+    fn declared_type(&self, ty: &Type) -> Ty {
+        match &ty.kind {
+            TypeKind::Int(ty) => Ty::Int(*ty),
+            TypeKind::Bool => Ty::Bool,
+            TypeKind::String => Ty::String,
+            TypeKind::Ptr => Ty::Ptr,
+            TypeKind::Void => Ty::Void,
+            TypeKind::Array { element, size } => match size {
+                ArraySize::Literal(size) => Ty::Array(Box::new(self.declared_type(element)), *size),
+                ArraySize::Const(name) => match self.table.constant(name) {
+                    Some(ConstValue::Int(size)) => match u32::try_from(size) {
+                        Ok(size) => Ty::Array(Box::new(self.declared_type(element)), size),
+                        Err(_) => Ty::Error,
+                    },
+                    _ => Ty::Error,
+                },
+            },
+            TypeKind::Tuple(elements) => Ty::Tuple(elements.iter().map(|element| self.declared_type(element)).collect()),
+            TypeKind::Function { params, return_type } => Ty::Function(
+                params.iter().map(|param| self.declared_type(param)).collect(),
+                Box::new(return_type.as_ref().map_or(Ty::Void, |ty| self.declared_type(ty))),
+            ),
+            TypeKind::Named { name, args } if args.is_empty() && self.type_params.contains(name) => Ty::Param(name.clone()),
+            TypeKind::Named { name, args } if args.is_empty() && self.is_self(name) => self.self_type.clone().unwrap_or(Ty::Error),
+            TypeKind::Dyn(interface) if self.interfaces.contains_key(interface.as_str()) => Ty::Dyn(interface.clone()),
+            TypeKind::Dyn(_) => Ty::Error,
+            TypeKind::Named { name, args } => {
+                let resolved = || args.iter().map(|arg| self.declared_type(arg)).collect();
+                match (self.structs.get(name.as_str()), self.enums.get(name.as_str())) {
+                    (Some(definition), _) if definition.type_params.len() == args.len() => Ty::Struct(name.clone(), resolved()),
+                    (None, Some(definition)) if definition.type_params.len() == args.len() => Ty::Enum(name.clone(), resolved()),
+                    _ => Ty::Error,
+                }
+            }
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.scopes.push(HashMap::new());
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Let(binding) => self.let_binding(binding),
+            StmtKind::Expr(expr) => {
+                self.expr(expr, None);
+            }
+            StmtKind::Return(_) if self.deferred => {
+                self.error(stmt.span, "cannot `return` from a deferred statement".to_string());
+            }
+            StmtKind::Return(Some(value)) => {
+                let expected = self.return_type.clone();
+                self.expect(value, &expected);
+            }
+            StmtKind::Return(None) => {
+                if !Ty::Void.compatible(&self.return_type) {
+                    let message = format!("`return;` in a function that returns `{}`", self.return_type);
+                    self.error(stmt.span, message);
+                }
+            }
+            StmtKind::If { condition, then_branch, else_branch } => {
+                self.expect(condition, &Ty::Bool);
+                self.block(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.block(else_branch);
+                }
+            }
+            StmtKind::For { init, condition, increment, body } => {
+                self.scopes.push(HashMap::new());
+                if let Some(init) = init {
+                    self.stmt(init);
+                }
+                if let Some(condition) = condition {
+                    self.expect(condition, &Ty::Bool);
+                }
+                if let Some(increment) = increment {
+                    self.expr(increment, None);
+                }
+                self.block(body);
+                self.scopes.pop();
+            }
+            StmtKind::While { condition, body } => {
+                self.expect(condition, &Ty::Bool);
+                self.block(body);
+            }
+            StmtKind::Defer(deferred) => {
+                let enclosing = std::mem::replace(&mut self.deferred, true);
+                self.stmt(deferred);
+                self.deferred = enclosing;
+            }
+        }
+    }
+
+    fn let_binding(&mut self, binding: &Let) {
+        let ty = match &binding.ty {
+            Some(declared) => {
+                let declared = self.resolve_type(declared);
+                self.expect(&binding.value, &declared);
+                declared
+            }
+            None => self.expr(&binding.value, None),
+        };
+        self.bind(&binding.pattern, &ty);
+    }
+
+    /// Checks `pattern` against `ty` and brings its bindings into scope.
+    fn bind(&mut self, pattern: &Pattern, ty: &Ty) {
+        let mut bindings: Vec<(String, Ty)> = Vec::new();
+        self.pattern(pattern, ty, &mut bindings);
+        let scope = self.scopes.last_mut().expect("no scope to bind in");
+        scope.extend(bindings);
+    }
+
+    fn pattern(&mut self, pattern: &Pattern, ty: &Ty, bindings: &mut Vec<(String, Ty)>) {
+        self.table.types.insert(pattern.id, ty.clone());
+        match &pattern.kind {
+            PatternKind::Wildcard => {}
+            PatternKind::Binding { name, .. } => {
+                if bindings.iter().any(|(bound, _)| bound == name) {
+                    self.error(pattern.span, format!("`{}` is bound more than once in the same pattern", name));
+                }
+                bindings.push((name.clone(), ty.clone()));
+            }
+            PatternKind::IntLiteral(value) => match ty {
+                Ty::Int(int) if !int.contains(*value) => {
+                    self.error(pattern.span, format!("literal `{}` does not fit in `{}`", value, int.name()));
+                }
+                Ty::Int(_) => {}
+                _ => self.pattern_mismatch(pattern, ty, &Ty::INT),
+            },
+            PatternKind::BoolLiteral(_) => self.pattern_mismatch(pattern, ty, &Ty::Bool),
+            PatternKind::Variant { path, fields } => {
+                self.deprecated_variant(pattern.span, path);
+                let payload = self.variant(path).and_then(|(definition, payload)| {
+                    // A generic enum's payload takes the type arguments of
+                    // the scrutinee.
+                    let (found, args) = match ty {
+                        Ty::Enum(name, args) if *name == definition.name => (ty.clone(), args.clone()),
+                        _ => {
+                            let params = definition.type_params.iter().map(|param| Ty::Param(param.name.clone())).collect();
+                            (Ty::Enum(definition.name.clone(), params), vec![Ty::Error; definition.type_params.len()])
+                        }
+                    };
+                    self.pattern_mismatch(pattern, ty, &found);
+                    let bindings = bind_params(&definition.type_params, &args);
+                    (payload.len() == fields.len()).then(|| payload.iter().map(|ty| ty.substitute(&bindings)).collect())
+                });
+                let payload = payload.unwrap_or_else(|| vec![Ty::Error; fields.len()]);
+                for (field, ty) in fields.iter().zip(&payload) {
+                    self.pattern(field, ty, bindings);
+                }
+            }
+            PatternKind::Tuple(elements) => {
+                let element_types = match ty {
+                    Ty::Tuple(types) if types.len() == elements.len() => types.clone(),
+                    Ty::Error => vec![Ty::Error; elements.len()],
+                    _ => {
+                        let message = format!("expected `{}`, found a tuple of {} element(s)", ty, elements.len());
+                        self.error(pattern.span, message);
+                        vec![Ty::Error; elements.len()]
+                    }
+                };
+                for (element, ty) in elements.iter().zip(&element_types) {
+                    self.pattern(element, ty, bindings);
+                }
+            }
+        }
+    }
+
+    fn pattern_mismatch(&mut self, pattern: &Pattern, expected: &Ty, found: &Ty) {
+        if !found.compatible(expected) {
+            self.error(pattern.span, format!("mismatched types: expected `{}`, found `{}`", expected, found));
+        }
+    }
+
+    /// The enum and payload types of `Enum::Variant`, or `None` if it does
+    /// not name one. The payload types are in terms of the enum's own type
+    /// parameters.
+    fn variant(&mut self, path: &[String]) -> Option<(&'a Enum, Vec<Ty>)> {
+        let [.., enum_name, variant_name] = path else {
+            return None;
+        };
+        let definition = self.enums.get(enum_name.as_str()).copied()?;
+        let variant = definition.variants.iter().find(|variant| variant.name == *variant_name)?;
+        let payload = self.with_type_params(&definition.type_params, false, |checker| {
+            variant.fields.iter().map(|ty| checker.declared_type(ty)).collect()
+        });
+        Some((definition, payload))
+    }
+
+    /// Warns about a use of the item `name`, a `kind` such as a function, if
+    /// it is deprecated.
+    fn deprecated(&mut self, span: Span, kind: &str, name: &str) {
+        let Some(message) = self.deprecated.get(name) else { return };
+        let mut text = format!("use of deprecated {} `{}`", kind, name);
+        if let Some(message) = message {
+            text = format!("{}: {}", text, message);
+        }
+        self.diagnostics.push(Diagnostic::warning(span, text));
+    }
+
+    /// Warns about a use of a variant of a deprecated enum.
+    fn deprecated_variant(&mut self, span: Span, path: &[String]) {
+        if let [.., enum_name, _] = path {
+            self.deprecated(span, "enum", enum_name);
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Ty> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    /// Checks `expr` and reports an error unless its type is compatible with
+    /// `expected`.
+    fn expect(&mut self, expr: &Expr, expected: &Ty) -> Ty {
+        let found = self.expr(expr, Some(expected));
+        self.coerce(expr, found, expected)
+    }
+
+    /// Reports an error unless the value of `expr`, of type `found`, can be
+    /// used where `expected` is expected, converting it to a `dyn` type if
+    /// need be. Returns the type of the value as used.
+    fn coerce(&mut self, expr: &Expr, found: Ty, expected: &Ty) -> Ty {
+        match expected {
+            Ty::Dyn(interface) if !matches!(found, Ty::Dyn(_) | Ty::Error) => {
+                if !self.implements(&found, interface) {
+                    return self.error(expr.span, format!("type `{}` does not implement interface `{}`", found, interface));
+                }
+                self.table.coercions.insert(expr.id, interface.clone());
+                expected.clone()
+            }
+            _ => {
+                if !found.compatible(expected) {
+                    self.error(expr.span, format!("mismatched types: expected `{}`, found `{}`", expected, found));
+                }
+                found
+            }
+        }
+    }
+
+    /// Whether values of type `ty` can be used as `interface`.
+    fn implements(&self, ty: &Ty, interface: &str) -> bool {
+        match ty {
+            Ty::Error => true,
+            Ty::Param(param) => self.bounds.get(param).is_some_and(|bounds| bounds.iter().any(|bound| bound == interface)),
+            Ty::Dyn(name) => name == interface,
+            _ => self.impls.iter().any(|(name, implementing)| name == interface && implementing == ty),
+        }
+    }
+
+    /// Infers the type of `expr`. `expected` is only a hint for literals that
+    /// cannot be typed on their own; the caller checks the result against it.
+    fn expr(&mut self, expr: &Expr, expected: Option<&Ty>) -> Ty {
+        let ty = self.expr_kind(expr, expected);
+        self.table.types.insert(expr.id, ty.clone());
+        ty
+    }
+
+    fn expr_kind(&mut self, expr: &Expr, expected: Option<&Ty>) -> Ty {
+        match &expr.kind {
+            ExprKind::IntLiteral(value) => self.int_literal(expr.span, i128::from(*value), expected),
+            ExprKind::BoolLiteral(_) => Ty::Bool,
+            ExprKind::StringLiteral(_) => Ty::String,
+            ExprKind::Interpolation(parts) => {
+                for part in parts {
+                    if let StringPart::Expr(hole) = part {
+                        let ty = self.expr(hole, None);
+                        if !matches!(ty, Ty::Int(_) | Ty::Bool | Ty::String | Ty::Error) {
+                            self.error(hole.span, format!("cannot interpolate a value of type `{}` into a string", ty));
+                        }
+                    }
+                }
+                Ty::String
+            }
+            ExprKind::Identifier(name) => match self.lookup(name) {
+                Some(ty) => {
+                    let ty = ty.clone();
+                    if self.scopes.iter().rposition(|scope| scope.contains_key(name)) == Some(0) {
+                        let kind = if self.consts.contains_key(name.as_str()) { "constant" } else { "global" };
+                        self.deprecated(expr.span, kind, name);
+                    }
+                    ty
+                }
+                None => match self.functions.get(name.as_str()) {
+                    Some(signature) if signature.variadic => {
+                        self.error(expr.span, format!("variadic function `{}` can only be called", name))
+                    }
+                    Some(signature) if signature.type_params.is_empty() => {
+                        let ty = signature.ty();
+                        self.deprecated(expr.span, "function", name);
+                        ty
+                    }
+                    Some(signature) => {
+                        // A generic function used as a value is instantiated
+                        // for the function type it is expected to have.
+                        let (type_params, ty) = (signature.type_params.clone(), signature.ty());
+                        self.deprecated(expr.span, "function", name);
+                        let mut bindings = HashMap::new();
+                        if let Some(expected) = expected {
+                            unify(&ty, expected, &mut bindings);
+                        }
+                        self.instantiate(expr, name, &type_params, bindings, &ty)
+                    }
+                    None => self.error(expr.span, format!("unknown name `{}`", name)),
+                },
+            },
+            ExprKind::Path(path) => match self.variant(path) {
+                Some((definition, payload)) if payload.is_empty() => {
+                    self.deprecated_variant(expr.span, path);
+                    let bindings = expected_bindings(definition, expected);
+                    self.enum_type(expr.span, definition, &bindings)
+                }
+                Some((_, payload)) => self.error(
+                    expr.span,
+                    format!("`{}` takes {} payload value(s)", path.join("::"), payload.len()),
+                ),
+                None => self.error(expr.span, format!("unknown enum variant `{}`", path.join("::"))),
+            },
+            ExprKind::Unary { op: UnaryOp::Neg, operand } => match operand.kind {
+                // `-128` is a literal of its own, which fits in `i8` although
+                // `128` does not.
+                ExprKind::IntLiteral(value) => {
+                    let ty = self.int_literal(expr.span, -i128::from(value), expected);
+                    self.table.types.insert(operand.id, ty.clone());
+                    ty
+                }
+                _ => {
+                    let ty = self.integer(operand, expected);
+                    if matches!(ty, Ty::Int(int) if !int.signed()) {
+                        self.error(expr.span, format!("cannot negate a value of unsigned type `{}`", ty));
+                    }
+                    ty
+                }
+            },
+            ExprKind::Unary { op: UnaryOp::Not, operand } => {
+                self.expect(operand, &Ty::Bool);
+                Ty::Bool
+            }
+            ExprKind::Binary { op, left, right } => match op {
+                BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div => self.integer_operands(*op, left, right, expected),
+                // The shift amount may have any integer type.
+                BinaryOp::Shl | BinaryOp::Shr => {
+                    let ty = self.integer(left, expected);
+                    self.integer(right, None);
+                    if let (Ty::Int(int), Some(amount)) = (&ty, int_literal_value(right)) {
+                        if amount < 0 || amount >= i128::from(int.bits()) {
+                            self.error(right.span, format!("shift amount {} is out of range for `{}`", amount, int.name()));
+                        }
+                    }
+                    ty
+                }
+                BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => {
+                    self.integer_operands(*op, left, right, None);
+                    Ty::Bool
+                }
+                BinaryOp::Eq | BinaryOp::Ne => {
+                    let (first, second) = literal_last(left, right);
+                    let ty = self.expr(first, None);
+                    self.expect(second, &ty);
+                    if !matches!(ty, Ty::Int(_) | Ty::Bool | Ty::String | Ty::Ptr | Ty::Error) {
+                        self.error(expr.span, format!("cannot compare values of type `{}` with `{}`", ty, op.symbol()));
+                    }
+                    Ty::Bool
+                }
+            },
+            ExprKind::Assign { target, value } => {
+                let mut ty = self.expr(target, None);
+                match &target.kind {
+                    ExprKind::Identifier(name) if self.lookup(name).is_none() && self.functions.contains_key(name.as_str()) => {
+                        ty = self.error(target.span, format!("cannot assign to function `{}`", name));
+                    }
+                    _ if !is_place(target) => {
+                        self.error(target.span, "cannot assign to this expression".to_string());
+                    }
+                    ExprKind::Index { base, .. } if self.table.types.get(&base.id) == Some(&Ty::String) => {
+                        ty = self.error(target.span, "cannot assign to a byte of a string; strings are immutable".to_string());
+                    }
+                    _ => {}
+                }
+                self.expect(value, &ty);
+                ty
+            }
+            ExprKind::Call { callee, args } => self.call(expr, callee, args, expected),
+            ExprKind::Index { base, index } => {
+                let base_ty = self.expr(base, None);
+                self.integer(index, None);
+                match base_ty {
+                    Ty::Array(element, size) => {
+                        if let Some(value) = int_literal_value(index) {
+                            if value < 0 || value >= i128::from(size) {
+                                let message = format!("index {} is out of bounds for `{}`", value, Ty::Array(element.clone(), size));
+                                self.error(index.span, message);
+                            }
+                        }
+                        *element
+                    }
+                    // Indexing a string gives the byte at that offset.
+                    Ty::String => Ty::Int(IntType::U8),
+                    Ty::Error => Ty::Error,
+                    other => self.error(base.span, format!("cannot index into a value of type `{}`", other)),
+                }
+            }
+            ExprKind::Field { base, name } => {
+                let base_ty = self.expr(base, None);
+                self.field(expr, base_ty, name)
+            }
+            ExprKind::TupleIndex { base, index } => match self.expr(base, None) {
+                Ty::Tuple(elements) => match elements.get(*index as usize) {
+                    Some(element) => element.clone(),
+                    None => self.error(expr.span, format!("tuple `{}` has no element {}", Ty::Tuple(elements.clone()), index)),
+                },
+                Ty::Error => Ty::Error,
+                other => self.error(expr.span, format!("type `{}` is not a tuple", other)),
+            },
+            ExprKind::ArrayLiteral(elements) => {
+                let hint = match expected {
+                    Some(Ty::Array(element, _)) => Some(element.as_ref().clone()),
+                    _ => None,
+                };
+                let Some((first, rest)) = elements.split_first() else {
+                    return match hint {
+                        Some(element) if !element.is_open() => Ty::Array(Box::new(element), 0),
+                        _ => self.error(expr.span, "cannot infer the type of an empty array literal".to_string()),
+                    };
+                };
+                // Elements expected to be `dyn` values are each converted.
+                let element = match hint {
+                    Some(hint @ Ty::Dyn(_)) => {
+                        self.expect(first, &hint);
+                        hint
+                    }
+                    hint => self.expr(first, hint.as_ref()),
+                };
+                for other in rest {
+                    self.expect(other, &element);
+                }
+                Ty::Array(Box::new(element), elements.len() as u32)
+            }
+            ExprKind::Tuple(elements) => {
+                let hints = match expected {
+                    Some(Ty::Tuple(types)) if types.len() == elements.len() => types.iter().map(Some).collect(),
+                    _ => vec![None; elements.len()],
+                };
+                Ty::Tuple(elements.iter().zip(hints).map(|(element, hint)| self.expr(element, hint)).collect())
+            }
+            ExprKind::StructLiteral { name, fields } => self.struct_literal(expr, name, fields, expected),
+            ExprKind::Match { scrutinee, arms } => self.match_expr(scrutinee, arms, expected),
+            ExprKind::Closure { params, return_type, body } => self.closure(params, return_type.as_ref(), body, expected),
+            ExprKind::Try(operand) => self.try_expr(expr, operand),
+            ExprKind::Cast { operand, ty } => {
+                let found = self.expr(operand, None);
+                let target = self.resolve_type(ty);
+                match (&found, &target) {
+                    (Ty::Error, _) | (_, Ty::Error) | (Ty::Int(_) | Ty::Bool, Ty::Int(_)) => {}
+                    (_, Ty::Int(_)) => {
+                        self.error(operand.span, format!("only integers and `bool` can be cast with `as`, found `{}`", found));
+                    }
+                    _ => {
+                        self.error(ty.span, format!("`as` can only convert to an integer type, not `{}`", target));
+                    }
+                }
+                target
+            }
+        }
+    }
+
+    /// The type of field `name` of a value of type `base`.
+    fn field(&mut self, expr: &Expr, base: Ty, name: &str) -> Ty {
+        match base {
+            Ty::Struct(struct_name, args) => {
+                let definition = self.structs[struct_name.as_str()];
+                match definition.fields.iter().find(|field| field.name == *name) {
+                    Some(field) => self.field_type(definition, field).substitute(&bind_params(&definition.type_params, &args)),
+                    None => {
+                        let message = format!("struct `{}` has no field `{}`", Ty::Struct(struct_name.clone(), args), name);
+                        self.error(expr.span, message)
+                    }
+                }
+            }
+            Ty::Error => Ty::Error,
+            other => self.error(expr.span, format!("type `{}` has no field `{}`", other, name)),
+        }
+    }
+
+    /// The type of the callee `x.name` of a call: a field of `x` if it has
+    /// one, otherwise a method of an interface it implements, taking the
+    /// arguments after `self`.
+    fn method(&mut self, callee: &Expr, base: &Expr, name: &str) -> Ty {
+        let receiver = self.expr(base, None);
+        let has_field = match &receiver {
+            Ty::Struct(struct_name, _) => self.structs[struct_name.as_str()].fields.iter().any(|field| field.name == name),
+            _ => false,
+        };
+        if has_field || receiver == Ty::Error {
+            return self.field(callee, receiver, name);
+        }
+        // `len` is built in: the length of a string in bytes.
+        if receiver == Ty::String && name == "len" {
+            return Ty::Function(Vec::new(), Box::new(Ty::INT));
+        }
+        // So is `as_ptr`: the address of its bytes, for passing it to C. They
+        // are always followed by a NUL byte that `len` does not count.
+        if receiver == Ty::String && name == "as_ptr" {
+            return Ty::Function(Vec::new(), Box::new(Ty::Ptr));
+        }
+        let interfaces: Vec<String> = match &receiver {
+            Ty::Param(param) => self.bounds.get(param).cloned().unwrap_or_default(),
+            Ty::Dyn(interface) => vec![interface.clone()],
+            _ => self.impls.iter().filter(|(_, ty)| *ty == receiver).map(|(interface, _)| interface.clone()).collect(),
+        };
+        let mut found = interfaces.iter().filter_map(|interface| {
+            let methods = self.interfaces.get(interface.as_str())?;
+            let (_, signature) = methods.iter().find(|(method, _)| method == name)?;
+            Some((interface, signature.ty()))
+        });
+        let Some((interface, ty)) = found.next() else {
+            return self.error(callee.span, format!("type `{}` has no field or method `{}`", receiver, name));
+        };
+        if let Some((other, _)) = found.next() {
+            let message = format!("method `{}` of `{}` is declared by both `{}` and `{}`", name, receiver, interface, other);
+            return self.error(callee.span, message);
+        }
+        if matches!(receiver, Ty::Dyn(_)) && mentions(&ty, "Self") {
+            let message = format!("method `{}` cannot be called on `{}` because its signature uses `Self`", name, receiver);
+            return self.error(callee.span, message);
+        }
+        self.table.methods.insert(callee.id, interface.clone());
+        ty.substitute(&HashMap::from([("Self".to_string(), receiver)]))
+    }
+
+    fn call(&mut self, expr: &Expr, callee: &Expr, args: &[Expr], expected: Option<&Ty>) -> Ty {
+        let (name, params, result) = match &callee.kind {
+            ExprKind::Identifier(name) if self.lookup(name).is_none() && self.functions.contains_key(name.as_str()) => {
+                self.deprecated(callee.span, "function", name);
+                let signature = &self.functions[name.as_str()];
+                if !signature.type_params.is_empty() {
+                    return self.generic_call(expr, callee, name, args, expected);
+                }
+                if signature.variadic {
+                    return self.variadic_call(expr, name, args);
+                }
+                (format!("function `{}`", name), signature.params.clone(), signature.return_type.clone())
+            }
+            ExprKind::Path(path) => match self.variant(path) {
+                Some((definition, payload)) => {
+                    self.deprecated_variant(callee.span, path);
+                    return self.variant_call(expr, path, definition, &payload, args, expected);
+                }
+                None => {
+                    for arg in args {
+                        self.expr(arg, None);
+                    }
+                    return self.error(callee.span, format!("unknown enum variant `{}`", path.join("::")));
+                }
+            },
+            _ => match self.callee(callee) {
+                Ty::Function(params, result) => {
+                    let name = match &callee.kind {
+                        ExprKind::Identifier(name) => format!("`{}`", name),
+                        ExprKind::Field { name, .. } if self.table.methods.contains_key(&callee.id) => format!("method `{}`", name),
+                        _ => format!("function of type `{}`", Ty::Function(params.clone(), result.clone())),
+                    };
+                    (name, params, *result)
+                }
+                ty => {
+                    for arg in args {
+                        self.expr(arg, None);
+                    }
+                    if ty == Ty::Error {
+                        return Ty::Error;
+                    }
+                    return self.error(callee.span, format!("cannot call a value of type `{}`", ty));
+                }
+            },
+        };
+        if args.len() != params.len() {
+            self.error(
+                expr.span,
+                format!("{} takes {} argument(s) but {} were given", name, params.len(), args.len()),
+            );
+        }
+        for (index, arg) in args.iter().enumerate() {
+            match params.get(index) {
+                Some(param) => self.expect(arg, param),
+                None => self.expr(arg, None),
+            };
+        }
+        result
+    }
+
+    /// Calls a variadic extern function, which takes at least its declared
+    /// parameters. The extra arguments must be values C can take.
+    fn variadic_call(&mut self, expr: &Expr, name: &str, args: &[Expr]) -> Ty {
+        let signature = &self.functions[name];
+        let (params, result) = (signature.params.clone(), signature.return_type.clone());
+        if args.len() < params.len() {
+            self.error(
+                expr.span,
+                format!("function `{}` takes at least {} argument(s) but {} were given", name, params.len(), args.len()),
+            );
+        }
+        for (index, arg) in args.iter().enumerate() {
+            if let Some(param) = params.get(index) {
+                self.expect(arg, param);
+                continue;
+            }
+            let ty = self.expr(arg, None);
+            if !matches!(ty, Ty::Int(_) | Ty::Bool | Ty::Ptr | Ty::Error) {
+                self.error(arg.span, format!("cannot pass a value of type `{}` as a variadic argument", ty));
+            }
+        }
+        result
+    }
+
+    /// Checks the callee of a call that is not a named function or variant.
+    fn callee(&mut self, callee: &Expr) -> Ty {
+        match &callee.kind {
+            ExprKind::Field { base, name } => {
+                let ty = self.method(callee, base, name);
+                self.table.types.insert(callee.id, ty.clone());
+                ty
+            }
+            _ => self.expr(callee, None),
+        }
+    }
+
+    /// Calls a generic function, inferring its type arguments from the
+    /// arguments and, for those they leave open, the expected result.
+    fn generic_call(&mut self, expr: &Expr, callee: &Expr, name: &str, args: &[Expr], expected: Option<&Ty>) -> Ty {
+        let signature = &self.functions[name];
+        let (type_params, params, result) = (signature.type_params.clone(), signature.params.clone(), signature.return_type.clone());
+        if args.len() != params.len() {
+            self.error(
+                expr.span,
+                format!("function `{}` takes {} argument(s) but {} were given", name, params.len(), args.len()),
+            );
+        }
+        // Closures go last so that the other arguments can determine the
+        // types of their parameters.
+        let mut order: Vec<usize> = (0..args.len()).collect();
+        order.sort_by_key(|&index| matches!(args[index].kind, ExprKind::Closure { .. }));
+        let mut bindings = HashMap::new();
+        for index in order {
+            let Some(param) = params.get(index) else {
+                self.expr(&args[index], None);
+                continue;
+            };
+            let hint = param.substitute(&open_params(&type_params, &bindings));
+            let found = self.expr(&args[index], Some(&hint));
+            unify(param, &found, &mut bindings);
+            let expected = param.substitute(&bindings);
+            self.coerce(&args[index], found, &expected);
+        }
+        if let Some(expected) = expected {
+            unify(&result, expected, &mut bindings);
+        }
+        let function = Ty::Function(params, Box::new(result));
+        match self.instantiate(callee, name, &type_params, bindings, &function) {
+            Ty::Function(_, result) => *result,
+            _ => Ty::Error,
+        }
+    }
+
+    /// Records the type arguments of the generic function `name` named by
+    /// `callee` and returns `ty` with them substituted. Parameters without a
+    /// binding could not be inferred and are reported, as are arguments that
+    /// do not implement the bounds of their parameter.
+    fn instantiate(&mut self, callee: &Expr, name: &str, type_params: &[String], mut bindings: HashMap<String, Ty>, ty: &Ty) -> Ty {
+        let bounds = self.functions[name].bounds.clone();
+        let mut args = Vec::new();
+        for param in type_params {
+            let arg = match bindings.get(param) {
+                Some(arg) => arg.clone(),
+                None => self.error(callee.span, format!("cannot infer type parameter `{}` of function `{}`", param, name)),
+            };
+            for bound in bounds.get(param).into_iter().flatten() {
+                if !self.implements(&arg, bound) {
+                    self.error(callee.span, format!("type `{}` does not implement interface `{}`", arg, bound));
+                }
+            }
+            bindings.insert(param.clone(), arg.clone());
+            args.push(arg);
+        }
+        self.table.instantiations.insert(callee.id, args);
+        ty.substitute(&bindings)
+    }
+
+    /// Constructs a value of the variant `path` with payload types `payload`,
+    /// taking the type arguments of a generic enum from the expected type if
+    /// it names the same enum, otherwise from the payload values.
+    fn variant_call(&mut self, expr: &Expr, path: &[String], definition: &Enum, payload: &[Ty], args: &[Expr], expected: Option<&Ty>) -> Ty {
+        if args.len() != payload.len() {
+            self.error(
+                expr.span,
+                format!("`{}` takes {} argument(s) but {} were given", path.join("::"), payload.len(), args.len()),
+            );
+        }
+        let type_params: Vec<String> = definition.type_params.iter().map(|param| param.name.clone()).collect();
+        let mut bindings = expected_bindings(definition, expected);
+        for (index, arg) in args.iter().enumerate() {
+            let Some(declared) = payload.get(index) else {
+                self.expr(arg, None);
+                continue;
+            };
+            let hint = declared.substitute(&open_params(&type_params, &bindings));
+            let found = self.expr(arg, Some(&hint));
+            unify(declared, &found, &mut bindings);
+            let ty = declared.substitute(&bindings);
+            self.coerce(arg, found, &ty);
+        }
+        self.enum_type(expr.span, definition, &bindings)
+    }
+
+    /// The type of a value of `definition` whose type parameters are bound by
+    /// `bindings`, reporting those that are not.
+    fn enum_type(&mut self, span: Span, definition: &Enum, bindings: &HashMap<String, Ty>) -> Ty {
+        let args = definition
+            .type_params
+            .iter()
+            .map(|param| match bindings.get(&param.name) {
+                Some(arg) => arg.clone(),
+                None => self.error(span, format!("cannot infer type parameter `{}` of enum `{}`", param.name, definition.name)),
+            })
+            .collect();
+        Ty::Enum(definition.name.clone(), args)
+    }
+
+    /// The type of an integer literal with the given value: `expected` if
+    /// that is an integer type, `int` otherwise.
+    fn int_literal(&mut self, span: Span, value: i128, expected: Option<&Ty>) -> Ty {
+        let ty = match expected {
+            Some(Ty::Int(ty)) => *ty,
+            _ => IntType::I32,
+        };
+        if !ty.contains(value) {
+            return self.error(span, format!("literal `{}` does not fit in `{}`", value, ty.name()));
+        }
+        Ty::Int(ty)
+    }
+
+    /// Checks an operand that may have any integer type; `hint` types it if
+    /// it is a literal. There are no implicit conversions between integer
+    /// types, only `as`.
+    fn integer(&mut self, expr: &Expr, hint: Option<&Ty>) -> Ty {
+        let hint = hint.filter(|ty| matches!(ty, Ty::Int(_)));
+        let found = self.expr(expr, hint);
+        self.as_integer(expr, found, hint)
+    }
+
+    /// Requires `found`, the type of `expr`, to be an integer type.
+    fn as_integer(&mut self, expr: &Expr, found: Ty, hint: Option<&Ty>) -> Ty {
+        match found {
+            ty @ (Ty::Int(_) | Ty::Error) => ty,
+            found => {
+                self.coerce(expr, found, hint.unwrap_or(&Ty::INT));
+                Ty::Error
+            }
+        }
+    }
+
+    /// Checks the operands of an arithmetic or ordering operator, which must
+    /// have the same integer type, and returns that type. A literal operand
+    /// takes the type of the other one, so `1 + x` is a `u8` if `x` is. `+`
+    /// also concatenates two strings.
+    fn integer_operands(&mut self, op: BinaryOp, left: &Expr, right: &Expr, hint: Option<&Ty>) -> Ty {
+        let (first, second) = literal_last(left, right);
+        let hint = hint.filter(|ty| matches!(ty, Ty::Int(_)));
+        let found = self.expr(first, hint);
+        if found == Ty::String && op == BinaryOp::Add {
+            self.expect(second, &Ty::String);
+            return Ty::String;
+        }
+        if self.unordered(op, first, &found) {
+            self.expr(second, None);
+            return Ty::Error;
+        }
+        match self.as_integer(first, found, hint) {
+            Ty::Error => {
+                let found = self.expr(second, hint);
+                if self.unordered(op, second, &found) {
+                    return Ty::Error;
+                }
+                self.as_integer(second, found, hint)
+            }
+            ty => {
+                let found = self.expr(second, Some(&ty));
+                if !self.unordered(op, second, &found) {
+                    self.coerce(second, found, &ty);
+                }
+                ty
+            }
+        }
+    }
+
+    /// Reports an operand `expr` of type `found` that `op` cannot order,
+    /// since only integers can be ordered. Returns whether it did.
+    fn unordered(&mut self, op: BinaryOp, expr: &Expr, found: &Ty) -> bool {
+        let ordering = matches!(op, BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge);
+        if ordering && !matches!(found, Ty::Int(_) | Ty::Error) {
+            self.error(expr.span, format!("`{}` can only compare integers, found `{}`", op.symbol(), found));
+            return true;
+        }
+        false
+    }
+
+    /// `value?` gives the payload of an `Option::Some` or `Result::Ok` and
+    /// returns anything else from the enclosing function, which must return
+    /// an `Option`, or a `Result` whose error type the error converts to.
+    fn try_expr(&mut self, expr: &Expr, operand: &Expr) -> Ty {
+        let ty = self.expr(operand, None);
+        if ty == Ty::Error {
+            return Ty::Error;
+        }
+        let Some((payload, error)) = self.try_types(&ty) else {
+            let message = format!("the `?` operator can only be applied to an `Option` or a `Result`, found `{}`", ty);
+            return self.error(operand.span, message);
+        };
+        if self.deferred {
+            self.error(expr.span, "`?` cannot return from a deferred statement".to_string());
+            return payload;
+        }
+        let return_type = self.return_type.clone();
+        match (self.try_types(&return_type), &error) {
+            _ if return_type == Ty::Error => {}
+            (Some((_, None)), None) => {}
+            (Some((_, Some(expected))), Some(error)) => {
+                if !error.compatible(&expected) {
+                    let message = format!("`?` cannot return an error of type `{}` from a function that returns `{}`", error, return_type);
+                    self.error(expr.span, message);
+                }
+            }
+            (_, error) => {
+                let expected = if error.is_some() { "a `Result`" } else { "an `Option`" };
+                let message = format!("`?` on `{}` can only be used in a function that returns {}, not `{}`", ty, expected, return_type);
+                self.error(expr.span, message);
+            }
+        }
+        payload
+    }
+
+    /// The payload type of an `Option`, or the payload and error types of a
+    /// `Result`, for `?`. Their instances, which `monomorphize` names after
+    /// them as in `Option<int>`, count as well.
+    fn try_types(&mut self, ty: &Ty) -> Option<(Ty, Option<Ty>)> {
+        let Ty::Enum(name, args) = ty else {
+            return None;
+        };
+        let (success, failure) = match name.split('<').next() {
+            Some("Option") => ("Some", None),
+            Some("Result") => ("Ok", Some("Err")),
+            _ => return None,
+        };
+        let mut payload = |variant: &str| {
+            let (definition, payload) = self.variant(&[name.clone(), variant.to_string()])?;
+            let [payload] = payload.as_slice() else {
+                return None;
+            };
+            Some(payload.substitute(&bind_params(&definition.type_params, args)))
+        };
+        let error = match failure {
+            Some(failure) => Some(payload(failure)?),
+            None => None,
+        };
+        Some((payload(success)?, error))
+    }
+
+    /// The declared type of a struct field, in terms of the struct's own type
+    /// parameters.
+    fn field_type(&mut self, definition: &Struct, field: &Field) -> Ty {
+        self.with_type_params(&definition.type_params, false, |checker| checker.declared_type(&field.ty))
+    }
+
+    fn struct_literal(&mut self, expr: &Expr, name: &str, inits: &[FieldInit], expected: Option<&Ty>) -> Ty {
+        let Some(definition) = self.structs.get(name).copied() else {
+            for init in inits {
+                self.expr(&init.value, None);
+            }
+            return self.error(expr.span, format!("unknown struct `{}`", name));
+        };
+        self.deprecated(expr.span, "struct", name);
+        // The type arguments of a generic struct come from the expected type
+        // if it names the same struct, otherwise from the field values.
+        let type_params: Vec<String> = definition.type_params.iter().map(|param| param.name.clone()).collect();
+        let mut bindings: HashMap<String, Ty> = match expected {
+            Some(Ty::Struct(expected_name, args)) if expected_name == name => {
+                bind_params(&definition.type_params, args).into_iter().filter(|(_, arg)| *arg != Ty::Error && !arg.is_open()).collect()
+            }
+            _ => HashMap::new(),
+        };
+        for (index, init) in inits.iter().enumerate() {
+            if inits[..index].iter().any(|earlier| earlier.name == init.name) {
+                self.error(init.span, format!("field `{}` is initialized more than once", init.name));
+            }
+            match definition.fields.iter().find(|field| field.name == init.name) {
+                Some(field) => {
+                    let declared = self.field_type(definition, field);
+                    let hint = declared.substitute(&open_params(&type_params, &bindings));
+                    let found = self.expr(&init.value, Some(&hint));
+                    unify(&declared, &found, &mut bindings);
+                    let ty = declared.substitute(&bindings);
+                    self.coerce(&init.value, found, &ty);
+                }
+                None => {
+                    self.error(init.span, format!("struct `{}` has no field `{}`", name, init.name));
+                    self.expr(&init.value, None);
+                }
+            }
+        }
+        let missing: Vec<String> = definition
+            .fields
+            .iter()
+            .filter(|field| !inits.iter().any(|init| init.name == field.name))
+            .map(|field| format!("`{}`", field.name))
+            .collect();
+        if !missing.is_empty() {
+            self.error(expr.span, format!("missing field(s) {} in `{}` literal", missing.join(", "), name));
+        }
+        let args = type_params
+            .iter()
+            .map(|param| match bindings.get(param) {
+                Some(arg) => arg.clone(),
+                None => self.error(expr.span, format!("cannot infer type parameter `{}` of struct `{}`", param, name)),
+            })
+            .collect();
+        Ty::Struct(name.to_string(), args)
+    }
+
+    /// The arms of a `match` must agree on a type; block arms are statements
+    /// and do not take part.
+    fn match_expr(&mut self, scrutinee: &Expr, arms: &[MatchArm], expected: Option<&Ty>) -> Ty {
+        let scrutinee_ty = self.expr(scrutinee, None);
+        let mut result: Option<Ty> = expected.filter(|ty| !ty.is_open()).cloned();
+        for arm in arms {
+            self.scopes.push(HashMap::new());
+            self.bind(&arm.pattern, &scrutinee_ty);
+            if let Some(guard) = &arm.guard {
+                self.expect(guard, &Ty::Bool);
+            }
+            match &arm.body {
+                ArmBody::Expr(body) => match &result {
+                    Some(ty) => {
+                        let ty = ty.clone();
+                        self.expect(body, &ty);
+                    }
+                    None => result = Some(self.expr(body, None)),
+                },
+                ArmBody::Block(block) => self.block(block),
+            }
+            self.scopes.pop();
+        }
+        result.unwrap_or(Ty::Void)
+    }
+
+    /// Parameters without annotations take their types from the function
+    /// type the closure is expected to have. An expression body gives the
+    /// return type; a block body returns `void` unless the closure declares
+    /// or is expected to have another return type.
+    fn closure(&mut self, params: &[ClosureParam], return_type: Option<&Type>, body: &ArmBody, expected: Option<&Ty>) -> Ty {
+        let (hint_params, hint_return) = match expected {
+            Some(Ty::Function(types, return_type)) if types.len() == params.len() => (
+                types.iter().map(|ty| Some(ty).filter(|ty| !ty.is_open())).collect(),
+                Some(return_type.as_ref()).filter(|ty| !ty.is_open()),
+            ),
+            _ => (vec![None; params.len()], None),
+        };
+        self.scopes.push(HashMap::new());
+        let mut param_types = Vec::new();
+        for (param, hint) in params.iter().zip(hint_params) {
+            let ty = match (&param.ty, hint) {
+                (Some(declared), _) => self.resolve_type(declared),
+                (None, Some(hint)) => hint.clone(),
+                (None, None) => self.error(
+                    param.span,
+                    format!("cannot infer the type of closure parameter `{}`", print_pattern(&param.pattern)),
+                ),
+            };
+            self.bind(&param.pattern, &ty);
+            param_types.push(ty);
+        }
+        // A closure created in a deferred statement returns from itself.
+        let deferred = std::mem::take(&mut self.deferred);
+        let result = match body {
+            // `?` in an expression body returns from the closure, whose
+            // return type is only known here if it is expected.
+            ArmBody::Expr(body) => {
+                let enclosing = std::mem::replace(&mut self.return_type, hint_return.cloned().unwrap_or(Ty::Error));
+                let result = self.expr(body, hint_return);
+                self.return_type = enclosing;
+                result
+            }
+            ArmBody::Block(block) => {
+                let result = match (return_type, hint_return) {
+                    (Some(declared), _) => self.resolve_type(declared),
+                    (None, Some(hint)) => hint.clone(),
+                    (None, None) => Ty::Void,
+                };
+                let enclosing = std::mem::replace(&mut self.return_type, result.clone());
+                self.block(block);
+                self.return_type = enclosing;
+                result
+            }
+        };
+        self.deferred = deferred;
+        self.scopes.pop();
+        Ty::Function(param_types, Box::new(result))
+    }
+
+    /// Reports an error and returns the `Error` type for the expression.
+    fn error(&mut self, span: Span, message: String) -> Ty {
+        self.diagnostics.push(Diagnostic::error(span, message));
+        Ty::Error
+    }
+}
+
+/// Binds the type parameters of a generic struct or enum to `args`.
+fn bind_params(type_params: &[TypeParam], args: &[Ty]) -> HashMap<String, Ty> {
+    type_params.iter().map(|param| param.name.clone()).zip(args.iter().cloned()).collect()
+}
+
+/// The type arguments of `definition` that the expected type gives, if it
+/// names the same enum. An expected type that failed to resolve gives `Error`
+/// for all of them, so that they are not reported as not inferred; an open
+/// hint gives none, so that they are inferred from the payload.
+fn expected_bindings(definition: &Enum, expected: Option<&Ty>) -> HashMap<String, Ty> {
+    match expected {
+        Some(Ty::Enum(name, args)) if *name == definition.name => {
+            bind_params(&definition.type_params, args).into_iter().filter(|(_, arg)| !arg.is_open()).collect()
+        }
+        Some(Ty::Error) => definition.type_params.iter().map(|param| (param.name.clone(), Ty::Error)).collect(),
+        _ => HashMap::new(),
+    }
+}
+
+/// `bindings`, with every type parameter it does not bind yet standing for
+/// `Infer`, so that a hint built from it asks nothing of the open ones.
+fn open_params(type_params: &[String], bindings: &HashMap<String, Ty>) -> HashMap<String, Ty> {
+    type_params
+        .iter()
+        .map(|param| (param.clone(), bindings.get(param).cloned().unwrap_or(Ty::Infer)))
+        .collect()
+}
+
+/// Binds the type parameters in `declared` that are still open to the
+/// matching parts of `found`. Mismatches are left for the caller to report
+/// once `declared` is substituted. A parameter matched against `Error` is
+/// bound to it, so a value that failed to check is not reported again as an
+/// inference failure; one matched against an open hint is left open.
+fn unify(declared: &Ty, found: &Ty, bindings: &mut HashMap<String, Ty>) {
+    match (declared, found) {
+        (Ty::Param(_), found) if found.is_open() => {}
+        (Ty::Param(name), _) => {
+            bindings.entry(name.clone()).or_insert_with(|| found.clone());
+        }
+        (Ty::Array(declared, _), Ty::Array(found, _)) => unify(declared, found, bindings),
+        (Ty::Tuple(declared), Ty::Tuple(found)) => {
+            for (declared, found) in declared.iter().zip(found) {
+                unify(declared, found, bindings);
+            }
+        }
+        (Ty::Struct(name, declared), Ty::Struct(found_name, found)) | (Ty::Enum(name, declared), Ty::Enum(found_name, found))
+            if name == found_name =>
+        {
+            for (declared, found) in declared.iter().zip(found) {
+                unify(declared, found, bindings);
+            }
+        }
+        (Ty::Function(declared, declared_result), Ty::Function(found, found_result)) => {
+            for (declared, found) in declared.iter().zip(found) {
+                unify(declared, found, bindings);
+            }
+            unify(declared_result, found_result, bindings);
+        }
+        _ => {}
+    }
+}
+
+/// Whether the type parameter `param` occurs in `ty`.
+fn mentions(ty: &Ty, param: &str) -> bool {
+    match ty {
+        Ty::Param(name) => name == param,
+        Ty::Array(element, _) => mentions(element, param),
+        Ty::Tuple(types) | Ty::Struct(_, types) | Ty::Enum(_, types) => types.iter().any(|ty| mentions(ty, param)),
+        Ty::Function(params, return_type) => params.iter().any(|ty| mentions(ty, param)) || mentions(return_type, param),
+        Ty::Int(_) | Ty::Bool | Ty::String | Ty::Ptr | Ty::Void | Ty::Dyn(_) | Ty::Error | Ty::Infer => false,
+    }
+}
+
+/// Whether constants can have the type `ty`: integers, `bool` and arrays of
+/// them.
+fn is_constant_type(ty: &Ty) -> bool {
+    match ty {
+        Ty::Int(_) | Ty::Bool | Ty::Error => true,
+        Ty::Array(element, _) => is_constant_type(element),
+        _ => false,
+    }
+}
+
+/// Whether `expr` is an integer literal, possibly negated.
+fn is_int_literal(expr: &Expr) -> bool {
+    int_literal_value(expr).is_some()
+}
+
+/// The value of `expr` if it is an integer literal, possibly negated.
+fn int_literal_value(expr: &Expr) -> Option<i128> {
+    match &expr.kind {
+        ExprKind::IntLiteral(value) => Some(i128::from(*value)),
+        ExprKind::Unary { op: UnaryOp::Neg, operand } => match operand.kind {
+            ExprKind::IntLiteral(value) => Some(-i128::from(value)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The operands of a binary operator in the order to check them in: an
+/// integer literal goes last, so that it takes the type of the other operand.
+fn literal_last<'e>(left: &'e Expr, right: &'e Expr) -> (&'e Expr, &'e Expr) {
+    if is_int_literal(left) && !is_int_literal(right) {
+        (right, left)
+    } else {
+        (left, right)
+    }
+}
+
+/// Whether `expr` denotes a storage location that can be assigned to.
+fn is_place(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Identifier(_) => true,
+        ExprKind::Index { base, .. } | ExprKind::Field { base, .. } | ExprKind::TupleIndex { base, .. } => is_place(base),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::prelude;
+
+    const PRELUDE: &str = "struct Point { x: int, y: int } enum Shape { Circle(int), Rect(int, int), Empty } function divmod(a: int, b: int) -> (int, int) { return (a / b, a - a / b * b); }";
+
+    fn errors(body: &str) -> Vec<String> {
+        let mut lexer = Lexer::new(format!("{} function f(n: int, b: bool, p: Point) {{ {} }}", PRELUDE, body));
+        let (_, diagnostics) = check_program(&Parser::new(&mut lexer).parse_program());
+        diagnostics.into_iter().map(|diagnostic| diagnostic.message).collect()
+    }
+
+    #[test]
+    fn accepts_well_typed_programs() {
+        assert_eq!(errors(""), Vec::<String>::new());
+        assert_eq!(
+            errors(
+                "let (q, r) = divmod(n, 2); let t: (int, (bool, Point)) = (q + r, (b, p)); t.1.1.x = t.0; \
+                 let a: int[2] = [n, p.y]; let e: bool[0] = []; let s = Shape::Rect(q, 1); \
+                 let area = match s { Shape::Circle(r) if r > 0 => r * r, Shape::Rect(w, h) => w * h, _ => 0 }; \
+                 if area == q == !b {} while (Point { x: 1, y: 2 }).x < n { n = n - 1; }"
+            ),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn reports_mismatched_types() {
+        assert_eq!(errors("let x: int = b;"), ["mismatched types: expected `int`, found `bool`"]);
+        assert_eq!(errors("if n {}"), ["mismatched types: expected `bool`, found `int`"]);
+        assert_eq!(errors("let t: (int, bool) = (n, n);"), ["mismatched types: expected `(int, bool)`, found `(int, int)`"]);
+        assert_eq!(errors("return 1;"), ["mismatched types: expected `void`, found `int`"]);
+        assert_eq!(errors("p == p;"), ["cannot compare values of type `Point` with `==`"]);
+        assert_eq!(errors("[1, b];"), ["mismatched types: expected `int`, found `bool`"]);
+    }
+
+    #[test]
+    fn checks_sized_integers_and_casts() {
+        assert_eq!(
+            errors(
+                "let x: u8 = 255; let y = 1 + x * 2; let z: u8 = y >> n; let w: i8 = -128; let big: u64 = n as u64 << 40; \
+                 let c = (x as i64 + big as i64) / 3 - b as i64; let a: int[2] = [1, 2]; a[x]; match x { 0 => 1, 255 => 2, _ => 3 }; if 1 < x == (w != -1) {}"
+            ),
+            Vec::<String>::new()
+        );
+        assert_eq!(errors("let x: u8 = 256;"), ["literal `256` does not fit in `u8`"]);
+        assert_eq!(errors("let x: i8 = -129; let y: u16 = -1;"), ["literal `-129` does not fit in `i8`", "literal `-1` does not fit in `u16`"]);
+        assert_eq!(errors("let x: u8 = 1; let y: i64 = x;"), ["mismatched types: expected `i64`, found `u8`"]);
+        assert_eq!(errors("let x: u8 = 1; n + x; x < n;"), ["mismatched types: expected `int`, found `u8`", "mismatched types: expected `u8`, found `int`"]);
+        assert_eq!(errors("let x: u32 = 1; -x;"), ["cannot negate a value of unsigned type `u32`"]);
+        assert_eq!(
+            errors("let x: u8 = 1; x << 8; n >> 31; x << -1; n >> -0;"),
+            ["shift amount 8 is out of range for `u8`", "shift amount -1 is out of range for `u8`"]
+        );
+        assert_eq!(errors("let x: u8 = 1; match x { 256 => 1, _ => 0 };"), ["literal `256` does not fit in `u8`"]);
+        assert_eq!(
+            errors("p as int; n as bool; (n, n) as u8;"),
+            [
+                "only integers and `bool` can be cast with `as`, found `Point`",
+                "`as` can only convert to an integer type, not `bool`",
+                "only integers and `bool` can be cast with `as`, found `(int, int)`"
+            ]
+        );
+        assert_eq!(errors("let x = 3000000000;"), ["literal `3000000000` does not fit in `int`"]);
+        assert_eq!(
+            errors("let x: u64 = 18446744073709551615; let y: i64 = -9223372036854775808; match x { 18446744073709551615 => 1, _ => 0 };"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            errors("let x: i64 = 9223372036854775808; let y: u32 = 18446744073709551615; let z: i64 = 1; match z { -9223372036854775809 => 1, _ => 0 };"),
+            [
+                "literal `9223372036854775808` does not fit in `i64`",
+                "literal `18446744073709551615` does not fit in `u32`",
+                "literal `-9223372036854775809` does not fit in `i64`"
+            ]
+        );
+    }
+
+    #[test]
+    fn warns_about_uses_of_deprecated_items() {
+        let source = "@deprecated(\"use g\") function f() {} @deprecated struct S { x: int } @deprecated enum E { A } \
+                      @deprecated interface I {} @deprecated const C: int = 1; @deprecated let G = 2; \
+                      function uses(s: S, d: dyn I) { f(); let h = f; S { x: C + G }; E::A; let G = 3; G; }";
+        let mut lexer = Lexer::new(source.to_string());
+        let (_, diagnostics) = check_program(&Parser::new(&mut lexer).parse_program());
+        assert!(diagnostics.iter().all(|diagnostic| !diagnostic.is_error()));
+        assert_eq!(
+            diagnostics.into_iter().map(|diagnostic| diagnostic.message).collect::<Vec<_>>(),
+            [
+                "use of deprecated struct `S`",
+                "use of deprecated interface `I`",
+                "use of deprecated function `f`: use g",
+                "use of deprecated function `f`: use g",
+                "use of deprecated struct `S`",
+                "use of deprecated constant `C`",
+                "use of deprecated global `G`",
+                "use of deprecated enum `E`"
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_strings_and_interpolation() {
+        assert_eq!(
+            errors(
+                "let s: string = \"a\" + \"b\"; let t = s + \"{n} {b} {s}\"; let c: u8 = t[n]; let l: int = s.len() + 1; \
+                 if s == t {} if \"x\" != s {}"
+            ),
+            Vec::<String>::new()
+        );
+        assert_eq!(errors("\"a\" + n; n + \"a\";"), ["mismatched types: expected `string`, found `int`", "mismatched types: expected `int`, found `string`"]);
+        assert_eq!(errors("\"a\" - n; \"a\" < n;"), ["mismatched types: expected `int`, found `string`", "`<` can only compare integers, found `string`"]);
+        assert_eq!(
+            errors("let s = \"a\"; s < \"q\"; n >= s; b > 1;"),
+            ["`<` can only compare integers, found `string`", "`>=` can only compare integers, found `string`", "`>` can only compare integers, found `bool`"]
+        );
+        assert_eq!(errors("let c: int = \"a\"[0]; \"a\"[b];"), ["mismatched types: expected `int`, found `u8`", "mismatched types: expected `int`, found `bool`"]);
+        assert_eq!(errors("let mut s = \"a\"; s[0] = 98;"), ["cannot assign to a byte of a string; strings are immutable"]);
+        assert_eq!(errors("\"at {p}\"; \"{divmod(n, 1)}\";"), ["cannot interpolate a value of type `Point` into a string", "cannot interpolate a value of type `(int, int)` into a string"]);
+        assert_eq!(errors("let s = \"a\"; s.size();"), ["type `string` has no field or method `size`"]);
+    }
+
+    #[test]
+    fn checks_extern_functions_and_variadic_calls() {
+        let externs = "struct Point { x: int } extern \"C\" function puts(s: ptr) -> int; extern \"C\" function printf(format: ptr, ...) -> int; \
+                       extern \"C\" function exit(code: int); extern \"stdcall\" function beep(); \
+                       extern \"C\" function bad(s: string, p: Point) -> (int, int);";
+        let errors = |body: &str| {
+            let mut lexer = Lexer::new(format!("{} function f(n: int, b: bool, s: string) {{ {} }}", externs, body));
+            let (_, diagnostics) = check_program(&Parser::new(&mut lexer).parse_program());
+            diagnostics.into_iter().map(|diagnostic| diagnostic.message).collect::<Vec<_>>()
+        };
+        let declarations = [
+            "unsupported calling convention \"stdcall\"; only \"C\" is supported",
+            "extern functions can only take integers, `bool` and `ptr`, found `string`",
+            "extern functions can only take integers, `bool` and `ptr`, found `Point`",
+            "extern functions can only return integers, `bool` and `ptr`, found `(int, int)`",
+        ];
+        assert_eq!(
+            errors("let r: int = puts(s.as_ptr()); printf(\"%d %d\\n\".as_ptr(), n, b); printf(s.as_ptr()); let e = exit; e(1); if s.as_ptr() == s.as_ptr() {}"),
+            declarations
+        );
+        let body_errors = |body: &str| errors(body)[declarations.len()..].to_vec();
+        assert_eq!(body_errors("puts(s);"), ["mismatched types: expected `ptr`, found `string`"]);
+        assert_eq!(body_errors("printf();"), ["function `printf` takes at least 1 argument(s) but 0 were given"]);
+        assert_eq!(
+            body_errors("printf(s.as_ptr(), s, (n, n));"),
+            ["cannot pass a value of type `string` as a variadic argument", "cannot pass a value of type `(int, int)` as a variadic argument"]
+        );
+        assert_eq!(body_errors("let p = printf;"), ["variadic function `printf` can only be called"]);
+    }
+
+    #[test]
+    fn checks_deferred_statements() {
+        assert_eq!(errors("let mut q = n; defer q = q + 1; defer if b { divmod(q, 2); } defer while q > 0 { q = q - 1; }"), Vec::<String>::new());
+        assert_eq!(errors("defer r = 1; let r = 0;"), ["unknown name `r`"]);
+        assert_eq!(errors("defer n = b;"), ["mismatched types: expected `int`, found `bool`"]);
+        assert_eq!(
+            errors("defer return; defer if b { return; } defer |m: int| { return; };"),
+            ["cannot `return` from a deferred statement", "cannot `return` from a deferred statement"]
+        );
+    }
+
+    #[test]
+    fn checks_tuple_indexing_and_destructuring() {
+        assert_eq!(errors("let t = divmod(n, 1); t.2;"), ["tuple `(int, int)` has no element 2"]);
+        assert_eq!(errors("n.0;"), ["type `int` is not a tuple"]);
+        assert_eq!(errors("let (a, b, c) = divmod(n, 1);"), ["expected `(int, int)`, found a tuple of 3 element(s)"]);
+        assert_eq!(errors("let (a, a) = divmod(n, 1);"), ["`a` is bound more than once in the same pattern"]);
+        assert_eq!(errors("let (q, r) = divmod(n, 1); let x: bool = r;"), ["mismatched types: expected `bool`, found `int`"]);
+    }
+
+    #[test]
+    fn checks_calls_fields_and_names() {
+        assert_eq!(errors("divmod(n);"), ["function `divmod` takes 2 argument(s) but 1 were given"]);
+        assert_eq!(errors("divmod(n, b);"), ["mismatched types: expected `int`, found `bool`"]);
+        assert_eq!(errors("Shape::Circle;"), ["`Shape::Circle` takes 1 payload value(s)"]);
+        assert_eq!(errors("p.z;"), ["struct `Point` has no field `z`"]);
+        assert_eq!(errors("Point { x: 1, x: 2 };"), ["field `x` is initialized more than once", "missing field(s) `y` in `Point` literal"]);
+        assert_eq!(errors("m + 1;"), ["unknown name `m`"]);
+        assert_eq!(errors("divmod = 1;"), ["cannot assign to function `divmod`"]);
+        assert_eq!(errors("1 = n;"), ["cannot assign to this expression"]);
+        assert_eq!(errors("let a = [1, 2]; a[2];"), ["index 2 is out of bounds for `int[2]`"]);
+        assert_eq!(errors("let a = [1, 2]; a[-1]; a[-0]; a[1];"), ["index -1 is out of bounds for `int[2]`"]);
+    }
+
+    #[test]
+    fn checks_function_values_and_closures() {
+        let apply = "function apply(f: fn(int) -> int, x: int) -> int { return f(x); }";
+        let check = |body: &str| {
+            let mut lexer = Lexer::new(format!("{} {} function g(n: int, b: bool) {{ {} }}", PRELUDE, apply, body));
+            let (_, diagnostics) = check_program(&Parser::new(&mut lexer).parse_program());
+            diagnostics.into_iter().map(|diagnostic| diagnostic.message).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            check(
+                "let add = |x: int| x + n; apply(add, apply(|x| x * 2, n)); let d: fn(int, int) -> (int, int) = divmod; \
+                 let h: fn(int) -> int = |x| -> int { return x; }; let v: fn() = || { return; }; (|y: bool| !y)(b);"
+            ),
+            Vec::<String>::new()
+        );
+        assert_eq!(check("let f = |x| x;"), ["cannot infer the type of closure parameter `x`"]);
+        assert_eq!(check("apply(|x| x == 1, n);"), ["mismatched types: expected `fn(int) -> int`, found `fn(int) -> bool`"]);
+        assert_eq!(check("apply(divmod, n);"), ["mismatched types: expected `fn(int) -> int`, found `fn(int, int) -> (int, int)`"]);
+        assert_eq!(check("let f = |x: int| -> bool { return x; };"), ["mismatched types: expected `bool`, found `int`"]);
+        assert_eq!(check("let f = |x: int| x; f(1, 2);"), ["`f` takes 1 argument(s) but 2 were given"]);
+        assert_eq!(check("(|| n)(b);"), ["function of type `fn() -> int` takes 0 argument(s) but 1 were given"]);
+    }
+
+    #[test]
+    fn checks_generic_functions_and_structs() {
+        let generics = "struct Pair<A, B> { first: A, second: B } function pick<T>(first: bool, a: T, b: T) -> T { if first { return a; } return b; } \
+                        function swap<A, B>(p: Pair<A, B>) -> Pair<B, A> { return Pair { first: p.second, second: p.first }; } \
+                        function map<T, U>(x: T, f: fn(T) -> U) -> U { return f(x); } function none<T>() -> T[0] { return []; } struct Tag<T> {}";
+        let check = |body: &str| {
+            let mut lexer = Lexer::new(format!("{} {} function g(n: int, b: bool) {{ {} }}", PRELUDE, generics, body));
+            let (_, diagnostics) = check_program(&Parser::new(&mut lexer).parse_program());
+            diagnostics.into_iter().map(|diagnostic| diagnostic.message).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            check(
+                "let x: int = pick(b, n, 2); let p: Pair<bool, int> = swap(Pair { first: n, second: b }); p.first == b; \
+                 let m: bool = map(n, |x| x > 0); let e: Point[0] = none(); let f: fn(bool, int, int) -> int = pick; \
+                 let q: Pair<(int, bool), Point[0]> = Pair { first: (n, b), second: [] }; pick(b, p, swap(swap(p))).second + 1;"
+            ),
+            Vec::<String>::new()
+        );
+        assert_eq!(check("pick(b, n, b);"), ["mismatched types: expected `int`, found `bool`"]);
+        assert_eq!(check("let x: bool = pick(b, n, n);"), ["mismatched types: expected `bool`, found `int`"]);
+        assert_eq!(check("none();"), ["cannot infer type parameter `T` of function `none`"]);
+        assert_eq!(check("let f = pick;"), ["cannot infer type parameter `T` of function `pick`"]);
+        assert_eq!(check("Tag {};"), ["cannot infer type parameter `T` of struct `Tag`"]);
+        assert_eq!(check("Pair { first: n, second: [] };"), ["cannot infer the type of an empty array literal"]);
+        assert_eq!(check("let p: Pair<int, bool> = Pair { first: b, second: b };"), ["mismatched types: expected `int`, found `bool`"]);
+        assert_eq!(check("let p: Pair<int> = Pair { first: n, second: b };"), ["type `Pair` takes 2 type argument(s) but 1 were given"]);
+        assert_eq!(check("swap(Pair { first: n, second: b }).third;"), ["struct `Pair<bool, int>` has no field `third`"]);
+
+        let body_errors = |source: &str| {
+            let mut lexer = Lexer::new(source.to_string());
+            let (_, diagnostics) = check_program(&Parser::new(&mut lexer).parse_program());
+            diagnostics.into_iter().map(|diagnostic| diagnostic.message).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            body_errors("function max<T>(a: T, b: T) -> T { if a > b { return a; } return b; }"),
+            ["`>` can only compare integers, found `T`"]
+        );
+        assert_eq!(
+            body_errors("function f<T, U>(a: T, b: U) -> T { a == a; return b; } struct S<T, T> { t: T } let x: T = 1;"),
+            [
+                "type parameter `T` is declared more than once",
+                "unknown type `T`",
+                "cannot compare values of type `T` with `==`",
+                "mismatched types: expected `T`, found `U`"
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_generic_enums_and_try() {
+        let functions = "function max<T>(a: T, b: T) -> T { return a; } function half(n: int) -> Option<int> { if n / 2 * 2 == n { return Option::Some(n / 2); } return Option::None; } \
+                         function parse(n: int) -> Result<int, bool> { if n < 0 { return Result::Err(false); } return Result::Ok(n); }";
+        let check = |return_type: &str, body: &str| {
+            let source = format!("{} {} {} function g(n: int, b: bool) -> {} {{ {} }}", prelude::SOURCE, PRELUDE, functions, return_type, body);
+            let mut lexer = Lexer::new(source);
+            let (_, diagnostics) = check_program(&Parser::new(&mut lexer).parse_program());
+            diagnostics.into_iter().map(|diagnostic| diagnostic.message).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            check(
+                "Option<int>",
+                "let q = half(n)?; let r: Option<(int, bool)> = Option::Some((half(q)?, b)); \
+                 let f: fn(Option<int>) -> Option<int> = |o| Option::Some(o? + 1); \
+                 return match r { Option::Some((x, _)) => f(Option::Some(x)), Option::None => Option::None };"
+            ),
+            Vec::<String>::new()
+        );
+        assert_eq!(check("Result<bool, bool>", "let x = parse(n)? + 1; return Result::Ok(x > 0);"), Vec::<String>::new());
+        assert_eq!(
+            check("int", "return half(n)?;"),
+            ["`?` on `Option<int>` can only be used in a function that returns an `Option`, not `int`"]
+        );
+        assert_eq!(
+            check("Option<int>", "return Option::Some(parse(n)?);"),
+            ["`?` on `Result<int, bool>` can only be used in a function that returns a `Result`, not `Option<int>`"]
+        );
+        assert_eq!(
+            check("Result<int, int>", "return Result::Ok(parse(n)?);"),
+            ["`?` cannot return an error of type `bool` from a function that returns `Result<int, int>`"]
+        );
+        assert_eq!(
+            check("Option<int>", "defer half(n)?; defer |m: int| -> Option<int> { return Option::Some(half(m)?); }; return Option::None;"),
+            ["`?` cannot return from a deferred statement"]
+        );
+        // Variants under a generic call or another variant take their type
+        // arguments from their payload.
+        assert_eq!(
+            check(
+                "void",
+                "let x = max(Option::Some(1), Option::None); let y = Option::Some(Option::Some(b)); \
+                 let wrong: Option<bool> = x; let right: Option<Option<bool>> = y; let z = max(Option::None, Option::None);"
+            ),
+            ["mismatched types: expected `Option<bool>`, found `Option<int>`", "cannot infer type parameter `T` of enum `Option`"]
+        );
+        assert_eq!(check("void", "n?;"), ["the `?` operator can only be applied to an `Option` or a `Result`, found `int`"]);
+        assert_eq!(check("void", "let o = Option::None;"), ["cannot infer type parameter `T` of enum `Option`"]);
+        assert_eq!(check("void", "let r: Result<int, bool> = Result::Ok(b);"), ["mismatched types: expected `int`, found `bool`"]);
+        assert_eq!(check("void", "Option::Some(n, b);"), ["`Option::Some` takes 1 argument(s) but 2 were given"]);
+        assert_eq!(check("void", "let o: Option = Option::None;"), ["type `Option` takes 1 type argument(s) but 0 were given"]);
+        assert_eq!(
+            check("void", "match half(n) { Option::Some(true) => {} _ => {} } match n { Option::None => {} _ => {} }"),
+            ["mismatched types: expected `int`, found `bool`", "mismatched types: expected `int`, found `Option<T>`"]
+        );
+    }
+
+    #[test]
+    fn checks_interfaces_impls_and_dispatch() {
+        let interfaces = "interface Show { function show(self) -> int; } interface Eq { function eq(self, other: Self) -> bool; } \
+                          impl Show for Point { function show(self) -> int { return self.x; } } \
+                          impl Eq for Point { function eq(self, other: Point) -> bool { return self.x == other.x; } } \
+                          impl Show for int { function show(self) -> int { return self; } } \
+                          function describe<T: Show>(x: T) -> int { return x.show(); } \
+                          function same<T: Show + Eq>(a: T, b: T) -> bool { return a.eq(b) == (a.show() == b.show()); }";
+        let check = |body: &str| {
+            let mut lexer = Lexer::new(format!("{} {} function g(n: int, b: bool, p: Point) {{ {} }}", PRELUDE, interfaces, body));
+            let (_, diagnostics) = check_program(&Parser::new(&mut lexer).parse_program());
+            diagnostics.into_iter().map(|diagnostic| diagnostic.message).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            check(
+                "p.show() + n.show() + describe(p); same(p, p); let d: dyn Show = p; d.show(); describe(d); \
+                 let all: dyn Show[2] = [p, n]; all[1].show();"
+            ),
+            Vec::<String>::new()
+        );
+        assert_eq!(check("describe(b);"), ["type `bool` does not implement interface `Show`"]);
+        assert_eq!(check("same(n, n);"), ["type `int` does not implement interface `Eq`"]);
+        assert_eq!(check("let d: dyn Eq = b;"), ["type `bool` does not implement interface `Eq`"]);
+        assert_eq!(check("let d: dyn Eq = p; d.eq(d);"), ["method `eq` cannot be called on `dyn Eq` because its signature uses `Self`"]);
+        assert_eq!(check("b.show();"), ["type `bool` has no field or method `show`"]);
+        assert_eq!(check("p.show(1);"), ["method `show` takes 0 argument(s) but 1 were given"]);
+        assert_eq!(check("p.eq(n);"), ["mismatched types: expected `Point`, found `int`"]);
+        assert_eq!(check("let d: dyn Display = p;"), ["unknown interface `Display`"]);
+
+        let item_errors = |items: &str| {
+            let mut lexer = Lexer::new(format!("{} interface Show {{ function show(self) -> int; }} {}", PRELUDE, items));
+            let (_, diagnostics) = check_program(&Parser::new(&mut lexer).parse_program());
+            diagnostics.into_iter().map(|diagnostic| diagnostic.message).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            item_errors("impl Show for Point { function show(self) -> bool { return true; } function hide(self) {} }"),
+            [
+                "method `show` has type `fn() -> bool` but interface `Show` declares `fn() -> int`",
+                "method `hide` is not a member of interface `Show`"
+            ]
+        );
+        assert_eq!(
+            item_errors("impl Show for bool {} impl Show for bool {} impl Display for int {}"),
+            [
+                "missing method(s) `show` in implementation of `Show` for `bool`",
+                "interface `Show` is already implemented for `bool`",
+                "missing method(s) `show` in implementation of `Show` for `bool`",
+                "unknown interface `Display`"
+            ]
+        );
+        assert_eq!(
+            item_errors("interface Bad { function f(x: int); function g(self); function g(self); } struct S<T: Show> { t: T } function h<T: Nope>() {}"),
+            [
+                "the first parameter of method `f` must be `self`",
+                "method `g` is declared more than once in interface `Bad`",
+                "bounds are only allowed on the type parameters of functions",
+                "unknown interface `Nope`"
+            ]
+        );
+        assert_eq!(
+            item_errors("function f(x: Self) {} function g<T>(x: T) -> int { return x.show(); }"),
+            ["unknown type `Self`", "type `T` has no field or method `show`"]
+        );
+    }
+
+    #[test]
+    fn checks_constants_and_array_sizes() {
+        let check = |source: &str| {
+            let mut lexer = Lexer::new(source.to_string());
+            let (table, diagnostics) = check_program(&Parser::new(&mut lexer).parse_program());
+            (table, diagnostics.into_iter().map(|diagnostic| diagnostic.message).collect::<Vec<_>>())
+        };
+        let (table, errors) = check(
+            "struct Grid { cells: int[N][N] } const N: int = 2 + 1; const ON: bool = N > 2; \
+             function f(g: Grid) -> int[N] { let row: int[3] = g.cells[0]; if ON { return row; } return [N, N * 2, 0]; }",
+        );
+        assert_eq!(errors, Vec::<String>::new());
+        assert_eq!(table.constant("N"), Some(ConstValue::Int(3)));
+        let (_, errors) = check(
+            "const N: int = 0 - 1; const B: bool = true; const P: (int, int) = (1, 2); const X: int = true; \
+             function f(a: int[N], b: int[B], c: int[M]) {}",
+        );
+        assert_eq!(
+            errors,
+            [
+                "tuples cannot be evaluated at compile time",
+                "array size `N` is negative: -1",
+                "array size `B` must be an integer constant, found `bool`",
+                "unknown constant `M`",
+                "constants must be integers, `bool` or arrays of them, found `(int, int)`",
+                "mismatched types: expected `int`, found `bool`"
+            ]
+        );
+    }
+
+    #[test]
+    fn records_expression_and_pattern_types() {
+        let mut lexer = Lexer::new(format!("{} function g() {{ let (q, r) = divmod(7, 2); }}", PRELUDE));
+        let program = Parser::new(&mut lexer).parse_program();
+        let (table, diagnostics) = check_program(&program);
+        assert!(diagnostics.is_empty());
+        let ItemKind::Function(g) = &program.items[3].kind else { panic!("expected function") };
+        let StmtKind::Let(binding) = &g.body.stmts[0].kind else { panic!("expected let") };
+        let pair = Ty::Tuple(vec![Ty::INT, Ty::INT]);
+        assert_eq!(table.type_of(binding.value.id), Some(&pair));
+        assert_eq!(table.type_of(binding.pattern.id), Some(&pair));
+        let PatternKind::Tuple(elements) = &binding.pattern.kind else { panic!("expected tuple pattern") };
+        assert_eq!(table.type_of(elements[1].id), Some(&Ty::INT));
+    }
+}
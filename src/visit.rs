@@ -0,0 +1,954 @@
+//! Traversal of the typed AST.
+//!
+//! `Visitor` walks a tree by shared reference and `VisitorMut` by mutable
+//! reference. Every `visit_*` method defaults to the matching `walk_*`
+//! function, which visits all children of the node; an implementation
+//! overrides only the nodes it cares about and calls `walk_*` itself when it
+//! wants to keep descending. The walk functions match exhaustively, so adding
+//! a node kind forces them to be updated here rather than in every pass.
+
+use crate::parser::{
+    ArmBody, Attribute, Block, ClosureParam, Enum, Expr, ExprKind, Field, FieldInit, Function, Impl, Interface, Item, ItemKind, Let,
+    MatchArm, Method, MethodSig, NodeId, Param, Pattern, PatternKind, Program, Stmt, StmtKind, StringPart, Struct, Type, TypeKind,
+    TypeParam, Variant,
+};
+
+pub trait Visitor: Sized {
+    fn visit_program(&mut self, program: &Program) {
+        walk_program(self, program)
+    }
+
+    fn visit_item(&mut self, item: &Item) {
+        walk_item(self, item)
+    }
+
+    fn visit_attribute(&mut self, attribute: &Attribute) {
+        walk_attribute(self, attribute)
+    }
+
+    fn visit_function(&mut self, function: &Function) {
+        walk_function(self, function)
+    }
+
+    fn visit_type_param(&mut self, param: &TypeParam) {
+        walk_type_param(self, param)
+    }
+
+    fn visit_param(&mut self, param: &Param) {
+        walk_param(self, param)
+    }
+
+    fn visit_let(&mut self, binding: &Let) {
+        walk_let(self, binding)
+    }
+
+    fn visit_struct(&mut self, definition: &Struct) {
+        walk_struct(self, definition)
+    }
+
+    fn visit_field(&mut self, field: &Field) {
+        walk_field(self, field)
+    }
+
+    fn visit_field_init(&mut self, init: &FieldInit) {
+        walk_field_init(self, init)
+    }
+
+    fn visit_enum(&mut self, definition: &Enum) {
+        walk_enum(self, definition)
+    }
+
+    fn visit_variant(&mut self, variant: &Variant) {
+        walk_variant(self, variant)
+    }
+
+    fn visit_interface(&mut self, definition: &Interface) {
+        walk_interface(self, definition)
+    }
+
+    fn visit_method_sig(&mut self, method: &MethodSig) {
+        walk_method_sig(self, method)
+    }
+
+    fn visit_impl(&mut self, definition: &Impl) {
+        walk_impl(self, definition)
+    }
+
+    fn visit_method(&mut self, method: &Method) {
+        walk_method(self, method)
+    }
+
+    fn visit_match_arm(&mut self, arm: &MatchArm) {
+        walk_match_arm(self, arm)
+    }
+
+    fn visit_closure_param(&mut self, param: &ClosureParam) {
+        walk_closure_param(self, param)
+    }
+
+    fn visit_pattern(&mut self, pattern: &Pattern) {
+        walk_pattern(self, pattern)
+    }
+
+    fn visit_type(&mut self, ty: &Type) {
+        walk_type(self, ty)
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        walk_block(self, block)
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        walk_stmt(self, stmt)
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr)
+    }
+}
+
+pub fn walk_program<V: Visitor>(visitor: &mut V, program: &Program) {
+    for item in &program.items {
+        visitor.visit_item(item);
+    }
+}
+
+pub fn walk_item<V: Visitor>(visitor: &mut V, item: &Item) {
+    for attribute in &item.attributes {
+        visitor.visit_attribute(attribute);
+    }
+    match &item.kind {
+        ItemKind::Function(function) => visitor.visit_function(function),
+        ItemKind::Global(binding) => visitor.visit_let(binding),
+        ItemKind::Const(constant) => {
+            visitor.visit_type(&constant.ty);
+            visitor.visit_expr(&constant.value);
+        }
+        ItemKind::Struct(definition) => visitor.visit_struct(definition),
+        ItemKind::Enum(definition) => visitor.visit_enum(definition),
+        ItemKind::Interface(definition) => visitor.visit_interface(definition),
+        ItemKind::Impl(definition) => visitor.visit_impl(definition),
+        ItemKind::Import(_) => {}
+        ItemKind::Extern(function) => {
+            for param in &function.params {
+                visitor.visit_param(param);
+            }
+            if let Some(return_type) = &function.return_type {
+                visitor.visit_type(return_type);
+            }
+        }
+    }
+}
+
+pub fn walk_function<V: Visitor>(visitor: &mut V, function: &Function) {
+    for param in &function.type_params {
+        visitor.visit_type_param(param);
+    }
+    for param in &function.params {
+        visitor.visit_param(param);
+    }
+    if let Some(return_type) = &function.return_type {
+        visitor.visit_type(return_type);
+    }
+    visitor.visit_block(&function.body);
+}
+
+pub fn walk_attribute<V: Visitor>(_visitor: &mut V, _attribute: &Attribute) {}
+
+pub fn walk_type_param<V: Visitor>(_visitor: &mut V, _param: &TypeParam) {}
+
+pub fn walk_param<V: Visitor>(visitor: &mut V, param: &Param) {
+    visitor.visit_pattern(&param.pattern);
+    visitor.visit_type(&param.ty);
+}
+
+pub fn walk_let<V: Visitor>(visitor: &mut V, binding: &Let) {
+    visitor.visit_pattern(&binding.pattern);
+    if let Some(ty) = &binding.ty {
+        visitor.visit_type(ty);
+    }
+    visitor.visit_expr(&binding.value);
+}
+
+pub fn walk_struct<V: Visitor>(visitor: &mut V, definition: &Struct) {
+    for param in &definition.type_params {
+        visitor.visit_type_param(param);
+    }
+    for field in &definition.fields {
+        visitor.visit_field(field);
+    }
+}
+
+pub fn walk_field<V: Visitor>(visitor: &mut V, field: &Field) {
+    visitor.visit_type(&field.ty);
+}
+
+pub fn walk_field_init<V: Visitor>(visitor: &mut V, init: &FieldInit) {
+    visitor.visit_expr(&init.value);
+}
+
+pub fn walk_enum<V: Visitor>(visitor: &mut V, definition: &Enum) {
+    for param in &definition.type_params {
+        visitor.visit_type_param(param);
+    }
+    for variant in &definition.variants {
+        visitor.visit_variant(variant);
+    }
+}
+
+pub fn walk_variant<V: Visitor>(visitor: &mut V, variant: &Variant) {
+    for ty in &variant.fields {
+        visitor.visit_type(ty);
+    }
+}
+
+pub fn walk_interface<V: Visitor>(visitor: &mut V, definition: &Interface) {
+    for method in &definition.methods {
+        visitor.visit_method_sig(method);
+    }
+}
+
+pub fn walk_method_sig<V: Visitor>(visitor: &mut V, method: &MethodSig) {
+    for param in &method.params {
+        visitor.visit_param(param);
+    }
+    if let Some(return_type) = &method.return_type {
+        visitor.visit_type(return_type);
+    }
+}
+
+pub fn walk_impl<V: Visitor>(visitor: &mut V, definition: &Impl) {
+    visitor.visit_type(&definition.ty);
+    for method in &definition.methods {
+        visitor.visit_method(method);
+    }
+}
+
+pub fn walk_method<V: Visitor>(visitor: &mut V, method: &Method) {
+    for attribute in &method.attributes {
+        visitor.visit_attribute(attribute);
+    }
+    visitor.visit_function(&method.function);
+}
+
+pub fn walk_match_arm<V: Visitor>(visitor: &mut V, arm: &MatchArm) {
+    visitor.visit_pattern(&arm.pattern);
+    if let Some(guard) = &arm.guard {
+        visitor.visit_expr(guard);
+    }
+    match &arm.body {
+        ArmBody::Expr(expr) => visitor.visit_expr(expr),
+        ArmBody::Block(block) => visitor.visit_block(block),
+    }
+}
+
+pub fn walk_closure_param<V: Visitor>(visitor: &mut V, param: &ClosureParam) {
+    visitor.visit_pattern(&param.pattern);
+    if let Some(ty) = &param.ty {
+        visitor.visit_type(ty);
+    }
+}
+
+pub fn walk_pattern<V: Visitor>(visitor: &mut V, pattern: &Pattern) {
+    match &pattern.kind {
+        PatternKind::Wildcard | PatternKind::Binding { .. } | PatternKind::IntLiteral(_) | PatternKind::BoolLiteral(_) => {}
+        PatternKind::Variant { fields, .. } => {
+            for field in fields {
+                visitor.visit_pattern(field);
+            }
+        }
+        PatternKind::Tuple(elements) => {
+            for element in elements {
+                visitor.visit_pattern(element);
+            }
+        }
+    }
+}
+
+pub fn walk_type<V: Visitor>(visitor: &mut V, ty: &Type) {
+    match &ty.kind {
+        TypeKind::Int(_) | TypeKind::Bool | TypeKind::String | TypeKind::Ptr | TypeKind::Void | TypeKind::Dyn(_) => {}
+        TypeKind::Named { args, .. } => {
+            for arg in args {
+                visitor.visit_type(arg);
+            }
+        }
+        TypeKind::Array { element, .. } => visitor.visit_type(element),
+        TypeKind::Tuple(elements) => {
+            for element in elements {
+                visitor.visit_type(element);
+            }
+        }
+        TypeKind::Function { params, return_type } => {
+            for param in params {
+                visitor.visit_type(param);
+            }
+            if let Some(return_type) = return_type {
+                visitor.visit_type(return_type);
+            }
+        }
+    }
+}
+
+pub fn walk_block<V: Visitor>(visitor: &mut V, block: &Block) {
+    for stmt in &block.stmts {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_stmt<V: Visitor>(visitor: &mut V, stmt: &Stmt) {
+    match &stmt.kind {
+        StmtKind::Let(binding) => visitor.visit_let(binding),
+        StmtKind::Expr(expr) => visitor.visit_expr(expr),
+        StmtKind::Return(value) => {
+            if let Some(value) = value {
+                visitor.visit_expr(value);
+            }
+        }
+        StmtKind::If { condition, then_branch, else_branch } => {
+            visitor.visit_expr(condition);
+            visitor.visit_block(then_branch);
+            if let Some(else_branch) = else_branch {
+                visitor.visit_block(else_branch);
+            }
+        }
+        StmtKind::For { init, condition, increment, body } => {
+            if let Some(init) = init {
+                visitor.visit_stmt(init);
+            }
+            if let Some(condition) = condition {
+                visitor.visit_expr(condition);
+            }
+            if let Some(increment) = increment {
+                visitor.visit_expr(increment);
+            }
+            visitor.visit_block(body);
+        }
+        StmtKind::While { condition, body } => {
+            visitor.visit_expr(condition);
+            visitor.visit_block(body);
+        }
+        StmtKind::Defer(stmt) => visitor.visit_stmt(stmt),
+    }
+}
+
+pub fn walk_expr<V: Visitor>(visitor: &mut V, expr: &Expr) {
+    match &expr.kind {
+        ExprKind::Unary { operand, .. } | ExprKind::Try(operand) => visitor.visit_expr(operand),
+        ExprKind::Cast { operand, ty } => {
+            visitor.visit_expr(operand);
+            visitor.visit_type(ty);
+        }
+        ExprKind::Binary { left, right, .. } => {
+            visitor.visit_expr(left);
+            visitor.visit_expr(right);
+        }
+        ExprKind::Assign { target, value } => {
+            visitor.visit_expr(target);
+            visitor.visit_expr(value);
+        }
+        ExprKind::Call { callee, args } => {
+            visitor.visit_expr(callee);
+            for arg in args {
+                visitor.visit_expr(arg);
+            }
+        }
+        ExprKind::Index { base, index } => {
+            visitor.visit_expr(base);
+            visitor.visit_expr(index);
+        }
+        ExprKind::Field { base, .. } | ExprKind::TupleIndex { base, .. } => visitor.visit_expr(base),
+        ExprKind::ArrayLiteral(elements) | ExprKind::Tuple(elements) => {
+            for element in elements {
+                visitor.visit_expr(element);
+            }
+        }
+        ExprKind::StructLiteral { fields, .. } => {
+            for init in fields {
+                visitor.visit_field_init(init);
+            }
+        }
+        ExprKind::Match { scrutinee, arms } => {
+            visitor.visit_expr(scrutinee);
+            for arm in arms {
+                visitor.visit_match_arm(arm);
+            }
+        }
+        ExprKind::Closure { params, return_type, body } => {
+            for param in params {
+                visitor.visit_closure_param(param);
+            }
+            if let Some(return_type) = return_type {
+                visitor.visit_type(return_type);
+            }
+            match &**body {
+                ArmBody::Expr(expr) => visitor.visit_expr(expr),
+                ArmBody::Block(block) => visitor.visit_block(block),
+            }
+        }
+        ExprKind::Interpolation(parts) => {
+            for part in parts {
+                if let StringPart::Expr(expr) = part {
+                    visitor.visit_expr(expr);
+                }
+            }
+        }
+        ExprKind::Path(_)
+        | ExprKind::Identifier(_)
+        | ExprKind::IntLiteral(_)
+        | ExprKind::BoolLiteral(_)
+        | ExprKind::StringLiteral(_) => {}
+    }
+}
+
+pub trait VisitorMut: Sized {
+    fn visit_program_mut(&mut self, program: &mut Program) {
+        walk_program_mut(self, program)
+    }
+
+    fn visit_item_mut(&mut self, item: &mut Item) {
+        walk_item_mut(self, item)
+    }
+
+    fn visit_attribute_mut(&mut self, attribute: &mut Attribute) {
+        walk_attribute_mut(self, attribute)
+    }
+
+    fn visit_function_mut(&mut self, function: &mut Function) {
+        walk_function_mut(self, function)
+    }
+
+    fn visit_type_param_mut(&mut self, param: &mut TypeParam) {
+        walk_type_param_mut(self, param)
+    }
+
+    fn visit_param_mut(&mut self, param: &mut Param) {
+        walk_param_mut(self, param)
+    }
+
+    fn visit_let_mut(&mut self, binding: &mut Let) {
+        walk_let_mut(self, binding)
+    }
+
+    fn visit_struct_mut(&mut self, definition: &mut Struct) {
+        walk_struct_mut(self, definition)
+    }
+
+    fn visit_field_mut(&mut self, field: &mut Field) {
+        walk_field_mut(self, field)
+    }
+
+    fn visit_field_init_mut(&mut self, init: &mut FieldInit) {
+        walk_field_init_mut(self, init)
+    }
+
+    fn visit_enum_mut(&mut self, definition: &mut Enum) {
+        walk_enum_mut(self, definition)
+    }
+
+    fn visit_variant_mut(&mut self, variant: &mut Variant) {
+        walk_variant_mut(self, variant)
+    }
+
+    fn visit_interface_mut(&mut self, definition: &mut Interface) {
+        walk_interface_mut(self, definition)
+    }
+
+    fn visit_method_sig_mut(&mut self, method: &mut MethodSig) {
+        walk_method_sig_mut(self, method)
+    }
+
+    fn visit_impl_mut(&mut self, definition: &mut Impl) {
+        walk_impl_mut(self, definition)
+    }
+
+    fn visit_method_mut(&mut self, method: &mut Method) {
+        walk_method_mut(self, method)
+    }
+
+    fn visit_match_arm_mut(&mut self, arm: &mut MatchArm) {
+        walk_match_arm_mut(self, arm)
+    }
+
+    fn visit_closure_param_mut(&mut self, param: &mut ClosureParam) {
+        walk_closure_param_mut(self, param)
+    }
+
+    fn visit_pattern_mut(&mut self, pattern: &mut Pattern) {
+        walk_pattern_mut(self, pattern)
+    }
+
+    fn visit_type_mut(&mut self, ty: &mut Type) {
+        walk_type_mut(self, ty)
+    }
+
+    fn visit_block_mut(&mut self, block: &mut Block) {
+        walk_block_mut(self, block)
+    }
+
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        walk_stmt_mut(self, stmt)
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr)
+    }
+}
+
+pub fn walk_program_mut<V: VisitorMut>(visitor: &mut V, program: &mut Program) {
+    for item in &mut program.items {
+        visitor.visit_item_mut(item);
+    }
+}
+
+pub fn walk_item_mut<V: VisitorMut>(visitor: &mut V, item: &mut Item) {
+    for attribute in &mut item.attributes {
+        visitor.visit_attribute_mut(attribute);
+    }
+    match &mut item.kind {
+        ItemKind::Function(function) => visitor.visit_function_mut(function),
+        ItemKind::Global(binding) => visitor.visit_let_mut(binding),
+        ItemKind::Const(constant) => {
+            visitor.visit_type_mut(&mut constant.ty);
+            visitor.visit_expr_mut(&mut constant.value);
+        }
+        ItemKind::Struct(definition) => visitor.visit_struct_mut(definition),
+        ItemKind::Enum(definition) => visitor.visit_enum_mut(definition),
+        ItemKind::Interface(definition) => visitor.visit_interface_mut(definition),
+        ItemKind::Impl(definition) => visitor.visit_impl_mut(definition),
+        ItemKind::Import(_) => {}
+        ItemKind::Extern(function) => {
+            for param in &mut function.params {
+                visitor.visit_param_mut(param);
+            }
+            if let Some(return_type) = &mut function.return_type {
+                visitor.visit_type_mut(return_type);
+            }
+        }
+    }
+}
+
+pub fn walk_function_mut<V: VisitorMut>(visitor: &mut V, function: &mut Function) {
+    for param in &mut function.type_params {
+        visitor.visit_type_param_mut(param);
+    }
+    for param in &mut function.params {
+        visitor.visit_param_mut(param);
+    }
+    if let Some(return_type) = &mut function.return_type {
+        visitor.visit_type_mut(return_type);
+    }
+    visitor.visit_block_mut(&mut function.body);
+}
+
+pub fn walk_attribute_mut<V: VisitorMut>(_visitor: &mut V, _attribute: &mut Attribute) {}
+
+pub fn walk_type_param_mut<V: VisitorMut>(_visitor: &mut V, _param: &mut TypeParam) {}
+
+pub fn walk_param_mut<V: VisitorMut>(visitor: &mut V, param: &mut Param) {
+    visitor.visit_pattern_mut(&mut param.pattern);
+    visitor.visit_type_mut(&mut param.ty);
+}
+
+pub fn walk_let_mut<V: VisitorMut>(visitor: &mut V, binding: &mut Let) {
+    visitor.visit_pattern_mut(&mut binding.pattern);
+    if let Some(ty) = &mut binding.ty {
+        visitor.visit_type_mut(ty);
+    }
+    visitor.visit_expr_mut(&mut binding.value);
+}
+
+pub fn walk_struct_mut<V: VisitorMut>(visitor: &mut V, definition: &mut Struct) {
+    for param in &mut definition.type_params {
+        visitor.visit_type_param_mut(param);
+    }
+    for field in &mut definition.fields {
+        visitor.visit_field_mut(field);
+    }
+}
+
+pub fn walk_field_mut<V: VisitorMut>(visitor: &mut V, field: &mut Field) {
+    visitor.visit_type_mut(&mut field.ty);
+}
+
+pub fn walk_field_init_mut<V: VisitorMut>(visitor: &mut V, init: &mut FieldInit) {
+    visitor.visit_expr_mut(&mut init.value);
+}
+
+pub fn walk_enum_mut<V: VisitorMut>(visitor: &mut V, definition: &mut Enum) {
+    for param in &mut definition.type_params {
+        visitor.visit_type_param_mut(param);
+    }
+    for variant in &mut definition.variants {
+        visitor.visit_variant_mut(variant);
+    }
+}
+
+pub fn walk_variant_mut<V: VisitorMut>(visitor: &mut V, variant: &mut Variant) {
+    for ty in &mut variant.fields {
+        visitor.visit_type_mut(ty);
+    }
+}
+
+pub fn walk_interface_mut<V: VisitorMut>(visitor: &mut V, definition: &mut Interface) {
+    for method in &mut definition.methods {
+        visitor.visit_method_sig_mut(method);
+    }
+}
+
+pub fn walk_method_sig_mut<V: VisitorMut>(visitor: &mut V, method: &mut MethodSig) {
+    for param in &mut method.params {
+        visitor.visit_param_mut(param);
+    }
+    if let Some(return_type) = &mut method.return_type {
+        visitor.visit_type_mut(return_type);
+    }
+}
+
+pub fn walk_impl_mut<V: VisitorMut>(visitor: &mut V, definition: &mut Impl) {
+    visitor.visit_type_mut(&mut definition.ty);
+    for method in &mut definition.methods {
+        visitor.visit_method_mut(method);
+    }
+}
+
+pub fn walk_method_mut<V: VisitorMut>(visitor: &mut V, method: &mut Method) {
+    for attribute in &mut method.attributes {
+        visitor.visit_attribute_mut(attribute);
+    }
+    visitor.visit_function_mut(&mut method.function);
+}
+
+pub fn walk_match_arm_mut<V: VisitorMut>(visitor: &mut V, arm: &mut MatchArm) {
+    visitor.visit_pattern_mut(&mut arm.pattern);
+    if let Some(guard) = &mut arm.guard {
+        visitor.visit_expr_mut(guard);
+    }
+    match &mut arm.body {
+        ArmBody::Expr(expr) => visitor.visit_expr_mut(expr),
+        ArmBody::Block(block) => visitor.visit_block_mut(block),
+    }
+}
+
+pub fn walk_closure_param_mut<V: VisitorMut>(visitor: &mut V, param: &mut ClosureParam) {
+    visitor.visit_pattern_mut(&mut param.pattern);
+    if let Some(ty) = &mut param.ty {
+        visitor.visit_type_mut(ty);
+    }
+}
+
+pub fn walk_pattern_mut<V: VisitorMut>(visitor: &mut V, pattern: &mut Pattern) {
+    match &mut pattern.kind {
+        PatternKind::Wildcard | PatternKind::Binding { .. } | PatternKind::IntLiteral(_) | PatternKind::BoolLiteral(_) => {}
+        PatternKind::Variant { fields, .. } => {
+            for field in fields {
+                visitor.visit_pattern_mut(field);
+            }
+        }
+        PatternKind::Tuple(elements) => {
+            for element in elements {
+                visitor.visit_pattern_mut(element);
+            }
+        }
+    }
+}
+
+pub fn walk_type_mut<V: VisitorMut>(visitor: &mut V, ty: &mut Type) {
+    match &mut ty.kind {
+        TypeKind::Int(_) | TypeKind::Bool | TypeKind::String | TypeKind::Ptr | TypeKind::Void | TypeKind::Dyn(_) => {}
+        TypeKind::Named { args, .. } => {
+            for arg in args {
+                visitor.visit_type_mut(arg);
+            }
+        }
+        TypeKind::Array { element, .. } => visitor.visit_type_mut(element),
+        TypeKind::Tuple(elements) => {
+            for element in elements {
+                visitor.visit_type_mut(element);
+            }
+        }
+        TypeKind::Function { params, return_type } => {
+            for param in params {
+                visitor.visit_type_mut(param);
+            }
+            if let Some(return_type) = return_type {
+                visitor.visit_type_mut(return_type);
+            }
+        }
+    }
+}
+
+pub fn walk_block_mut<V: VisitorMut>(visitor: &mut V, block: &mut Block) {
+    for stmt in &mut block.stmts {
+        visitor.visit_stmt_mut(stmt);
+    }
+}
+
+pub fn walk_stmt_mut<V: VisitorMut>(visitor: &mut V, stmt: &mut Stmt) {
+    match &mut stmt.kind {
+        StmtKind::Let(binding) => visitor.visit_let_mut(binding),
+        StmtKind::Expr(expr) => visitor.visit_expr_mut(expr),
+        StmtKind::Return(value) => {
+            if let Some(value) = value {
+                visitor.visit_expr_mut(value);
+            }
+        }
+        StmtKind::If { condition, then_branch, else_branch } => {
+            visitor.visit_expr_mut(condition);
+            visitor.visit_block_mut(then_branch);
+            if let Some(else_branch) = else_branch {
+                visitor.visit_block_mut(else_branch);
+            }
+        }
+        StmtKind::For { init, condition, increment, body } => {
+            if let Some(init) = init {
+                visitor.visit_stmt_mut(init);
+            }
+            if let Some(condition) = condition {
+                visitor.visit_expr_mut(condition);
+            }
+            if let Some(increment) = increment {
+                visitor.visit_expr_mut(increment);
+            }
+            visitor.visit_block_mut(body);
+        }
+        StmtKind::While { condition, body } => {
+            visitor.visit_expr_mut(condition);
+            visitor.visit_block_mut(body);
+        }
+        StmtKind::Defer(stmt) => visitor.visit_stmt_mut(stmt),
+    }
+}
+
+pub fn walk_expr_mut<V: VisitorMut>(visitor: &mut V, expr: &mut Expr) {
+    match &mut expr.kind {
+        ExprKind::Unary { operand, .. } | ExprKind::Try(operand) => visitor.visit_expr_mut(operand),
+        ExprKind::Cast { operand, ty } => {
+            visitor.visit_expr_mut(operand);
+            visitor.visit_type_mut(ty);
+        }
+        ExprKind::Binary { left, right, .. } => {
+            visitor.visit_expr_mut(left);
+            visitor.visit_expr_mut(right);
+        }
+        ExprKind::Assign { target, value } => {
+            visitor.visit_expr_mut(target);
+            visitor.visit_expr_mut(value);
+        }
+        ExprKind::Call { callee, args } => {
+            visitor.visit_expr_mut(callee);
+            for arg in args {
+                visitor.visit_expr_mut(arg);
+            }
+        }
+        ExprKind::Index { base, index } => {
+            visitor.visit_expr_mut(base);
+            visitor.visit_expr_mut(index);
+        }
+        ExprKind::Field { base, .. } | ExprKind::TupleIndex { base, .. } => visitor.visit_expr_mut(base),
+        ExprKind::ArrayLiteral(elements) | ExprKind::Tuple(elements) => {
+            for element in elements {
+                visitor.visit_expr_mut(element);
+            }
+        }
+        ExprKind::StructLiteral { fields, .. } => {
+            for init in fields {
+                visitor.visit_field_init_mut(init);
+            }
+        }
+        ExprKind::Match { scrutinee, arms } => {
+            visitor.visit_expr_mut(scrutinee);
+            for arm in arms {
+                visitor.visit_match_arm_mut(arm);
+            }
+        }
+        ExprKind::Closure { params, return_type, body } => {
+            for param in params {
+                visitor.visit_closure_param_mut(param);
+            }
+            if let Some(return_type) = return_type {
+                visitor.visit_type_mut(return_type);
+            }
+            match &mut **body {
+                ArmBody::Expr(expr) => visitor.visit_expr_mut(expr),
+                ArmBody::Block(block) => visitor.visit_block_mut(block),
+            }
+        }
+        ExprKind::Interpolation(parts) => {
+            for part in parts {
+                if let StringPart::Expr(expr) = part {
+                    visitor.visit_expr_mut(expr);
+                }
+            }
+        }
+        ExprKind::Path(_)
+        | ExprKind::Identifier(_)
+        | ExprKind::IntLiteral(_)
+        | ExprKind::BoolLiteral(_)
+        | ExprKind::StringLiteral(_) => {}
+    }
+}
+
+/// Gives every node of `program` a fresh id, children before parents as the
+/// parser does, for a tree that was put together from several parses.
+pub fn renumber(program: &mut Program) {
+    Renumber(0).visit_program_mut(program);
+}
+
+struct Renumber(u32);
+
+impl Renumber {
+    fn next(&mut self) -> NodeId {
+        self.0 += 1;
+        NodeId(self.0 - 1)
+    }
+}
+
+impl VisitorMut for Renumber {
+    fn visit_program_mut(&mut self, program: &mut Program) {
+        walk_program_mut(self, program);
+        program.id = self.next();
+    }
+
+    fn visit_item_mut(&mut self, item: &mut Item) {
+        walk_item_mut(self, item);
+        item.id = self.next();
+    }
+
+    fn visit_attribute_mut(&mut self, attribute: &mut Attribute) {
+        attribute.id = self.next();
+    }
+
+    fn visit_type_param_mut(&mut self, param: &mut TypeParam) {
+        param.id = self.next();
+    }
+
+    fn visit_param_mut(&mut self, param: &mut Param) {
+        walk_param_mut(self, param);
+        param.id = self.next();
+    }
+
+    fn visit_field_mut(&mut self, field: &mut Field) {
+        walk_field_mut(self, field);
+        field.id = self.next();
+    }
+
+    fn visit_field_init_mut(&mut self, init: &mut FieldInit) {
+        walk_field_init_mut(self, init);
+        init.id = self.next();
+    }
+
+    fn visit_method_sig_mut(&mut self, method: &mut MethodSig) {
+        walk_method_sig_mut(self, method);
+        method.id = self.next();
+    }
+
+    fn visit_method_mut(&mut self, method: &mut Method) {
+        walk_method_mut(self, method);
+        method.id = self.next();
+    }
+
+    fn visit_variant_mut(&mut self, variant: &mut Variant) {
+        walk_variant_mut(self, variant);
+        variant.id = self.next();
+    }
+
+    fn visit_match_arm_mut(&mut self, arm: &mut MatchArm) {
+        walk_match_arm_mut(self, arm);
+        arm.id = self.next();
+    }
+
+    fn visit_closure_param_mut(&mut self, param: &mut ClosureParam) {
+        walk_closure_param_mut(self, param);
+        param.id = self.next();
+    }
+
+    fn visit_pattern_mut(&mut self, pattern: &mut Pattern) {
+        walk_pattern_mut(self, pattern);
+        pattern.id = self.next();
+    }
+
+    fn visit_type_mut(&mut self, ty: &mut Type) {
+        walk_type_mut(self, ty);
+        ty.id = self.next();
+    }
+
+    fn visit_block_mut(&mut self, block: &mut Block) {
+        walk_block_mut(self, block);
+        block.id = self.next();
+    }
+
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        walk_stmt_mut(self, stmt);
+        stmt.id = self.next();
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr);
+        expr.id = self.next();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::{BinaryOp, Parser};
+
+    fn parse(source: &str) -> Program {
+        let mut lexer = Lexer::new(source.to_string());
+        Parser::new(&mut lexer).parse_program()
+    }
+
+    #[derive(Default)]
+    struct IdentifierCollector {
+        names: Vec<String>,
+    }
+
+    impl Visitor for IdentifierCollector {
+        fn visit_expr(&mut self, expr: &Expr) {
+            if let ExprKind::Identifier(name) = &expr.kind {
+                self.names.push(name.clone());
+            }
+            walk_expr(self, expr);
+        }
+    }
+
+    #[test]
+    fn visitor_reaches_expressions_in_every_position() {
+        let program = parse(
+            "let g: int = a; function f(p: int) -> int { let l = b; c; if d { e; } else { h; } for (let i = j; k; m = n) { o; } while q { r(s); } return t(|u: int| v); }",
+        );
+        let mut collector = IdentifierCollector::default();
+        collector.visit_program(&program);
+        assert_eq!(
+            collector.names,
+            ["a", "b", "c", "d", "e", "h", "j", "k", "m", "n", "o", "q", "r", "s", "t", "v"]
+        );
+    }
+
+    /// Rewrites `x - x` to `0`, leaving everything else untouched.
+    struct SelfSubtraction;
+
+    impl VisitorMut for SelfSubtraction {
+        fn visit_expr_mut(&mut self, expr: &mut Expr) {
+            walk_expr_mut(self, expr);
+            if let ExprKind::Binary { op: BinaryOp::Sub, left, right } = &expr.kind {
+                if let (ExprKind::Identifier(l), ExprKind::Identifier(r)) = (&left.kind, &right.kind) {
+                    if l == r {
+                        expr.kind = ExprKind::IntLiteral(0);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn visitor_mut_rewrites_nested_expressions() {
+        let mut program = parse("function f(x: int) -> int { return (x - x) + f(x - x); }");
+        SelfSubtraction.visit_program_mut(&mut program);
+        let mut collector = IdentifierCollector::default();
+        collector.visit_program(&program);
+        assert_eq!(collector.names, ["f"]);
+    }
+}